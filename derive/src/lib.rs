@@ -0,0 +1,290 @@
+/*
+* Copyright 2018-2020 TON DEV SOLUTIONS LTD.
+*
+* Licensed under the SOFTWARE EVALUATION License (the "License"); you may not use
+* this file except in compliance with the License.
+*
+* Unless required by applicable law or agreed to in writing, software
+* distributed under the License is distributed on an "AS IS" BASIS,
+* WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+* See the License for the specific TON DEV software governing permissions and
+* limitations under the License.
+*/
+
+//! Derive macros for `ton_types::StoreCell` and `ton_types::LoadCell`.
+//! Attributes are described in `ton_types::tlb`.
+
+use proc_macro::TokenStream;
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use quote::{format_ident, quote};
+use syn::{
+    parse_macro_input, Attribute, Data, DeriveInput, Error, Fields, LitInt, Result,
+};
+
+#[proc_macro_derive(StoreCell, attributes(tlb))]
+pub fn derive_store_cell(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    store_cell(&input).unwrap_or_else(Error::into_compile_error).into()
+}
+
+#[proc_macro_derive(LoadCell, attributes(tlb))]
+pub fn derive_load_cell(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    load_cell(&input).unwrap_or_else(Error::into_compile_error).into()
+}
+
+struct Tag {
+    value: LitInt,
+    bits: LitInt,
+}
+
+#[derive(Default)]
+struct FieldAttrs {
+    bits: Option<LitInt>,
+    maybe: bool,
+    is_ref: bool,
+    dict: Option<LitInt>,
+}
+
+fn parse_tag(attrs: &[Attribute]) -> Result<Option<Tag>> {
+    let mut value = None;
+    let mut bits = None;
+    for attr in attrs.iter().filter(|attr| attr.path().is_ident("tlb")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("tag") {
+                value = Some(meta.value()?.parse::<LitInt>()?);
+            } else if meta.path.is_ident("tag_bits") {
+                bits = Some(meta.value()?.parse::<LitInt>()?);
+            } else {
+                return Err(meta.error("expected `tag` or `tag_bits`"))
+            }
+            Ok(())
+        })?;
+    }
+    let Some(value) = value else {
+        return match bits {
+            Some(bits) => Err(Error::new(bits.span(), "`tag_bits` without `tag`")),
+            None => Ok(None)
+        }
+    };
+    let bits = match bits {
+        Some(bits) => bits,
+        None => LitInt::new(&infer_tag_bits(&value)?.to_string(), value.span())
+    };
+    Ok(Some(Tag { value, bits }))
+}
+
+// length of hex and binary literals is defined by their digits
+fn infer_tag_bits(value: &LitInt) -> Result<usize> {
+    let text = value.to_string().replace('_', "");
+    let digits = |prefix: &str| text.strip_prefix(prefix).map(|digits| digits.len());
+    if let Some(len) = digits("0x") {
+        Ok(len * 4)
+    } else if let Some(len) = digits("0b") {
+        Ok(len)
+    } else {
+        Err(Error::new(value.span(), "`tag_bits` is required for tag which is not hex or binary literal"))
+    }
+}
+
+fn parse_field_attrs(attrs: &[Attribute]) -> Result<FieldAttrs> {
+    let mut result = FieldAttrs::default();
+    for attr in attrs.iter().filter(|attr| attr.path().is_ident("tlb")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("bits") {
+                result.bits = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident("maybe") {
+                result.maybe = true;
+            } else if meta.path.is_ident("ref") {
+                result.is_ref = true;
+            } else if meta.path.is_ident("dict") {
+                result.dict = Some(meta.value()?.parse()?);
+            } else {
+                return Err(meta.error("expected `bits`, `maybe`, `ref` or `dict`"))
+            }
+            Ok(())
+        })?;
+        let kinds = result.bits.is_some() as u8 + result.is_ref as u8 + result.dict.is_some() as u8;
+        if kinds > 1 {
+            return Err(Error::new_spanned(attr, "only one of `bits`, `ref` and `dict` can be used"))
+        }
+    }
+    Ok(result)
+}
+
+fn store_field(attrs: &FieldAttrs, value: TokenStream2) -> TokenStream2 {
+    let store = |value: TokenStream2| match (&attrs.bits, attrs.is_ref) {
+        (Some(bits), _) => quote! { ::ton_types::tlb::store_uint(builder, *#value, #bits)?; },
+        (None, true) => quote! { ::ton_types::tlb::store_ref(builder, #value)?; },
+        (None, false) => quote! { ::ton_types::StoreCell::store_to(#value, builder)?; },
+    };
+    if !attrs.maybe {
+        return store(value)
+    }
+    let store = store(quote! { value });
+    quote! {
+        match #value {
+            Some(value) => {
+                ::ton_types::IBitstring::append_bit_one(builder)?;
+                #store
+            }
+            None => {
+                ::ton_types::IBitstring::append_bit_zero(builder)?;
+            }
+        }
+    }
+}
+
+fn load_field(attrs: &FieldAttrs) -> TokenStream2 {
+    let load = match (&attrs.bits, attrs.is_ref, &attrs.dict) {
+        (Some(bits), _, _) => quote! { ::ton_types::tlb::load_uint(slice, #bits)? },
+        (None, true, _) => quote! { ::ton_types::tlb::load_ref(slice)? },
+        (None, false, Some(bit_len)) => quote! { ::ton_types::tlb::load_dict(slice, #bit_len)? },
+        (None, false, None) => quote! { ::ton_types::LoadCell::load_from(slice)? },
+    };
+    match attrs.maybe {
+        true => quote! {
+            match ::ton_types::SliceData::get_next_bit(slice)? {
+                true => Some(#load),
+                false => None,
+            }
+        },
+        false => load
+    }
+}
+
+fn store_tag(tag: &Option<Tag>) -> TokenStream2 {
+    match tag {
+        Some(Tag { value, bits }) => quote! { ::ton_types::tlb::store_tag(builder, #value, #bits)?; },
+        None => quote! {}
+    }
+}
+
+// returns pattern binding fields to variables and code storing them
+fn store_fields(fields: &Fields) -> Result<(TokenStream2, TokenStream2)> {
+    let mut names = Vec::new();
+    let mut stores = Vec::new();
+    for (index, field) in fields.iter().enumerate() {
+        let name = match &field.ident {
+            Some(ident) => ident.clone(),
+            None => format_ident!("field_{}", index),
+        };
+        stores.push(store_field(&parse_field_attrs(&field.attrs)?, quote! { #name }));
+        names.push(name);
+    }
+    let pattern = match fields {
+        Fields::Named(_) => quote! { { #(#names),* } },
+        Fields::Unnamed(_) => quote! { ( #(#names),* ) },
+        Fields::Unit => quote! {},
+    };
+    Ok((pattern, quote! { #(#stores)* }))
+}
+
+fn load_fields(fields: &Fields) -> Result<TokenStream2> {
+    let mut loads = Vec::new();
+    for field in fields {
+        let load = load_field(&parse_field_attrs(&field.attrs)?);
+        loads.push(match &field.ident {
+            Some(ident) => quote! { #ident: #load },
+            None => load,
+        });
+    }
+    Ok(match fields {
+        Fields::Named(_) => quote! { { #(#loads),* } },
+        Fields::Unnamed(_) => quote! { ( #(#loads),* ) },
+        Fields::Unit => quote! {},
+    })
+}
+
+fn store_cell(input: &DeriveInput) -> Result<TokenStream2> {
+    let name = &input.ident;
+    let body = match &input.data {
+        Data::Struct(data) => {
+            let tag = store_tag(&parse_tag(&input.attrs)?);
+            let (pattern, stores) = store_fields(&data.fields)?;
+            quote! {
+                let Self #pattern = self;
+                #tag
+                #stores
+            }
+        }
+        Data::Enum(data) => {
+            let mut arms = Vec::new();
+            for variant in &data.variants {
+                let ident = &variant.ident;
+                let tag = parse_tag(&variant.attrs)?
+                    .ok_or_else(|| Error::new_spanned(variant, "enum variant must have `tag`"))?;
+                let tag = store_tag(&Some(tag));
+                let (pattern, stores) = store_fields(&variant.fields)?;
+                arms.push(quote! {
+                    Self::#ident #pattern => {
+                        #tag
+                        #stores
+                    }
+                });
+            }
+            quote! {
+                match self {
+                    #(#arms)*
+                }
+            }
+        }
+        Data::Union(_) => return Err(Error::new(Span::call_site(), "unions are not supported")),
+    };
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::ton_types::StoreCell for #name #ty_generics #where_clause {
+            fn store_to(&self, builder: &mut ::ton_types::BuilderData) -> ::ton_types::Result<()> {
+                #body
+                Ok(())
+            }
+        }
+    })
+}
+
+fn load_cell(input: &DeriveInput) -> Result<TokenStream2> {
+    let name = &input.ident;
+    let type_name = name.to_string();
+    let body = match &input.data {
+        Data::Struct(data) => {
+            let tag = match parse_tag(&input.attrs)? {
+                Some(Tag { value, bits }) => quote! {
+                    ::ton_types::tlb::load_tag(slice, #value, #bits, #type_name)?;
+                },
+                None => quote! {}
+            };
+            let fields = load_fields(&data.fields)?;
+            quote! {
+                #tag
+                Ok(Self #fields)
+            }
+        }
+        Data::Enum(data) => {
+            let mut arms = Vec::new();
+            for variant in &data.variants {
+                let ident = &variant.ident;
+                let Tag { value, bits } = parse_tag(&variant.attrs)?
+                    .ok_or_else(|| Error::new_spanned(variant, "enum variant must have `tag`"))?;
+                let fields = load_fields(&variant.fields)?;
+                arms.push(quote! {
+                    if ::ton_types::tlb::check_tag(slice, #value, #bits) {
+                        return Ok(Self::#ident #fields)
+                    }
+                });
+            }
+            quote! {
+                #(#arms)*
+                ::ton_types::tlb::unknown_tag(#type_name)
+            }
+        }
+        Data::Union(_) => return Err(Error::new(Span::call_site(), "unions are not supported")),
+    };
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::ton_types::LoadCell for #name #ty_generics #where_clause {
+            fn load_from(slice: &mut ::ton_types::SliceData) -> ::ton_types::Result<Self> {
+                #body
+            }
+        }
+    })
+}
@@ -0,0 +1,9 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use ton_types::fuzz::FuzzParams;
+
+fuzz_target!(|data: &[u8]| {
+    if let Some(boc) = FuzzParams::default().set_corrupt(true).boc(data) {
+        boc.check_invariants().unwrap();
+    }
+});
@@ -0,0 +1,183 @@
+#ifndef TON_TYPES_H
+#define TON_TYPES_H
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+enum ExceptionCode
+#if __STDC_VERSION__ >= 202311L
+  : int32_t
+#endif // __STDC_VERSION__ >= 202311L
+ {
+  ExceptionCode_NormalTermination = 0,
+  ExceptionCode_AlternativeTermination = 1,
+  ExceptionCode_StackUnderflow = 2,
+  ExceptionCode_StackOverflow = 3,
+  ExceptionCode_IntegerOverflow = 4,
+  ExceptionCode_RangeCheckError = 5,
+  ExceptionCode_InvalidOpcode = 6,
+  ExceptionCode_TypeCheckError = 7,
+  ExceptionCode_CellOverflow = 8,
+  ExceptionCode_CellUnderflow = 9,
+  ExceptionCode_DictionaryError = 10,
+  ExceptionCode_UnknownError = 11,
+  ExceptionCode_FatalError = 12,
+  ExceptionCode_OutOfGas = 13,
+  ExceptionCode_IllegalInstruction = 14,
+  ExceptionCode_PrunedCellAccess = 15,
+};
+#if __STDC_VERSION__ >= 202311L
+typedef enum ExceptionCode ExceptionCode;
+#else
+typedef int32_t ExceptionCode;
+#endif // __STDC_VERSION__ >= 202311L
+
+/**
+ * Stable codes of errors which are not exceptions, they don't intersect with `ExceptionCode` ones
+ */
+enum ErrorCode
+#if __STDC_VERSION__ >= 202311L
+  : int32_t
+#endif // __STDC_VERSION__ >= 202311L
+ {
+  ErrorCode_QuotaCellsExceeded = 100,
+  ErrorCode_QuotaBytesExceeded = 101,
+  ErrorCode_ParseBitsExceeded = 110,
+  ErrorCode_ParseReferencesExceeded = 111,
+  ErrorCode_ParseCellsExceeded = 112,
+  ErrorCode_ParseError = 113,
+  ErrorCode_IoError = 120,
+  ErrorCode_UnexpectedEof = 121,
+};
+#if __STDC_VERSION__ >= 202311L
+typedef enum ErrorCode ErrorCode;
+#else
+typedef int32_t ErrorCode;
+#endif // __STDC_VERSION__ >= 202311L
+
+/**
+ * Opaque handle of cell
+ */
+typedef struct TonCell TonCell;
+
+/**
+ * Opaque handle of slice, it keeps its cell alive
+ */
+typedef struct TonSlice TonSlice;
+
+/**
+ * Reads single root BOC, returns null if data is not a valid BOC.
+ * # Safety
+ * `data` must point to `len` readable bytes, `error_code` must be null or writable
+ */
+struct TonCell *ton_boc_parse(const uint8_t *data, size_t len, int32_t *error_code);
+
+/**
+ * Writes cell as BOC, returns buffer to be freed by `ton_bytes_free` and stores its length to `len`.
+ * # Safety
+ * `cell` must be a live handle, `len` must be writable, `error_code` must be null or writable
+ */
+uint8_t *ton_boc_serialize(const struct TonCell *cell,
+                           size_t *len,
+                           int32_t *error_code);
+
+/**
+ * # Safety
+ * `data` and `len` must be returned by `ton_boc_serialize`, each buffer is freed only once
+ */
+void ton_bytes_free(uint8_t *data, size_t len);
+
+/**
+ * # Safety
+ * `cell` must be null or a live handle, each handle is freed only once
+ */
+void ton_cell_free(struct TonCell *cell);
+
+/**
+ * Returns new handle of the same cell
+ * # Safety
+ * `cell` must be a live handle
+ */
+struct TonCell *ton_cell_clone(const struct TonCell *cell);
+
+/**
+ * Writes 32 bytes of representation hash to `hash`.
+ * # Safety
+ * `cell` must be a live handle and `hash` must point to 32 writable bytes
+ */
+bool ton_cell_repr_hash(const struct TonCell *cell, uint8_t *hash);
+
+/**
+ * # Safety
+ * `cell` must be a live handle
+ */
+size_t ton_cell_bit_length(const struct TonCell *cell);
+
+/**
+ * # Safety
+ * `cell` must be a live handle
+ */
+size_t ton_cell_references_count(const struct TonCell *cell);
+
+/**
+ * Returns new handle of child cell or null if there is no such reference.
+ * # Safety
+ * `cell` must be a live handle, `error_code` must be null or writable
+ */
+struct TonCell *ton_cell_reference(const struct TonCell *cell, size_t index, int32_t *error_code);
+
+/**
+ * Starts reading of cell, returns null for exotic cells.
+ * # Safety
+ * `cell` must be a live handle, `error_code` must be null or writable
+ */
+struct TonSlice *ton_slice_new(const struct TonCell *cell, int32_t *error_code);
+
+/**
+ * # Safety
+ * `slice` must be null or a live handle, each handle is freed only once
+ */
+void ton_slice_free(struct TonSlice *slice);
+
+/**
+ * # Safety
+ * `slice` must be a live handle
+ */
+size_t ton_slice_remaining_bits(const struct TonSlice *slice);
+
+/**
+ * # Safety
+ * `slice` must be a live handle
+ */
+size_t ton_slice_remaining_references(const struct TonSlice *slice);
+
+/**
+ * Reads unsigned integer of `bits` (up to 64) bits, slice is not moved on failure.
+ * # Safety
+ * `slice` must be a live handle, `value` must be writable, `error_code` must be null or writable
+ */
+bool ton_slice_get_next_int(struct TonSlice *slice,
+                            size_t bits,
+                            uint64_t *value,
+                            int32_t *error_code);
+
+/**
+ * Reads `len` whole bytes to `data`, slice is not moved on failure.
+ * # Safety
+ * `slice` must be a live handle, `data` must point to `len` writable bytes,
+ * `error_code` must be null or writable
+ */
+bool ton_slice_get_next_bytes(struct TonSlice *slice,
+                              uint8_t *data,
+                              size_t len,
+                              int32_t *error_code);
+
+/**
+ * Returns new handle of next reference or null if there are no references left.
+ * # Safety
+ * `slice` must be a live handle, `error_code` must be null or writable
+ */
+struct TonCell *ton_slice_get_next_reference(struct TonSlice *slice, int32_t *error_code);
+
+#endif  /* TON_TYPES_H */
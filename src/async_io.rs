@@ -0,0 +1,118 @@
+/*
+* Copyright 2018-2020 TON DEV SOLUTIONS LTD.
+*
+* Licensed under the SOFTWARE EVALUATION License (the "License"); you may not use
+* this file except in compliance with the License.
+*
+* Unless required by applicable law or agreed to in writing, software
+* distributed under the License is distributed on an "AS IS" BASIS,
+* WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+* See the License for the specific TON DEV software governing permissions and
+* limitations under the License.
+*/
+
+//! Asynchronous BOC reading and writing over `futures::io` traits, so any runtime can be used.
+//! Feature `tokio` adds thin adapters for `tokio::io` traits.
+//! Only one BOC is read from the stream, its length is calculated from its header.
+
+use alloc::sync::Arc;
+
+use futures_util::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+#[cfg(feature = "tokio")]
+use tokio_util::compat::{TokioAsyncReadCompatExt, TokioAsyncWriteCompatExt};
+
+use crate::{
+    cell::Cell,
+    cells_serialization::{
+        calc_boc_len, deserialize_cells_tree_header, serialize_toc, BocDeserializer,
+        BOC_GENERIC_TAG, MAX_ROOTS_COUNT,
+    },
+    fail,
+    types::Result,
+};
+
+// magic, flags and ref size byte, offset size byte
+const BOC_PREFIX_LEN: usize = 6;
+
+/// Reads bytes of one BOC from stream
+pub async fn read_boc<R: AsyncRead + Unpin>(src: &mut R) -> Result<Vec<u8>> {
+    let mut data = vec![0; BOC_PREFIX_LEN];
+    src.read_exact(&mut data).await?;
+    let generic = data[..4] == BOC_GENERIC_TAG.to_be_bytes();
+    let ref_size = match generic {
+        true => (data[4] & 0b0000_0111) as usize,
+        false => data[4] as usize
+    };
+    let offset_size = data[5] as usize;
+    if ref_size == 0 || ref_size > 4 || offset_size == 0 || offset_size > 8 {
+        // let header parser report the error
+        deserialize_cells_tree_header(&mut data.as_slice())?;
+        fail!("invalid BOC header")
+    }
+    // cells, roots and absent counts and total cells size
+    read_more(src, &mut data, 3 * ref_size + offset_size).await?;
+    if generic {
+        let mut roots_count = 0;
+        for byte in &data[BOC_PREFIX_LEN + ref_size..BOC_PREFIX_LEN + 2 * ref_size] {
+            roots_count = (roots_count << 8) | *byte as usize;
+        }
+        if roots_count > MAX_ROOTS_COUNT {
+            fail!("too many roots")
+        }
+        read_more(src, &mut data, roots_count * ref_size).await?;
+    }
+    let header = deserialize_cells_tree_header(&mut data.as_slice())?;
+    let len = calc_boc_len(&header, data.len() as u64);
+    // buffer grows while data is read, so wrong header does not cause huge allocation
+    let rest = len - data.len() as u64;
+    let read = src.take(rest).read_to_end(&mut data).await? as u64;
+    if read != rest {
+        fail!("BOC is truncated: {} bytes are read of {}", data.len(), len)
+    }
+    Ok(data)
+}
+
+async fn read_more<R: AsyncRead + Unpin>(src: &mut R, data: &mut Vec<u8>, len: usize) -> Result<()> {
+    let start = data.len();
+    data.resize(start + len, 0);
+    src.read_exact(&mut data[start..]).await?;
+    Ok(())
+}
+
+pub async fn deserialize_cells_tree_async<R: AsyncRead + Unpin>(src: &mut R) -> Result<Vec<Cell>> {
+    let data = read_boc(src).await?;
+    Ok(BocDeserializer::new().deserialize_inmem(Arc::new(data))?.roots)
+}
+
+pub async fn deserialize_tree_of_cells_async<R: AsyncRead + Unpin>(src: &mut R) -> Result<Cell> {
+    let data = read_boc(src).await?;
+    BocDeserializer::new().deserialize_inmem(Arc::new(data))?.withdraw_one_root()
+}
+
+/// Writes BOC of cell to stream and flushes it
+pub async fn serialize_tree_of_cells_async<W: AsyncWrite + Unpin>(cell: &Cell, dst: &mut W) -> Result<()> {
+    let data = serialize_toc(cell)?;
+    dst.write_all(&data).await?;
+    dst.flush().await?;
+    Ok(())
+}
+
+#[cfg(feature = "tokio")]
+pub async fn read_boc_tokio<R: tokio::io::AsyncRead + Unpin>(src: &mut R) -> Result<Vec<u8>> {
+    read_boc(&mut src.compat()).await
+}
+
+#[cfg(feature = "tokio")]
+pub async fn deserialize_cells_tree_tokio<R: tokio::io::AsyncRead + Unpin>(src: &mut R) -> Result<Vec<Cell>> {
+    deserialize_cells_tree_async(&mut src.compat()).await
+}
+
+#[cfg(feature = "tokio")]
+pub async fn deserialize_tree_of_cells_tokio<R: tokio::io::AsyncRead + Unpin>(src: &mut R) -> Result<Cell> {
+    deserialize_tree_of_cells_async(&mut src.compat()).await
+}
+
+#[cfg(feature = "tokio")]
+pub async fn serialize_tree_of_cells_tokio<W: tokio::io::AsyncWrite + Unpin>(cell: &Cell, dst: &mut W) -> Result<()> {
+    serialize_tree_of_cells_async(cell, &mut dst.compat_write()).await
+}
@@ -0,0 +1,51 @@
+/*
+* Copyright 2018-2020 TON DEV SOLUTIONS LTD.
+*
+* Licensed under the SOFTWARE EVALUATION License (the "License"); you may not use
+* this file except in compliance with the License.
+*
+* Unless required by applicable law or agreed to in writing, software
+* distributed under the License is distributed on an "AS IS" BASIS,
+* WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+* See the License for the specific TON DEV software governing permissions and
+* limitations under the License.
+*/
+
+use alloc::{boxed::Box, vec::Vec};
+
+use crate::{error, Result};
+use crate::types::OnceBox;
+
+/// Allocator of cells' data buffers, can be used to route allocations to pools or to track them.
+/// BuilderData keeps its data inline (see BUILDER_DATA_INLINE_CAPACITY), data growing over it
+/// is moved to buffer of this allocator.
+pub trait CellAllocator: Send + Sync {
+    /// Returns empty buffer with capacity at least `capacity` bytes
+    fn allocate(&self, capacity: usize) -> Vec<u8>;
+    /// Receives buffer of dropped cell, it may also be allocated not by this allocator
+    fn release(&self, buffer: Vec<u8>);
+}
+
+static ALLOCATOR: OnceBox<dyn CellAllocator> = OnceBox::new();
+
+/// Sets global allocator for cells' data, it can be done only once before cells are created
+pub fn set_cell_allocator(allocator: Box<dyn CellAllocator>) -> Result<()> {
+    ALLOCATOR.set(allocator).map_err(|_| error!("cell allocator is already set"))
+}
+
+pub(crate) fn allocate_cell_buffer(capacity: usize) -> Vec<u8> {
+    match ALLOCATOR.get() {
+        Some(allocator) => {
+            let mut buffer = allocator.allocate(capacity);
+            buffer.clear();
+            buffer
+        }
+        None => Vec::with_capacity(capacity)
+    }
+}
+
+pub(crate) fn release_cell_buffer(buffer: &mut Vec<u8>) {
+    if let Some(allocator) = ALLOCATOR.get() {
+        allocator.release(core::mem::take(buffer));
+    }
+}
@@ -0,0 +1,235 @@
+/*
+* Copyright 2018-2020 TON DEV SOLUTIONS LTD.
+*
+* Licensed under the SOFTWARE EVALUATION License (the "License"); you may not use
+* this file except in compliance with the License.
+*
+* Unless required by applicable law or agreed to in writing, software
+* distributed under the License is distributed on an "AS IS" BASIS,
+* WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+* See the License for the specific TON DEV software governing permissions and
+* limitations under the License.
+*/
+
+use alloc::{vec, vec::Vec};
+use arbitrary::{Arbitrary, Error, Unstructured};
+
+use crate::cell::{
+    BuilderData, Cell, CellType, LevelMask, SliceData, MAX_DATA_BITS, MAX_REFERENCES_COUNT, MAX_SAFE_DEPTH,
+};
+use crate::dictionary::HashmapE;
+use crate::merkle::{pruned_branch_builder, MerkleProof, MerkleUpdate};
+use crate::tlb::Serializable;
+use crate::types::UInt256;
+
+/// Limits for generated cell trees, used by `Arbitrary` implementations with default values
+#[derive(Clone, Debug)]
+pub struct ArbitraryCellParams {
+    /// max depth of generated tree
+    pub max_depth: usize,
+    /// max count of cells in generated tree
+    pub max_cells: usize,
+    /// max count of references in ordinary cell
+    pub max_refs: usize,
+    /// generate pruned branches, library references and merkle cells
+    pub exotic: bool,
+    /// max count of entries in generated dictionary
+    pub max_dict_entries: usize,
+}
+
+impl Default for ArbitraryCellParams {
+    fn default() -> Self {
+        Self {
+            max_depth: 8,
+            max_cells: 64,
+            max_refs: MAX_REFERENCES_COUNT,
+            exotic: true,
+            max_dict_entries: 64,
+        }
+    }
+}
+
+impl ArbitraryCellParams {
+    pub fn with_limits(max_depth: usize, max_cells: usize) -> Self {
+        Self { max_depth, max_cells, ..Self::default() }
+    }
+
+    pub fn set_max_refs(mut self, max_refs: usize) -> Self {
+        self.max_refs = max_refs.min(MAX_REFERENCES_COUNT);
+        self
+    }
+
+    pub fn set_exotic(mut self, exotic: bool) -> Self {
+        self.exotic = exotic;
+        self
+    }
+
+    pub fn set_max_dict_entries(mut self, max_dict_entries: usize) -> Self {
+        self.max_dict_entries = max_dict_entries;
+        self
+    }
+
+    pub fn cell(&self, u: &mut Unstructured) -> arbitrary::Result<Cell> {
+        let mut budget = self.max_cells.max(1);
+        self.gen_cell(u, self.max_depth, &mut budget)
+    }
+
+    /// Ordinary builder with references to generated subtrees
+    pub fn builder(&self, u: &mut Unstructured) -> arbitrary::Result<BuilderData> {
+        let mut budget = self.max_cells;
+        self.gen_builder(u, self.max_depth, &mut budget)
+    }
+
+    /// Slice with random window of data and references of generated builder
+    pub fn slice(&self, u: &mut Unstructured) -> arbitrary::Result<SliceData> {
+        let builder = self.builder(u)?;
+        let mut slice = SliceData::load_builder(builder).map_err(|_| Error::IncorrectFormat)?;
+        let bits = slice.remaining_bits();
+        let start = u.int_in_range(0..=bits)?;
+        let end = u.int_in_range(start..=bits)?;
+        slice.shrink_data(start..end);
+        let refs = slice.remaining_references();
+        let start = u.int_in_range(0..=refs)?;
+        let end = u.int_in_range(start..=refs)?;
+        slice.shrink_references(start..end);
+        Ok(slice)
+    }
+
+    /// Dictionary with random keys of `bit_len` bits, values are generated builders
+    pub fn dictionary(&self, u: &mut Unstructured, bit_len: usize) -> arbitrary::Result<HashmapE> {
+        let mut dict = HashmapE::with_bit_len(bit_len);
+        if bit_len == 0 {
+            return Ok(dict)
+        }
+        let count = u.int_in_range(0..=self.max_dict_entries)?;
+        let mut budget = self.max_cells;
+        for _ in 0..count {
+            let key = SliceData::load_builder(gen_bits(u, bit_len)?).map_err(|_| Error::IncorrectFormat)?;
+            if key.remaining_bits() != bit_len {
+                break
+            }
+            // keep place for the label
+            let value_bits = MAX_DATA_BITS.saturating_sub(bit_len + 12);
+            let value = self.gen_builder_with_bits(u, self.max_depth.saturating_sub(1), &mut budget, value_bits)?;
+            dict.set_builder(key, &value).map_err(|_| Error::IncorrectFormat)?;
+        }
+        Ok(dict)
+    }
+
+    fn gen_cell(&self, u: &mut Unstructured, depth: usize, budget: &mut usize) -> arbitrary::Result<Cell> {
+        *budget = budget.saturating_sub(1);
+        let kind = match self.exotic {
+            true => u.choose_index(16)?,
+            false => 0,
+        };
+        let builder = match kind {
+            12 => {
+                // stored depths must leave place for the rest of the tree
+                let max_depth = MAX_SAFE_DEPTH.saturating_sub(self.max_depth as u16);
+                pruned_branch(u, max_depth)?
+            }
+            13 => library_reference(u)?,
+            14 if depth > 0 => {
+                let proof = self.gen_cell(u, depth - 1, budget)?;
+                MerkleProof { hash: proof.hash(0), depth: proof.depth(0), proof }
+                    .write_to_new_cell().map_err(|_| Error::IncorrectFormat)?
+            }
+            15 if depth > 0 => {
+                let old = self.gen_cell(u, depth - 1, budget)?;
+                let new = self.gen_cell(u, depth - 1, budget)?;
+                MerkleUpdate {
+                    old_hash: old.hash(0),
+                    new_hash: new.hash(0),
+                    old_depth: old.depth(0),
+                    new_depth: new.depth(0),
+                    old,
+                    new,
+                }.write_to_new_cell().map_err(|_| Error::IncorrectFormat)?
+            }
+            _ => self.gen_builder(u, depth, budget)?
+        };
+        builder.into_cell().map_err(|_| Error::IncorrectFormat)
+    }
+
+    fn gen_builder(&self, u: &mut Unstructured, depth: usize, budget: &mut usize) -> arbitrary::Result<BuilderData> {
+        self.gen_builder_with_bits(u, depth, budget, MAX_DATA_BITS)
+    }
+
+    fn gen_builder_with_bits(
+        &self,
+        u: &mut Unstructured,
+        depth: usize,
+        budget: &mut usize,
+        max_bits: usize,
+    ) -> arbitrary::Result<BuilderData> {
+        let bits = u.int_in_range(0..=max_bits)?;
+        let mut builder = gen_bits(u, bits)?;
+        if depth > 0 {
+            let refs = u.int_in_range(0..=self.max_refs.min(MAX_REFERENCES_COUNT))?;
+            for _ in 0..refs {
+                if *budget == 0 {
+                    break
+                }
+                let child = self.gen_cell(u, depth - 1, budget)?;
+                builder.checked_append_reference(child).map_err(|_| Error::IncorrectFormat)?;
+            }
+        }
+        Ok(builder)
+    }
+}
+
+// takes as many bits as remaining input allows
+fn gen_bits(u: &mut Unstructured, bits: usize) -> arbitrary::Result<BuilderData> {
+    let bits = bits.min(u.len() * 8);
+    let data = u.bytes(bits.div_ceil(8))?;
+    BuilderData::with_raw(data.into(), bits).map_err(|_| Error::IncorrectFormat)
+}
+
+fn exotic_builder(cell_type: CellType, level_mask: LevelMask, data: &[u8]) -> arbitrary::Result<BuilderData> {
+    let mut builder = BuilderData::with_raw(data.into(), data.len() * 8).map_err(|_| Error::IncorrectFormat)?;
+    builder.set_type(cell_type);
+    builder.set_level_mask(level_mask);
+    Ok(builder)
+}
+
+fn pruned_branch(u: &mut Unstructured, max_depth: u16) -> arbitrary::Result<BuilderData> {
+    let level_mask = LevelMask::with_mask(u.int_in_range(1..=7)?);
+    let level = level_mask.level() as usize;
+    let hashes = (0..level).map(|_| Ok(UInt256::from(<[u8; 32]>::arbitrary(u)?)))
+        .collect::<arbitrary::Result<Vec<_>>>()?;
+    let depths = (0..level).map(|_| u.int_in_range(0..=max_depth))
+        .collect::<arbitrary::Result<Vec<_>>>()?;
+    pruned_branch_builder(level_mask, &hashes, &depths).map_err(|_| Error::IncorrectFormat)
+}
+
+// type + hash
+fn library_reference(u: &mut Unstructured) -> arbitrary::Result<BuilderData> {
+    let mut data = vec![u8::from(CellType::LibraryReference)];
+    data.extend_from_slice(&<[u8; 32]>::arbitrary(u)?);
+    exotic_builder(CellType::LibraryReference, LevelMask::with_mask(0), &data)
+}
+
+impl<'a> Arbitrary<'a> for Cell {
+    fn arbitrary(u: &mut Unstructured<'a>) -> arbitrary::Result<Self> {
+        ArbitraryCellParams::default().cell(u)
+    }
+}
+
+impl<'a> Arbitrary<'a> for BuilderData {
+    fn arbitrary(u: &mut Unstructured<'a>) -> arbitrary::Result<Self> {
+        ArbitraryCellParams::default().builder(u)
+    }
+}
+
+impl<'a> Arbitrary<'a> for SliceData {
+    fn arbitrary(u: &mut Unstructured<'a>) -> arbitrary::Result<Self> {
+        ArbitraryCellParams::default().slice(u)
+    }
+}
+
+impl<'a> Arbitrary<'a> for HashmapE {
+    fn arbitrary(u: &mut Unstructured<'a>) -> arbitrary::Result<Self> {
+        let bit_len = u.int_in_range(1..=32)?;
+        ArbitraryCellParams::default().dictionary(u, bit_len)
+    }
+}
@@ -0,0 +1,253 @@
+/*
+* Copyright 2018-2020 TON DEV SOLUTIONS LTD.
+*
+* Licensed under the SOFTWARE EVALUATION License (the "License"); you may not use
+* this file except in compliance with the License.
+*
+* Unless required by applicable law or agreed to in writing, software
+* distributed under the License is distributed on an "AS IS" BASIS,
+* WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+* See the License for the specific TON DEV software governing permissions and
+* limitations under the License.
+*/
+
+//! Archive of cell trees which is read in place without deserialization.
+//!
+//! Layout (integers are little endian u32):
+//! `magic`, `cells count`, `roots count`, `roots indexes`, `cells offsets`, `cells`.
+//! Each cell is its raw representation followed by indexes of its references. References always have greater indexes than the cell.
+//! Hashes and depths are stored only for cells of level 0 other than pruned branches,
+//! the rest are calculated when cell is constructed.
+//! Only header and tables sizes are checked when archive is opened, each cell is checked
+//! when it is accessed.
+
+use alloc::{sync::Arc, vec, vec::Vec};
+
+use smallvec::SmallVec;
+
+use crate::{
+    cell::{
+        self, append_tag_to, calc_d1, calc_d2, topological_order, Cell, CellType, DataCell, LevelMask,
+        MAX_SAFE_DEPTH,
+    },
+    fail,
+    types::{FxHashMap, Result, UInt256},
+};
+
+const ARCHIVE_MAGIC: [u8; 4] = *b"TCA\x01";
+const HEADER_SIZE: usize = 12;
+
+/// Archive of cell trees opened from bytes
+#[derive(Clone)]
+pub struct CellArchive {
+    data: Arc<Vec<u8>>,
+    cells_count: usize,
+    roots_count: usize,
+}
+
+impl CellArchive {
+    /// Writes trees of `roots` to archive, shared subtrees are written once
+    pub fn write(roots: &[Cell]) -> Result<Vec<u8>> {
+        let order = topological_order(roots)?;
+        let index_of = order.iter().enumerate()
+            .map(|(index, cell)| (cell.repr_hash(), index as u32))
+            .collect::<FxHashMap<_, _>>();
+        let mut result = Vec::new();
+        result.extend_from_slice(&ARCHIVE_MAGIC);
+        result.extend_from_slice(&(order.len() as u32).to_le_bytes());
+        result.extend_from_slice(&(roots.len() as u32).to_le_bytes());
+        for root in roots {
+            result.extend_from_slice(&index_of[&root.repr_hash()].to_le_bytes());
+        }
+        let offsets_start = result.len();
+        result.resize(offsets_start + 4 * order.len(), 0);
+        let mut data = Vec::new();
+        for (index, cell) in order.iter().enumerate() {
+            let offset = match u32::try_from(result.len()) {
+                Ok(offset) => offset,
+                Err(_) => fail!("archive is bigger than 4 GB")
+            };
+            result[offsets_start + 4 * index..offsets_start + 4 * index + 4].copy_from_slice(&offset.to_le_bytes());
+            let cell_type = cell.cell_type();
+            let store_hashes = cell_type != CellType::PrunedBranch && cell.level() == 0;
+            let bits = cell.bit_length();
+            result.push(calc_d1(cell.level_mask(), store_hashes, cell_type, cell.references_count()));
+            result.push(calc_d2(bits));
+            if store_hashes {
+                for hash in cell.hashes() {
+                    result.extend_from_slice(hash.as_slice());
+                }
+                for depth in cell.depths() {
+                    result.extend_from_slice(&depth.to_be_bytes());
+                }
+            }
+            append_tag_to(cell.data(), bits, &mut data);
+            result.extend_from_slice(&data[..bits.div_ceil(8)]);
+            for child in 0..cell.references_count() {
+                result.extend_from_slice(&index_of[&cell.reference_repr_hash(child)?].to_le_bytes());
+            }
+        }
+        Ok(result)
+    }
+
+    /// Opens archive checking its header
+    pub fn with_data(data: Arc<Vec<u8>>) -> Result<Self> {
+        if data.len() < HEADER_SIZE || data[..4] != ARCHIVE_MAGIC {
+            fail!("data is not a cell archive")
+        }
+        let cells_count = read_u32(&data, 4) as usize;
+        let roots_count = read_u32(&data, 8) as usize;
+        if data.len() < HEADER_SIZE.saturating_add(roots_count.saturating_add(cells_count).saturating_mul(4)) {
+            fail!("cell archive is too small for {} roots and {} cells", roots_count, cells_count)
+        }
+        Ok(Self { data, cells_count, roots_count })
+    }
+
+    pub fn cells_count(&self) -> usize {
+        self.cells_count
+    }
+
+    pub fn roots_count(&self) -> usize {
+        self.roots_count
+    }
+
+    pub fn data(&self) -> &Arc<Vec<u8>> {
+        &self.data
+    }
+
+    pub fn root(&self, index: usize) -> Result<ArchivedCell<'_>> {
+        if index >= self.roots_count {
+            fail!("archive has {} roots, there is no root {}", self.roots_count, index)
+        }
+        self.cell(read_u32(&self.data, HEADER_SIZE + 4 * index) as usize)
+    }
+
+    /// Cell by index, its layout and references are checked
+    pub fn cell(&self, index: usize) -> Result<ArchivedCell<'_>> {
+        if index >= self.cells_count {
+            fail!("archive has {} cells, there is no cell {}", self.cells_count, index)
+        }
+        let offset = read_u32(&self.data, HEADER_SIZE + 4 * (self.roots_count + index)) as usize;
+        if offset >= self.data.len() {
+            fail!("offset {} of cell {} is out of archive", offset, index)
+        }
+        let buf = &self.data[offset..];
+        cell::check_cell_buf(buf, true)?;
+        if cell::absent(buf) {
+            fail!("cell {} is absent", index)
+        }
+        let refs_offset = offset + cell::full_len(buf);
+        let refs_count = cell::refs_count(buf);
+        if self.data.len() < refs_offset + 4 * refs_count {
+            fail!("references of cell {} are out of archive", index)
+        }
+        for i in 0..refs_count {
+            let child = read_u32(&self.data, refs_offset + 4 * i) as usize;
+            if child <= index || child >= self.cells_count {
+                fail!("cell {} has invalid reference {}", index, child)
+            }
+        }
+        Ok(ArchivedCell { archive: self, index, offset })
+    }
+}
+
+/// View of cell in archive
+#[derive(Clone, Copy)]
+pub struct ArchivedCell<'a> {
+    archive: &'a CellArchive,
+    index: usize,
+    offset: usize,
+}
+
+impl<'a> ArchivedCell<'a> {
+    fn buf(&self) -> &'a [u8] {
+        &self.archive.data[self.offset..]
+    }
+
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    pub fn cell_type(&self) -> CellType {
+        cell::cell_type(self.buf())
+    }
+
+    pub fn level_mask(&self) -> LevelMask {
+        cell::level_mask(self.buf())
+    }
+
+    pub fn bit_length(&self) -> usize {
+        cell::bit_len(self.buf())
+    }
+
+    /// Data bytes, last one contains completion tag if length is not aligned
+    pub fn data(&self) -> &'a [u8] {
+        cell::cell_data(self.buf())
+    }
+
+    pub fn references_count(&self) -> usize {
+        cell::refs_count(self.buf())
+    }
+
+    pub fn reference(&self, index: usize) -> Result<ArchivedCell<'a>> {
+        if index >= self.references_count() {
+            fail!("cell {} has no reference {}", self.index, index)
+        }
+        let offset = self.offset + cell::full_len(self.buf()) + 4 * index;
+        self.archive.cell(read_u32(&self.archive.data, offset) as usize)
+    }
+
+    /// Stored representation hash, if it is not stored the cell tree is constructed to calculate it
+    pub fn repr_hash(&self) -> Result<UInt256> {
+        match cell::store_hashes(self.buf()) {
+            true => Ok(UInt256::from(cell::hash(self.buf(), cell::hashes_count(self.buf()) - 1))),
+            false => Ok(self.to_cell()?.repr_hash())
+        }
+    }
+
+    /// Constructs cell tree referencing archive's data, hashes are calculated and checked
+    pub fn to_cell(&self) -> Result<Cell> {
+        self.construct(true)
+    }
+
+    /// Constructs cell tree referencing archive's data, stored hashes are trusted
+    pub fn to_cell_unchecked(&self) -> Result<Cell> {
+        self.construct(false)
+    }
+
+    // children have greater indexes, so cells are constructed from the greatest index
+    fn construct(&self, check: bool) -> Result<Cell> {
+        let mut reachable = FxHashMap::default();
+        let mut stack = vec![*self];
+        while let Some(cell) = stack.pop() {
+            if reachable.insert(cell.index, cell).is_none() {
+                for i in 0..cell.references_count() {
+                    stack.push(cell.reference(i)?);
+                }
+            }
+        }
+        let mut indexes = reachable.keys().copied().collect::<Vec<_>>();
+        indexes.sort_unstable_by(|a, b| b.cmp(a));
+        let mut done = FxHashMap::<usize, Cell>::default();
+        for index in indexes {
+            let cell = reachable[&index];
+            let mut refs = SmallVec::new();
+            for i in 0..cell.references_count() {
+                refs.push(done[&cell.reference(i)?.index].clone());
+            }
+            let data_cell = match check {
+                true => DataCell::with_external_data_and_max_depth(refs, &self.archive.data, cell.offset, MAX_SAFE_DEPTH)?,
+                false => DataCell::with_external_data_unchecked(refs, &self.archive.data, cell.offset, MAX_SAFE_DEPTH)?,
+            };
+            done.insert(index, Cell::with_cell_impl(data_cell));
+        }
+        match done.remove(&self.index) {
+            Some(cell) => Ok(cell),
+            None => fail!("cell {} is not constructed", self.index)
+        }
+    }
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+}
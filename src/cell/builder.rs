@@ -11,40 +11,133 @@
 * limitations under the License.
 */
 
-use std::convert::From;
-use std::fmt;
+use core::convert::From;
+use core::fmt;
+use alloc::vec::Vec;
 
 use smallvec::SmallVec;
 
 use crate::cell::{
-    append_tag, find_tag, Cell, CellType, DataCell, LevelMask, SliceData,
-    MAX_DATA_BITS, MAX_SAFE_DEPTH,
+    allocate_cell_buffer, append_tag_to, calc_d1, calc_d2, find_tag, release_cell_buffer,
+    with_scratch_buffer, Cell, CellType, DataCell, LevelMask, SliceData, MAX_DATA_BITS, MAX_DATA_BYTES,
+    MAX_SAFE_DEPTH,
 };
+use crate::failpoints::{self, FailPoint};
 use crate::types::{ExceptionCode, Result};
 use crate::fail;
 
-const EXACT_CAPACITY: usize = 128;
+/// Count of data bytes stored inline in BuilderData (without heap allocation), longer data is moved
+/// to buffer of cell allocator. Feature `compact_builder` reduces it to shrink the size of BuilderData
+#[cfg(not(feature = "compact_builder"))]
+pub const BUILDER_DATA_INLINE_CAPACITY: usize = 64;
+#[cfg(feature = "compact_builder")]
+pub const BUILDER_DATA_INLINE_CAPACITY: usize = 32;
+
+/// Count of references stored inline in BuilderData (without heap allocation)
+#[cfg(not(feature = "compact_builder"))]
+pub const BUILDER_REFS_INLINE_CAPACITY: usize = 4;
+#[cfg(feature = "compact_builder")]
+pub const BUILDER_REFS_INLINE_CAPACITY: usize = 2;
+
+/// Data passed to and from BuilderData, it is the same with any features
+pub type BuilderDataBuffer = SmallVec<[u8; 128]>;
+/// References passed to and from BuilderData, they are the same with any features
+pub type BuilderReferences = SmallVec<[Cell; 4]>;
+
+// storage of BuilderData, it is smaller than public buffers
+type DataStorage = SmallVec<[u8; BUILDER_DATA_INLINE_CAPACITY]>;
+type RefsStorage = SmallVec<[Cell; BUILDER_REFS_INLINE_CAPACITY]>;
+
+// reserved capacity of data, it is kept inline
+const EXACT_CAPACITY: usize = BUILDER_DATA_INLINE_CAPACITY;
+
+// heap buffer is moved, inline items are copied
+fn convert<A: smallvec::Array, B: smallvec::Array<Item = A::Item>>(from: SmallVec<A>) -> SmallVec<B> {
+    if from.spilled() {
+        SmallVec::from_vec(from.into_vec())
+    } else {
+        from.into_iter().collect()
+    }
+}
+
+// inline data which does not fit storage is moved to buffer of cell allocator
+fn data_storage(data: BuilderDataBuffer) -> DataStorage {
+    if data.spilled() || data.len() <= BUILDER_DATA_INLINE_CAPACITY {
+        convert(data)
+    } else {
+        let mut buffer = allocate_cell_buffer(data.len().max(MAX_DATA_BYTES + 1));
+        buffer.extend_from_slice(&data);
+        SmallVec::from_vec(buffer)
+    }
+}
 
-#[derive(Debug, Default, PartialEq, Eq)]
+#[derive(Debug, Default)]
 pub struct BuilderData {
-    data: SmallVec<[u8; 128]>,
-    length_in_bits: usize,
-    references: SmallVec<[Cell; 4]>,
+    data: DataStorage,
+    length_in_bits: u16,
+    references: RefsStorage,
     cell_type: CellType,
     level_mask: LevelMask,
+    // data cell the builder was created from, returned by finalize if nothing has changed,
+    // its data is reused if only references are replaced
+    origin: Option<Cell>,
+}
+
+impl PartialEq for BuilderData {
+    fn eq(&self, other: &Self) -> bool {
+        self.data == other.data
+            && self.length_in_bits == other.length_in_bits
+            && self.references == other.references
+            && self.cell_type == other.cell_type
+            && self.level_mask == other.level_mask
+    }
+}
+
+impl Eq for BuilderData {}
+
+// spilled data is returned to cell allocator
+impl Drop for BuilderData {
+    fn drop(&mut self) {
+        self.release_data();
+    }
+}
+
+/// Wipes data including spare capacity, referenced trees are wiped as `Cell` ones.
+/// Buffers freed when data grows over inline capacity are not wiped.
+#[cfg(feature = "zeroize")]
+impl zeroize::Zeroize for BuilderData {
+    fn zeroize(&mut self) {
+        let capacity = self.data.capacity();
+        self.data.resize(capacity, 0);
+        self.data.as_mut_slice().zeroize();
+        self.data.clear();
+        self.length_in_bits = 0;
+        for mut cell in self.references.drain(..) {
+            cell.zeroize();
+        }
+        if let Some(mut origin) = self.origin.take() {
+            origin.zeroize();
+        }
+        self.cell_type = CellType::Ordinary;
+        self.level_mask = LevelMask::with_mask(0);
+    }
 }
 
 impl Clone for BuilderData {
     fn clone(&self) -> Self {
-        Self {
-            // NOTE: Without explicit `from_slice` there will be an
-            // iterator with collect instead of simple `memcpy`
-            data: SmallVec::from_slice(&self.data),
+        let mut clone = Self {
+            data: SmallVec::new(),
             length_in_bits: self.length_in_bits,
             references: self.references.clone(),
             cell_type: self.cell_type,
-            level_mask: self.level_mask
-        }
+            level_mask: self.level_mask,
+            origin: self.origin.clone(),
+        };
+        // NOTE: Without explicit `extend_from_slice` there will be an
+        // iterator with collect instead of simple `memcpy`
+        clone.reserve_data(self.data.len());
+        clone.data.extend_from_slice(&self.data);
+        clone
     }
 }
 
@@ -69,10 +162,12 @@ impl BuilderData {
             references: SmallVec::new(),
             cell_type: CellType::Ordinary,
             level_mask: LevelMask(0),
+            origin: None,
         }
     }
 
-    pub fn with_raw(mut data: SmallVec<[u8; 128]>, length_in_bits: usize) -> Result<BuilderData> {
+    pub fn with_raw(data: BuilderDataBuffer, length_in_bits: usize) -> Result<BuilderData> {
+        let mut data = data_storage(data);
         if length_in_bits > data.len() * 8 {
             fail!(ExceptionCode::FatalError)
         } else if length_in_bits > BuilderData::bits_capacity() {
@@ -87,17 +182,22 @@ impl BuilderData {
                 *last_byte = (*last_byte >> (8 - data_shift)) << (8 - data_shift);
             }
         }
-        data.reserve_exact(EXACT_CAPACITY - data.len());
+        data.reserve_exact(EXACT_CAPACITY.saturating_sub(data.len()));
         Ok(BuilderData {
             data,
-            length_in_bits,
+            length_in_bits: length_in_bits as u16,
             references: SmallVec::new(),
             cell_type: CellType::Ordinary,
             level_mask: LevelMask::with_mask(0),
+            origin: None,
         })
     }
 
-    pub fn with_raw_and_refs<TRefs>(data: SmallVec<[u8; 128]>, length_in_bits: usize, refs: TRefs) -> Result<BuilderData>
+    pub fn with_raw_vec(data: Vec<u8>, length_in_bits: usize) -> Result<BuilderData> {
+        Self::with_raw(data.into(), length_in_bits)
+    }
+
+    pub fn with_raw_and_refs<TRefs>(data: BuilderDataBuffer, length_in_bits: usize, refs: TRefs) -> Result<BuilderData>
     where
         TRefs: IntoIterator<Item = Cell>
     {
@@ -108,7 +208,7 @@ impl BuilderData {
         Ok(builder)
     }
 
-    pub fn with_bitstring(data: SmallVec<[u8; 128]>) -> Result<BuilderData> {
+    pub fn with_bitstring(data: BuilderDataBuffer) -> Result<BuilderData> {
         let length_in_bits = find_tag(data.as_slice());
         if length_in_bits == 0 {
             Ok(BuilderData::new())
@@ -125,7 +225,17 @@ impl BuilderData {
     pub fn into_cell(self) -> Result<Cell> { self.finalize(MAX_SAFE_DEPTH) }
 
     /// use max_depth to limit depth
-    pub fn finalize(mut self, max_depth: u16) -> Result<Cell> {
+    pub fn finalize(self, max_depth: u16) -> Result<Cell> {
+        self.finalize_ex(max_depth, true)
+    }
+
+    /// finalize without checking of exotic cell's layout and level mask,
+    /// use only for data which is already verified
+    pub fn finalize_unchecked(self, max_depth: u16) -> Result<Cell> {
+        self.finalize_ex(max_depth, false)
+    }
+
+    fn finalize_ex(mut self, max_depth: u16, check: bool) -> Result<Cell> {
         if self.cell_type == CellType::Ordinary {
             // For Ordinary cells - level is set automatically,
             // for other types - it must be set manually by set_level_mask()
@@ -133,17 +243,56 @@ impl BuilderData {
                 self.level_mask |= r.level_mask();
             }
         }
-        append_tag(&mut self.data, self.length_in_bits);
+        if let Some(origin) = self.origin.take() {
+            if self.has_same_data(&origin) {
+                if origin.repr_depth() <= max_depth && self.has_same_references(&origin) {
+                    return Ok(origin)
+                }
+                // only references are replaced, so data buffer (shared for cells of BOC) is taken
+                // from origin, stored hashes would not match new ones
+                if !origin.store_hashes() {
+                    let references = core::mem::take(&mut self.references).into_iter().collect();
+                    let cell = DataCell::construct_cell(origin.cell_data().without_hashes(), references, max_depth, check)?;
+                    return Ok(Cell::with_cell_impl(cell))
+                }
+            }
+        }
+        let bits = self.length_in_bits();
+        let references = core::mem::take(&mut self.references).into_iter().collect();
+        let cell = with_scratch_buffer(|data| {
+            append_tag_to(&self.data, bits, data);
+            if check {
+                DataCell::with_max_depth(references, data, self.cell_type, self.level_mask.mask(), max_depth)
+            } else {
+                DataCell::with_max_depth_unchecked(references, data, self.cell_type, self.level_mask.mask(), max_depth)
+            }
+        })?;
+        Ok(Cell::with_cell_impl(cell))
+    }
 
-        Ok(Cell::with_cell_impl(
-            DataCell::with_max_depth(
-                self.references,
-                &self.data,
-                self.cell_type,
-                self.level_mask.mask(),
-                max_depth,
-            )?
-        ))
+    /// checks if finalization would produce cell with the same data and descriptors as `cell`
+    fn has_same_data(&self, cell: &Cell) -> bool {
+        if self.cell_type != cell.cell_type()
+            || self.level_mask != cell.level_mask()
+            || self.length_in_bits() != cell.bit_length()
+            || self.references.len() != cell.references_count() {
+            return false
+        }
+        let full = self.length_in_bits() / 8;
+        let rem = self.length_in_bits() % 8;
+        let (data, cell_data) = (self.data.as_slice(), cell.data());
+        let len = full + (rem != 0) as usize;
+        if data.len() < len || cell_data.len() < len || data[..full] != cell_data[..full] {
+            return false
+        }
+        rem == 0 || (data[full] ^ cell_data[full]) >> (8 - rem) == 0
+    }
+
+    /// checks if references are the same as in `cell` with the same data, so its hashes can be reused
+    fn has_same_references(&self, cell: &Cell) -> bool {
+        self.references.iter().enumerate().all(|(i, r)| {
+            matches!(cell.reference_repr_hash(i), Ok(hash) if hash == r.repr_hash())
+        })
     }
 
     pub fn references(&self) -> &[Cell] {
@@ -175,9 +324,12 @@ impl BuilderData {
                 SmallVec::from_slice(cell.data()),
                 cell.bit_length()
         ).unwrap();
-        builder.references = cell.clone_references();
+        builder.references = cell.clone_references().into_iter().collect();
         builder.cell_type = cell.cell_type();
         builder.level_mask = cell.level_mask();
+        if cell.is_data_cell() {
+            builder.origin = Some(cell.clone());
+        }
         builder
     }
 
@@ -194,21 +346,32 @@ impl BuilderData {
         builder
     }
 
-    pub fn update_cell<T, P, R>(&mut self, mutate: T, args: P) -> R
+    /// mutates copies of parts of builder, fails with CellOverflow and leaves builder unchanged
+    /// if resulting length does not fit cell
+    pub fn update_cell<T, P, R>(&mut self, mutate: T, args: P) -> Result<R>
     where
-        T: Fn(&mut SmallVec<[u8; 128]>, &mut usize, &mut SmallVec<[Cell;4]>, P)  -> R
+        T: Fn(&mut BuilderDataBuffer, &mut usize, &mut BuilderReferences, P)  -> R
     {
-        let result = mutate(&mut self.data, &mut self.length_in_bits, &mut self.references, args);
+        let mut data = BuilderDataBuffer::from_slice(&self.data);
+        let mut bits = self.length_in_bits();
+        let mut references = self.references.iter().cloned().collect();
+        let result = mutate(&mut data, &mut bits, &mut references, args);
+        if bits > BuilderData::bits_capacity() {
+            fail!(ExceptionCode::CellOverflow)
+        }
+        self.release_data();
+        self.data = data_storage(data);
+        self.length_in_bits = bits as u16;
+        self.references = convert(references);
 
-        debug_assert!(self.length_in_bits <= BuilderData::bits_capacity());
         debug_assert!(self.data.len() * 8 <= BuilderData::bits_capacity() + 1);
-        result
+        Ok(result)
     }
 
     /// returns data of cell
-    pub fn cell_data(&mut self, data: &mut SmallVec<[u8; 128]>, bits: &mut usize, children: &mut SmallVec<[Cell;4]>) {
+    pub fn cell_data(&mut self, data: &mut BuilderDataBuffer, bits: &mut usize, children: &mut BuilderReferences) {
         *data = SmallVec::from_slice(&self.data);
-        *bits = self.length_in_bits;
+        *bits = self.length_in_bits();
         children.clear();
         let n = self.references.len();
         for i in 0..n {
@@ -217,7 +380,7 @@ impl BuilderData {
     }
 
     pub fn length_in_bits(&self) -> usize {
-        self.length_in_bits
+        self.length_in_bits as usize
     }
 
     pub fn can_append(&self, x: &BuilderData) -> bool {
@@ -225,23 +388,102 @@ impl BuilderData {
     }
 
     pub fn prepend_raw(&mut self, slice: &[u8], bits: usize) -> Result<&mut Self> {
-        if bits != 0 {
-            let mut buffer = BuilderData::with_raw(SmallVec::from_slice(slice), bits)?;
-            buffer.append_raw(self.data(), self.length_in_bits())?;
-            self.length_in_bits = buffer.length_in_bits;
-            self.data = buffer.data;
+        if bits == 0 {
+            return Ok(self)
+        } else if slice.len() * 8 < bits {
+            fail!(ExceptionCode::FatalError)
+        } else if self.length_in_bits() + bits > BuilderData::bits_capacity() {
+            fail!(ExceptionCode::CellOverflow)
+        }
+        let old_len = self.length_in_bits().div_ceil(8);
+        self.data.truncate(old_len);
+        if !self.length_in_bits().is_multiple_of(8) {
+            self.data[old_len - 1] &= 0xff << (8 - self.length_in_bits() % 8);
+        }
+        self.length_in_bits += bits as u16;
+        let new_len = self.length_in_bits().div_ceil(8);
+        self.reserve_data(new_len);
+        self.data.resize(new_len, 0);
+
+        // shift existing data right by `bits` in place, starting from the end
+        let (q, r) = (bits / 8, bits % 8);
+        for dst in (q..new_len).rev() {
+            let j = dst - q;
+            let cur = if j < old_len { self.data[j] } else { 0 };
+            self.data[dst] = if r == 0 {
+                cur
+            } else {
+                let prev = if j >= 1 && j - 1 < old_len { self.data[j - 1] } else { 0 };
+                (cur >> r) | (prev << (8 - r))
+            };
+        }
+
+        // put prefix
+        self.data[..q].copy_from_slice(&slice[..q]);
+        if r != 0 {
+            self.data[q] = (self.data[q] & (0xff >> r)) | (slice[q] & (0xff << (8 - r)));
         }
         Ok(self)
     }
 
     pub fn append_raw(&mut self, slice: &[u8], bits: usize) -> Result<&mut Self> {
+        failpoints::inject(FailPoint::BuilderAppend)?;
         if slice.len() * 8 < bits {
             fail!(ExceptionCode::FatalError)
         } else if (self.length_in_bits() + bits) > BuilderData::bits_capacity() {
             fail!(ExceptionCode::CellOverflow)
-        } else if bits != 0 {
-            if (self.length_in_bits() % 8) == 0 {
-                if (bits % 8) == 0 {
+        } else {
+            self.append_raw_unchecked(slice, bits);
+        }
+        assert!(self.length_in_bits() <= BuilderData::bits_capacity());
+        assert!(self.data().len() * 8 <= BuilderData::bits_capacity() + 1);
+        Ok(self)
+    }
+
+    /// Appends several raw parts checking capacity once for all of them
+    pub fn append_raw_batch(&mut self, parts: &[(&[u8], usize)]) -> Result<&mut Self> {
+        failpoints::inject(FailPoint::BuilderAppend)?;
+        let mut total_bits = 0;
+        for (slice, bits) in parts {
+            if slice.len() * 8 < *bits {
+                fail!(ExceptionCode::FatalError)
+            }
+            total_bits += bits;
+        }
+        if self.length_in_bits() + total_bits > BuilderData::bits_capacity() {
+            fail!(ExceptionCode::CellOverflow)
+        }
+        for (slice, bits) in parts {
+            self.append_raw_unchecked(slice, *bits);
+        }
+        debug_assert!(self.data().len() * 8 <= BuilderData::bits_capacity() + 1);
+        Ok(self)
+    }
+
+    // data growing over inline capacity is moved to buffer of cell allocator,
+    // `len` is the most length of data while it is changed
+    fn reserve_data(&mut self, len: usize) {
+        if !self.data.spilled() && len > self.data.inline_size() {
+            let mut buffer = allocate_cell_buffer(len.max(MAX_DATA_BYTES + 1));
+            buffer.extend_from_slice(&self.data);
+            self.data = SmallVec::from_vec(buffer);
+        }
+    }
+
+    fn release_data(&mut self) {
+        if self.data.spilled() {
+            release_cell_buffer(&mut core::mem::take(&mut self.data).into_vec());
+        }
+    }
+
+    // capacity and slice length must be checked by caller
+    fn append_raw_unchecked(&mut self, slice: &[u8], bits: usize) {
+        if bits != 0 {
+            // slice is appended entirely and then truncated, shifted bits take one more byte
+            let shifted = !self.length_in_bits().is_multiple_of(8) as usize;
+            self.reserve_data(self.length_in_bits() / 8 + slice.len() + shifted);
+            if self.length_in_bits().is_multiple_of(8) {
+                if bits.is_multiple_of(8) {
                     self.append_without_shifting(slice, bits);
                 } else {
                     self.append_with_slice_shifting(slice, bits);
@@ -250,29 +492,26 @@ impl BuilderData {
                 self.append_with_double_shifting(slice, bits);
             }
         }
-        assert!(self.length_in_bits() <= BuilderData::bits_capacity());
-        assert!(self.data().len() * 8 <= BuilderData::bits_capacity() + 1);
-        Ok(self)
     }
 
     fn append_without_shifting(&mut self, slice: &[u8], bits: usize) {
         assert_eq!(bits % 8, 0);
         assert_eq!(self.length_in_bits() % 8, 0);
 
-        self.data.truncate(self.length_in_bits / 8);
+        self.data.truncate(self.length_in_bits() / 8);
         self.data.extend_from_slice(slice);
-        self.length_in_bits += bits;
-        self.data.truncate(self.length_in_bits / 8);
+        self.length_in_bits += bits as u16;
+        self.data.truncate(self.length_in_bits() / 8);
     }
 
     fn append_with_slice_shifting(&mut self, slice: &[u8], bits: usize) {
-        assert!(bits % 8 != 0);
+        assert!(!bits.is_multiple_of(8));
         assert_eq!(self.length_in_bits() % 8, 0);
 
-        self.data.truncate(self.length_in_bits / 8);
+        self.data.truncate(self.length_in_bits() / 8);
         self.data.extend_from_slice(slice);
-        self.length_in_bits += bits;
-        self.data.truncate(1 + self.length_in_bits / 8);
+        self.length_in_bits += bits as u16;
+        self.data.truncate(1 + self.length_in_bits() / 8);
 
         let slice_shift = bits % 8;
         let mut last_byte = self.data.pop().expect("Empty slice going to another way");
@@ -282,9 +521,9 @@ impl BuilderData {
     }
 
     fn append_with_double_shifting(&mut self, slice: &[u8], bits: usize) {
-        let self_shift = self.length_in_bits % 8;
-        self.data.truncate(1 + self.length_in_bits / 8);
-        self.length_in_bits += bits;
+        let self_shift = self.length_in_bits() % 8;
+        self.data.truncate(1 + self.length_in_bits() / 8);
+        self.length_in_bits += bits as u16;
 
         let last_bits = self.data.pop().unwrap() >> (8 - self_shift);
         let mut y: u16 = last_bits.into();
@@ -294,11 +533,11 @@ impl BuilderData {
         }
         self.data.push((y << (8 - self_shift)) as u8);
 
-        let shift = self.length_in_bits % 8;
+        let shift = self.length_in_bits() % 8;
         if shift == 0 {
-            self.data.truncate(self.length_in_bits / 8);
+            self.data.truncate(self.length_in_bits() / 8);
         } else {
-            self.data.truncate(self.length_in_bits / 8 + 1);
+            self.data.truncate(self.length_in_bits() / 8 + 1);
             let mut last_byte = self.data.pop().unwrap();
             last_byte >>= 8 - shift;
             last_byte <<= 8 - shift;
@@ -314,11 +553,28 @@ impl BuilderData {
         self.level_mask
     }
 
+    /// Descriptor bytes d1 and d2 of cell to be built, level mask of ordinary cell is taken from references
+    pub fn descriptor(&self) -> [u8; 2] {
+        let mut level_mask = self.level_mask;
+        if self.cell_type == CellType::Ordinary {
+            for reference in self.references.iter() {
+                level_mask |= reference.level_mask();
+            }
+        }
+        [calc_d1(level_mask, false, self.cell_type, self.references.len()), calc_d2(self.length_in_bits())]
+    }
+
+    /// Exact bytes hashed to get representation hash of cell to be built
+    pub fn repr_preimage(&self) -> Result<Vec<u8>> {
+        self.clone().into_cell()?.repr_preimage()
+    }
+
     pub fn level_mask_mut(&mut self) -> &mut LevelMask {
         &mut self.level_mask
     }
 
     pub fn checked_append_reference(&mut self, cell: Cell) -> Result<&mut Self> {
+        failpoints::inject(FailPoint::BuilderAppend)?;
         if self.references_free() == 0 {
             fail!(ExceptionCode::CellOverflow)
         } else {
@@ -336,18 +592,24 @@ impl BuilderData {
         }
     }
 
-    pub fn replace_data(&mut self, data: SmallVec<[u8; 128]>, length_in_bits: usize) {
-        self.length_in_bits = std::cmp::min(std::cmp::min(length_in_bits, MAX_DATA_BITS), data.len() * 8);
-        self.data = data;
+    /// Replaces data failing if length is bigger than data or than cell can hold
+    pub fn replace_data(&mut self, data: BuilderDataBuffer, length_in_bits: usize) -> Result<&mut Self> {
+        if length_in_bits > MAX_DATA_BITS || length_in_bits > data.len() * 8 {
+            fail!("can not replace data of builder with {} bits of {} bytes", length_in_bits, data.len())
+        }
+        self.length_in_bits = length_in_bits as u16;
+        self.release_data();
+        self.data = data_storage(data);
+        Ok(self)
     }
 
-    pub fn replace_reference_cell(&mut self, index: usize, child: Cell) {
+    /// Replaces existing reference failing if there is no reference with such index
+    pub fn replace_reference_cell(&mut self, index: usize, child: Cell) -> Result<&mut Self> {
         match self.references.get_mut(index) {
-            None => {
-                log::error!("replacing not existed cell by index {} with cell hash {:x}", index, child.repr_hash());
-            }
+            None => fail!("replacing not existed cell by index {} with cell hash {:x}", index, child.repr_hash()),
             Some(old) => *old = child
         }
+        Ok(self)
     }
 
     pub fn set_type(&mut self, cell_type: CellType) {
@@ -363,10 +625,10 @@ impl BuilderData {
     }
 
     pub fn trunc(&mut self, length_in_bits: usize) -> Result<()> {
-        if self.length_in_bits < length_in_bits {
+        if self.length_in_bits() < length_in_bits {
             fail!(ExceptionCode::FatalError)
         } else {
-            self.length_in_bits = length_in_bits;
+            self.length_in_bits = length_in_bits as u16;
             self.data.truncate(1 + length_in_bits / 8);
             Ok(())
         }
@@ -377,7 +639,7 @@ impl BuilderData {
 
 impl fmt::Display for BuilderData {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "data: {} len: {} reference count: {}", hex::encode(&self.data), self.length_in_bits, self.references.len())
+        write!(f, "data: {} len: {} reference count: {}", hex::encode(&self.data), self.length_in_bits(), self.references.len())
     }
 }
 
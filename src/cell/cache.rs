@@ -0,0 +1,120 @@
+/*
+* Copyright 2018-2020 TON DEV SOLUTIONS LTD.
+*
+* Licensed under the SOFTWARE EVALUATION License (the "License"); you may not use
+* this file except in compliance with the License.
+*
+* Unless required by applicable law or agreed to in writing, software
+* distributed under the License is distributed on an "AS IS" BASIS,
+* WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+* See the License for the specific TON DEV software governing permissions and
+* limitations under the License.
+*/
+
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+use crate::cell::Cell;
+use crate::types::{FxHashMap, UInt256};
+
+#[derive(Default)]
+struct LruState {
+    cells: FxHashMap<UInt256, (Cell, u64)>,
+    order: BTreeMap<u64, UInt256>,
+    tick: u64,
+}
+
+impl LruState {
+    fn touch(&mut self, hash: &UInt256) -> Option<Cell> {
+        let tick = self.tick + 1;
+        let (cell, used) = self.cells.get_mut(hash)?;
+        self.order.remove(used);
+        self.order.insert(tick, *hash);
+        *used = tick;
+        self.tick = tick;
+        Some(cell.clone())
+    }
+}
+
+/// Bounded cache of cells by representation hash, least recently used cells are evicted first.
+/// Can be shared between threads and plugged into BOC reading (see `BocDeserializer::set_cell_cache`)
+pub struct LruCellCache {
+    capacity: usize,
+    state: Mutex<LruState>,
+}
+
+impl LruCellCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            state: Mutex::new(LruState::default()),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn len(&self) -> usize {
+        self.lock().cells.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn get(&self, hash: &UInt256) -> Option<Cell> {
+        self.lock().touch(hash)
+    }
+
+    pub fn contains(&self, hash: &UInt256) -> bool {
+        self.lock().cells.contains_key(hash)
+    }
+
+    /// Puts cell to the cache evicting least recently used one if capacity is exceeded.
+    /// Evicted cell is dropped after the lock is released, so dropping of its tree doesn't block readers
+    pub fn insert(&self, cell: Cell) {
+        if self.capacity == 0 {
+            return
+        }
+        let hash = cell.repr_hash();
+        let mut evicted = None;
+        {
+            let mut state = self.lock();
+            if state.touch(&hash).is_some() {
+                return
+            }
+            if state.cells.len() >= self.capacity {
+                if let Some((_, hash)) = state.order.pop_first() {
+                    evicted = state.cells.remove(&hash);
+                }
+            }
+            state.tick += 1;
+            let tick = state.tick;
+            state.order.insert(tick, hash);
+            state.cells.insert(hash, (cell, tick));
+        }
+        drop(evicted);
+    }
+
+    pub fn remove(&self, hash: &UInt256) -> Option<Cell> {
+        let mut state = self.lock();
+        let (cell, used) = state.cells.remove(hash)?;
+        state.order.remove(&used);
+        Some(cell)
+    }
+
+    pub fn clear(&self) {
+        let cells = {
+            let mut state = self.lock();
+            state.order.clear();
+            core::mem::take(&mut state.cells)
+        };
+        drop(cells);
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, LruState> {
+        // cache state is always consistent, so poisoning can be ignored
+        self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
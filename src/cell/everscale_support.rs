@@ -0,0 +1,130 @@
+/*
+* Copyright 2018-2020 TON DEV SOLUTIONS LTD.
+*
+* Licensed under the SOFTWARE EVALUATION License (the "License"); you may not use
+* this file except in compliance with the License.
+*
+* Unless required by applicable law or agreed to in writing, software
+* distributed under the License is distributed on an "AS IS" BASIS,
+* WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+* See the License for the specific TON DEV software governing permissions and
+* limitations under the License.
+*/
+
+//! Conversions with everscale-types. Cells are rebuilt from data and references
+//! (shared subtrees are converted once) and their hashes are checked to be equal.
+
+use everscale_types::cell::{Cell as EsCell, CellBuilder, DynCell, HashBytes};
+use smallvec::SmallVec;
+
+use crate::{
+    cell::{BuilderData, Cell, CellType, LevelMask},
+    fail,
+    types::{Error, FxHashMap, Result, UInt256},
+};
+
+impl From<UInt256> for HashBytes {
+    fn from(value: UInt256) -> Self {
+        HashBytes(value.inner())
+    }
+}
+
+impl From<&UInt256> for HashBytes {
+    fn from(value: &UInt256) -> Self {
+        HashBytes(*value.as_array())
+    }
+}
+
+impl From<HashBytes> for UInt256 {
+    fn from(value: HashBytes) -> Self {
+        UInt256::from(value.0)
+    }
+}
+
+impl From<&HashBytes> for UInt256 {
+    fn from(value: &HashBytes) -> Self {
+        UInt256::from(value.0)
+    }
+}
+
+impl TryFrom<&Cell> for EsCell {
+    type Error = Error;
+    fn try_from(cell: &Cell) -> Result<Self> {
+        to_everscale(cell, &mut FxHashMap::default())
+    }
+}
+
+impl TryFrom<Cell> for EsCell {
+    type Error = Error;
+    fn try_from(cell: Cell) -> Result<Self> {
+        EsCell::try_from(&cell)
+    }
+}
+
+impl TryFrom<&DynCell> for Cell {
+    type Error = Error;
+    fn try_from(cell: &DynCell) -> Result<Self> {
+        from_everscale(cell, &mut FxHashMap::default())
+    }
+}
+
+impl TryFrom<&EsCell> for Cell {
+    type Error = Error;
+    fn try_from(cell: &EsCell) -> Result<Self> {
+        Cell::try_from(cell.as_ref())
+    }
+}
+
+impl TryFrom<EsCell> for Cell {
+    type Error = Error;
+    fn try_from(cell: EsCell) -> Result<Self> {
+        Cell::try_from(cell.as_ref())
+    }
+}
+
+fn to_everscale(cell: &Cell, done: &mut FxHashMap<UInt256, EsCell>) -> Result<EsCell> {
+    let hash = cell.repr_hash();
+    if let Some(result) = done.get(&hash) {
+        return Ok(result.clone())
+    }
+    let mut builder = CellBuilder::new();
+    builder.set_exotic(cell.cell_type() != CellType::Ordinary);
+    builder.store_raw(cell.data(), cell.bit_length() as u16)?;
+    for index in 0..cell.references_count() {
+        builder.store_reference(to_everscale(&cell.reference(index)?, done)?)?;
+    }
+    let result = builder.build()?;
+    if result.repr_hash().0 != *hash.as_array() {
+        fail!("hash of converted cell {:x} differs from {:x}", UInt256::from(result.repr_hash()), hash)
+    }
+    done.insert(hash, result.clone());
+    Ok(result)
+}
+
+fn from_everscale(cell: &DynCell, done: &mut FxHashMap<UInt256, Cell>) -> Result<Cell> {
+    let hash = UInt256::from(cell.repr_hash());
+    if let Some(result) = done.get(&hash) {
+        return Ok(result.clone())
+    }
+    let mut builder = BuilderData::with_raw(SmallVec::from_slice(cell.data()), cell.bit_len() as usize)?;
+    let descriptor = cell.descriptor();
+    if descriptor.is_exotic() {
+        match cell.data().first() {
+            Some(cell_type) => builder.set_type(CellType::try_from(*cell_type)?),
+            None => fail!("exotic cell {:x} has no type", hash)
+        }
+        builder.set_level_mask(LevelMask::with_mask(descriptor.level_mask().to_byte()));
+    }
+    for index in 0..descriptor.reference_count() {
+        match cell.reference(index) {
+            Some(child) => builder.checked_append_reference(from_everscale(child, done)?)?,
+            None => fail!("cell {:x} has no reference {}", hash, index)
+        };
+    }
+    let result = builder.into_cell()?;
+    if result.repr_hash() != hash {
+        fail!("hash of converted cell {:x} differs from {:x}", result.repr_hash(), hash)
+    }
+    done.insert(hash, result.clone());
+    Ok(result)
+}
@@ -0,0 +1,150 @@
+/*
+* Copyright 2018-2020 TON DEV SOLUTIONS LTD.
+*
+* Licensed under the SOFTWARE EVALUATION License (the "License"); you may not use
+* this file except in compliance with the License.
+*
+* Unless required by applicable law or agreed to in writing, software
+* distributed under the License is distributed on an "AS IS" BASIS,
+* WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+* See the License for the specific TON DEV software governing permissions and
+* limitations under the License.
+*/
+
+use smallvec::SmallVec;
+
+use crate::{
+    cell::{
+        calc_hashes_depths, check_type_and_level, find_tag, Cell, CellType, LevelMask,
+        ABSENT_D1, DEPTH_SIZE, EXOTIC_D1_FLAG, LEVELMASK_D1_OFFSET, MAX_REFERENCES_COUNT,
+        REFS_D1_MASK, SHA256_SIZE,
+    },
+    fail,
+    types::{Result, UInt256},
+};
+
+/// Hashes and depths of cell's children used for calculation of cell's hashes
+pub(crate) trait ChildHashes {
+    fn level_mask(&self) -> LevelMask;
+    fn hash(&self, index: usize) -> UInt256;
+    fn depth(&self, index: usize) -> u16;
+}
+
+impl ChildHashes for Cell {
+    fn level_mask(&self) -> LevelMask {
+        Cell::level_mask(self)
+    }
+    fn hash(&self, index: usize) -> UInt256 {
+        Cell::hash(self, index)
+    }
+    fn depth(&self, index: usize) -> u16 {
+        Cell::depth(self, index)
+    }
+}
+
+impl ChildHashes for CellHashes {
+    fn level_mask(&self) -> LevelMask {
+        self.level_mask
+    }
+    fn hash(&self, index: usize) -> UInt256 {
+        CellHashes::hash(self, index)
+    }
+    fn depth(&self, index: usize) -> u16 {
+        CellHashes::depth(self, index)
+    }
+}
+
+/// Hashes and depths of all significant levels of cell, indexed the same way as in `Cell`
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CellHashes {
+    level_mask: LevelMask,
+    hashes_depths: SmallVec<[(UInt256, u16); 4]>,
+}
+
+impl CellHashes {
+    pub fn from_cell(cell: &Cell) -> Self {
+        Self {
+            level_mask: cell.level_mask(),
+            hashes_depths: cell.hashes().into_iter().zip(cell.depths()).collect(),
+        }
+    }
+
+    pub fn level_mask(&self) -> LevelMask {
+        self.level_mask
+    }
+
+    pub fn level(&self) -> u8 {
+        self.level_mask.level()
+    }
+
+    /// Returns higher hash for given index (last one - representation hash)
+    pub fn hash(&self, index: usize) -> UInt256 {
+        self.hashes_depths[self.level_mask.calc_hash_index(index)].0
+    }
+
+    pub fn depth(&self, index: usize) -> u16 {
+        self.hashes_depths[self.level_mask.calc_hash_index(index)].1
+    }
+
+    pub fn repr_hash(&self) -> UInt256 {
+        self.hash(MAX_LEVEL)
+    }
+
+    pub fn repr_depth(&self) -> u16 {
+        self.depth(MAX_LEVEL)
+    }
+}
+
+const MAX_LEVEL: usize = 3;
+
+/// Calculates hashes and depths of cell from its description bytes, data (with completion tag
+/// as in BOC) and hashes of children without constructing the cell.
+/// Cell is checked the same way as while creating `DataCell`, store hashes flag is ignored.
+pub fn calc_cell_hashes(descriptor: [u8; 2], data: &[u8], children: &[CellHashes]) -> Result<CellHashes> {
+    let [d1, d2] = descriptor;
+    if d1 == ABSENT_D1 {
+        fail!("absent cells are not supported")
+    }
+    let refs_count = (d1 & REFS_D1_MASK) as usize;
+    if refs_count > MAX_REFERENCES_COUNT {
+        fail!("Too big references count: {}", refs_count)
+    }
+    if refs_count != children.len() {
+        fail!("cell has {} references but {} children are given", refs_count, children.len())
+    }
+    let data_len = ((d2 >> 1) + (d2 & 1)) as usize;
+    if data.len() != data_len {
+        fail!("cell has {} bytes of data but {} are given", data_len, data.len())
+    }
+    let bit_len = match d2 & 1 {
+        0 => data_len * 8,
+        _ => find_tag(data)
+    };
+    let cell_type = if d1 & EXOTIC_D1_FLAG == 0 {
+        CellType::Ordinary
+    } else {
+        match data.first() {
+            Some(byte) => CellType::try_from(*byte).unwrap_or(CellType::Unknown),
+            None => fail!("exotic cells must have non zero data length")
+        }
+    };
+    let level_mask = LevelMask::with_mask(d1 >> LEVELMASK_D1_OFFSET);
+    let level_mask = check_type_and_level(cell_type, bit_len, data, level_mask, false, children)?;
+
+    let mut hashes_depths = SmallVec::new();
+    if cell_type == CellType::PrunedBranch {
+        // pruned cell stores all hashes and depths (except representation) in data
+        let level = level_mask.level() as usize;
+        for index in 0..level {
+            let offset = 1 + 1 + index * SHA256_SIZE;
+            let hash = UInt256::from_slice(&data[offset..offset + SHA256_SIZE]);
+            let offset = 1 + 1 + level * SHA256_SIZE + index * DEPTH_SIZE;
+            let depth = ((data[offset] as u16) << 8) | (data[offset + 1] as u16);
+            hashes_depths.push((hash, depth));
+        }
+    }
+    for (_, hash, depth) in calc_hashes_depths(cell_type, level_mask, d2, data, bit_len, children, 0)? {
+        hashes_depths.push((UInt256::from(hash), depth));
+    }
+    Ok(CellHashes { level_mask, hashes_depths })
+}
@@ -0,0 +1,123 @@
+/*
+* Copyright 2018-2020 TON DEV SOLUTIONS LTD.
+*
+* Licensed under the SOFTWARE EVALUATION License (the "License"); you may not use
+* this file except in compliance with the License.
+*
+* Unless required by applicable law or agreed to in writing, software
+* distributed under the License is distributed on an "AS IS" BASIS,
+* WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+* See the License for the specific TON DEV software governing permissions and
+* limitations under the License.
+*/
+
+use smallvec::SmallVec;
+
+use crate::cell::{BuilderData, MAX_DATA_BITS, MAX_DATA_BYTES};
+
+/// Slice from bitstring literal, see `parse_bits_literal` for notation.
+/// Literal is parsed at compile time so it must be a constant,
+/// for runtime strings use `SliceData::from_string`.
+#[macro_export]
+macro_rules! bits {
+    ($literal:expr) => {{
+        const BITS: ([u8; $crate::cell::MAX_DATA_BYTES], usize) = $crate::cell::parse_bits_literal($literal);
+        $crate::cell::SliceData::load_builder($crate::cell::builder_from_bits_literal(&BITS))
+            .expect("ordinary cell can be always loaded")
+    }};
+}
+
+/// Ordinary cell from bitstring literal (see `parse_bits_literal`) and child cells:
+/// `cell!("x{01}", [cell!("b{1}")?, child])?`. Evaluates to `Result<Cell>`, it fails
+/// if the cell can not be finalized (e.g. children are too deep).
+#[macro_export]
+macro_rules! cell {
+    () => {
+        $crate::Result::<$crate::cell::Cell>::Ok($crate::cell::Cell::default())
+    };
+    ($literal:expr) => {
+        $crate::cell!($literal, [])
+    };
+    ($literal:expr, [$($child:expr),* $(,)?]) => {{
+        const BITS: ([u8; $crate::cell::MAX_DATA_BYTES], usize) = $crate::cell::parse_bits_literal($literal);
+        const _: () = assert!(
+            <[&str]>::len(&[$(stringify!($child)),*]) <= $crate::cell::MAX_REFERENCES_COUNT,
+            "cell can not have more than 4 references"
+        );
+        #[allow(unused_mut)]
+        let mut builder = $crate::cell::builder_from_bits_literal(&BITS);
+        $(
+            builder.checked_append_reference($child).expect("references count is checked");
+        )*
+        builder.into_cell()
+    }};
+}
+
+/// Parses bitstring literal: `x{hex}`, `b{binary}` or just hex digits.
+/// Trailing `_` of hex literal removes trailing zeros and one bit as in Fift (`x{A4_}` is `b{10100}`).
+/// Panics on invalid literal, being called in const context it fails compilation.
+pub const fn parse_bits_literal(literal: &str) -> ([u8; MAX_DATA_BYTES], usize) {
+    let bytes = literal.as_bytes();
+    let len = bytes.len();
+    let (mut index, end, binary) = if len >= 3 && bytes[1] == b'{' && bytes[len - 1] == b'}' {
+        match bytes[0] {
+            b'x' => (2, len - 1, false),
+            b'b' => (2, len - 1, true),
+            _ => panic!("unknown prefix of bitstring literal")
+        }
+    } else {
+        (0, len, false)
+    };
+    let mut data = [0; MAX_DATA_BYTES];
+    let mut bits = 0;
+    let mut completion_tag = false;
+    while index < end {
+        let ch = bytes[index];
+        index += 1;
+        if completion_tag {
+            panic!("completion tag `_` must be the last in bitstring literal")
+        }
+        let (value, digit_bits) = match (binary, ch) {
+            (false, b'_') => {
+                completion_tag = true;
+                continue
+            }
+            (true, b'0'..=b'1') => (ch - b'0', 1),
+            (false, b'0'..=b'9') => (ch - b'0', 4),
+            (false, b'a'..=b'f') => (ch - b'a' + 10, 4),
+            (false, b'A'..=b'F') => (ch - b'A' + 10, 4),
+            _ => panic!("invalid digit in bitstring literal")
+        };
+        if bits + digit_bits > MAX_DATA_BYTES * 8 {
+            panic!("bitstring literal is too long")
+        }
+        let mut i = digit_bits;
+        while i > 0 {
+            i -= 1;
+            if (value >> i) & 1 != 0 {
+                data[bits / 8] |= 0x80 >> (bits % 8);
+            }
+            bits += 1;
+        }
+    }
+    if completion_tag {
+        while bits > 0 && data[(bits - 1) / 8] & (0x80 >> ((bits - 1) % 8)) == 0 {
+            bits -= 1;
+        }
+        if bits == 0 {
+            panic!("no completion bit in bitstring literal")
+        }
+        bits -= 1;
+        data[bits / 8] &= !(0x80 >> (bits % 8));
+    }
+    if bits > MAX_DATA_BITS {
+        panic!("bitstring literal is longer than 1023 bits")
+    }
+    (data, bits)
+}
+
+#[doc(hidden)]
+pub fn builder_from_bits_literal((data, bits): &([u8; MAX_DATA_BYTES], usize)) -> BuilderData {
+    BuilderData::with_raw(SmallVec::from_slice(&data[..bits.div_ceil(8)]), *bits)
+        .expect("literal length is checked while parsing")
+}
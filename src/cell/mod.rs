@@ -12,16 +12,29 @@
 */
 
 use crate::{error, fail};
-use crate::types::{ExceptionCode, Result, UInt256, ByteOrderRead, FxDashSet};
-use std::{
-    sync::{Arc, Weak},
+use crate::hashing::{sha256_batch, Sha256};
+use crate::failpoints::{self, FailPoint};
+use crate::metrics::{self, MetricsEvent};
+#[cfg(feature = "quota")]
+use crate::quota::{self, QuotaCharge};
+use crate::io::{ErrorKind, Read, Write};
+use crate::types::{ExceptionCode, Result, UInt256, ByteOrderRead};
+#[cfg(feature = "std")]
+use crate::types::{FxDashMap, FxDashSet};
+use alloc::{format, string::{String, ToString}, sync::Arc, vec, vec::Vec};
+#[cfg(feature = "std")]
+use alloc::sync::Weak;
+use core::{
     fmt,
+    hash::{BuildHasherDefault, Hash, Hasher},
     ops::{BitOr, BitOrAssign, Deref},
-    {cmp::{max, min}, io::{Read, Write, ErrorKind}},
+    cmp::{max, min},
     convert::TryInto,
     fmt::{Display, Formatter},
+    str::FromStr,
 };
-use sha2::{Sha256, Digest};
+#[cfg(feature = "std")]
+use core::cell::RefCell;
 use num::{FromPrimitive, ToPrimitive};
 use smallvec::{smallvec, SmallVec};
 
@@ -191,6 +204,10 @@ impl fmt::Display for CellType {
 
 pub trait CellImpl: Sync + Send {
     fn data(&self) -> &[u8];
+    /// Data of cell when only `bits` bits from `offset` are read, usage tracking cells record the range
+    fn data_range(&self, _offset: usize, _bits: usize) -> &[u8] {
+        self.data()
+    }
     fn raw_data(&self) -> Result<&[u8]>;
     fn cell_data(&self) -> &CellData;
     fn bit_length(&self) -> usize;
@@ -222,6 +239,19 @@ pub trait CellImpl: Sync + Send {
     fn tree_cell_count(&self) -> u64 { 0 }
 
     fn virtualization(&self) -> u8 { 0 }
+
+    /// Takes away references of uniquely owned cell, it is used to drop deep trees without recursion
+    fn take_references(&mut self) -> SmallVec<[Cell; 4]> { SmallVec::new() }
+
+    /// Wipes data of uniquely owned cell before drop, cell is not usable after it
+    fn wipe_data(&mut self) {}
+
+    /// Takes away data (with completion tag) and references of uniquely owned cell if its buffer
+    /// is not shared, cell is not usable after it
+    fn take_parts(&mut self) -> Option<(Vec<u8>, SmallVec<[Cell; 4]>)> { None }
+
+    /// Plain `DataCell` without any wrapper, builders created from it finalize back to it
+    fn is_data_cell(&self) -> bool { false }
 }
 
 #[derive(Clone)]
@@ -242,6 +272,10 @@ impl Cell {
         self.0.virtualization()
     }
 
+    pub(crate) fn is_data_cell(&self) -> bool {
+        self.0.is_data_cell()
+    }
+
     pub fn with_cell_impl<T: 'static + CellImpl>(cell_impl: T) -> Self {
         Cell(Arc::new(cell_impl))
     }
@@ -288,8 +322,9 @@ impl Cell {
         self.0.data()
     }
 
-    fn raw_data(&self) -> Result<&[u8]> {
-        self.0.raw_data()
+    /// Data of cell for reading `bits` bits from `offset`
+    pub fn data_range(&self, offset: usize, bits: usize) -> &[u8] {
+        self.0.data_range(offset, bits)
     }
 
     pub fn cell_data(&self) -> &CellData {
@@ -394,9 +429,117 @@ impl Cell {
         self.0.is_pruned()
     }
 
+    /// Descriptor bytes d1 and d2 of cell as in BOC without stored hashes
+    pub fn descriptor(&self) -> [u8; 2] {
+        [
+            calc_d1(self.level_mask(), false, self.cell_type(), self.references_count()),
+            calc_d2(self.bit_length())
+        ]
+    }
+
+    /// Exact bytes hashed to get `hash(index)`: descriptor for the level, data (or hash of
+    /// previous level), depths and hashes of references. Hashes of pruned branch below its
+    /// level are stored in data, so they have no preimage. Virtual cells are not supported.
+    pub fn hash_preimage(&self, index: usize) -> Result<Vec<u8>> {
+        if self.virtualization() != 0 {
+            fail!("hash preimage of virtual cell can not be calculated")
+        }
+        let cell_type = self.cell_type();
+        let level_mask = self.level_mask();
+        let index = index.min(MAX_LEVEL);
+        // significant level of the hash, see calc_hashes_depths
+        let (level, descr_mask) = if cell_type == CellType::PrunedBranch {
+            if level_mask.calc_hash_index(index) != level_mask.level() as usize {
+                fail!("hash {} of pruned branch is stored in its data", index)
+            }
+            (0, level_mask)
+        } else {
+            let level = (1..=index).rev().find(|level| level_mask.mask() & (1 << (level - 1)) != 0).unwrap_or(0);
+            (level, LevelMask::with_level(level as u8))
+        };
+        let child_level = level + self.is_merkle() as usize;
+        let refs = self.references_count();
+        let mut preimage = Vec::with_capacity(2 + 128 + refs * (DEPTH_SIZE + SHA256_SIZE));
+        preimage.push(calc_d1(descr_mask, false, cell_type, refs));
+        preimage.push(calc_d2(self.bit_length()));
+        if level == 0 {
+            let bit_len = self.bit_length();
+            preimage.extend_from_slice(&self.data()[..bit_len.div_ceil(8)]);
+        } else {
+            preimage.extend_from_slice(self.hash(level - 1).as_slice());
+        }
+        for index in 0..refs {
+            preimage.extend_from_slice(&self.reference(index)?.depth(child_level).to_be_bytes());
+        }
+        for index in 0..refs {
+            preimage.extend_from_slice(self.reference(index)?.hash(child_level).as_slice());
+        }
+        Ok(preimage)
+    }
+
+    /// Exact bytes hashed to get representation hash
+    pub fn repr_preimage(&self) -> Result<Vec<u8>> {
+        self.hash_preimage(MAX_LEVEL)
+    }
+
+    /// Converts cell to builder moving data buffer and references out of uniquely owned cell,
+    /// otherwise they are copied as in `BuilderData::from_cell`. Moved buffer is not reallocated,
+    /// but data is shifted in it over descriptor bytes (and short data is copied inline)
+    pub fn try_into_builder(mut self) -> Result<BuilderData> {
+        let cell_type = self.cell_type();
+        let level_mask = self.level_mask();
+        let bit_length = self.bit_length();
+        let parts = Arc::get_mut(&mut self.0).and_then(|cell| cell.take_parts());
+        match parts {
+            Some((data, references)) => {
+                let mut builder = BuilderData::with_raw(SmallVec::from_vec(data), bit_length)?;
+                for reference in references {
+                    builder.checked_append_reference(reference)?;
+                }
+                builder.set_type(cell_type);
+                builder.set_level_mask(level_mask);
+                Ok(builder)
+            }
+            None => Ok(BuilderData::from_cell(&self))
+        }
+    }
+
+    /// Returns hash of original cell stored in pruned branch for `level` (less than level of pruned cell)
+    pub fn pruned_hash(&self, level: u8) -> Result<UInt256> {
+        let (data, _) = self.pruned_data(level)?;
+        let offset = 1 + 1 + level as usize * SHA256_SIZE;
+        Ok(UInt256::from_slice(&data[offset..offset + SHA256_SIZE]))
+    }
+
+    /// Returns depth of original cell stored in pruned branch for `level` (less than level of pruned cell)
+    pub fn pruned_depth(&self, level: u8) -> Result<u16> {
+        let (data, count) = self.pruned_data(level)?;
+        let offset = 1 + 1 + count * SHA256_SIZE + level as usize * DEPTH_SIZE;
+        Ok(((data[offset] as u16) << 8) | (data[offset + 1] as u16))
+    }
+
+    // level mask is taken from data because virtualization changes mask of cell
+    fn pruned_data(&self, level: u8) -> Result<(&[u8], usize)> {
+        if !self.is_pruned() {
+            fail!("cell {:x} is not a pruned branch but {}", self.repr_hash(), self.cell_type())
+        }
+        let data = self.data();
+        let count = match data.get(1) {
+            Some(mask) => LevelMask::with_mask(*mask).level() as usize,
+            None => fail!("pruned branch {:x} has no level mask", self.repr_hash())
+        };
+        if level as usize >= count {
+            fail!("pruned branch {:x} has {} levels, level {} is requested", self.repr_hash(), count, level)
+        }
+        if data.len() < 1 + 1 + count * (SHA256_SIZE + DEPTH_SIZE) {
+            fail!("pruned branch {:x} has too short data: {} bytes", self.repr_hash(), data.len())
+        }
+        Ok((data, count))
+    }
+
     pub fn to_hex_string(&self, lower: bool) -> String {
         let bit_length = self.bit_length();
-        if bit_length % 8 == 0 {
+        if bit_length.is_multiple_of(8) {
             if lower {
                 hex::encode(self.data())
             } else {
@@ -462,7 +605,7 @@ impl Cell {
         full: bool,
         root: bool,
         remaining_depth: u16
-    ) -> std::result::Result<String, fmt::Error> {
+    ) -> core::result::Result<String, fmt::Error> {
         self.format_without_refs(f, &indent, last_child, full, root)?;
         if remaining_depth > 0 {
             if !root {
@@ -470,10 +613,13 @@ impl Cell {
                 indent.push(if last_child { ' ' } else { '│' });
             }
             for i in 0..self.references_count() {
-                let child = self.reference(i).unwrap();
                 writeln!(f)?;
-                indent = child.format_with_refs_tree(
-                    f, indent, i == self.references_count() - 1, full, false, remaining_depth - 1)?;
+                let last_child = i == self.references_count() - 1;
+                match self.reference(i) {
+                    Ok(child) => indent = child.format_with_refs_tree(
+                        f, indent, last_child, full, false, remaining_depth - 1)?,
+                    Err(err) => Self::format_absent_reference(f, &indent, last_child, &err)?
+                }
             }
             if !root {
                 indent.pop();
@@ -482,9 +628,77 @@ impl Cell {
         }
         Ok(indent)
     }
-    fn tree_bits_count(&self) -> u64 { self.0.tree_bits_count() }
+    // reference which can't be loaded (e.g. absent in storage) is printed with error instead of subtree
+    fn format_absent_reference(f: &mut fmt::Formatter, indent: &str, last_child: bool, err: &crate::Error) -> fmt::Result {
+        Self::print_indent(f, indent, last_child, true)?;
+        write!(f, "absent reference: {}", err)
+    }
 
-    fn tree_cell_count(&self) -> u64 { self.0.tree_cell_count() }
+    fn format_metadata_tree(
+        &self,
+        f: &mut fmt::Formatter,
+        indent: &mut String,
+        last_child: bool,
+        root: bool,
+        remaining_depth: usize,
+        printed: &mut crate::types::FxHashSet<UInt256>,
+    ) -> fmt::Result {
+        if !root {
+            Self::print_indent(f, indent, last_child, true)?;
+        }
+        write!(f, "{} level_mask: {:03b} bits: {} refs: {} repr_hash: {:x} depths:", self.cell_type(),
+            self.level_mask().mask(), self.bit_length(), self.references_count(), self.repr_hash())?;
+        for level in 0..=self.level() {
+            write!(f, " {}={}", level, self.depth(level as usize))?;
+        }
+        if f.alternate() {
+            write!(f, " data: {}", self.to_hex_string(true))?;
+        }
+        if !printed.insert(self.repr_hash()) {
+            return write!(f, " (repeated)")
+        }
+        if remaining_depth == 0 {
+            return Ok(())
+        }
+        let len = indent.len();
+        if !root {
+            indent.push(' ');
+            indent.push(if last_child { ' ' } else { '│' });
+        }
+        for i in 0..self.references_count() {
+            writeln!(f)?;
+            let last_child = i == self.references_count() - 1;
+            match self.reference(i) {
+                Ok(child) => child.format_metadata_tree(f, indent, last_child, false, remaining_depth - 1, printed)?,
+                Err(err) => Self::format_absent_reference(f, indent, last_child, &err)?
+            }
+        }
+        indent.truncate(len);
+        Ok(())
+    }
+
+    /// Human readable view of cell tree, the same as alternate `Display` of cell prints
+    pub fn tree(&self) -> CellTree<'_> {
+        CellTree { cell: self, metadata: false }
+    }
+
+    /// Standard base64 of BOC with the cell as single root, it is parsed back by `FromStr`
+    pub fn to_base64(&self) -> Result<String> {
+        let boc = crate::cells_serialization::serialize_toc(self)?;
+        Ok(base64::encode(boc))
+    }
+
+    /// Returns total count of data bits in the tree (cells are counted for each reference to them).
+    /// It is aggregated from children when cell is constructed, so call is O(1).
+    /// Count saturates at 2^56 - 1, so trees with many shared subtrees don't overflow it.
+    /// Custom implementations of `CellImpl` which do not provide the count return 0.
+    pub fn tree_bits_count(&self) -> u64 { self.0.tree_bits_count() }
+
+    /// Returns total count of cells in the tree (cells are counted for each reference to them).
+    /// It is aggregated from children when cell is constructed, so call is O(1).
+    /// Count saturates at 2^56 - 1, so trees with many shared subtrees don't overflow it.
+    /// Custom implementations of `CellImpl` which do not provide the count return 0.
+    pub fn tree_cell_count(&self) -> u64 { self.0.tree_cell_count() }
 }
 
 impl Deref for Cell {
@@ -494,6 +708,7 @@ impl Deref for Cell {
     }
 }
 
+#[cfg(feature = "std")]
 impl Cell {
     pub fn read_from_file(file_name: &str) -> Self {
         let bytes = std::fs::read(file_name).unwrap();
@@ -525,16 +740,82 @@ impl PartialEq<UInt256> for Cell {
 
 impl Eq for Cell {}
 
+/// Hash of representation hash, the same as of `UInt256`
+impl Hash for Cell {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.repr_hash().hash(state)
+    }
+}
+
+/// Cells in `CellSet` and `CellMap` can be looked up by representation hash
+impl hashbrown::Equivalent<Cell> for UInt256 {
+    fn equivalent(&self, key: &Cell) -> bool {
+        key.repr_hash() == *self
+    }
+}
+
+/// Set of cells unique by representation hash
+/// cbindgen:ignore
+pub type CellSet = hashbrown::HashSet<Cell, BuildHasherDefault<rustc_hash::FxHasher>>;
+/// Map keyed by cells unique by representation hash
+/// cbindgen:ignore
+pub type CellMap<V> = hashbrown::HashMap<Cell, V, BuildHasherDefault<rustc_hash::FxHasher>>;
+
 impl fmt::Debug for Cell {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "{:x}", self.repr_hash())
     }
 }
 
+/// Standard base64 of BOC with the cell as single root (see `Cell::to_base64`), it is parsed back
+/// by `FromStr`. Alternate flag prints tree view with metadata, the same as `{:#}` of `Cell::tree`.
+/// Cell which can not be serialized (e.g. with absent references in storage) gives `fmt::Error`
 impl fmt::Display for Cell {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        self.format_with_refs_tree(f, "".to_string(), true, f.alternate(), true,
-                                   min(f.precision().unwrap_or(0), MAX_DEPTH as usize) as u16)?;
+        if f.alternate() {
+            return fmt::Display::fmt(&self.tree(), f)
+        }
+        f.write_str(&self.to_base64().map_err(|_| fmt::Error)?)
+    }
+}
+
+/// Parses base64 of BOC with single root (as `Cell::to_base64` writes it),
+/// see `base64_decode` for accepted variants
+impl FromStr for Cell {
+    type Err = crate::Error;
+    fn from_str(value: &str) -> Result<Self> {
+        let boc = crate::base64_decode(value.trim())?;
+        crate::cells_serialization::deserialize_tree_of_cells(&mut boc.as_slice())
+    }
+}
+
+/// Tree view of cell and its children, see `Cell::tree`
+pub struct CellTree<'a> {
+    cell: &'a Cell,
+    metadata: bool,
+}
+
+impl CellTree<'_> {
+    /// Every cell of the tree is printed on its own line with type, level mask, bit length,
+    /// references count, representation hash and depths of all levels, repeated subtrees are printed once
+    pub fn with_metadata(mut self) -> Self {
+        self.metadata = true;
+        self
+    }
+}
+
+/// Alternate flag prints types, level masks, hashes and depths (data for metadata dump),
+/// precision sets depth of printed children (all children of metadata dump by default).
+/// References which can't be loaded are printed with error
+impl fmt::Display for CellTree<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.metadata {
+            let depth = f.precision().unwrap_or(MAX_DEPTH as usize);
+            let mut printed = crate::types::FxHashSet::default();
+            return self.cell.format_metadata_tree(f, &mut String::new(), true, true, depth, &mut printed)
+        }
+        self.cell.format_with_refs_tree(f, "".to_string(), true, f.alternate(), true,
+                                        min(f.precision().unwrap_or(0), MAX_DEPTH as usize) as u16)?;
         Ok(())
     }
 }
@@ -554,7 +835,7 @@ impl fmt::UpperHex for Cell {
 impl fmt::Binary for Cell {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let bitlen = self.bit_length();
-        if bitlen % 8 == 0 {
+        if bitlen.is_multiple_of(8) {
             write!(
                 f,
                 "{}",
@@ -614,6 +895,46 @@ pub fn append_tag<const N: usize>(data: &mut SmallVec<[u8; N]>, bits: usize) {
     }
 }
 
+/// Copies `bits` of data to `buf` and appends completion tag
+pub(crate) fn append_tag_to(data: &[u8], bits: usize, buf: &mut Vec<u8>) {
+    let len = bits / 8;
+    let shift = bits % 8;
+    buf.clear();
+    buf.extend_from_slice(&data[..len]);
+    match data.get(len) {
+        Some(last_byte) if shift != 0 => buf.push((last_byte & (0xff << (8 - shift))) | (0x80 >> shift)),
+        _ => buf.push(0x80)
+    }
+}
+
+#[cfg(feature = "std")]
+thread_local! {
+    static SCRATCH_BUFFER: RefCell<Vec<u8>> = RefCell::new(Vec::with_capacity(MAX_DATA_BYTES));
+}
+
+/// Runs `f` with cleared thread local buffer to avoid transient allocations
+#[cfg(feature = "std")]
+pub(crate) fn with_scratch_buffer<R>(f: impl FnOnce(&mut Vec<u8>) -> R) -> R {
+    SCRATCH_BUFFER.with(|buffer| match buffer.try_borrow_mut() {
+        Ok(mut buffer) => {
+            buffer.clear();
+            let result = f(&mut buffer);
+            // buffer keeps data of the last cell
+            #[cfg(feature = "zeroize")]
+            zeroize::Zeroize::zeroize(&mut *buffer);
+            result
+        }
+        // nested call - fallback to new buffer
+        Err(_) => f(&mut Vec::new())
+    })
+}
+
+/// Without std there are no thread locals, so buffer is allocated on every call
+#[cfg(not(feature = "std"))]
+pub(crate) fn with_scratch_buffer<R>(f: impl FnOnce(&mut Vec<u8>) -> R) -> R {
+    f(&mut Vec::with_capacity(MAX_DATA_BYTES))
+}
+
 // Cell layout:
 // [D1] [D2] [data: 0..128 bytes] (hashes: 0..4 big endian u256) (depths: 0..4 big endian u16)
 // first byte is so called desription byte 1:
@@ -641,7 +962,7 @@ pub fn calc_d1(
 
 #[inline(always)]
 pub fn calc_d2(data_bit_len: usize) -> u8{
-    ((data_bit_len / 8) << 1) as u8 + (data_bit_len % 8 != 0) as u8
+    ((data_bit_len / 8) << 1) as u8 + !data_bit_len.is_multiple_of(8) as u8
 }
 
 // A lot of helper-functions which incapsulates cell's layout.
@@ -857,7 +1178,7 @@ fn build_cell_buf(
     }
 
     let data_bit_len = find_tag(data);
-    let data_len = (data_bit_len / 8) + (data_bit_len % 8 != 0) as usize;
+    let data_len = (data_bit_len / 8) + !data_bit_len.is_multiple_of(8) as usize;
     let level_mask = LevelMask::with_mask(level_mask);
     let level = level_mask.level();
     let hashes_count = if store_hashes {
@@ -873,7 +1194,8 @@ fn build_cell_buf(
     debug_assert!(level_mask.mask() <= MAX_LEVEL_MASK);
     debug_assert!(data.len() >= data_len);
 
-    let mut buf = vec![0; full_length];
+    let mut buf = allocate_cell_buffer(full_length);
+    buf.resize(full_length, 0);
     buf[0] = calc_d1(level_mask, store_hashes, cell_type, refs);
     buf[1] = calc_d2(data_bit_len);
     let mut offset = 2;
@@ -916,7 +1238,7 @@ fn set_depth(buf: &mut [u8], index: usize, depth: u16) {
     buf[offset + 1] = (depth & 0xff) as u8;
 }
 
-fn check_cell_buf(buf: &[u8], unbounded: bool) -> Result<()> {
+pub(crate) fn check_cell_buf(buf: &[u8], unbounded: bool) -> Result<()> {
     if buf.len() < 2 {
         fail!("Buffer is too small to read description bytes")
     }
@@ -949,7 +1271,7 @@ fn check_cell_buf(buf: &[u8], unbounded: bool) -> Result<()> {
             fail!("exotic cells must have non zero data length")
         }
         let data_bit_len = bit_len(buf);
-        let expected_len = data_bit_len / 8 + (data_bit_len % 8 != 0) as usize;
+        let expected_len = data_bit_len / 8 + !data_bit_len.is_multiple_of(8) as usize;
         if cell_data.len() != expected_len {
             log::warn!(
                 "Data len wrote in description byte 2 ({} bytes) does not correspond to real length \
@@ -962,6 +1284,14 @@ fn check_cell_buf(buf: &[u8], unbounded: bool) -> Result<()> {
     Ok(())
 }
 
+impl Drop for CellBuffer {
+    fn drop(&mut self) {
+        if let CellBuffer::Local(buf) = self {
+            allocator::release_cell_buffer(buf);
+        }
+    }
+}
+
 #[derive(Clone, Debug, PartialEq)]
 enum CellBuffer {
     Local(Vec<u8>),
@@ -992,10 +1322,222 @@ impl CellBuffer {
     }
 }
 
+// Check data size and references count, returns checked level mask
+fn check_type_and_level<C: ChildHashes>(
+    cell_type: CellType,
+    bit_len: usize,
+    data: &[u8],
+    level_mask: LevelMask,
+    store_hashes: bool,
+    references: &[C]
+) -> Result<LevelMask> {
+    match cell_type {
+        CellType::PrunedBranch => {
+            // type + level_mask + level * (hashes + depths)
+            let expected = 8 * (1 + 1 + (level_mask.level() as usize) * (SHA256_SIZE + DEPTH_SIZE));
+            if bit_len != expected {
+                fail!("fail creating pruned branch cell: {} != {}", bit_len, expected)
+            }
+            if !references.is_empty() {
+                fail!("fail creating pruned branch cell: references {} != 0", references.len())
+            }
+            if data[0] != u8::from(CellType::PrunedBranch) {
+                fail!("fail creating pruned branch cell: data[0] {} != {}", data[0], u8::from(CellType::PrunedBranch))
+            }
+            if data[1] != level_mask.0 {
+                fail!("fail creating pruned branch cell: data[1] {} != {}", data[1], level_mask.0)
+            }
+            let level = level_mask.level() as usize;
+            if level == 0 {
+                fail!("Pruned branch cell must have non zero level");
+            }
+            let mut offset = 1 + 1 + level * SHA256_SIZE;
+            for _ in 0..level {
+                let depth = ((data[offset] as u16) << 8) | (data[offset + 1] as u16);
+                if depth > MAX_DEPTH {
+                    fail!("Depth of pruned branch cell is too big");
+                }
+                offset += DEPTH_SIZE;
+            }
+            if store_hashes {
+                fail!("store_hashes flag is not supported for pruned branch cell");
+            }
+        }
+        CellType::MerkleProof => {
+            // type + hash + depth
+            if bit_len != 8 * (1 + SHA256_SIZE + 2) {
+                fail!("fail creating merkle proof cell: bit_len {} != {}", bit_len, 8 * (1 + SHA256_SIZE + 2))
+            }
+            if references.len() != 1 {
+                fail!("fail creating merkle proof cell: references {} != 1", references.len())
+            }
+        }
+        CellType::MerkleUpdate => {
+            // type + 2 * (hash + depth)
+            if bit_len != 8 * (1 + 2 * (SHA256_SIZE + 2)) {
+                fail!("fail creating merkle unpdate cell: bit_len {} != {}", bit_len, 8 * (1 + 2 * (SHA256_SIZE + 2)))
+            }
+            if references.len() != 2 {
+                fail!("fail creating merkle unpdate cell: references {} != 2", references.len())
+            }
+        }
+        CellType::Ordinary => {
+            if bit_len > MAX_DATA_BITS {
+                fail!("fail creating ordinary cell: bit_len {} > {}", bit_len, MAX_DATA_BITS)
+            }
+            if references.len() > MAX_REFERENCES_COUNT {
+                fail!("fail creating ordinary cell: references {} > {}", references.len(), MAX_REFERENCES_COUNT)
+            }
+        }
+        CellType::LibraryReference => {
+            if bit_len != 8 * (1 + SHA256_SIZE) {
+                fail!("fail creating libray reference cell: bit_len {} != {}", bit_len, 8 * (1 + SHA256_SIZE))
+            }
+            if !references.is_empty() {
+                fail!("fail creating libray reference cell: references {} != 0", references.len())
+            }
+        }
+        CellType::Unknown => {
+            fail!("fail creating unknown cell")
+        }
+    }
+
+    // Check level
+
+    let mut children_mask = LevelMask::with_mask(0);
+    for child in references.iter() {
+        children_mask |= child.level_mask();
+    }
+    let calculated = match cell_type {
+        CellType::Ordinary => children_mask,
+        CellType::PrunedBranch => level_mask,
+        CellType::LibraryReference => LevelMask::with_mask(0),
+        CellType::MerkleProof => LevelMask::for_merkle_cell(children_mask),
+        CellType::MerkleUpdate => LevelMask::for_merkle_cell(children_mask),
+        CellType::Unknown => fail!(ExceptionCode::RangeCheckError)
+    };
+    if level_mask != calculated {
+        fail!("Level mask mismatch {} != {}, type: {}",
+            level_mask, calculated, cell_type);
+    }
+    Ok(calculated)
+}
+
+// Level index, hash and depth
+type LevelHashDepth = (usize, [u8; 32], u16);
+
+// Receives hashed representation by parts: hasher itself or buffer of batch
+trait HashInput {
+    fn put(&mut self, data: &[u8]);
+}
+
+impl HashInput for Sha256 {
+    fn put(&mut self, data: &[u8]) {
+        self.update(data)
+    }
+}
+
+impl HashInput for Vec<u8> {
+    fn put(&mut self, data: &[u8]) {
+        self.extend_from_slice(data)
+    }
+}
+
+// Writes hashed representation of level `i` and returns its depth.
+// The first significant level includes data, next ones include previous hash
+#[allow(clippy::too_many_arguments)]
+fn put_level_repr<C: ChildHashes>(
+    input: &mut impl HashInput,
+    i: usize,
+    cell_type: CellType,
+    level_mask: LevelMask,
+    d2: u8,
+    data: &[u8],
+    bit_len: usize,
+    prev_hash: Option<&[u8; 32]>,
+    references: &[C],
+    max_depth: u16
+) -> Result<u16> {
+    let is_merkle_cell = cell_type == CellType::MerkleProof || cell_type == CellType::MerkleUpdate;
+
+    // descr bytes
+    let level_mask = if cell_type == CellType::PrunedBranch {
+        level_mask
+    } else {
+        LevelMask::with_level(i as u8)
+    };
+    input.put(&[calc_d1(level_mask, false, cell_type, references.len()), d2]);
+
+    // data
+    match prev_hash {
+        None => {
+            let data_size = (bit_len / 8) + usize::from(!bit_len.is_multiple_of(8));
+            input.put(&data[..data_size]);
+        }
+        Some(prev_hash) => input.put(prev_hash)
+    }
+
+    // depth
+    let mut depth = 0;
+    for child in references.iter() {
+        let child_depth = child.depth(i + is_merkle_cell as usize);
+        depth = max(depth, child_depth + 1);
+        if ((max_depth != 0) && (depth > max_depth)) || (depth > MAX_DEPTH) {
+            fail!("fail creating cell: depth {} > {}", depth, core::cmp::min(max_depth, MAX_DEPTH))
+        }
+        input.put(&child_depth.to_be_bytes());
+    }
+
+    // hashes
+    for child in references.iter() {
+        let child_hash = child.hash(i + is_merkle_cell as usize);
+        input.put(child_hash.as_slice());
+    }
+    Ok(depth)
+}
+
+// Hashes and depths of significant levels started from smallest index.
+// Representation hash is calculated last and "includes" all previous hashes.
+// For pruned branch cell only representation hash is calculated
+fn calc_hashes_depths<C: ChildHashes>(
+    cell_type: CellType,
+    level_mask: LevelMask,
+    d2: u8,
+    data: &[u8],
+    bit_len: usize,
+    references: &[C],
+    max_depth: u16
+) -> Result<SmallVec<[LevelHashDepth; 4]>> {
+    let is_pruned_cell = cell_type == CellType::PrunedBranch;
+    let mut result = SmallVec::<[LevelHashDepth; 4]>::new();
+    for i in 0..=3 {
+
+        // Hash is calculated only for "1" bits of level mask.
+        // Hash for i = 0 is calculated anyway.
+        // For example if mask = 0b010 i = 0, 2
+        // for example if mask = 0b001 i = 0, 1
+        // for example if mask = 0b011 i = 0, 1, 2
+        if i != 0 && (is_pruned_cell || ((1 << (i - 1)) & level_mask.mask()) == 0) {
+            continue;
+        }
+
+        let mut hasher = Sha256::new();
+        let prev_hash = result.last().map(|(_, hash, _)| hash);
+        let depth = put_level_repr(&mut hasher, i, cell_type, level_mask, d2, data, bit_len,
+            prev_hash, references, max_depth)?;
+        result.push((i, hasher.finalize(), depth));
+    }
+    Ok(result)
+}
+
+// Hashes and depths only for significant levels. Most of cells are level 0 and have
+// exactly one hash, so it is stored inline without separate allocation
+type HashesDepths = SmallVec<[(UInt256, u16); 1]>;
+
 #[derive(Clone, Debug, PartialEq)]
 pub struct CellData {
     buf: CellBuffer,
-    hashes_depths: Vec<(UInt256, u16)>,
+    hashes_depths: HashesDepths,
 }
 
 impl Default for CellData {
@@ -1005,6 +1547,29 @@ impl Default for CellData {
 }
 
 impl CellData {
+    #[cfg(feature = "zeroize")]
+    fn wipe(&mut self) {
+        if let CellBuffer::Local(buf) = &mut self.buf {
+            zeroize::Zeroize::zeroize(buf);
+        }
+    }
+
+    /// Moves data (with completion tag) out of local buffer shifting it over descriptors and hashes,
+    /// buffer is left empty
+    fn take_data(&mut self) -> Option<Vec<u8>> {
+        match &mut self.buf {
+            CellBuffer::Local(buf) => {
+                let offset = data_offset(buf);
+                let len = cell_data(buf).len();
+                let mut data = core::mem::take(buf);
+                data.truncate(offset + len);
+                data.drain(..offset);
+                Some(data)
+            }
+            CellBuffer::External { .. } => None
+        }
+    }
+
     pub fn new() -> Self {
         Self::with_params(
             CellType::Ordinary,
@@ -1034,7 +1599,7 @@ impl CellData {
             level(&buffer) as usize + 1
         };
         let allocate_for_hashes = (!store_hashes) as usize * hashes_count;
-        let mut hashes_depths = Vec::with_capacity(allocate_for_hashes);
+        let mut hashes_depths = HashesDepths::with_capacity(allocate_for_hashes);
         match (store_hashes, hashes, depths) {
             (true, _, _) => (),
             (_, None, None) => (),
@@ -1061,7 +1626,7 @@ impl CellData {
                 buf: buffer.clone(),
                 offset,
             },
-            hashes_depths: Vec::with_capacity(allocate_for_hashes)
+            hashes_depths: HashesDepths::with_capacity(allocate_for_hashes)
         })
     }
 
@@ -1072,7 +1637,7 @@ impl CellData {
         let allocate_for_hashes = (!store_hashes(&data)) as usize * (level(&data) as usize + 1);
         Ok(Self{
             buf: CellBuffer::Local(data),
-            hashes_depths: Vec::with_capacity(allocate_for_hashes)
+            hashes_depths: HashesDepths::with_capacity(allocate_for_hashes)
         })
     }
 
@@ -1112,6 +1677,11 @@ impl CellData {
         refs_count(self.buf.unbounded_data())
     }
 
+    // Same data buffer (shared if it is external) without calculated hashes and depths
+    fn without_hashes(&self) -> Self {
+        Self { buf: self.buf.clone(), hashes_depths: HashesDepths::new() }
+    }
+
     fn set_hash_depth(&mut self, index: usize, hash: &[u8; 32], depth: u16) -> Result<()> {
         if self.store_hashes() {
             set_hash(self.buf.unbounded_data_mut()?, index, hash);
@@ -1171,8 +1741,8 @@ impl CellData {
         let bitlen = self.bit_length();
         writer.write_all(&[self.cell_type().to_u8().unwrap()])?;
         writer.write_all(&(bitlen as u16).to_le_bytes())?;
-        writer.write_all(&self.data()[0..(bitlen + 7) / 8])?;
-        if bitlen % 8 == 0 {
+        writer.write_all(&self.data()[0..bitlen.div_ceil(8)])?;
+        if bitlen.is_multiple_of(8) {
             writer.write_all(&[0])?;// for compatibility
         }
 
@@ -1209,38 +1779,38 @@ impl CellData {
     /// Binary deserialization of cell data
     pub fn deserialize<T: Read>(reader: &mut T) -> Result<Self> {
         let cell_type: CellType = FromPrimitive::from_u8(reader.read_byte()?)
-            .ok_or_else(|| std::io::Error::from(ErrorKind::InvalidData))?;
+            .ok_or_else(|| crate::io::Error::from(ErrorKind::InvalidData))?;
         let bitlen = reader.read_le_u16()? as usize;
-        let data_len = (bitlen + 7) / 8;
-        let data = if bitlen % 8 == 0 {
-            let mut data = vec![0; data_len + 1];
-            reader.read_exact(&mut data[..data_len])?;
-            let _ = reader.read_byte()?; // for compatibility
-            data[data_len] = 0x80;
-            data
-        } else {
-            let mut data = vec![0; data_len];
-            reader.read_exact(&mut data)?;
-            data
-        };
-        let level_mask = reader.read_byte()?;
-        let store_hashes = Self::read_bool(reader)?;
+        let data_len = bitlen.div_ceil(8);
+        with_scratch_buffer(|data| {
+            if bitlen.is_multiple_of(8) {
+                data.resize(data_len + 1, 0);
+                reader.read_exact(&mut data[..data_len])?;
+                let _ = reader.read_byte()?; // for compatibility
+                data[data_len] = 0x80;
+            } else {
+                data.resize(data_len, 0);
+                reader.read_exact(data)?;
+            }
+            let level_mask = reader.read_byte()?;
+            let store_hashes = Self::read_bool(reader)?;
 
-        let hashes = Self::read_short_array_opt(reader,
-                                                |reader| Ok(UInt256::from(reader.read_u256()?)))?;
-        let depths = Self::read_short_array_opt(reader,
-                                                |reader| reader.read_le_u16())?;
+            let hashes = Self::read_short_array_opt(reader,
+                                                    |reader| Ok(UInt256::from(reader.read_u256()?)))?;
+            let depths = Self::read_short_array_opt(reader,
+                                                    |reader| reader.read_le_u16())?;
 
-        let refs = reader.read_byte()?;
+            let refs = reader.read_byte()?;
 
-        Self::with_params(cell_type, &data, level_mask, refs, store_hashes, hashes, depths)
+            Self::with_params(cell_type, data, level_mask, refs, store_hashes, hashes, depths)
+        })
     }
 
-    fn read_short_array_opt<R, T, F>(reader: &mut R, read_func: F) -> std::io::Result<Option<[T; 4]>>
+    fn read_short_array_opt<R, T, F>(reader: &mut R, read_func: F) -> crate::io::Result<Option<[T; 4]>>
         where
             R: Read,
             T: Default,
-            F: Fn(&mut R) -> std::io::Result<T>
+            F: Fn(&mut R) -> crate::io::Result<T>
     {
         if Self::read_bool(reader)? {
             Ok(Some(Self::read_short_array(reader, read_func)?))
@@ -1249,15 +1819,15 @@ impl CellData {
         }
     }
 
-    fn read_short_array<R, T, F>(reader: &mut R, read_func: F) -> std::io::Result<[T; 4]>
+    fn read_short_array<R, T, F>(reader: &mut R, read_func: F) -> crate::io::Result<[T; 4]>
     where
         R: Read,
         T: Default,
-        F: Fn(&mut R) -> std::io::Result<T>
+        F: Fn(&mut R) -> crate::io::Result<T>
     {
         let count = reader.read_byte()?;
         if count > 4 {
-            return Err(std::io::Error::from(ErrorKind::InvalidData))
+            return Err(crate::io::Error::from(ErrorKind::InvalidData))
         }
         let mut result = [T::default(), T::default(), T::default(), T::default()];
         for i in 0..count {
@@ -1266,11 +1836,11 @@ impl CellData {
         Ok(result)
     }
 
-    fn read_bool<R: Read>(reader: &mut R) -> std::io::Result<bool> {
+    fn read_bool<R: Read>(reader: &mut R) -> crate::io::Result<bool> {
         match reader.read_byte()? {
             1 => Ok(true),
             0 => Ok(false),
-            _ => Err(std::io::Error::from(ErrorKind::InvalidData))
+            _ => Err(crate::io::Error::from(ErrorKind::InvalidData))
         }
     }
 }
@@ -1281,6 +1851,9 @@ pub struct DataCell {
     references: SmallVec<[Cell; 4]>,
     tree_bits_count: u64,
     tree_cell_count: u64,
+    // returns quota back when cell is dropped
+    #[cfg(feature = "quota")]
+    _quota: Option<QuotaCharge>,
 }
 
 impl Default for DataCell {
@@ -1311,7 +1884,7 @@ impl DataCell {
     ) -> Result<DataCell> {
         let cell_data = CellData::with_params(cell_type, data, level_mask, references.len() as u8,
             false, None, None)?;
-        Self::construct_cell(cell_data, references, max_depth)
+        Self::construct_cell(cell_data, references, max_depth, true)
     }
 
     pub fn with_params(
@@ -1326,21 +1899,30 @@ impl DataCell {
         let store_hashes = hashes.is_some();
         let cell_data = CellData::with_params(cell_type, data, level_mask, references.len() as u8,
             store_hashes, hashes, depths)?;
-        Self::construct_cell(cell_data, references, 0)
+        Self::construct_cell(cell_data, references, 0, true)
     }
 
     pub fn with_external_data(
         references: SmallVec<[Cell; 4]>,
         buffer: &Arc<Vec<u8>>,
         offset: usize,
+    ) -> Result<DataCell> {
+        Self::with_external_data_and_max_depth(references, buffer, offset, 0)
+    }
+
+    pub fn with_external_data_and_max_depth(
+        references: SmallVec<[Cell; 4]>,
+        buffer: &Arc<Vec<u8>>,
+        offset: usize,
+        max_depth: u16
     ) -> Result<DataCell> {
         let cell_data = CellData::with_external_data(buffer, offset)?;
-        Self::construct_cell(cell_data, references, 0)
+        Self::construct_cell(cell_data, references, max_depth, true)
     }
 
     pub fn with_raw_data(references: SmallVec<[Cell; 4]>, data: Vec<u8>) -> Result<DataCell> {
         let cell_data = CellData::with_raw_data(data)?;
-        Self::construct_cell(cell_data, references, 0)
+        Self::construct_cell(cell_data, references, 0, true)
     }
 
     pub fn with_raw_data_and_max_depth(
@@ -1349,14 +1931,76 @@ impl DataCell {
         max_depth: u16
     ) -> Result<DataCell> {
         let cell_data = CellData::with_raw_data(data)?;
-        Self::construct_cell(cell_data, references, max_depth)
+        Self::construct_cell(cell_data, references, max_depth, true)
+    }
+
+    /// Same as `with_max_depth` but trusts the data: exotic cell's layout and level mask are not checked
+    pub fn with_max_depth_unchecked(
+        references: SmallVec<[Cell; 4]>,
+        data: &[u8], // with completion tag!
+        cell_type: CellType,
+        level_mask: u8,
+        max_depth: u16
+    ) -> Result<DataCell> {
+        let cell_data = CellData::with_params(cell_type, data, level_mask, references.len() as u8,
+            false, None, None)?;
+        Self::construct_cell(cell_data, references, max_depth, false)
+    }
+
+    /// Same as `with_external_data_and_max_depth` but trusts the data: exotic cell's layout,
+    /// level mask and stored hashes are not checked
+    pub fn with_external_data_unchecked(
+        references: SmallVec<[Cell; 4]>,
+        buffer: &Arc<Vec<u8>>,
+        offset: usize,
+        max_depth: u16
+    ) -> Result<DataCell> {
+        let cell_data = CellData::with_external_data(buffer, offset)?;
+        Self::construct_cell(cell_data, references, max_depth, false)
+    }
+
+    /// Same as `with_raw_data_and_max_depth` but trusts the data: exotic cell's layout,
+    /// level mask and stored hashes are not checked
+    pub fn with_raw_data_unchecked(
+        references: SmallVec<[Cell; 4]>,
+        data: Vec<u8>,
+        max_depth: u16
+    ) -> Result<DataCell> {
+        let cell_data = CellData::with_raw_data(data)?;
+        Self::construct_cell(cell_data, references, max_depth, false)
     }
 
     fn construct_cell(
         cell_data: CellData,
         references: SmallVec<[Cell; 4]>,
-        max_depth: u16
+        max_depth: u16,
+        check: bool,
     ) -> Result<DataCell> {
+        let mut cell = Self::with_parts(cell_data, references)?;
+        // stored hashes are trusted if data is not checked
+        cell.finalize(check, check, max_depth)?;
+        metrics::record(MetricsEvent::CellCreated, 1);
+        Ok(cell)
+    }
+
+    /// Same as `with_external_data_and_max_depth` (or `with_external_data_unchecked`) for many
+    /// cells of the buffer, representation hashes of level 0 cells are calculated by `sha256_batch`
+    pub(crate) fn with_external_data_batch(
+        cells: impl IntoIterator<Item = (SmallVec<[Cell; 4]>, usize)>,
+        buffer: &Arc<Vec<u8>>,
+        max_depth: u16,
+        check: bool,
+    ) -> Result<Vec<DataCell>> {
+        let mut cells = cells.into_iter()
+            .map(|(references, offset)| Self::with_parts(CellData::with_external_data(buffer, offset)?, references))
+            .collect::<Result<Vec<_>>>()?;
+        Self::finalize_batch(&mut cells, check, max_depth)?;
+        metrics::record(MetricsEvent::CellCreated, cells.len() as u64);
+        Ok(cells)
+    }
+
+    // Not finalized cell
+    fn with_parts(cell_data: CellData, references: SmallVec<[Cell; 4]>) -> Result<DataCell> {
         const MAX_56_BITS: u64 = 0x00FF_FFFF_FFFF_FFFFu64;
         let mut tree_bits_count = cell_data.bit_length() as u64;
         let mut tree_cell_count = 1u64;
@@ -1370,199 +2014,109 @@ impl DataCell {
         if tree_cell_count > MAX_56_BITS {
             tree_cell_count = MAX_56_BITS;
         }
-        let mut cell = DataCell {
+        #[cfg(feature = "quota")]
+        let quota = quota::charge(core::mem::size_of::<DataCell>() + cell_data.raw_data().len())?;
+        Ok(DataCell {
             cell_data,
             references,
             tree_bits_count,
             tree_cell_count,
-        };
-        cell.finalize(true, max_depth)?;
-        Ok(cell)
+            #[cfg(feature = "quota")]
+            _quota: quota,
+        })
+    }
+
+    // Check data size and references count, returns checked level mask
+    fn check_type_and_level(&self) -> Result<LevelMask> {
+        check_type_and_level(
+            self.cell_type(),
+            self.bit_length(),
+            self.data(),
+            self.cell_data.level_mask(),
+            self.store_hashes(),
+            &self.references
+        )
     }
 
-    fn finalize(&mut self, force: bool, max_depth: u16) -> Result<()> {
+    fn finalize(&mut self, force: bool, check: bool, max_depth: u16) -> Result<()> {
         if !force && self.store_hashes() {
             return Ok(());
         }
+        metrics::record(MetricsEvent::CellFinalized, 1);
+        failpoints::inject(FailPoint::CellFinalize)?;
 
         //let now = std::time::Instant::now();
 
-        // Check data size and references count
-
         let bit_len = self.bit_length();
         let cell_type = self.cell_type();
-        let store_hashes = self.store_hashes();
 
         // println!("{} {}bits {:03b}", self.cell_type(), bit_len, self.level_mask().mask());
 
-        match cell_type {
-            CellType::PrunedBranch => {
-                // type + level_mask + level * (hashes + depths)
-                let expected = 8 * (1 + 1 + (self.level() as usize) * (SHA256_SIZE + DEPTH_SIZE));
-                if bit_len != expected {
-                    fail!("fail creating pruned branch cell: {} != {}", bit_len, expected)
-                }
-                if !self.references.is_empty() {
-                    fail!("fail creating pruned branch cell: references {} != 0", self.references.len())
-                }
-                if self.data()[0] != u8::from(CellType::PrunedBranch) {
-                    fail!("fail creating pruned branch cell: data[0] {} != {}", self.data()[0], u8::from(CellType::PrunedBranch))
-                }
-                if self.data()[1] != self.cell_data.level_mask().0 {
-                    fail!("fail creating pruned branch cell: data[1] {} != {}", self.data()[1], self.cell_data.level_mask().0)
-                }
-                let level = self.level() as usize;
-                if level == 0 {
-                    fail!("Pruned branch cell must have non zero level");
-                }
-                let data = self.data();
-                let mut offset = 1 + 1 + level * SHA256_SIZE;
-                for _ in 0..level {
-                    let depth = ((data[offset] as u16) << 8) | (data[offset + 1] as u16);
-                    if depth > MAX_DEPTH {
-                        fail!("Depth of pruned branch cell is too big");
-                    }
-                    offset += DEPTH_SIZE;
-                }
-                if store_hashes {
-                    fail!("store_hashes flag is not supported for pruned branch cell");
-                }
-            }
-            CellType::MerkleProof => {
-                // type + hash + depth
-                if bit_len != 8 * (1 + SHA256_SIZE + 2) {
-                    fail!("fail creating merkle proof cell: bit_len {} != {}", bit_len, 8 * (1 + SHA256_SIZE + 2))
-                }
-                if self.references.len() != 1 {
-                    fail!("fail creating merkle proof cell: references {} != 1", self.references.len())
-                }
-            }
-            CellType::MerkleUpdate => {
-                // type + 2 * (hash + depth)
-                if bit_len != 8 * (1 + 2 * (SHA256_SIZE + 2)) {
-                    fail!("fail creating merkle unpdate cell: bit_len {} != {}", bit_len, 8 * (1 + 2 * (SHA256_SIZE + 2)))
-                }
-                if self.references.len() != 2 {
-                    fail!("fail creating merkle unpdate cell: references {} != 2", self.references.len())
-                }
-            }
-            CellType::Ordinary => {
-                if bit_len > MAX_DATA_BITS {
-                    fail!("fail creating ordinary cell: bit_len {} > {}", bit_len, MAX_DATA_BITS)
-                }
-                if self.references.len() > MAX_REFERENCES_COUNT {
-                    fail!("fail creating ordinary cell: references {} > {}", self.references.len(), MAX_REFERENCES_COUNT)
-                }
-            }
-            CellType::LibraryReference => {
-                if bit_len != 8 * (1 + SHA256_SIZE) {
-                    fail!("fail creating libray reference cell: bit_len {} != {}", bit_len, 8 * (1 + SHA256_SIZE))
-                }
-                if !self.references.is_empty() {
-                    fail!("fail creating libray reference cell: references {} != 0", self.references.len())
-                }
-            }
-            CellType::Unknown => {
-                fail!("fail creating unknown cell")
-            }
-        }
-
-        // Check level
-
-        let mut children_mask = LevelMask::with_mask(0);
-        for child in self.references.iter() {
-            children_mask |= child.level_mask();
-        }
-        let level_mask = match cell_type {
-            CellType::Ordinary => children_mask,
-            CellType::PrunedBranch => self.level_mask(),
-            CellType::LibraryReference => LevelMask::with_mask(0),
-            CellType::MerkleProof => LevelMask::for_merkle_cell(children_mask),
-            CellType::MerkleUpdate => LevelMask::for_merkle_cell(children_mask),
-            CellType::Unknown => fail!(ExceptionCode::RangeCheckError)
+        let level_mask = if check {
+            self.check_type_and_level()?
+        } else {
+            self.cell_data.level_mask()
         };
-        if self.cell_data.level_mask() != level_mask {
-            fail!("Level mask mismatch {} != {}, type: {}",
-                self.cell_data.level_mask(), level_mask, cell_type);
-        }
 
         // calculate hashes and depths
 
-        let is_merkle_cell = self.is_merkle();
-        let is_pruned_cell = self.is_pruned();
+        let d2 = self.raw_data()?[1];
+        let hashes = calc_hashes_depths(cell_type, level_mask, d2, self.data(), bit_len, &self.references, max_depth)?;
+        for (hash_array_index, (i, hash, depth)) in hashes.into_iter().enumerate() {
+            self.put_hash_depth(hash_array_index, i, &hash, depth)?;
+        }
 
-        let mut d1d2: [u8; 2] = self.raw_data()?[..2].try_into()?;
+        //FINALIZATION_NANOS.fetch_add(now.elapsed().as_nanos() as u64, Ordering::Relaxed);
 
-        // Hashes are calculated started from smallest indexes.
-        // Representation hash is calculated last and "includes" all previous hashes
-        // For pruned branch cell only representation hash is calculated
-        let mut hash_array_index = 0;
-        for i in 0..=3 {
+        Ok(())
+    }
 
-            // Hash is calculated only for "1" bits of level mask.
-            // Hash for i = 0 is calculated anyway.
-            // For example if mask = 0b010 i = 0, 2
-            // for example if mask = 0b001 i = 0, 1
-            // for example if mask = 0b011 i = 0, 1, 2
-            if i != 0 && (is_pruned_cell || ((1 << (i - 1)) & level_mask.mask()) == 0) {
+    // Same as `finalize(check, check, max_depth)` for every cell. Hashes of level 0 cells
+    // are independent, so they are calculated at once, other cells are finalized one by one
+    fn finalize_batch(cells: &mut [DataCell], check: bool, max_depth: u16) -> Result<()> {
+        let mut batch = Vec::with_capacity(cells.len());
+        let mut preimages = Vec::with_capacity(cells.len());
+        for (index, cell) in cells.iter_mut().enumerate() {
+            if cell.cell_data.level_mask().mask() != 0 {
+                cell.finalize(check, check, max_depth)?;
                 continue;
             }
-
-            let mut hasher = Sha256::new();
-
-            // descr bytes
-            let level_mask = if is_pruned_cell {
-                self.level_mask()
-            } else {
-                LevelMask::with_level(i as u8)
-            };
-            d1d2[0] = calc_d1(level_mask, false, cell_type, self.references.len());
-            hasher.update(d1d2);
-
-            // data
-            if i == 0 {
-                let data_size = (bit_len / 8) + usize::from(bit_len % 8 != 0);
-                hasher.update(&self.data()[..data_size]);
-            } else {
-                hasher.update(self.cell_data.raw_hash(i - 1));
+            if !check && cell.store_hashes() {
+                continue;
             }
-
-            // depth
-            let mut depth = 0;
-            for child in self.references.iter() {
-                let child_depth = child.depth(i + is_merkle_cell as usize);
-                depth = max(depth, child_depth + 1);
-                if ((max_depth != 0) && (depth > max_depth)) || (depth > MAX_DEPTH) {
-                    fail!("fail creating cell: depth {} > {}", depth, std::cmp::min(max_depth, MAX_DEPTH))
-                }
-                hasher.update(child_depth.to_be_bytes());
+            metrics::record(MetricsEvent::CellFinalized, 1);
+            failpoints::inject(FailPoint::CellFinalize)?;
+            if check {
+                cell.check_type_and_level()?;
             }
+            let mut preimage = Vec::with_capacity(2 + MAX_DATA_BYTES + MAX_REFERENCES_COUNT * (DEPTH_SIZE + SHA256_SIZE));
+            let d2 = cell.raw_data()?[1];
+            let depth = put_level_repr(&mut preimage, 0, cell.cell_type(), LevelMask::with_mask(0), d2,
+                cell.data(), cell.bit_length(), None, &cell.references, max_depth)?;
+            batch.push((index, depth));
+            preimages.push(preimage);
+        }
+        for ((index, depth), hash) in batch.into_iter().zip(sha256_batch(&preimages)) {
+            cells[index].put_hash_depth(0, 0, hash.as_array(), depth)?;
+        }
+        Ok(())
+    }
 
-            // hashes
-            for child in self.references.iter() {
-                let child_hash = child.hash(i + is_merkle_cell as usize);
-                hasher.update(child_hash.as_slice());
+    // Stores calculated hash and depth of level `i` or checks stored ones
+    fn put_hash_depth(&mut self, hash_array_index: usize, i: usize, hash: &[u8; 32], depth: u16) -> Result<()> {
+        if self.store_hashes() {
+            let stored_depth = self.cell_data.depth(i);
+            if depth != stored_depth {
+                fail!("Calculated depth is not equal stored one ({} != {})", depth, stored_depth);
             }
-
-            let hash: [u8; 32] = hasher.finalize().into();
-            if store_hashes {
-                let stored_depth = self.cell_data.depth(i);
-                if depth != stored_depth {
-                    fail!("Calculated depth is not equal stored one ({} != {})", depth, stored_depth);
-                }
-                let stored_hash = self.cell_data.raw_hash(i);
-                if hash.as_slice() != stored_hash {
-                    fail!("Calculated hash is not equal stored one");
-                }
-            } else {
-                self.cell_data.set_hash_depth(hash_array_index, &hash, depth)?;
-                hash_array_index += 1;
+            let stored_hash = self.cell_data.raw_hash(i);
+            if hash != stored_hash {
+                fail!("Calculated hash is not equal stored one");
             }
+            Ok(())
+        } else {
+            self.cell_data.set_hash_depth(hash_array_index, hash, depth)
         }
-
-        //FINALIZATION_NANOS.fetch_add(now.elapsed().as_nanos() as u64, Ordering::Relaxed);
-
-        Ok(())
     }
 
     pub fn cell_data(&self) -> &CellData {
@@ -1570,6 +2124,29 @@ impl DataCell {
     }
 }
 
+impl Drop for DataCell {
+    // Dropping of long chains must not be recursive to avoid stack overflow, so references
+    // of uniquely owned children are moved out and dropped in the loop
+    fn drop(&mut self) {
+        let mut references = core::mem::take(&mut self.references);
+        let mut stack = Vec::new();
+        loop {
+            for mut cell in references.drain(..) {
+                if let Some(cell) = Arc::get_mut(&mut cell.0) {
+                    let children = cell.take_references();
+                    if !children.is_empty() {
+                        stack.push(children);
+                    }
+                }
+            }
+            match stack.pop() {
+                Some(next) => references = next,
+                None => break
+            }
+        }
+    }
+}
+
 impl CellImpl for DataCell {
     fn data(&self) -> &[u8] {
         self.cell_data.data()
@@ -1618,21 +2195,111 @@ impl CellImpl for DataCell {
     fn tree_bits_count(&self) -> u64 { self.tree_bits_count }
 
     fn tree_cell_count(&self) -> u64 { self.tree_cell_count }
+
+    fn take_references(&mut self) -> SmallVec<[Cell; 4]> {
+        core::mem::take(&mut self.references)
+    }
+
+    #[cfg(feature = "zeroize")]
+    fn wipe_data(&mut self) {
+        self.cell_data.wipe()
+    }
+
+    fn take_parts(&mut self) -> Option<(Vec<u8>, SmallVec<[Cell; 4]>)> {
+        let data = self.cell_data.take_data()?;
+        Some((data, core::mem::take(&mut self.references)))
+    }
+
+    fn is_data_cell(&self) -> bool { true }
 }
 
-#[derive(Clone)]
+/// Parts of cell accessed through usage tree with detailed tracking:
+/// indexes of loaded references and ranges of read data bits
+#[cfg(feature = "std")]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CellUsage {
+    refs: u8,
+    bits: [u64; 16],
+}
+
+#[cfg(feature = "std")]
+impl CellUsage {
+    pub fn is_reference_used(&self, index: usize) -> bool {
+        index < MAX_REFERENCES_COUNT && self.refs & (1 << index) != 0
+    }
+
+    pub fn used_references(&self) -> impl Iterator<Item = usize> + '_ {
+        (0..MAX_REFERENCES_COUNT).filter(|index| self.is_reference_used(*index))
+    }
+
+    pub fn is_bit_used(&self, bit: usize) -> bool {
+        bit < 1024 && self.bits[bit / 64] & (1 << (63 - bit % 64)) != 0
+    }
+
+    /// Read data bits as sorted not adjacent ranges
+    pub fn used_bits(&self) -> Vec<core::ops::Range<usize>> {
+        let mut ranges = Vec::<core::ops::Range<usize>>::new();
+        for bit in (0..1024).filter(|bit| self.is_bit_used(*bit)) {
+            match ranges.last_mut() {
+                Some(range) if range.end == bit => range.end += 1,
+                _ => ranges.push(bit..bit + 1)
+            }
+        }
+        ranges
+    }
+
+    pub fn merge(&mut self, other: &CellUsage) {
+        self.refs |= other.refs;
+        for (word, other) in self.bits.iter_mut().zip(other.bits.iter()) {
+            *word |= *other;
+        }
+    }
+
+    fn add_reference(&mut self, index: usize) {
+        if index < MAX_REFERENCES_COUNT {
+            self.refs |= 1 << index;
+        }
+    }
+
+    fn add_bits(&mut self, offset: usize, bits: usize) {
+        let end = min(offset + bits, 1024);
+        let mut bit = offset;
+        while bit < end {
+            let start = bit % 64;
+            let stop = min(64, start + end - bit);
+            let tail = u64::MAX.checked_shr(stop as u32).unwrap_or(0);
+            self.bits[bit / 64] |= (u64::MAX >> start) & !tail;
+            bit += stop - start;
+        }
+    }
+}
+
+// Cell is shared between threads, so it is marked as visited atomically
+// to avoid locking of visited set on every access
+#[cfg(feature = "std")]
 struct UsageCell {
     cell: Cell,
     visit_on_load: bool,
+    marked: core::sync::atomic::AtomicBool,
     visited: Weak<FxDashSet<UInt256>>,
+    // not upgradable if details are not tracked
+    usage: Weak<FxDashMap<UInt256, CellUsage>>,
 }
 
+#[cfg(feature = "std")]
 impl UsageCell {
-    fn new(inner: Cell, visit_on_load: bool, visited: Weak<FxDashSet<UInt256>>) -> Self {
+    fn new(
+        inner: Cell,
+        visit_on_load: bool,
+        visited: Weak<FxDashSet<UInt256>>,
+        usage: Weak<FxDashMap<UInt256, CellUsage>>,
+    ) -> Self {
         let cell = Self {
             cell: inner,
             visit_on_load,
+            marked: Default::default(),
             visited,
+            usage,
         };
         if visit_on_load {
             cell.visit();
@@ -1640,26 +2307,45 @@ impl UsageCell {
         cell
     }
     fn visit(&self) -> bool {
+        if self.marked.load(core::sync::atomic::Ordering::Relaxed) {
+            return true
+        }
         if let Some(visited) = self.visited.upgrade() {
-            visited.insert(self.cell.repr_hash());
+            let hash = self.cell.repr_hash();
+            if !visited.contains(&hash) {
+                visited.insert(hash);
+            }
+            self.marked.store(true, core::sync::atomic::Ordering::Relaxed);
             return true;
         }
         false
     }
+    fn record(&self, update: impl FnOnce(&mut CellUsage)) {
+        if let Some(usage) = self.usage.upgrade() {
+            update(&mut usage.entry(self.cell.repr_hash()).or_default());
+        }
+    }
 }
 
+#[cfg(feature = "std")]
 impl CellImpl for UsageCell {
     fn data(&self) -> &[u8] {
+        self.data_range(0, self.cell.bit_length())
+    }
+
+    fn data_range(&self, offset: usize, bits: usize) -> &[u8] {
         if !self.visit_on_load {
             self.visit();
         }
-        self.cell.data()
+        self.record(|usage| usage.add_bits(offset, bits));
+        self.cell.data_range(offset, bits)
     }
 
     fn raw_data(&self) -> Result<&[u8]> {
         if !self.visit_on_load {
             self.visit();
         }
+        self.record(|usage| usage.add_bits(0, self.cell.bit_length()));
         self.cell.raw_data()
     }
 
@@ -1667,6 +2353,7 @@ impl CellImpl for UsageCell {
         if !self.visit_on_load {
             self.visit();
         }
+        self.record(|usage| usage.add_bits(0, self.cell.bit_length()));
         self.cell.cell_data()
     }
 
@@ -1679,10 +2366,11 @@ impl CellImpl for UsageCell {
     }
 
     fn reference(&self, index: usize) -> Result<Cell> {
-        if self.visit_on_load && self.visited.upgrade().is_some() ||
+        if self.visit_on_load && self.visited.strong_count() != 0 ||
             self.visit() {
+            self.record(|usage| usage.add_reference(index));
             let cell = UsageCell::new(
-                self.cell.reference(index)?, self.visit_on_load, self.visited.clone());
+                self.cell.reference(index)?, self.visit_on_load, self.visited.clone(), self.usage.clone());
             Ok(Cell::with_cell_impl(cell))
         } else {
             self.cell.reference(index)
@@ -1734,6 +2422,10 @@ impl CellImpl for VirtualCell {
         self.cell.data()
     }
 
+    fn data_range(&self, offset: usize, bits: usize) -> &[u8] {
+        self.cell.data_range(offset, bits)
+    }
+
     fn raw_data(&self) -> Result<&[u8]> {
         fail!("Virtual cell doesn't support raw_data()");
     }
@@ -1782,30 +2474,53 @@ impl CellImpl for VirtualCell {
 
 }
 
+/// Tracks cells visited through its root cell. Tree and its cells can be used
+/// from several threads at once, visited set is sharded.
+#[cfg(feature = "std")]
 #[derive(Default)]
 pub struct UsageTree {
     root: Cell,
     visited: Arc<FxDashSet<UInt256>>,
+    usage: Option<Arc<FxDashMap<UInt256, CellUsage>>>,
 }
 
+#[cfg(feature = "std")]
+const _: fn() = || {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<UsageTree>();
+    assert_send_sync::<CellUsage>();
+};
+
+#[cfg(feature = "std")]
 impl UsageTree {
     pub fn with_root(root: Cell) -> Self {
-        let visited = Arc::new(Default::default());
-        let usage_cell = UsageCell::new(root, false, Arc::downgrade(&visited));
-        let root = Cell::with_cell_impl_arc(Arc::new(usage_cell));
-        Self { root, visited }
+        Self::with_params(root, false)
     }
 
     pub fn with_params(root: Cell, visit_on_load: bool) -> Self {
         let visited = Arc::new(Default::default());
         let root = Cell::with_cell_impl_arc(Arc::new(
-            UsageCell::new(root, visit_on_load, Arc::downgrade(&visited))
+            UsageCell::new(root, visit_on_load, Arc::downgrade(&visited), Weak::new())
+        ));
+        Self { root, visited, usage: None }
+    }
+
+    /// Tree also records read bits and loaded references of every cell, see `cell_usage`
+    pub fn with_details(root: Cell, visit_on_load: bool) -> Self {
+        let visited = Arc::new(Default::default());
+        let usage = Arc::new(Default::default());
+        let root = Cell::with_cell_impl_arc(Arc::new(
+            UsageCell::new(root, visit_on_load, Arc::downgrade(&visited), Arc::downgrade(&usage))
         ));
-        Self { root, visited }
+        Self { root, visited, usage: Some(usage) }
+    }
+
+    fn usage_weak(&self) -> Weak<FxDashMap<UInt256, CellUsage>> {
+        self.usage.as_ref().map(Arc::downgrade).unwrap_or_default()
     }
 
     pub fn use_cell(&self, cell: Cell, visit_on_load: bool) -> Cell {
-        let usage_cell = UsageCell::new(cell, visit_on_load, Arc::downgrade(&self.visited));
+        let usage_cell = UsageCell::new(cell, visit_on_load, Arc::downgrade(&self.visited), self.usage_weak());
         usage_cell.visit();
         Cell::with_cell_impl(usage_cell)
     }
@@ -1823,6 +2538,41 @@ impl UsageTree {
     pub fn contains(&self, hash: &UInt256) -> bool {
         self.visited.get(hash).is_some()
     }
+
+    /// Iterates over hashes of visited cells, cells visited meanwhile may be skipped
+    pub fn visited_hashes(&self) -> impl Iterator<Item = UInt256> + '_ {
+        self.visited.iter().map(|hash| *hash)
+    }
+
+    pub fn visited_count(&self) -> usize {
+        self.visited.len()
+    }
+
+    /// Adds cells visited in `other` tree, so proof covers accesses of both trees
+    pub fn merge(&self, other: &UsageTree) {
+        if Arc::ptr_eq(&self.visited, &other.visited) {
+            return
+        }
+        for hash in other.visited.iter() {
+            self.visited.insert(*hash);
+        }
+        if let (Some(usage), Some(other)) = (&self.usage, &other.usage) {
+            for item in other.iter() {
+                usage.entry(*item.key()).or_default().merge(item.value());
+            }
+        }
+    }
+
+    /// Read bits and loaded references of cell, `None` if cell is not used
+    /// or tree is created without details
+    pub fn cell_usage(&self, hash: &UInt256) -> Option<CellUsage> {
+        self.usage.as_ref()?.get(hash).map(|usage| usage.clone())
+    }
+
+    /// Copy of visited set, tree continues tracking
+    pub fn snapshot(&self) -> crate::types::FxHashSet<UInt256> {
+        self.visited_hashes().collect()
+    }
 }
 
 mod slice;
@@ -1837,6 +2587,91 @@ mod builder_operations;
 
 pub use self::builder_operations::*;
 
+mod macros;
+
+pub use self::macros::*;
+
+mod hashes;
+
+pub use self::hashes::{calc_cell_hashes, CellHashes};
+use self::hashes::ChildHashes;
+
+mod parser;
+
+pub use self::parser::*;
+
+#[cfg(feature = "std")]
+mod sharded;
+
+#[cfg(feature = "std")]
+pub use self::sharded::*;
+
+#[cfg(feature = "std")]
+mod cache;
+
+#[cfg(feature = "std")]
+pub use self::cache::*;
+
+mod allocator;
+
+pub use self::allocator::{set_cell_allocator, CellAllocator};
+pub(crate) use self::allocator::{allocate_cell_buffer, release_cell_buffer};
+
+#[cfg(feature = "serde")]
+mod serde_support;
+
+#[cfg(feature = "arbitrary")]
+mod arbitrary_support;
+
+#[cfg(feature = "everscale-types")]
+mod everscale_support;
+
+#[cfg(feature = "zeroize")]
+mod zeroize_support;
+
+#[cfg(feature = "archive")]
+mod archive;
+
+#[cfg(feature = "archive")]
+pub use self::archive::*;
+
+#[cfg(feature = "protobuf")]
+pub mod proto;
+
+/// Cells of trees without duplicates, parents go before children
+#[cfg(any(feature = "archive", feature = "protobuf", feature = "testing"))]
+pub(crate) fn topological_order(roots: &[Cell]) -> Result<Vec<Cell>> {
+    let mut visited = crate::types::FxHashSet::default();
+    let mut post_order = Vec::new();
+    let mut stack = Vec::<(Cell, usize)>::new();
+    for root in roots {
+        if !visited.insert(root.repr_hash()) {
+            continue
+        }
+        stack.push((root.clone(), 0));
+        while let Some((cell, next)) = stack.last_mut() {
+            if cell.virtualization() != 0 {
+                fail!("virtual cells can not be exported")
+            }
+            if *next == cell.references_count() {
+                post_order.push(cell.clone());
+                stack.pop();
+                continue
+            }
+            let child = cell.reference(*next)?;
+            *next += 1;
+            if visited.insert(child.repr_hash()) {
+                stack.push((child, 0));
+            }
+        }
+    }
+    post_order.reverse();
+    Ok(post_order)
+}
+
+#[cfg(feature = "arbitrary")]
+pub use self::arbitrary_support::ArbitraryCellParams;
+
 pub(crate) fn to_hex_string(data: &[u8], len: usize, lower: bool) -> String {
     if len == 0 {
         return String::new();
@@ -0,0 +1,382 @@
+/*
+* Copyright 2018-2020 TON DEV SOLUTIONS LTD.
+*
+* Licensed under the SOFTWARE EVALUATION License (the "License"); you may not use
+* this file except in compliance with the License.
+*
+* Unless required by applicable law or agreed to in writing, software
+* distributed under the License is distributed on an "AS IS" BASIS,
+* WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+* See the License for the specific TON DEV software governing permissions and
+* limitations under the License.
+*/
+
+//! `Parser` reads values from slice within budgets of bits, references and cells and reports
+//! failures as `ParseError` with position and stack of fields being read.
+
+use alloc::{format, string::{String, ToString}, vec::Vec};
+
+use crate::{
+    cell::{Cell, SliceData},
+    tlb::LoadCell,
+    types::{Error, ErrorCode, ExceptionCode, Result, UInt256},
+};
+
+/// Budgets counted over all cells read by parser
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ParseLimits {
+    pub max_bits: usize,
+    pub max_references: usize,
+    /// Cells whose data is read: the first one and every entered reference
+    pub max_cells: usize,
+}
+
+impl Default for ParseLimits {
+    fn default() -> Self {
+        Self {
+            max_bits: usize::MAX,
+            max_references: usize::MAX,
+            max_cells: usize::MAX,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, thiserror::Error)]
+pub enum ParseErrorKind {
+    #[error("budget of {limit} bits is exceeded")]
+    BitsExceeded { limit: usize },
+    #[error("budget of {limit} references is exceeded")]
+    ReferencesExceeded { limit: usize },
+    #[error("budget of {limit} cells is exceeded")]
+    CellsExceeded { limit: usize },
+    #[error("{0}")]
+    Exception(ExceptionCode),
+    #[error("{0}")]
+    Other(String),
+}
+
+/// Failure of parser at bit `offset` of cell entered at `depth` references from the first one,
+/// `context` lists descriptions of values being read from outer to inner
+#[derive(Clone, Debug, PartialEq, Eq, thiserror::Error)]
+#[error("{kind} at bit {offset} of cell at depth {depth}{}", fmt_context(.context))]
+pub struct ParseError {
+    pub kind: ParseErrorKind,
+    pub offset: usize,
+    pub depth: usize,
+    pub context: Vec<String>,
+}
+
+impl ParseErrorKind {
+    /// Stable numeric code (see `ExceptionCode::codes_table`)
+    pub fn code(&self) -> i32 {
+        match self {
+            Self::BitsExceeded { .. } => ErrorCode::ParseBitsExceeded.code(),
+            Self::ReferencesExceeded { .. } => ErrorCode::ParseReferencesExceeded.code(),
+            Self::CellsExceeded { .. } => ErrorCode::ParseCellsExceeded.code(),
+            Self::Exception(code) => code.code(),
+            Self::Other(_) => ErrorCode::ParseError.code(),
+        }
+    }
+}
+
+impl ParseError {
+    /// Stable numeric code of its kind
+    pub fn code(&self) -> i32 {
+        self.kind.code()
+    }
+}
+
+fn fmt_context(context: &[String]) -> String {
+    let mut result = String::new();
+    for (index, description) in context.iter().rev().enumerate() {
+        result += if index == 0 { " while reading " } else { " in " };
+        result += description;
+    }
+    result
+}
+
+/// Cursor over slice checking budgets and tracking context of read values
+#[derive(Clone, Debug)]
+pub struct Parser {
+    slice: SliceData,
+    limits: ParseLimits,
+    bits: usize,
+    references: usize,
+    cells: usize,
+    depth: usize,
+    context: Vec<String>,
+}
+
+impl Parser {
+    /// Parser of slice without budgets, slice is not counted as cell
+    pub fn new(slice: SliceData) -> Self {
+        Self::with_limits(slice, ParseLimits::default())
+    }
+
+    /// Parser of slice, slice is not counted as cell
+    pub fn with_limits(slice: SliceData, limits: ParseLimits) -> Self {
+        Self {
+            slice,
+            limits,
+            bits: 0,
+            references: 0,
+            cells: 0,
+            depth: 0,
+            context: Vec::new(),
+        }
+    }
+
+    /// Parser of cell, cell is counted in budget
+    pub fn load_cell(cell: Cell, limits: ParseLimits) -> Result<Self> {
+        let mut parser = Self::with_limits(SliceData::load_cell(cell)?, limits);
+        parser.charge_cell()?;
+        Ok(parser)
+    }
+
+    pub fn slice(&self) -> &SliceData {
+        &self.slice
+    }
+
+    pub fn into_slice(self) -> SliceData {
+        self.slice
+    }
+
+    pub fn limits(&self) -> &ParseLimits {
+        &self.limits
+    }
+
+    pub fn bits_read(&self) -> usize {
+        self.bits
+    }
+
+    pub fn references_read(&self) -> usize {
+        self.references
+    }
+
+    pub fn cells_read(&self) -> usize {
+        self.cells
+    }
+
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    pub fn context(&self) -> &[String] {
+        &self.context
+    }
+
+    pub fn remaining_bits(&self) -> usize {
+        self.slice.remaining_bits()
+    }
+
+    pub fn remaining_references(&self) -> usize {
+        self.slice.remaining_references()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.slice.is_empty()
+    }
+
+    /// Runs `f` with `description` pushed on context stack
+    pub fn with_context<T>(
+        &mut self,
+        description: impl Into<String>,
+        f: impl FnOnce(&mut Self) -> Result<T>
+    ) -> Result<T> {
+        self.context.push(description.into());
+        let result = f(self).map_err(|err| self.wrap_error(err));
+        self.context.pop();
+        result
+    }
+
+    /// Runs `f` in context "field `field` of `type_name`"
+    pub fn field<T>(
+        &mut self,
+        field: &str,
+        type_name: &str,
+        f: impl FnOnce(&mut Self) -> Result<T>
+    ) -> Result<T> {
+        self.with_context(format!("field {} of {}", field, type_name), f)
+    }
+
+    /// Error at current position and context
+    pub fn error(&self, kind: ParseErrorKind) -> Error {
+        ParseError {
+            kind,
+            offset: self.slice.pos(),
+            depth: self.depth,
+            context: self.context.clone(),
+        }.into()
+    }
+
+    pub fn fail<T>(&self, message: impl ToString) -> Result<T> {
+        Err(self.error(ParseErrorKind::Other(message.to_string())))
+    }
+
+    /// Converts error to `ParseError` at current position, errors of parser are left as is
+    fn wrap_error(&self, err: Error) -> Error {
+        if err.downcast_ref::<ParseError>().is_some() {
+            return err
+        }
+        match err.downcast::<ExceptionCode>() {
+            Ok(code) => self.error(ParseErrorKind::Exception(code)),
+            Err(err) => self.error(ParseErrorKind::Other(err.to_string()))
+        }
+    }
+
+    fn charge(&mut self, bits: usize, references: usize) -> Result<()> {
+        if self.bits.saturating_add(bits) > self.limits.max_bits {
+            return Err(self.error(ParseErrorKind::BitsExceeded { limit: self.limits.max_bits }))
+        }
+        if self.references.saturating_add(references) > self.limits.max_references {
+            return Err(self.error(ParseErrorKind::ReferencesExceeded { limit: self.limits.max_references }))
+        }
+        self.bits += bits;
+        self.references += references;
+        Ok(())
+    }
+
+    fn charge_cell(&mut self) -> Result<()> {
+        if self.cells >= self.limits.max_cells {
+            return Err(self.error(ParseErrorKind::CellsExceeded { limit: self.limits.max_cells }))
+        }
+        self.cells += 1;
+        Ok(())
+    }
+
+    /// Reads with `f` from inner slice, reading over remaining budgets of bits and references fails
+    pub fn read<T>(&mut self, f: impl FnOnce(&mut SliceData) -> Result<T>) -> Result<T> {
+        self.read_limited(false, f)
+    }
+
+    // `f` gets window of slice fitting remaining budgets, so it can't read over them.
+    // If `count_cells` is set, consumed references are also counted as entered cells
+    fn read_limited<T>(&mut self, count_cells: bool, f: impl FnOnce(&mut SliceData) -> Result<T>) -> Result<T> {
+        let bits_left = self.limits.max_bits.saturating_sub(self.bits);
+        let references_left = self.limits.max_references.saturating_sub(self.references);
+        let cells_left = match count_cells {
+            true => self.limits.max_cells.saturating_sub(self.cells),
+            false => usize::MAX
+        };
+        let bits_cut = self.slice.remaining_bits() > bits_left;
+        let references_cut = self.slice.remaining_references() > references_left;
+        let cells_cut = self.slice.remaining_references() > cells_left;
+        if !bits_cut && !references_cut && !cells_cut {
+            let (bits, references) = (self.slice.remaining_bits(), self.slice.remaining_references());
+            let value = f(&mut self.slice).map_err(|err| self.wrap_error(err))?;
+            self.charge_consumed(
+                bits.saturating_sub(self.slice.remaining_bits()),
+                references.saturating_sub(self.slice.remaining_references()),
+                count_cells
+            );
+            return Ok(value)
+        }
+        let mut window = self.slice.clone();
+        if bits_cut {
+            window.shrink_data(..bits_left);
+        }
+        if references_cut || cells_cut {
+            window.shrink_references(..core::cmp::min(references_left, cells_left));
+        }
+        let (bits, references) = (window.remaining_bits(), window.remaining_references());
+        let value = match f(&mut window) {
+            Ok(value) => value,
+            // underflow of window means that budget is exceeded, bits are reported first if several are cut
+            Err(err) => return Err(match err.downcast_ref::<ExceptionCode>() {
+                Some(ExceptionCode::CellUnderflow) if bits_cut =>
+                    self.error(ParseErrorKind::BitsExceeded { limit: self.limits.max_bits }),
+                Some(ExceptionCode::CellUnderflow) if references_cut =>
+                    self.error(ParseErrorKind::ReferencesExceeded { limit: self.limits.max_references }),
+                Some(ExceptionCode::CellUnderflow) =>
+                    self.error(ParseErrorKind::CellsExceeded { limit: self.limits.max_cells }),
+                _ => self.wrap_error(err)
+            })
+        };
+        let consumed_bits = bits.saturating_sub(window.remaining_bits());
+        let consumed_references = references.saturating_sub(window.remaining_references());
+        self.slice.move_by(consumed_bits).map_err(|err| self.wrap_error(err))?;
+        self.slice.shrink_references(consumed_references..);
+        self.charge_consumed(consumed_bits, consumed_references, count_cells);
+        Ok(value)
+    }
+
+    // Budgets are checked before reading, so consumed parts fit them
+    fn charge_consumed(&mut self, bits: usize, references: usize, count_cells: bool) {
+        self.bits += bits;
+        self.references += references;
+        if count_cells {
+            self.cells += references;
+        }
+    }
+
+    fn read_bits<T>(&mut self, bits: usize, f: impl FnOnce(&mut SliceData) -> Result<T>) -> Result<T> {
+        self.charge(bits, 0)?;
+        f(&mut self.slice).map_err(|err| self.wrap_error(err))
+    }
+
+    pub fn load_bit(&mut self) -> Result<bool> {
+        self.read_bits(1, |slice| slice.get_next_bit())
+    }
+
+    /// Unsigned integer of up to 64 bits
+    pub fn load_uint(&mut self, bits: usize) -> Result<u64> {
+        self.read_bits(bits, |slice| slice.get_next_int(bits))
+    }
+
+    pub fn load_bytes(&mut self, count: usize) -> Result<Vec<u8>> {
+        match count.checked_mul(8) {
+            Some(bits) => self.read_bits(bits, |slice| slice.get_next_bytes(count)),
+            None => Err(self.error(ParseErrorKind::BitsExceeded { limit: self.limits.max_bits }))
+        }
+    }
+
+    pub fn load_hash(&mut self) -> Result<UInt256> {
+        self.read_bits(256, |slice| slice.get_next_hash())
+    }
+
+    pub fn load_slice(&mut self, bits: usize) -> Result<SliceData> {
+        self.read_bits(bits, |slice| slice.get_next_slice(bits))
+    }
+
+    /// Next reference, its cell is not counted until it is entered
+    pub fn load_reference(&mut self) -> Result<Cell> {
+        self.charge(0, 1)?;
+        self.slice.checked_drain_reference().map_err(|err| self.wrap_error(err))
+    }
+
+    /// Reads value with `LoadCell` within remaining budgets. References consumed by it are counted
+    /// as entered cells, but cells deeper than them are not visible to parser,
+    /// so values stored in references should be read by `load_ref`
+    pub fn load<T: LoadCell>(&mut self) -> Result<T> {
+        self.read_limited(true, T::load_from)
+    }
+
+    /// Reads value with `LoadCell` from next reference entered by `with_reference`
+    pub fn load_ref<T: LoadCell>(&mut self) -> Result<T> {
+        self.with_reference(|parser| parser.load())
+    }
+
+    /// Runs `f` on parser of next reference, position in current cell is restored afterwards
+    pub fn with_reference<T>(&mut self, f: impl FnOnce(&mut Self) -> Result<T>) -> Result<T> {
+        let cell = self.load_reference()?;
+        self.charge_cell()?;
+        let slice = SliceData::load_cell(cell).map_err(|err| self.wrap_error(err))?;
+        let parent = core::mem::replace(&mut self.slice, slice);
+        self.depth += 1;
+        let result = f(self).map_err(|err| self.wrap_error(err));
+        self.depth -= 1;
+        self.slice = parent;
+        result
+    }
+
+    /// Fails if current cell is not read completely
+    pub fn check_consumed(&self) -> Result<()> {
+        if !self.slice.is_empty() {
+            return self.fail(format!(
+                "{} bits and {} references left unread",
+                self.slice.remaining_bits(), self.slice.remaining_references()
+            ))
+        }
+        Ok(())
+    }
+}
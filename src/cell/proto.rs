@@ -0,0 +1,203 @@
+/*
+* Copyright 2018-2020 TON DEV SOLUTIONS LTD.
+*
+* Licensed under the SOFTWARE EVALUATION License (the "License"); you may not use
+* this file except in compliance with the License.
+*
+* Unless required by applicable law or agreed to in writing, software
+* distributed under the License is distributed on an "AS IS" BASIS,
+* WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+* See the License for the specific TON DEV software governing permissions and
+* limitations under the License.
+*/
+
+//! Protobuf messages of `proto/cells.proto` and their conversions with cells.
+//! Messages are encoded and decoded with `prost::Message`.
+
+use alloc::vec::Vec;
+
+use prost::Message;
+use smallvec::SmallVec;
+
+use crate::{
+    cell::{self, topological_order, BuilderData, Cell, LevelMask},
+    error, fail,
+    types::{Error, FxHashMap, Result},
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, prost::Enumeration)]
+#[repr(i32)]
+pub enum CellType {
+    Ordinary = 0,
+    PrunedBranch = 1,
+    LibraryReference = 2,
+    MerkleProof = 3,
+    MerkleUpdate = 4,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct CellNode {
+    #[prost(enumeration = "CellType", tag = "1")]
+    pub cell_type: i32,
+    #[prost(uint32, tag = "2")]
+    pub level_mask: u32,
+    /// Data without completion tag
+    #[prost(bytes = "vec", tag = "3")]
+    pub data: Vec<u8>,
+    #[prost(uint32, tag = "4")]
+    pub bit_length: u32,
+    /// Indexes of children, they are greater than index of the cell
+    #[prost(uint32, repeated, tag = "5")]
+    pub references: Vec<u32>,
+    /// Checked when it is not empty
+    #[prost(bytes = "vec", tag = "6")]
+    pub repr_hash: Vec<u8>,
+}
+
+/// Cells ordered from roots to leaves, shared cells are stored once
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct CellTree {
+    #[prost(message, repeated, tag = "1")]
+    pub cells: Vec<CellNode>,
+    #[prost(uint32, repeated, tag = "2")]
+    pub roots: Vec<u32>,
+}
+
+impl From<cell::CellType> for CellType {
+    fn from(cell_type: cell::CellType) -> Self {
+        match cell_type {
+            cell::CellType::PrunedBranch => CellType::PrunedBranch,
+            cell::CellType::LibraryReference => CellType::LibraryReference,
+            cell::CellType::MerkleProof => CellType::MerkleProof,
+            cell::CellType::MerkleUpdate => CellType::MerkleUpdate,
+            cell::CellType::Ordinary | cell::CellType::Unknown => CellType::Ordinary,
+        }
+    }
+}
+
+impl From<CellType> for cell::CellType {
+    fn from(cell_type: CellType) -> Self {
+        match cell_type {
+            CellType::Ordinary => cell::CellType::Ordinary,
+            CellType::PrunedBranch => cell::CellType::PrunedBranch,
+            CellType::LibraryReference => cell::CellType::LibraryReference,
+            CellType::MerkleProof => cell::CellType::MerkleProof,
+            CellType::MerkleUpdate => cell::CellType::MerkleUpdate,
+        }
+    }
+}
+
+impl CellTree {
+    /// Trees of `roots` with hashes of every cell
+    pub fn from_roots(roots: &[Cell]) -> Result<Self> {
+        let order = topological_order(roots)?;
+        let index_of = order.iter().enumerate()
+            .map(|(index, cell)| (cell.repr_hash(), index as u32))
+            .collect::<FxHashMap<_, _>>();
+        let mut cells = Vec::with_capacity(order.len());
+        for cell in &order {
+            let bits = cell.bit_length();
+            let mut data = cell.data()[..bits.div_ceil(8)].to_vec();
+            if bits % 8 != 0 {
+                if let Some(last) = data.last_mut() {
+                    *last &= 0xff << (8 - bits % 8);
+                }
+            }
+            let mut references = Vec::with_capacity(cell.references_count());
+            for index in 0..cell.references_count() {
+                references.push(index_of[&cell.reference_repr_hash(index)?]);
+            }
+            cells.push(CellNode {
+                cell_type: CellType::from(cell.cell_type()) as i32,
+                level_mask: cell.level_mask().mask() as u32,
+                data,
+                bit_length: bits as u32,
+                references,
+                repr_hash: cell.repr_hash().as_slice().to_vec(),
+            });
+        }
+        let roots = roots.iter().map(|root| index_of[&root.repr_hash()]).collect();
+        Ok(Self { cells, roots })
+    }
+
+    /// Constructs cells from leaves to roots checking given hashes
+    pub fn to_roots(&self) -> Result<Vec<Cell>> {
+        let mut done = Vec::<Option<Cell>>::new();
+        done.resize(self.cells.len(), None);
+        for (index, node) in self.cells.iter().enumerate().rev() {
+            done[index] = Some(node.to_cell(index, &done)?);
+        }
+        let mut roots = Vec::with_capacity(self.roots.len());
+        for root in &self.roots {
+            match done.get(*root as usize) {
+                Some(Some(cell)) => roots.push(cell.clone()),
+                _ => fail!("there is no root cell {}", root)
+            }
+        }
+        Ok(roots)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.encode_to_vec()
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Self> {
+        Self::decode(data).map_err(|err| error!("invalid protobuf of cell tree: {}", err))
+    }
+}
+
+impl CellNode {
+    fn to_cell(&self, index: usize, done: &[Option<Cell>]) -> Result<Cell> {
+        let bits = self.bit_length as usize;
+        if self.data.len() != bits.div_ceil(8) {
+            fail!("cell {} has {} bytes of data for {} bits", index, self.data.len(), bits)
+        }
+        let mut builder = BuilderData::with_raw(SmallVec::from_slice(&self.data), bits)?;
+        let cell_type = CellType::try_from(self.cell_type)
+            .map_err(|_| error!("cell {} has unknown type {}", index, self.cell_type))?;
+        if cell_type != CellType::Ordinary {
+            if self.level_mask > 7 {
+                fail!("cell {} has invalid level mask {}", index, self.level_mask)
+            }
+            builder.set_type(cell_type.into());
+            builder.set_level_mask(LevelMask::with_mask(self.level_mask as u8));
+        }
+        for child in &self.references {
+            let child = *child as usize;
+            if child <= index {
+                fail!("cell {} has invalid reference {}", index, child)
+            }
+            match done.get(child) {
+                Some(Some(cell)) => builder.checked_append_reference(cell.clone())?,
+                _ => fail!("cell {} has invalid reference {}", index, child)
+            };
+        }
+        let cell = builder.into_cell()?;
+        if !self.repr_hash.is_empty() && cell.repr_hash().as_slice() != self.repr_hash.as_slice() {
+            fail!("hash of cell {} is {:x} instead of given one", index, cell.repr_hash())
+        }
+        if cell.level_mask().mask() as u32 != self.level_mask {
+            fail!("level mask of cell {} is {} instead of given {}", index, cell.level_mask().mask(), self.level_mask)
+        }
+        Ok(cell)
+    }
+}
+
+impl TryFrom<&Cell> for CellTree {
+    type Error = Error;
+    fn try_from(cell: &Cell) -> Result<Self> {
+        Self::from_roots(core::slice::from_ref(cell))
+    }
+}
+
+/// Tree must have single root
+impl TryFrom<&CellTree> for Cell {
+    type Error = Error;
+    fn try_from(tree: &CellTree) -> Result<Self> {
+        let mut roots = tree.to_roots()?;
+        match roots.len() {
+            1 => Ok(roots.remove(0)),
+            count => fail!("cell tree has {} roots instead of one", count)
+        }
+    }
+}
@@ -0,0 +1,76 @@
+/*
+* Copyright 2018-2020 TON DEV SOLUTIONS LTD.
+*
+* Licensed under the SOFTWARE EVALUATION License (the "License"); you may not use
+* this file except in compliance with the License.
+*
+* Unless required by applicable law or agreed to in writing, software
+* distributed under the License is distributed on an "AS IS" BASIS,
+* WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+* See the License for the specific TON DEV software governing permissions and
+* limitations under the License.
+*/
+
+use alloc::vec::Vec;
+use core::fmt;
+
+use serde::de::{self, Deserialize, Deserializer, SeqAccess, Visitor};
+use serde::ser::{self, Serialize, Serializer};
+
+use crate::cell::Cell;
+use crate::cells_serialization::{deserialize_tree_of_cells, serialize_toc};
+
+// Cell is stored as BOC: base64 string for human-readable formats and raw bytes otherwise
+impl Serialize for Cell {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let boc = serialize_toc(self).map_err(ser::Error::custom)?;
+        if serializer.is_human_readable() {
+            serializer.serialize_str(&base64::encode(boc))
+        } else {
+            serializer.serialize_bytes(&boc)
+        }
+    }
+}
+
+struct BocVisitor;
+
+impl BocVisitor {
+    fn read<E: de::Error>(boc: &[u8]) -> Result<Cell, E> {
+        deserialize_tree_of_cells(&mut &boc[..]).map_err(E::custom)
+    }
+}
+
+impl<'de> Visitor<'de> for BocVisitor {
+    type Value = Cell;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("BOC with single root as base64 string or bytes")
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<Cell, E> {
+        let boc = crate::base64_decode(value).map_err(E::custom)?;
+        Self::read(&boc)
+    }
+
+    fn visit_bytes<E: de::Error>(self, value: &[u8]) -> Result<Cell, E> {
+        Self::read(value)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Cell, A::Error> {
+        let mut boc = Vec::with_capacity(seq.size_hint().unwrap_or_default());
+        while let Some(byte) = seq.next_element::<u8>()? {
+            boc.push(byte);
+        }
+        Self::read(&boc)
+    }
+}
+
+impl<'de> Deserialize<'de> for Cell {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        if deserializer.is_human_readable() {
+            deserializer.deserialize_str(BocVisitor)
+        } else {
+            deserializer.deserialize_bytes(BocVisitor)
+        }
+    }
+}
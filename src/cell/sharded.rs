@@ -0,0 +1,99 @@
+/*
+* Copyright 2018-2020 TON DEV SOLUTIONS LTD.
+*
+* Licensed under the SOFTWARE EVALUATION License (the "License"); you may not use
+* this file except in compliance with the License.
+*
+* Unless required by applicable law or agreed to in writing, software
+* distributed under the License is distributed on an "AS IS" BASIS,
+* WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+* See the License for the specific TON DEV software governing permissions and
+* limitations under the License.
+*/
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use smallvec::SmallVec;
+
+use crate::cell::{Cell, CellData, CellImpl, CellType, LevelMask};
+use crate::types::{Result, UInt256};
+
+/// Handle for popular cell cloned by many threads at once (config root, empty cell, etc.).
+/// Every shard is a separate allocation with its own reference counter pointing to the
+/// same cell, so threads cloning the cell don't contend on the single atomic counter.
+/// Shards are aligned to cache lines, so their counters don't share one.
+#[derive(Clone)]
+pub struct ShardedCell {
+    shards: Vec<Cell>,
+}
+
+impl ShardedCell {
+    pub fn new(cell: Cell) -> Self {
+        let count = std::thread::available_parallelism().map_or(1, |count| count.get());
+        Self::with_shards(cell, count)
+    }
+
+    pub fn with_shards(cell: Cell, count: usize) -> Self {
+        // the cell itself is not a shard, its counter is shared with other owners of the cell
+        let shards = (0..count.max(1)).map(|_| Cell::with_cell_impl(ShardCell(cell.0.clone()))).collect();
+        Self { shards }
+    }
+
+    /// Returns cell from shard of the current thread
+    pub fn cell(&self) -> Cell {
+        thread_local! {
+            static SHARD_INDEX: usize = {
+                static NEXT_INDEX: AtomicUsize = AtomicUsize::new(0);
+                NEXT_INDEX.fetch_add(1, Ordering::Relaxed)
+            };
+        }
+        let index = SHARD_INDEX.with(|index| *index) % self.shards.len();
+        self.shards[index].clone()
+    }
+
+    pub fn shards_count(&self) -> usize {
+        self.shards.len()
+    }
+}
+
+impl From<Cell> for ShardedCell {
+    fn from(cell: Cell) -> Self {
+        Self::new(cell)
+    }
+}
+
+// 128 bytes: adjacent cache lines are prefetched together on modern x86
+#[repr(align(128))]
+struct ShardCell(Arc<dyn CellImpl>);
+
+impl CellImpl for ShardCell {
+    fn data(&self) -> &[u8] { self.0.data() }
+    fn data_range(&self, offset: usize, bits: usize) -> &[u8] { self.0.data_range(offset, bits) }
+    fn raw_data(&self) -> Result<&[u8]> { self.0.raw_data() }
+    fn cell_data(&self) -> &CellData { self.0.cell_data() }
+    fn bit_length(&self) -> usize { self.0.bit_length() }
+    fn references_count(&self) -> usize { self.0.references_count() }
+    fn reference(&self, index: usize) -> Result<Cell> { self.0.reference(index) }
+    fn reference_repr_hash(&self, index: usize) -> Result<UInt256> { self.0.reference_repr_hash(index) }
+    fn cell_type(&self) -> CellType { self.0.cell_type() }
+    fn level_mask(&self) -> LevelMask { self.0.level_mask() }
+    fn hash(&self, index: usize) -> UInt256 { self.0.hash(index) }
+    fn depth(&self, index: usize) -> u16 { self.0.depth(index) }
+    fn store_hashes(&self) -> bool { self.0.store_hashes() }
+    fn tree_bits_count(&self) -> u64 { self.0.tree_bits_count() }
+    fn tree_cell_count(&self) -> u64 { self.0.tree_cell_count() }
+    fn virtualization(&self) -> u8 { self.0.virtualization() }
+    // parts of the cell can be taken only when the last shard is uniquely owned
+    fn take_references(&mut self) -> SmallVec<[Cell; 4]> {
+        Arc::get_mut(&mut self.0).map(|cell| cell.take_references()).unwrap_or_default()
+    }
+    fn wipe_data(&mut self) {
+        if let Some(cell) = Arc::get_mut(&mut self.0) {
+            cell.wipe_data()
+        }
+    }
+    fn take_parts(&mut self) -> Option<(Vec<u8>, SmallVec<[Cell; 4]>)> {
+        Arc::get_mut(&mut self.0).and_then(|cell| cell.take_parts())
+    }
+}
@@ -11,16 +11,34 @@
 * limitations under the License.
 */
 
-use std::cmp;
-use std::convert::TryInto;
-use std::fmt;
-use std::hash::{Hash, Hasher};
-use std::ops::{Bound, Range, RangeBounds};
+use alloc::{string::String, vec::Vec};
+use core::cmp;
+use core::convert::TryInto;
+use core::fmt;
+use core::hash::{Hash, Hasher};
+use core::ops::{Bound, Range, RangeBounds};
 
 use crate::{error, fail, cell::{BuilderData, Cell, CellType, IBitstring, LevelMask}, parse_slice_base};
 use crate::types::{ExceptionCode, Result, UInt256};
 use smallvec::SmallVec;
 
+/// Loads 64 bits starting from bit index, left aligned; bits past the end of data are zero
+pub(super) fn load_word(data: &[u8], index: usize) -> u64 {
+    let q = index / 8;
+    let r = index % 8;
+    let mut buf = [0u8; 9];
+    if q < data.len() {
+        let len = core::cmp::min(9, data.len() - q);
+        buf[..len].copy_from_slice(&data[q..q + len]);
+    }
+    let word = u64::from_be_bytes(buf[..8].try_into().unwrap());
+    if r == 0 {
+        word
+    } else {
+        (word << r) | (buf[8] >> (8 - r)) as u64
+    }
+}
+
 #[derive(Eq, Clone)]
 pub struct SliceData {
     pub(super) cell: Cell,
@@ -201,23 +219,24 @@ impl SliceData {
 
     pub fn remaining_data(&self) -> BuilderData {
         let start = self.data_window.start / 8;
-        let end = (self.data_window.end + 7) / 8;
+        let end = self.data_window.end.div_ceil(8);
         if self.data_window.start >= self.data_window.end {
             return BuilderData::new()
         }
         let trailing = self.data_window.start % 8;
+        let data = self.cell.data_range(self.data_window.start, self.remaining_bits());
         if trailing == 0 {
             BuilderData::with_raw(
-                SmallVec::from_slice(&self.cell.data()[start..end]),
+                SmallVec::from_slice(&data[start..end]),
                 self.remaining_bits()
             ).unwrap()
         } else if trailing + self.remaining_bits() <= 8 {
-            let vec = smallvec::smallvec![self.cell.data()[start] << trailing];
+            let vec = smallvec::smallvec![data[start] << trailing];
             BuilderData::with_raw(vec, self.remaining_bits()).unwrap()
         } else {
-            let vec = smallvec::smallvec![self.cell.data()[start] << trailing];
+            let vec = smallvec::smallvec![data[start] << trailing];
             let mut builder = BuilderData::with_raw(vec, 8 - trailing).unwrap();
-            builder.append_raw(& self.cell.data()[start + 1..end], trailing + self.remaining_bits() - 8).unwrap();
+            builder.append_raw(&data[start + 1..end], trailing + self.remaining_bits() - 8).unwrap();
             builder
         }
     }
@@ -297,18 +316,11 @@ impl SliceData {
     }
 
     pub fn get_bit_opt(&self, offset: usize) -> Option<bool> {
-        if offset >= self.remaining_bits() {
-            None
-        } else {
-            let index = self.data_window.start + offset;
-            let q = index / 8;
-            let r = index % 8;
-            Some((self.cell.data()[q] >> (7 - r) & 1) != 0)
-        }
+        self.as_slice_ref().get_bit_opt(offset)
     }
 
     pub fn get_bit(&self, offset: usize) -> Result<bool> {
-        self.get_bit_opt(offset).ok_or_else(|| error!(ExceptionCode::CellUnderflow))
+        self.as_slice_ref().get_bit(offset)
     }
 
     /// Returns subslice of current slice
@@ -323,54 +335,29 @@ impl SliceData {
     }
 
     pub fn get_bits(&self, offset: usize, bits: usize) -> Result<u8> {
-        if offset + bits > self.remaining_bits() {
-            fail!(ExceptionCode::CellUnderflow)
-        }
-        if bits == 0 || bits > 8 {
-            fail!(ExceptionCode::RangeCheckError)
-        }
-        let index = self.data_window.start + offset;
-        let q = index / 8;
-        let r = index % 8;
-        if r == 0 {
-            Ok(self.cell.data()[q] >> (8 - r - bits))
-        } else if bits <= (8 - r) {
-            Ok(self.cell.data()[q] >> (8 - r - bits) & ((1 << bits) - 1))
-        } else {
-            let mut ret = 0u16;
-            if q < self.cell.data().len() {
-                ret |= (self.cell.data()[q] as u16) << 8;
-            }
-            if q < self.cell.data().len() - 1 {
-                ret |= self.cell.data()[q + 1] as u16;
-            }
-            Ok((ret >> (8 - r)) as u8 >> (8 - bits))
-        }
+        self.as_slice_ref().get_bits(offset, bits)
     }
 
     pub fn get_byte(&self, offset: usize) -> Result<u8> {
         self.get_bits(offset, 8)
     }
 
+    // readers are implemented once by SliceRef, windows are moved as it has moved them
+    fn read_next<T>(&mut self, read: impl FnOnce(&mut SliceRef) -> Result<T>) -> Result<T> {
+        let mut slice = self.as_slice_ref();
+        let result = read(&mut slice);
+        let SliceRef { data_window, references_window, .. } = slice;
+        self.data_window = data_window;
+        self.references_window = references_window;
+        result
+    }
+
     pub fn get_next_bits(&mut self, bits: usize) -> Result<Vec<u8>> {
-        if bits > self.remaining_bits() {
-            fail!(ExceptionCode::CellUnderflow)
-        }
-        let bytes = bits / 8;
-        let mut vec = (0..bytes).map(|i| self.get_byte(i * 8).unwrap()).collect::<Vec<_>>();
-        let remainder = bits % 8;
-        if remainder != 0 {
-            let v = self.get_bits(bytes * 8, remainder)?;
-            vec.push(v << (8 - remainder));
-        }
-        self.move_by(bits)?;
-        Ok(vec)
+        self.read_next(|slice| slice.get_next_bits(bits))
     }
 
     pub fn get_next_bit(&mut self) -> Result<bool> {
-        let bit = self.get_bit(0)?;
-        self.move_by(1)?;
-        Ok(bit)
+        self.read_next(|slice| slice.get_next_bit())
     }
 
     pub fn get_next_bit_int(&mut self) -> Result<usize> {
@@ -384,34 +371,11 @@ impl SliceData {
     }
 
     pub fn get_next_byte(&mut self) -> Result<u8> {
-        let value = self.get_byte(0)?;
-        self.move_by(8)?;
-        Ok(value)
+        self.read_next(|slice| slice.get_next_byte())
     }
 
     pub fn get_next_int(&mut self, bits: usize) -> Result<u64> {
-        if bits > self.remaining_bits() {
-            fail!(ExceptionCode::CellUnderflow)
-        }
-        if bits == 0 {
-            return Ok(0)
-        }
-        if bits > 64 {
-            // get_next_int_bytes
-            fail!("too many bits {} > 64", bits)
-        }
-        let mut value: u64 = 0;
-        let bytes = bits / 8;
-        for i in 0..bytes {
-            value |= (self.get_byte(8 * i)? as u64) << (8 * (7 - i));
-        }
-        let remainder = bits % 8;
-        if remainder != 0 {
-            let r = self.get_bits(bytes * 8, remainder)? as u64;
-            value |= r << (8 * (7 - bytes) + (8 - remainder));
-        }
-        self.move_by(bits)?;
-        Ok(value >> (64 - bits))
+        self.read_next(|slice| slice.get_next_int(bits))
     }
 
     pub fn get_next_size(&mut self, max_value: usize) -> Result<u64> {
@@ -423,69 +387,35 @@ impl SliceData {
     }
 
     pub fn get_next_u16(&mut self) -> Result<u16> {
-        let mut value: u16 = 0;
-        for i in 0..2 {
-            value |= (self.get_byte(8 * i)? as u16) << (8 * (1 - i));
-        }
-        self.move_by(16)?;
-        Ok(value)
+        self.read_next(|slice| slice.get_next_u16())
     }
 
     pub fn get_next_i16(&mut self) -> Result<i16> {
-        let mut value: i16 = 0;
-        for i in 0..2 {
-            value |= (self.get_byte(8 * i)? as i16) << (8 * (1 - i));
-        }
-        self.move_by(16)?;
-        Ok(value)
+        self.read_next(|slice| slice.get_next_i16())
     }
 
     pub fn get_next_u32(&mut self) -> Result<u32> {
-        let mut value: u32 = 0;
-        for i in 0..4 {
-            value |= (self.get_byte(8 * i)? as u32) << (8 * (3 - i));
-        }
-        self.move_by(32)?;
-        Ok(value)
+        self.read_next(|slice| slice.get_next_u32())
     }
 
     pub fn get_next_i32(&mut self) -> Result<i32> {
-        let mut value: i32 = 0;
-        for i in 0..4 {
-            value |= (self.get_byte(8 * i)? as i32) << (8 * (3 - i));
-        }
-        self.move_by(32)?;
-        Ok(value)
+        self.read_next(|slice| slice.get_next_i32())
     }
 
     pub fn get_next_u64(&mut self) -> Result<u64> {
-        let mut value: u64 = 0;
-        for i in 0..8 {
-            value |= (self.get_byte(8 * i)? as u64) << (8 * (7 - i));
-        }
-        self.move_by(64)?;
-        Ok(value)
+        self.read_next(|slice| slice.get_next_u64())
     }
 
     pub fn get_next_u128(&mut self) -> Result<u128> {
-        let mut value: u128 = 0;
-        for i in 0..16 {
-            value |= (self.get_byte(8 * i)? as u128) << (8 * (15 - i));
-        }
-        self.move_by(128)?;
-        Ok(value)
+        self.read_next(|slice| slice.get_next_u128())
     }
 
     pub fn get_next_hash(&mut self) -> Result<UInt256> {
-        let hash: [u8; 32] = self.get_next_bytes(32)?.try_into().unwrap();
-        Ok(UInt256::from(hash))
+        self.read_next(|slice| slice.get_next_hash())
     }
 
     pub fn get_next_bytes(&mut self, bytes: usize) -> Result<Vec<u8>> {
-        if bytes * 8 > self.remaining_bits() {
-            fail!(ExceptionCode::CellUnderflow)
-        }
-        Ok((0..bytes).map(|_| self.get_next_byte().unwrap()).collect::<Vec<_>>())
+        self.read_next(|slice| slice.get_next_bytes(bytes))
     }
 
     pub fn get_bytestring(&self, mut offset: usize) -> Vec<u8> {
@@ -521,11 +451,7 @@ impl SliceData {
 
     /// Returns Cell from references if present and next bit in slice is one
     pub fn get_next_dictionary(&mut self) -> Result<Option<Cell>> {
-        if self.get_next_bit()? {
-            Ok(Some(self.checked_drain_reference()?))
-        } else {
-            Ok(None)
-        }
+        self.read_next(|slice| slice.get_next_dictionary())
     }
 
     /// Returns subslice of current slice and moves pointer
@@ -713,7 +639,7 @@ impl SliceData {
     }
 
     pub fn withdraw(&mut self) -> SliceData {
-        std::mem::replace(self, SliceData::new_empty())
+        core::mem::replace(self, SliceData::new_empty())
     }
 }
 
@@ -731,8 +657,8 @@ impl fmt::Display for SliceData {
                 self.data_window.end,
                 self.references_window.start,
                 self.references_window.end,
-                hex::encode(&self.get_bytestring_on_stack(0)),
-                self.cell)
+                hex::encode(self.get_bytestring_on_stack(0)),
+                self.cell.tree())
     }
 }
 
@@ -753,3 +679,231 @@ impl fmt::UpperHex for SliceData {
         write!(f, "{}", super::to_hex_string(data.as_slice(), len, false))
     }
 }
+
+/// Borrowed view of cell's data and references without cloning and dropping the cell for
+/// every short-lived slice. Readers of SliceData are implemented by it.
+#[derive(Clone)]
+pub struct SliceRef<'a> {
+    cell: &'a Cell,
+    data_window: Range<usize>,
+    references_window: Range<usize>,
+}
+
+impl<'a> SliceRef<'a> {
+    pub fn load_cell(cell: &'a Cell) -> Result<Self> {
+        if cell.is_pruned() {
+            fail!(ExceptionCode::PrunedCellAccess)
+        }
+        Ok(Self {
+            cell,
+            data_window: 0..cell.bit_length(),
+            references_window: 0..cell.references_count(),
+        })
+    }
+
+    /// converts to owned slice, only this call clones the cell
+    pub fn to_slice_data(&self) -> SliceData {
+        SliceData {
+            cell: self.cell.clone(),
+            data_window: self.data_window.clone(),
+            references_window: self.references_window.clone(),
+        }
+    }
+
+    pub fn cell(&self) -> &'a Cell {
+        self.cell
+    }
+
+    pub fn remaining_bits(&self) -> usize {
+        self.data_window.end.saturating_sub(self.data_window.start)
+    }
+
+    pub fn remaining_references(&self) -> usize {
+        self.references_window.end.saturating_sub(self.references_window.start)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data_window.start >= self.data_window.end
+    }
+
+    pub fn pos(&self) -> usize {
+        self.data_window.start
+    }
+
+    pub fn move_by(&mut self, offset: usize) -> Result<()> {
+        if self.data_window.start + offset <= self.data_window.end {
+            self.data_window.start += offset;
+            Ok(())
+        } else {
+            fail!(ExceptionCode::CellUnderflow)
+        }
+    }
+
+    pub fn reference(&self, i: usize) -> Result<Cell> {
+        if self.references_window.start + i < self.references_window.end {
+            self.cell.reference(self.references_window.start + i)
+        } else {
+            fail!(ExceptionCode::CellUnderflow)
+        }
+    }
+
+    pub fn checked_drain_reference(&mut self) -> Result<Cell> {
+        let cell = self.reference(0)?;
+        self.references_window.start += 1;
+        Ok(cell)
+    }
+
+    pub fn get_bit_opt(&self, offset: usize) -> Option<bool> {
+        self.get_int(offset, 1).ok().map(|bit| bit != 0)
+    }
+
+    pub fn get_bit(&self, offset: usize) -> Result<bool> {
+        Ok(self.get_int(offset, 1)? != 0)
+    }
+
+    pub fn get_bits(&self, offset: usize, bits: usize) -> Result<u8> {
+        if offset + bits > self.remaining_bits() {
+            fail!(ExceptionCode::CellUnderflow)
+        } else if bits == 0 || bits > 8 {
+            fail!(ExceptionCode::RangeCheckError)
+        }
+        Ok(self.get_int(offset, bits)? as u8)
+    }
+
+    pub fn get_byte(&self, offset: usize) -> Result<u8> {
+        self.get_bits(offset, 8)
+    }
+
+    fn get_int(&self, offset: usize, bits: usize) -> Result<u64> {
+        if offset + bits > self.remaining_bits() {
+            fail!(ExceptionCode::CellUnderflow)
+        }
+        if bits == 0 {
+            return Ok(0)
+        }
+        if bits > 64 {
+            fail!("too many bits {} > 64", bits)
+        }
+        let index = self.data_window.start + offset;
+        Ok(load_word(self.cell.data_range(index, bits), index) >> (64 - bits))
+    }
+
+    pub fn get_next_bit(&mut self) -> Result<bool> {
+        let bit = self.get_bit(0)?;
+        self.move_by(1)?;
+        Ok(bit)
+    }
+
+    pub fn get_next_int(&mut self, bits: usize) -> Result<u64> {
+        let value = self.get_int(0, bits)?;
+        self.move_by(bits)?;
+        Ok(value)
+    }
+
+    pub fn get_next_byte(&mut self) -> Result<u8> {
+        Ok(self.get_next_int(8)? as u8)
+    }
+
+    pub fn get_next_u16(&mut self) -> Result<u16> {
+        Ok(self.get_next_int(16)? as u16)
+    }
+
+    pub fn get_next_i16(&mut self) -> Result<i16> {
+        Ok(self.get_next_int(16)? as i16)
+    }
+
+    pub fn get_next_u32(&mut self) -> Result<u32> {
+        Ok(self.get_next_int(32)? as u32)
+    }
+
+    pub fn get_next_i32(&mut self) -> Result<i32> {
+        Ok(self.get_next_int(32)? as i32)
+    }
+
+    pub fn get_next_u64(&mut self) -> Result<u64> {
+        self.get_next_int(64)
+    }
+
+    pub fn get_next_u128(&mut self) -> Result<u128> {
+        let hi = self.get_int(0, 64)?;
+        let lo = self.get_int(64, 64)?;
+        self.move_by(128)?;
+        Ok(((hi as u128) << 64) | lo as u128)
+    }
+
+    pub fn get_next_bits(&mut self, bits: usize) -> Result<Vec<u8>> {
+        if bits > self.remaining_bits() {
+            fail!(ExceptionCode::CellUnderflow)
+        }
+        let mut vec = Vec::with_capacity(bits.div_ceil(8));
+        let mut offset = 0;
+        while offset < bits {
+            let index = self.data_window.start + offset;
+            let chunk = cmp::min(64, bits - offset);
+            let word = load_word(self.cell.data_range(index, chunk), index);
+            let word = if chunk == 64 { word } else { word & !(u64::MAX >> chunk) };
+            vec.extend_from_slice(&word.to_be_bytes()[..chunk.div_ceil(8)]);
+            offset += chunk;
+        }
+        self.move_by(bits)?;
+        Ok(vec)
+    }
+
+    pub fn get_next_bytes(&mut self, bytes: usize) -> Result<Vec<u8>> {
+        match bytes.checked_mul(8) {
+            Some(bits) => self.get_next_bits(bits),
+            None => fail!(ExceptionCode::CellUnderflow)
+        }
+    }
+
+    pub fn get_next_hash(&mut self) -> Result<UInt256> {
+        let hash: [u8; 32] = self.get_next_bits(256)?.try_into().unwrap();
+        Ok(UInt256::from(hash))
+    }
+
+    /// Returns Cell from references if present and next bit in slice is one
+    pub fn get_next_dictionary(&mut self) -> Result<Option<Cell>> {
+        if self.get_next_bit()? {
+            Ok(Some(self.checked_drain_reference()?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Returns view of next `size` bits without references
+    pub fn get_next_slice(&mut self, size: usize) -> Result<SliceRef<'a>> {
+        if size > self.remaining_bits() {
+            fail!(ExceptionCode::CellUnderflow)
+        }
+        let start = self.data_window.start;
+        self.data_window.start += size;
+        Ok(SliceRef {
+            cell: self.cell,
+            data_window: start..start + size,
+            references_window: 0..0,
+        })
+    }
+}
+
+impl SliceData {
+    /// Returns borrowed view of the slice
+    pub fn as_slice_ref(&self) -> SliceRef<'_> {
+        SliceRef {
+            cell: &self.cell,
+            data_window: self.data_window.clone(),
+            references_window: self.references_window.clone(),
+        }
+    }
+}
+
+impl<'a> From<SliceRef<'a>> for SliceData {
+    fn from(slice: SliceRef<'a>) -> Self {
+        slice.to_slice_data()
+    }
+}
+
+impl fmt::Debug for SliceRef<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self.to_slice_data())
+    }
+}
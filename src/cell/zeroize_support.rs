@@ -0,0 +1,42 @@
+/*
+* Copyright 2018-2020 TON DEV SOLUTIONS LTD.
+*
+* Licensed under the SOFTWARE EVALUATION License (the "License"); you may not use
+* this file except in compliance with the License.
+*
+* Unless required by applicable law or agreed to in writing, software
+* distributed under the License is distributed on an "AS IS" BASIS,
+* WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+* See the License for the specific TON DEV software governing permissions and
+* limitations under the License.
+*/
+
+//! Wiping of cells containing secrets. Wrap cell, slice or builder into `zeroize::Zeroizing`
+//! to wipe it on drop. Only cells owned by the wiped value alone are wiped, shared ones
+//! and cells read from in-memory BOC (their data is in BOC buffer) are left as is.
+
+use alloc::{sync::Arc, vec};
+
+use zeroize::Zeroize;
+
+use crate::cell::{Cell, SliceData};
+
+/// Wipes data of unshared cells of the tree, cell becomes empty one
+impl Zeroize for Cell {
+    fn zeroize(&mut self) {
+        let mut stack = vec![core::mem::take(self)];
+        while let Some(mut cell) = stack.pop() {
+            if let Some(cell) = Arc::get_mut(&mut cell.0) {
+                cell.wipe_data();
+                stack.extend(cell.take_references());
+            }
+        }
+    }
+}
+
+/// Wipes cell of the slice as `Cell` does, slice becomes empty one
+impl Zeroize for SliceData {
+    fn zeroize(&mut self) {
+        core::mem::take(self).cell.zeroize();
+    }
+}
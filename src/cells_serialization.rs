@@ -12,30 +12,49 @@
 */
 
 
-use std::{
-    io::{Read, Write, Seek, SeekFrom},
-    sync::Arc, ops::Deref,
-};
+use alloc::{boxed::Box, sync::Arc, vec, vec::Vec};
+use core::ops::Deref;
 
-use crc::{Crc, CRC_32_ISCSI};
 use smallvec::{smallvec, SmallVec};
-use rustc_hash::{FxHashMap, FxHashSet};
-
-const CASTAGNOLI: Crc<u32> = Crc::<u32>::new(&CRC_32_ISCSI);
 
 use crate::{
-    cell::{self, Cell, DataCell, SHA256_SIZE, DEPTH_SIZE, MAX_DATA_BYTES, MAX_SAFE_DEPTH},
-    ByteOrderRead, UInt256, Result, fail, error, MAX_REFERENCES_COUNT, full_len,
+    cell::{self, Cell, CellType, DataCell, SHA256_SIZE, DEPTH_SIZE, MAX_DATA_BYTES, MAX_SAFE_DEPTH},
+    hashing::{crc32c, Crc32c, Sha256},
+    io::{Cursor, Read, Seek, SeekFrom, Write},
+    ByteOrderRead, FxHashMap, FxHashSet, UInt256, Result, fail, error, MAX_REFERENCES_COUNT, full_len,
+    failpoints::{self, FailPoint},
+    merkle::MerkleProof,
+    metrics::{self, MetricsEvent},
+    quota,
+    tlb::Deserializable,
 };
+#[cfg(feature = "std")]
+use crate::cell::LruCellCache;
 
 pub const ROOT_COUNT_SOFT_LIMIT: usize = 1 << 16;
 pub const CELL_COUNT_SOFT_LIMIT: usize = 1 << 16;
 
 const BOC_INDEXED_TAG: u32 = 0x68ff65f3;
 const BOC_INDEXED_CRC32_TAG: u32 = 0xacc3a728;
-const BOC_GENERIC_TAG: u32 = 0xb5ee9c72;
+pub(crate) const BOC_GENERIC_TAG: u32 = 0xb5ee9c72;
+
+pub(crate) const MAX_ROOTS_COUNT: usize = 1024;
+
+// Phases of BOC reading and writing are logged with feature `tracing`: spans measure time
+// of phases, events give byte offsets and cell indexes. Without the feature nothing is compiled.
+macro_rules! boc_span {
+    ($guard:ident, $($args:tt)*) => {
+        #[cfg(feature = "tracing")]
+        let $guard = tracing::debug_span!($($args)*).entered();
+    };
+}
 
-const MAX_ROOTS_COUNT: usize = 1024;
+macro_rules! boc_event {
+    ($level:ident, $($args:tt)*) => {
+        #[cfg(feature = "tracing")]
+        tracing::$level!($($args)*);
+    };
+}
 
 #[derive(PartialEq, Eq, Debug, Clone)]
 pub enum BocSerialiseMode {
@@ -118,7 +137,7 @@ pub struct BagOfCells<S: OrderedCellsStorage> {
 
 impl BagOfCells<SimpleOrderedCellsStorage> {
     pub fn with_root(root_cell: &Cell) -> Self {
-        Self::with_roots_and_absent(std::slice::from_ref(root_cell), &[])
+        Self::with_roots_and_absent(core::slice::from_ref(root_cell), &[])
     }
 
     pub fn with_roots(root_cells: &[Cell]) -> Self {
@@ -259,6 +278,20 @@ impl<S: OrderedCellsStorage> BagOfCells<S> {
             None)
     }
 
+    /// Writes BOC and returns SHA-256 of written bytes
+    pub fn write_to_hashed<T: Write>(self, dest: &mut T, include_index: bool) -> Result<UInt256> {
+        let mut dest = Sha256Writer::new(dest);
+        self.write_to(&mut dest, include_index)?;
+        Ok(dest.finish().1)
+    }
+
+    /// SHA-256 of BOC file calculated without keeping its bytes
+    pub fn file_hash(self, include_index: bool) -> Result<UInt256> {
+        let mut dest = Sha256Writer::new(NullWriter);
+        self.write_to(&mut dest, include_index)?;
+        Ok(dest.finish().1)
+    }
+
     pub fn write_to_ex<T: Write>(
         self,
         dest: &mut T,
@@ -277,18 +310,20 @@ impl<S: OrderedCellsStorage> BagOfCells<S> {
         custom_offset_size: Option<usize>,
         abort: &dyn Fn() -> bool,
     ) -> Result<()> {
+        failpoints::inject(FailPoint::BocWrite)?;
+        boc_span!(_span, "boc_write", cells = self.total_cells, roots = self.roots_count());
         let mut dest = IoCrcFilter::new(dest);
 
         let bytes_total_cells = number_of_bytes_to_fit(self.total_cells);
         let ref_size = custom_ref_size.map_or(bytes_total_cells, |crs| {
             debug_assert!(crs >= bytes_total_cells);
-            std::cmp::max(crs, bytes_total_cells)
+            core::cmp::max(crs, bytes_total_cells)
         });
         let total_cells_size = self.total_data_size + self.total_references * ref_size;
         let bytes_total_size = number_of_bytes_to_fit(total_cells_size);
         let offset_size = custom_offset_size.map_or(bytes_total_size, |cos| {
             debug_assert!(cos >= bytes_total_size);
-            std::cmp::max(cos, bytes_total_size)
+            core::cmp::max(cos, bytes_total_size)
         });
 
         debug_assert!(ref_size <= 4);
@@ -353,6 +388,7 @@ impl<S: OrderedCellsStorage> BagOfCells<S> {
         dest.write_all(&(self.roots_count() as u64).to_be_bytes()[(8 - ref_size)..8])?;
         dest.write_all(&(self.absent_count as u64).to_be_bytes()[(8 - ref_size)..8])?;
         dest.write_all(&(total_cells_size as u64).to_be_bytes()[(8 - offset_size)..8])?;
+        boc_event!(debug, ref_size, offset_size, total_cells_size, include_index, include_crc, "BOC header written");
 
         // Root list
         if include_root_list {
@@ -382,11 +418,13 @@ impl<S: OrderedCellsStorage> BagOfCells<S> {
         }
 
         // Cells
+        boc_span!(_cells_span, "boc_write_cells", offset = dest.bytes_written);
         for cell_rev_index in (0..self.total_cells).rev() {
             check_abort(abort)?;
             let cell = &self.cells.get_cell_by_index(cell_rev_index as u32)?;
-            dest.write_all(cell.raw_data()?)?;
             let cell_index = self.total_cells - 1 - cell_rev_index;
+            boc_event!(trace, cell_index, offset = dest.bytes_written, "cell written");
+            dest.write_all(cell.raw_data()?)?;
             for i in 0..cell.references_count() {
                 let child_hash = cell.reference_repr_hash(i).unwrap();
                 let child_index = self.total_cells - 1 -
@@ -395,11 +433,16 @@ impl<S: OrderedCellsStorage> BagOfCells<S> {
                 dest.write_all(&(child_index as u64).to_be_bytes()[(8 - ref_size)..8])?;
             }
         }
+        #[cfg(feature = "tracing")]
+        drop(_cells_span);
 
+        let written = dest.bytes_written + if include_crc { 4 } else { 0 };
         if include_crc {
             let (dest, crc) = dest.sum32();
             dest.write_all(&crc.to_le_bytes())?;
         }
+        metrics::record(MetricsEvent::BocBytesWritten, written as u64);
+        boc_event!(debug, written, "BOC written");
 
         self.cells.cleanup()?;
 
@@ -459,7 +502,7 @@ impl<S: OrderedCellsStorage> BagOfCells<S> {
         } else {
             let bits = cell.bit_length();
             *total_data_size += 2 + (bits / 8);
-            if bits % 8 != 0 {
+            if !bits.is_multiple_of(8) {
                 *total_data_size += 1;
             }
             if cell.store_hashes() {
@@ -470,7 +513,7 @@ impl<S: OrderedCellsStorage> BagOfCells<S> {
     }
 }
 
-fn check_abort(abort: &dyn Fn() -> bool) -> Result<()> {
+pub(crate) fn check_abort(abort: &dyn Fn() -> bool) -> Result<()> {
     if abort() {
         fail!("Operation was aborted");
     }
@@ -513,6 +556,111 @@ impl BocDeserializeResult {
     }
 }
 
+/// Roots of BOC with layout it was read with, written back with the same flags and sizes
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Boc {
+    roots: Vec<Cell>,
+    mode: BocSerialiseMode,
+    ref_size: Option<usize>,
+    offset_size: Option<usize>,
+}
+
+impl Boc {
+    /// Generic BOC without index and CRC, sizes are minimal
+    pub fn with_roots(roots: Vec<Cell>) -> Self {
+        Self::with_mode(roots, BocSerialiseMode::Generic { index: false, crc: false, cache_bits: false, flags: 0 })
+    }
+
+    pub fn with_mode(roots: Vec<Cell>, mode: BocSerialiseMode) -> Self {
+        Self { roots, mode, ref_size: None, offset_size: None }
+    }
+
+    pub fn read(src: &[u8]) -> Result<Self> {
+        Ok(BocDeserializer::new().deserialize(&mut &src[..])?.into())
+    }
+
+    pub fn read_inmem(src: Arc<Vec<u8>>) -> Result<Self> {
+        Ok(BocDeserializer::new().deserialize_inmem(src)?.into())
+    }
+
+    pub fn roots(&self) -> &[Cell] {
+        &self.roots
+    }
+
+    pub fn roots_count(&self) -> usize {
+        self.roots.len()
+    }
+
+    pub fn root(&self, index: usize) -> Option<&Cell> {
+        self.roots.get(index)
+    }
+
+    pub fn root_by_hash(&self, hash: &UInt256) -> Option<&Cell> {
+        self.roots.iter().find(|root| root.repr_hash() == *hash)
+    }
+
+    /// Fails if BOC has not exactly one root
+    pub fn single_root(&self) -> Result<&Cell> {
+        match self.roots.as_slice() {
+            [root] => Ok(root),
+            roots => fail!("BOC has {} roots instead of one", roots.len())
+        }
+    }
+
+    pub fn into_roots(self) -> Vec<Cell> {
+        self.roots
+    }
+
+    pub fn mode(&self) -> &BocSerialiseMode {
+        &self.mode
+    }
+
+    pub fn has_index(&self) -> bool {
+        match self.mode {
+            BocSerialiseMode::Indexed | BocSerialiseMode::IndexedCrc => true,
+            BocSerialiseMode::Generic { index, .. } => index,
+        }
+    }
+
+    pub fn has_crc(&self) -> bool {
+        match self.mode {
+            BocSerialiseMode::Indexed => false,
+            BocSerialiseMode::IndexedCrc => true,
+            BocSerialiseMode::Generic { crc, .. } => crc,
+        }
+    }
+
+    /// Size of references and offsets BOC was read with
+    pub fn ref_size(&self) -> Option<usize> {
+        self.ref_size
+    }
+
+    pub fn offset_size(&self) -> Option<usize> {
+        self.offset_size
+    }
+
+    pub fn write_to<T: Write>(&self, dest: &mut T) -> Result<()> {
+        BagOfCells::with_roots(&self.roots).write_to_ex(dest, self.mode.clone(), self.ref_size, self.offset_size)
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let mut data = Vec::new();
+        self.write_to(&mut data)?;
+        Ok(data)
+    }
+}
+
+impl From<BocDeserializeResult> for Boc {
+    fn from(result: BocDeserializeResult) -> Self {
+        Self {
+            roots: result.roots,
+            mode: result.header.mode,
+            ref_size: Some(result.header.ref_size),
+            offset_size: Some(result.header.offset_size),
+        }
+    }
+}
+
 pub trait IndexedCellsStorage {
     fn insert(&mut self, index: u32, cell: RawCell) -> Result<()>;
     fn remove(&mut self, index: u32) -> Result<RawCell>;
@@ -559,6 +707,11 @@ pub fn deserialize_tree_of_cells_inmem(src: Arc<Vec<u8>>) -> Result<Cell> {
     BocDeserializer::new().deserialize_inmem(src)?.withdraw_one_root()
 }
 
+/// Reads single root BOC taking ownership of the bytes (as they come from wasm-bindgen)
+pub fn deserialize_tree_of_cells_vec(src: Vec<u8>) -> Result<Cell> {
+    deserialize_tree_of_cells_inmem(Arc::new(src))
+}
+
 pub fn serialize_tree_of_cells<T: Write>(cell: &Cell, dst: &mut T) -> Result<()> {
     BagOfCells::with_root(cell).write_to(dst, false)
 }
@@ -570,12 +723,40 @@ pub fn serialize_toc(cell: &Cell) -> Result<Vec<u8>> {
     Ok(dst)
 }
 
+/// SHA-256 of BOC of cell, the same as hash of `serialize_toc` result
+pub fn boc_file_hash(cell: &Cell) -> Result<UInt256> {
+    BagOfCells::with_root(cell).file_hash(false)
+}
+
 // Absent cells is deserialized into cell with hash. Caller have to know about the cells and process it by itself.
 // Returns vector with root cells
 pub fn deserialize_cells_tree(src: &mut &[u8]) -> Result<Vec<Cell>> {
     Ok(BocDeserializer::new().deserialize(src)?.roots)
 }
 
+/// Reads all roots of BOC taking ownership of the bytes (as they come from wasm-bindgen)
+pub fn deserialize_cells_tree_vec(src: Vec<u8>) -> Result<Vec<Cell>> {
+    Ok(BocDeserializer::new().deserialize_inmem(Arc::new(src))?.roots)
+}
+
+/// Reads single root BOC from `Bytes`, see `BocDeserializer::deserialize_bytes`
+#[cfg(feature = "bytes")]
+pub fn deserialize_tree_of_cells_bytes(src: bytes::Bytes) -> Result<Cell> {
+    BocDeserializer::new().deserialize_bytes(src)?.withdraw_one_root()
+}
+
+/// Reads all roots of BOC from `Bytes`, see `BocDeserializer::deserialize_bytes`
+#[cfg(feature = "bytes")]
+pub fn deserialize_cells_tree_bytes(src: bytes::Bytes) -> Result<Vec<Cell>> {
+    Ok(BocDeserializer::new().deserialize_bytes(src)?.roots)
+}
+
+/// Writes BOC with single root into `Bytes` without copying of written data
+#[cfg(feature = "bytes")]
+pub fn serialize_toc_bytes(cell: &Cell) -> Result<bytes::Bytes> {
+    Ok(serialize_toc(cell)?.into())
+}
+
 pub fn deserialize_cells_tree_ex(
     src: &mut &[u8]
 ) -> Result<(Vec<Cell>, BocSerialiseMode, usize, usize)> {
@@ -605,20 +786,38 @@ pub fn deserialize_cells_tree_inmem_with_abort(
     Ok((r.roots, r.header.mode, r.header.ref_size, r.header.offset_size))
 }
 
+pub type CellLookup<'a> = &'a dyn Fn(&UInt256) -> Option<Cell>;
+
 pub struct BocDeserializer<'a> {
     abort: &'a dyn Fn() -> bool,
-    indexed_cells: Box<dyn IndexedCellsStorage>,
+    indexed_cells: Option<Box<dyn IndexedCellsStorage>>,
     done_cells: Box<dyn DoneCellsStorage>,
     max_depth: u16,
+    use_arena: bool,
+    cell_lookup: Option<CellLookup<'a>>,
+    #[cfg(feature = "std")]
+    cell_cache: Option<&'a LruCellCache>,
+    threads: usize,
+    unchecked: bool,
+    lenient: bool,
+    expected_proof: Option<UInt256>,
 }
 
 impl<'a> Default for BocDeserializer<'a> {
     fn default() -> Self {
         Self {
             abort: &|| false,
-            indexed_cells: Box::<FxHashMap<u32, RawCell>>::default(),
+            indexed_cells: None,
             done_cells: Box::<FxHashMap<u32, Cell>>::default(),
             max_depth: MAX_SAFE_DEPTH,
+            use_arena: false,
+            cell_lookup: None,
+            #[cfg(feature = "std")]
+            cell_cache: None,
+            threads: 1,
+            unchecked: false,
+            lenient: false,
+            expected_proof: None,
         }
     }
 }
@@ -627,7 +826,7 @@ impl<'a> BocDeserializer<'a> {
     pub fn new() -> Self { Self::default() }
 
     pub fn set_indexed_cells_storage(mut self, ics: Box<dyn IndexedCellsStorage>) -> Self {
-        self.indexed_cells = ics;
+        self.indexed_cells = Some(ics);
         self
     }
 
@@ -646,8 +845,312 @@ impl<'a> BocDeserializer<'a> {
         self
     }
 
+    /// Read all cells' data into one shared buffer instead of separate allocation per cell.
+    /// The buffer is freed when the last of deserialized cells is dropped, so any single cell
+    /// kept alive keeps data of the whole BOC in memory: copy the cells which outlive the rest
+    /// of the tree (e.g. with `BuilderData::from_cell`) or don't use arena for them.
+    /// Only data is shared, cells themselves (hashes, depths, references) are still
+    /// allocated one by one. Indexed cells storage is not used in this mode.
+    pub fn set_use_arena(mut self, use_arena: bool) -> Self {
+        self.use_arena = use_arena;
+        self
+    }
+
+    /// Set lookup for cells already known by caller. Found cells are used instead of
+    /// deserialized ones. If cell's hashes are stored in BOC the cell is looked up before
+    /// construction, so it is neither constructed nor hashed. Stored hashes are not trusted:
+    /// found cell must have the same data and references as the cell in BOC
+    /// (it is not checked by `deserialize_unchecked`).
+    pub fn set_cell_lookup(mut self, lookup: CellLookup<'a>) -> Self {
+        self.cell_lookup = Some(lookup);
+        self
+    }
+
+    /// Construct cells and verify their hashes using several threads. Cells of the same
+    /// height in the tree don't depend on each other, so they are processed in parallel.
+    /// In this mode cells' data is read into one shared buffer like with `set_use_arena`
+    /// and is kept in memory while any of deserialized cells is alive.
+    pub fn set_threads(mut self, threads: usize) -> Self {
+        self.threads = threads;
+        self
+    }
+
+    /// Accept BOCs of other implementations with benign deviations: non-zero flags,
+    /// cache bits without index and cells referencing cells with smaller indexes.
+    /// Such BOC is rewritten into canonical form first, then it is read as usual
+    /// with all hashes checked. Index of source BOC is not used.
+    pub fn set_lenient(mut self, lenient: bool) -> Self {
+        self.lenient = lenient;
+        self
+    }
+
+    /// Expect BOC with single root which is merkle proof of tree with `hash`. Root cell is
+    /// checked as soon as it is read, so BOC with wrong proof is rejected before construction
+    /// of other cells. Proven tree is checked against the hash after construction.
+    pub fn set_expected_proof(mut self, hash: UInt256) -> Self {
+        self.expected_proof = Some(hash);
+        self
+    }
+
+    // index of root cell and expected hash of proven tree
+    fn proof_root(&self, header: &BocHeader) -> Result<Option<(u32, UInt256)>> {
+        let expected = match &self.expected_proof {
+            Some(expected) => expected,
+            None => return Ok(None)
+        };
+        if header.roots_count != 1 {
+            fail!("BOC with merkle proof has {} roots instead of one", header.roots_count)
+        }
+        let root_index = match header.magic {
+            BOC_GENERIC_TAG => header.roots_indexes[0],
+            _ => 0
+        };
+        if root_index as usize >= header.cells_count {
+            fail!("root index {} is out of range", root_index)
+        }
+        Ok(Some((root_index, *expected)))
+    }
+
+    // both hash declared by root and hash of constructed proven tree are checked,
+    // the first one is not verified against the tree by unchecked reading
+    fn check_proof(&self, roots: &[Cell]) -> Result<()> {
+        if let (Some(expected), [root]) = (&self.expected_proof, roots) {
+            if root.cell_type() != CellType::MerkleProof {
+                fail!("root cell of BOC is {} instead of merkle proof", root.cell_type())
+            }
+            let proof = MerkleProof::construct_from_cell(root.clone())?;
+            if proof.hash != *expected || proof.virtual_root().repr_hash() != *expected {
+                fail!("BOC contains merkle proof of other tree than expected {:x}", expected)
+            }
+        }
+        Ok(())
+    }
+
+    fn construct_cells_parallel(
+        &mut self,
+        buf: &Arc<Vec<u8>>,
+        layout: &CellsLayout,
+        max_depth: u16,
+    ) -> Result<()> {
+        const MIN_CELLS_PER_THREAD: usize = 64;
+
+        // cells created by workers are charged to quota of current thread
+        #[cfg(all(feature = "std", not(target_arch = "wasm32")))]
+        let quota = quota::InheritedQuota::current();
+
+        // group cells by height, leaves are in the first wave
+        let mut done = vec![None; layout.len()];
+        let mut heights = vec![0; layout.len()];
+        let mut waves: Vec<Vec<usize>> = Vec::new();
+        // found cells are checked when their children are constructed
+        let mut found = Vec::new();
+        for cell_index in (0..layout.len()).rev() {
+            check_abort(self.abort)?;
+            let data = &buf[layout.offset(cell_index)..];
+            if let Some(cell) = self.lookup_stored_cell(data) {
+                done[cell_index] = Some(cell);
+                found.push(cell_index);
+                continue;
+            }
+            let mut height = 0;
+            for r in layout.refs(cell_index, data) {
+                match heights.get(*r as usize) {
+                    Some(h) => height = core::cmp::max(height, *h),
+                    None => fail!("reference out of range, cells_count: {}, ref: {}", layout.len(), r)
+                }
+            }
+            heights[cell_index] = height + 1;
+            if waves.len() <= height {
+                waves.push(Vec::new());
+            }
+            waves[height].push(cell_index);
+        }
+
+        for wave in waves {
+            check_abort(self.abort)?;
+            let threads = core::cmp::min(self.threads, wave.len() / MIN_CELLS_PER_THREAD).max(1);
+            let chunk_size = wave.len().div_ceil(threads);
+            let done_ref = &done;
+            let check = !self.unchecked;
+            // cells of the wave are independent, so their hashes are calculated (or verified) at once
+            let construct = move |chunk: &[usize]| -> Result<Vec<Cell>> {
+                let parts = chunk.iter().map(|cell_index| {
+                    let offset = layout.offset(*cell_index);
+                    let mut refs = SmallVec::new();
+                    for raw_ref in layout.refs(*cell_index, &buf[offset..]) {
+                        match &done_ref[*raw_ref as usize] {
+                            Some(cell) => refs.push(cell.clone()),
+                            None => fail!("reference {} is not constructed", raw_ref)
+                        }
+                    }
+                    Ok((refs, offset))
+                }).collect::<Result<Vec<_>>>()?;
+                let cells = DataCell::with_external_data_batch(parts, buf, max_depth, check)?;
+                Ok(cells.into_iter().map(Cell::with_cell_impl).collect())
+            };
+            #[cfg(all(feature = "std", not(target_arch = "wasm32")))]
+            let results = if threads == 1 {
+                vec!(construct(&wave))
+            } else {
+                let quota = &quota;
+                std::thread::scope(|scope| {
+                    let handles = wave.chunks(chunk_size)
+                        .map(|chunk| scope.spawn(move || {
+                            let _quota = quota.enter();
+                            construct(chunk)
+                        }))
+                        .collect::<Vec<_>>();
+                    handles.into_iter()
+                        .map(|handle| handle.join().unwrap_or_else(|_| Err(error!("cell construction thread panicked"))))
+                        .collect::<Vec<_>>()
+                })
+            };
+            // without threads (no std or wasm) all chunks are processed by current thread
+            #[cfg(any(not(feature = "std"), target_arch = "wasm32"))]
+            let results = wave.chunks(chunk_size).map(construct).collect::<Vec<_>>();
+            for (chunk, result) in wave.chunks(chunk_size).zip(results) {
+                for (cell_index, cell) in chunk.iter().zip(result?) {
+                    done[*cell_index] = Some(self.reuse_cell(cell));
+                }
+            }
+        }
+
+        for cell_index in found {
+            let data = &buf[layout.offset(cell_index)..];
+            let mut refs = SmallVec::<[Cell; 4]>::new();
+            for raw_ref in layout.refs(cell_index, data) {
+                match done.get(*raw_ref as usize) {
+                    Some(Some(cell)) => refs.push(cell.clone()),
+                    _ => fail!("reference {} is not constructed", raw_ref)
+                }
+            }
+            if let Some(cell) = &done[cell_index] {
+                self.check_stored_cell(cell, data, &refs)?;
+            }
+        }
+
+        for (cell_index, cell) in done.into_iter().enumerate() {
+            match cell {
+                Some(cell) => self.done_cells.insert(cell_index as u32, cell)?,
+                None => fail!("cell {} is not constructed", cell_index)
+            }
+        }
+        Ok(())
+    }
+
+    /// Set cache of cells. Cached cells are used instead of deserialized ones (like with
+    /// `set_cell_lookup`) and all deserialized cells are put to the cache.
+    #[cfg(feature = "std")]
+    pub fn set_cell_cache(mut self, cache: &'a LruCellCache) -> Self {
+        self.cell_cache = Some(cache);
+        self
+    }
+
+    fn raw_data_cell(&self, references: SmallVec<[Cell; 4]>, data: Vec<u8>) -> Result<DataCell> {
+        if self.unchecked {
+            DataCell::with_raw_data_unchecked(references, data, self.max_depth)
+        } else {
+            DataCell::with_raw_data_and_max_depth(references, data, self.max_depth)
+        }
+    }
+
+    #[cfg(feature = "std")]
+    fn has_known_cells(&self) -> bool {
+        self.cell_lookup.is_some() || self.cell_cache.is_some()
+    }
+
+    #[cfg(not(feature = "std"))]
+    fn has_known_cells(&self) -> bool {
+        self.cell_lookup.is_some()
+    }
+
+    #[cfg(feature = "std")]
+    fn cache_cell(&self, cell: &Cell) {
+        if let Some(cache) = self.cell_cache {
+            cache.insert(cell.clone());
+        }
+    }
+
+    #[cfg(not(feature = "std"))]
+    fn cache_cell(&self, _cell: &Cell) {}
+
+    fn find_cell(&self, hash: &UInt256) -> Option<Cell> {
+        #[cfg(feature = "std")]
+        if let Some(cell) = self.cell_cache.and_then(|cache| cache.get(hash)) {
+            return Some(cell)
+        }
+        let cell = (self.cell_lookup?)(hash)?;
+        self.cache_cell(&cell);
+        Some(cell)
+    }
+
+    fn lookup_stored_cell(&self, raw_data: &[u8]) -> Option<Cell> {
+        if !self.has_known_cells() {
+            return None
+        }
+        if cell::absent(raw_data) || !cell::store_hashes(raw_data) {
+            return None
+        }
+        let repr_index = cell::hashes_count(raw_data) - 1;
+        self.find_cell(&UInt256::from(cell::hash(raw_data, repr_index)))
+    }
+
+    // Stored hashes of BOC are not trusted unless reading is unchecked, so cell found by them
+    // must have the same descriptors, data and references as the cell in BOC
+    fn check_stored_cell(&self, cell: &Cell, raw_data: &[u8], references: &[Cell]) -> Result<()> {
+        if self.unchecked {
+            return Ok(())
+        }
+        let same = raw_data[0] & !cell::HASHES_D1_FLAG
+                == cell::calc_d1(cell.level_mask(), false, cell.cell_type(), cell.references_count())
+            && raw_data[1] == cell::calc_d2(cell.bit_length())
+            && cell.data() == cell::cell_data(raw_data)
+            && cell.references_count() == references.len()
+            && references.iter().enumerate().all(|(index, child)| {
+                matches!(cell.reference_repr_hash(index), Ok(hash) if hash == child.repr_hash())
+            });
+        if !same {
+            fail!("cell {:x} found by hash stored in BOC differs from the cell in BOC", cell.repr_hash())
+        }
+        Ok(())
+    }
+
+    fn reuse_cell(&self, cell: Cell) -> Cell {
+        if !self.has_known_cells() {
+            return cell
+        }
+        match self.find_cell(&cell.repr_hash()) {
+            Some(cell) => cell,
+            None => {
+                self.cache_cell(&cell);
+                cell
+            }
+        }
+    }
+
+    /// Deserializes trusted data (e.g. previously verified and saved by caller) skipping
+    /// checks of exotic cells, level masks and stored hashes. CRC is still checked.
+    pub fn deserialize_unchecked(mut self, src: &mut &[u8]) -> Result<BocDeserializeResult> {
+        self.unchecked = true;
+        self.deserialize(src)
+    }
+
+    /// Same as `deserialize_unchecked` for in-memory data
+    pub fn deserialize_inmem_unchecked(mut self, data: Arc<Vec<u8>>) -> Result<BocDeserializeResult> {
+        self.unchecked = true;
+        self.deserialize_inmem(data)
+    }
+
     pub fn deserialize(mut self, src: &mut &[u8]) -> Result<BocDeserializeResult> {
+        failpoints::inject(FailPoint::BocRead)?;
+        if self.lenient {
+            let (data, len) = canonicalize_boc(src)?;
+            *src = &src[len..];
+            self.lenient = false;
+            return self.deserialize_inmem(Arc::new(data))
+        }
         let full_len = src.len() as u64;
+        boc_span!(_span, "boc_read", len = full_len);
 
         let mut src = IoCrcFilter::new(src);
 
@@ -655,6 +1158,12 @@ impl<'a> BocDeserializer<'a> {
         src.has_crc = header.has_crc;
 
         let header_len = full_len - src.remaining() as u64;
+        quota::check(header.cells_count, header.tot_cells_size)?;
+        boc_event!(
+            debug, header_len, cells = header.cells_count, roots = header.roots_count,
+            cells_size = header.tot_cells_size, index = header.index_included, crc = header.has_crc,
+            "BOC header read"
+        );
 
         check_abort(self.abort)?;
 
@@ -666,28 +1175,107 @@ impl<'a> BocDeserializer<'a> {
             src.read_exact(&mut raw_index)?;
         }
 
-        // Read cells
+        let proof_root = self.proof_root(&header)?;
+
+        // Read cells. Unless custom indexed cells storage is used, cells are read
+        // into one buffer, their offsets and references are kept in flat arrays
+        let use_layout = self.indexed_cells.is_none() || self.use_arena || self.threads > 1;
+        let mut cells_data = Vec::new();
+        let mut layout = CellsLayout::default();
         let mut actual_data_size = src.remaining();
-        for cell_index in 0..header.cells_count {
-            check_abort(self.abort)?;
-            let raw_cell = read_raw_cell(&mut src, header.ref_size, cell_index, header.cells_count)?;
-            self.indexed_cells.insert(cell_index as u32, raw_cell)?;
+        boc_span!(_cells_span, "boc_read_cells", offset = full_len - actual_data_size as u64);
+        match self.indexed_cells.as_mut().filter(|_| !use_layout) {
+            Some(indexed_cells) => {
+                for cell_index in 0..header.cells_count {
+                    check_abort(self.abort)?;
+                    boc_event!(trace, cell_index, offset = full_len - src.remaining() as u64, "cell read");
+                    let raw_cell = read_raw_cell(&mut src, header.ref_size, cell_index, header.cells_count)?;
+                    match &proof_root {
+                        Some((index, hash)) if *index == cell_index as u32 => check_proof_root(&raw_cell.data, hash)?,
+                        _ => ()
+                    }
+                    indexed_cells.insert(cell_index as u32, raw_cell)?;
+                }
+            }
+            None => {
+                cells_data.reserve_exact(header.tot_cells_size);
+                layout = CellsLayout::with_capacity(header.cells_count);
+                for cell_index in 0..header.cells_count {
+                    check_abort(self.abort)?;
+                    boc_event!(trace, cell_index, offset = full_len - src.remaining() as u64, "cell read");
+                    let offset = cells_data.len();
+                    let refs = read_raw_cell_to(&mut src, header.ref_size, cell_index, header.cells_count, true, &mut cells_data)?;
+                    match &proof_root {
+                        Some((index, hash)) if *index == cell_index as u32 => check_proof_root(&cells_data[offset..], hash)?,
+                        _ => ()
+                    }
+                    layout.push(offset, refs);
+                }
+            }
         }
         actual_data_size -= src.remaining();
         if actual_data_size != header.tot_cells_size {
             fail!("actual data size disagrees with the size from header")
         }
+        #[cfg(feature = "tracing")]
+        drop(_cells_span);
 
         // Resolving references & constructing cells from leaves to roots
-        for cell_index in (0..header.cells_count).rev() {
-            check_abort(self.abort)?;
-            let raw_cell = self.indexed_cells.remove(cell_index as u32)?;
-            let mut refs = smallvec!();
-            for i in 0..cell::refs_count(&raw_cell.data) {
-                refs.push(self.done_cells.get(raw_cell.refs[i])?)
+        boc_span!(_construct_span, "boc_construct_cells", threads = self.threads, arena = self.use_arena);
+        let cells_data = Arc::new(cells_data);
+        if !use_layout {
+            for cell_index in (0..header.cells_count).rev() {
+                check_abort(self.abort)?;
+                let raw_cell = match self.indexed_cells.as_mut() {
+                    Some(indexed_cells) => indexed_cells.remove(cell_index as u32)?,
+                    None => fail!("indexed cells storage is not set")
+                };
+                let mut refs = smallvec!();
+                for i in 0..cell::refs_count(&raw_cell.data) {
+                    refs.push(self.done_cells.get(raw_cell.refs[i])?)
+                }
+                let cell = match self.lookup_stored_cell(&raw_cell.data) {
+                    Some(cell) => {
+                        self.check_stored_cell(&cell, &raw_cell.data, &refs)?;
+                        cell
+                    }
+                    None => {
+                        let cell = self.raw_data_cell(refs, raw_cell.data)?;
+                        self.reuse_cell(Cell::with_cell_impl(cell))
+                    }
+                };
+                self.done_cells.insert(cell_index as u32, cell)?;
+            }
+        } else if self.threads > 1 {
+            self.construct_cells_parallel(&cells_data, &layout, self.max_depth)?;
+        } else {
+            for cell_index in (0..header.cells_count).rev() {
+                check_abort(self.abort)?;
+                let offset = layout.offset(cell_index);
+                let data = &cells_data[offset..];
+                let mut refs = smallvec!();
+                for raw_ref in layout.refs(cell_index, data) {
+                    refs.push(self.done_cells.get(*raw_ref)?)
+                }
+                let cell = match self.lookup_stored_cell(data) {
+                    Some(cell) => {
+                        self.check_stored_cell(&cell, data, &refs)?;
+                        cell
+                    }
+                    None => {
+                        let cell = if self.use_arena {
+                            external_data_cell(refs, &cells_data, offset, self.max_depth, !self.unchecked)?
+                        } else {
+                            let len = cell::full_len(data);
+                            let mut buf = cell::allocate_cell_buffer(len);
+                            buf.extend_from_slice(&data[..len]);
+                            self.raw_data_cell(refs, buf)?
+                        };
+                        self.reuse_cell(Cell::with_cell_impl(cell))
+                    }
+                };
+                self.done_cells.insert(cell_index as u32, cell)?;
             }
-            let cell = DataCell::with_raw_data_and_max_depth(refs, raw_cell.data, self.max_depth)?;
-            self.done_cells.insert(cell_index as u32, Cell::with_cell_impl(cell))?;
         }
 
         let roots_indexes = if header.magic == BOC_GENERIC_TAG {
@@ -700,7 +1288,11 @@ impl<'a> BocDeserializer<'a> {
             check_abort(self.abort)?;
             roots.push(self.done_cells.get(*i)?);
         }
+        self.check_proof(&roots)?;
+        #[cfg(feature = "tracing")]
+        drop(_construct_span);
 
+        let read = full_len - src.remaining() as u64;
         if header.has_crc {
             let (src, crc) = src.sum32();
             let read_crc = src.read_le_u32()?;
@@ -708,18 +1300,45 @@ impl<'a> BocDeserializer<'a> {
                 fail!("crc not the same, values: {}, {}", read_crc, crc)
             }
         }
+        metrics::record(MetricsEvent::BocBytesRead, read + if header.has_crc { 4 } else { 0 });
+        boc_event!(debug, read, "BOC read");
 
         self.done_cells.cleanup()?;
 
-        self.indexed_cells.cleanup()?;
+        if let Some(indexed_cells) = self.indexed_cells.as_mut() {
+            indexed_cells.cleanup()?;
+        }
 
         Ok(BocDeserializeResult { roots, header })
     }
 
+    /// Reads BOC in memory like `deserialize_inmem`. Buffer is taken without copying
+    /// if `Bytes` is the only owner of the data allocated as `Vec`, otherwise it is copied.
+    #[cfg(feature = "bytes")]
+    pub fn deserialize_bytes(self, data: bytes::Bytes) -> Result<BocDeserializeResult> {
+        self.deserialize_inmem(Arc::new(data.into()))
+    }
+
     pub fn deserialize_inmem(mut self, data: Arc<Vec<u8>>) -> Result<BocDeserializeResult> {
-        let mut src = std::io::Cursor::new(data.deref());
+        failpoints::inject(FailPoint::BocRead)?;
+        if self.lenient {
+            let (canonical, len) = canonicalize_boc(&data)?;
+            if len != data.len() {
+                fail!("Actual boc length {} in not equal calculated one {}", data.len(), len)
+            }
+            self.lenient = false;
+            return self.deserialize_inmem(Arc::new(canonical))
+        }
+        boc_span!(_span, "boc_read_inmem", len = data.len());
+        let mut src = Cursor::new(data.deref());
 
         let header = deserialize_cells_tree_header(&mut src)?;
+        quota::check(header.cells_count, header.tot_cells_size)?;
+        boc_event!(
+            debug, header_len = src.position(), cells = header.cells_count, roots = header.roots_count,
+            cells_size = header.tot_cells_size, index = header.index_included, crc = header.has_crc,
+            "BOC header read"
+        );
 
         precheck_cells_tree_len(&header, src.position(), data.len() as u64, false)?;
 
@@ -727,6 +1346,7 @@ impl<'a> BocDeserializer<'a> {
         let mut index2 = vec!();
         let index = &data[src.position() as usize..];
         if !header.index_included {
+            boc_span!(_index_span, "boc_build_index", offset = src.position());
             index2 = Vec::with_capacity(header.cells_count);
             for _ in 0_usize..header.cells_count {
                 check_abort(self.abort)?;
@@ -739,14 +1359,12 @@ impl<'a> BocDeserializer<'a> {
 
         // Resolving references & constructing cells from leaves to roots
         let cells_start = src.position() as usize + header.cells_count * header.offset_size;
-        for cell_index in (0..header.cells_count).rev() {
-            check_abort(self.abort)?;
-
+        let cell_offset = |cell_index: usize| -> Result<usize> {
             let offset = if header.index_included {
                 let mut offset = cells_start;
                 if cell_index > 0 {
                     let o = (cell_index - 1) * header.offset_size;
-                    let mut o2 = std::io::Cursor::new(&index[o..o + header.offset_size])
+                    let mut o2 = Cursor::new(&index[o..o + header.offset_size])
                         .read_be_uint(header.offset_size)? as usize;
                     if header.has_cache_bits {
                         o2 >>= 1;
@@ -761,16 +1379,47 @@ impl<'a> BocDeserializer<'a> {
             if data.len() <= offset {
                 fail!("Invalid data: data too short or index is invalid");
             }
-            let mut src = std::io::Cursor::new(&data[offset..]);
-            let refs_indexes = read_refs_indexes(&mut src, header.ref_size, cell_index, header.cells_count)?;
-            let mut refs = SmallVec::with_capacity(refs_indexes.len());
-            for ref_cell_index in refs_indexes {
-                let child = self.done_cells.get(ref_cell_index)?;
-                refs.push(child.clone());
+            Ok(offset)
+        };
+        if let Some((root_index, hash)) = self.proof_root(&header)? {
+            check_proof_root(&data[cell_offset(root_index as usize)?..], &hash)?;
+        }
+        boc_span!(_construct_span, "boc_construct_cells", threads = self.threads, offset = cells_start);
+        if self.threads > 1 {
+            let mut layout = CellsLayout::with_capacity(header.cells_count);
+            for cell_index in 0..header.cells_count {
+                check_abort(self.abort)?;
+                let offset = cell_offset(cell_index)?;
+                let mut src = Cursor::new(&data[offset..]);
+                let refs_indexes = read_refs_indexes(&mut src, header.ref_size, cell_index, header.cells_count)?;
+                let mut refs = [0; 4];
+                refs.iter_mut().zip(refs_indexes).for_each(|(r, index)| *r = index);
+                layout.push(offset, refs);
             }
+            self.construct_cells_parallel(&data, &layout, 0)?;
+        } else {
+            for cell_index in (0..header.cells_count).rev() {
+                check_abort(self.abort)?;
+
+                let offset = cell_offset(cell_index)?;
+                boc_event!(trace, cell_index, offset, "cell constructed");
+                let mut src = Cursor::new(&data[offset..]);
+                let refs_indexes = read_refs_indexes(&mut src, header.ref_size, cell_index, header.cells_count)?;
+                let mut refs = SmallVec::with_capacity(refs_indexes.len());
+                for ref_cell_index in refs_indexes {
+                    let child = self.done_cells.get(ref_cell_index)?;
+                    refs.push(child.clone());
+                }
+                if let Some(cell) = self.lookup_stored_cell(&data[offset..]) {
+                    self.check_stored_cell(&cell, &data[offset..], &refs)?;
+                    self.done_cells.insert(cell_index as u32, cell)?;
+                    continue;
+                }
 
-            let cell = DataCell::with_external_data(refs, &data, offset)?;
-            self.done_cells.insert(cell_index as u32, Cell::with_cell_impl(cell))?;
+                let cell = external_data_cell(refs, &data, offset, 0, !self.unchecked)?;
+                let cell = self.reuse_cell(Cell::with_cell_impl(cell));
+                self.done_cells.insert(cell_index as u32, cell)?;
+            }
         }
 
         let mut roots = Vec::with_capacity(header.roots_count);
@@ -782,11 +1431,12 @@ impl<'a> BocDeserializer<'a> {
         } else {
             roots.push(self.done_cells.get(0)?);
         }
+        self.check_proof(&roots)?;
+        #[cfg(feature = "tracing")]
+        drop(_construct_span);
 
         if header.has_crc {
-            let mut hasher = CASTAGNOLI.digest();
-            hasher.update(&data[..data.len() - 4]);
-            let crc = hasher.finalize();
+            let crc = crc32c(&data[..data.len() - 4]);
             src.set_position(data.len() as u64 - 4);
             let read_crc = src.read_le_u32()?;
             if read_crc != crc {
@@ -796,6 +1446,9 @@ impl<'a> BocDeserializer<'a> {
 
         self.done_cells.cleanup()?;
 
+        metrics::record(MetricsEvent::BocBytesRead, data.len() as u64);
+        boc_event!(debug, read = data.len(), "BOC read");
+
         Ok(BocDeserializeResult {
             roots,
             header,
@@ -803,7 +1456,75 @@ impl<'a> BocDeserializer<'a> {
     }
 }
 
-fn deserialize_cells_tree_header<T>(src: &mut T) -> Result<BocHeader> where T: Read {
+// merkle proof cell: type + virtual hash + virtual depth
+fn check_proof_root(buf: &[u8], expected: &UInt256) -> Result<()> {
+    if buf.len() < 2 || buf.len() < cell::full_len(buf) {
+        fail!("Invalid data: root cell of merkle proof is truncated")
+    }
+    if cell::cell_type(buf) != CellType::MerkleProof {
+        fail!("root cell of BOC is {} instead of merkle proof", cell::cell_type(buf))
+    }
+    let data = cell::cell_data(buf);
+    if data.len() < 1 + SHA256_SIZE + DEPTH_SIZE || &data[1..1 + SHA256_SIZE] != expected.as_slice() {
+        fail!("BOC contains merkle proof of other tree than expected {:x}", expected)
+    }
+    Ok(())
+}
+
+/// Offsets of cells in common buffer and their references, stored in flat arrays
+#[derive(Default)]
+struct CellsLayout {
+    offsets: Vec<usize>,
+    refs: Vec<[u32; 4]>,
+}
+
+impl CellsLayout {
+    fn with_capacity(cells_count: usize) -> Self {
+        Self {
+            offsets: Vec::with_capacity(cells_count),
+            refs: Vec::with_capacity(cells_count),
+        }
+    }
+
+    fn push(&mut self, offset: usize, refs: [u32; 4]) {
+        self.offsets.push(offset);
+        self.refs.push(refs);
+    }
+
+    fn len(&self) -> usize {
+        self.offsets.len()
+    }
+
+    fn offset(&self, cell_index: usize) -> usize {
+        self.offsets[cell_index]
+    }
+
+    /// references of cell, `data` is cell's raw data
+    fn refs(&self, cell_index: usize, data: &[u8]) -> &[u32] {
+        let count = if cell::absent(data) { 0 } else { cell::refs_count(data) };
+        &self.refs[cell_index][..core::cmp::min(count, MAX_REFERENCES_COUNT)]
+    }
+}
+
+fn external_data_cell(
+    references: SmallVec<[Cell; 4]>,
+    buffer: &Arc<Vec<u8>>,
+    offset: usize,
+    max_depth: u16,
+    check: bool,
+) -> Result<DataCell> {
+    if check {
+        DataCell::with_external_data_and_max_depth(references, buffer, offset, max_depth)
+    } else {
+        DataCell::with_external_data_unchecked(references, buffer, offset, max_depth)
+    }
+}
+
+pub(crate) fn deserialize_cells_tree_header<T>(src: &mut T) -> Result<BocHeader> where T: Read {
+    read_cells_tree_header(src, false)
+}
+
+fn read_cells_tree_header<T>(src: &mut T, lenient: bool) -> Result<BocHeader> where T: Read {
     let magic = src.read_be_u32()?;
     let first_byte = src.read_byte()?;
 
@@ -830,7 +1551,7 @@ fn deserialize_cells_tree_header<T>(src: &mut T) -> Result<BocHeader> where T: R
             has_crc = first_byte & 0b0100_0000 != 0;
             has_cache_bits = first_byte & 0b0010_0000 != 0;
             let flags = (first_byte & 0b0001_1000) >> 3;
-            if flags != 0 {
+            if flags != 0 && !lenient {
                 fail!("non-zero flags field is not supported")
             }
             ref_size = (first_byte & 0b0000_0111) as usize;
@@ -838,13 +1559,13 @@ fn deserialize_cells_tree_header<T>(src: &mut T) -> Result<BocHeader> where T: R
                 index: index_included,
                 crc: has_crc,
                 cache_bits: has_cache_bits,
-                flags: 0,
+                flags,
             };
         }
         _ => fail!("unknown BOC_TAG: {}", magic)
     };
 
-    if has_cache_bits && !index_included {
+    if has_cache_bits && !index_included && !lenient {
         fail!("invalid header")
     }
 
@@ -928,10 +1649,14 @@ fn deserialize_cells_tree_header<T>(src: &mut T) -> Result<BocHeader> where T: R
     })
 }
 
-fn precheck_cells_tree_len(header: &BocHeader, header_len: u64, actual_len: u64, unbounded: bool) -> Result<()> {
-    // calculate boc len
+// full length of BOC with given header
+pub(crate) fn calc_boc_len(header: &BocHeader, header_len: u64) -> u64 {
     let index_size = header.index_included as u64 * ((header.cells_count * header.offset_size) as u64);
-    let len = header_len + index_size + header.tot_cells_size as u64 + header.has_crc as u64 * 4;
+    header_len + index_size + header.tot_cells_size as u64 + header.has_crc as u64 * 4
+}
+
+fn precheck_cells_tree_len(header: &BocHeader, header_len: u64, actual_len: u64, unbounded: bool) -> Result<()> {
+    let len = calc_boc_len(header, header_len);
     if unbounded {
         if actual_len < len {
             fail!("Actual boc length {} is smaller than calculated one {}", actual_len, len);
@@ -950,28 +1675,39 @@ fn skip_cell<T>(src: &mut T, ref_size: usize) -> Result<()> where T: Read + Seek
     Ok(())
 }
 
-fn read_raw_cell<T>(
+pub(crate) fn read_raw_cell<T>(
     src: &mut T,
     ref_size: usize,
     cell_index: usize,
     cells_count: usize,
 ) -> Result<RawCell> where T: Read {
+    let mut data = Vec::new();
+    let refs = read_raw_cell_to(src, ref_size, cell_index, cells_count, true, &mut data)?;
+    Ok(RawCell { data, refs })
+}
+
+/// Reads cell's raw data appending it to the end of `buf`. Returns indexes of cell's references
+/// If `sorted` references must have greater indexes than the cell
+fn read_raw_cell_to<T>(
+    src: &mut T,
+    ref_size: usize,
+    cell_index: usize,
+    cells_count: usize,
+    sorted: bool,
+    buf: &mut Vec<u8>,
+) -> Result<[u32; 4]> where T: Read {
     let mut refs = [0; 4];
-    let mut data;
     let mut d1d2 = [0_u8; 2];
     src.read_exact(&mut d1d2)?;
-    if cell::absent(&d1d2) {
-        // absent cells are depricated. We support it only for "node se".
-        // It contains only one description byte (constant) and hash.
-        data = vec!(0; 1 + SHA256_SIZE);
-        data[..2].copy_from_slice(&d1d2);
-        src.read_exact(&mut data[2..])?;
-    } else {
-        let data_len = cell::full_len(&d1d2);
-        data = vec!(0; data_len);
-        data[..2].copy_from_slice(&d1d2);
-        src.read_exact(&mut data[2..])?;
-
+    // absent cells are depricated. We support it only for "node se".
+    // It contains only one description byte (constant) and hash.
+    let data_len = cell::full_len(&d1d2);
+    let start = buf.len();
+    buf.resize(start + data_len, 0);
+    let data = &mut buf[start..];
+    data[..2].copy_from_slice(&d1d2);
+    src.read_exact(&mut data[2..])?;
+    if !cell::absent(&d1d2) {
         let tag_completed = d1d2[1] & 1 != 0;
         if tag_completed && data_len > 2 && (data[data_len - 1] & 0x7f == 0) {
             fail!("overly long tag-completed encoding")
@@ -982,7 +1718,7 @@ fn read_raw_cell<T>(
         }
         for reference in refs.iter_mut().take(refs_count) {
             let r = src.read_be_uint(ref_size)? as u32;
-            if r > cells_count as u32 || r <= cell_index as u32 {
+            if r > cells_count as u32 || (sorted && r <= cell_index as u32) || r == cell_index as u32 {
                 fail!("reference out of range, cells_count: {}, ref: {}, cell_index: {}", cells_count, r, cell_index)
             } else {
                 *reference = r;
@@ -990,7 +1726,107 @@ fn read_raw_cell<T>(
         }
     }
 
-    Ok(RawCell { data, refs })
+    Ok(refs)
+}
+
+/// Rewrites BOC into canonical form where references go to cells with greater indexes,
+/// flags are cleared and there is no index. Returns new BOC and length of source one.
+pub fn canonicalize_boc(data: &[u8]) -> Result<(Vec<u8>, usize)> {
+    let mut src = Cursor::new(data);
+    let header = read_cells_tree_header(&mut src, true)?;
+    let header_len = src.position() as usize;
+    precheck_cells_tree_len(&header, header_len as u64, data.len() as u64, true)?;
+    if header.index_included {
+        src.seek(SeekFrom::Current((header.cells_count * header.offset_size) as i64))?;
+    }
+    let cells_start = src.position() as usize;
+    let mut cells_data = Vec::with_capacity(header.tot_cells_size);
+    let mut layout = CellsLayout::with_capacity(header.cells_count);
+    for cell_index in 0..header.cells_count {
+        let offset = cells_data.len();
+        let refs = read_raw_cell_to(&mut src, header.ref_size, cell_index, header.cells_count, false, &mut cells_data)?;
+        layout.push(offset, refs);
+    }
+    let mut len = src.position() as usize;
+    if len - cells_start != header.tot_cells_size {
+        fail!("actual data size disagrees with the size from header")
+    }
+    if header.has_crc {
+        let crc = crc32c(&data[..len]);
+        let read_crc = src.read_le_u32()?;
+        if read_crc != crc {
+            fail!("crc not the same, values: {}, {}", read_crc, crc)
+        }
+        len += 4;
+    }
+
+    // depth-first order from roots, then the rest of cells
+    let roots = match header.magic {
+        BOC_GENERIC_TAG => header.roots_indexes.clone(),
+        _ => vec![0]
+    };
+    let refs_of = |index: usize| layout.refs(index, &cells_data[layout.offset(index)..]);
+    let mut state = vec![0_u8; header.cells_count]; // 0 - not visited, 1 - in stack, 2 - done
+    let mut post_order = Vec::with_capacity(header.cells_count);
+    let starts = roots.iter().map(|root| *root as usize).chain(0..header.cells_count).collect::<Vec<_>>();
+    for start in starts {
+        if state[start] != 0 {
+            continue
+        }
+        state[start] = 1;
+        let mut stack = vec![(start, 0)];
+        while let Some((index, next)) = stack.last_mut() {
+            let index = *index;
+            match refs_of(index).get(*next) {
+                Some(child) => {
+                    *next += 1;
+                    let child = *child as usize;
+                    if child >= header.cells_count {
+                        fail!("reference out of range, cells_count: {}, ref: {}, cell_index: {}", header.cells_count, child, index)
+                    }
+                    match state[child] {
+                        0 => {
+                            state[child] = 1;
+                            stack.push((child, 0));
+                        }
+                        1 => fail!("cells of BOC have cyclic references"),
+                        _ => ()
+                    }
+                }
+                None => {
+                    state[index] = 2;
+                    post_order.push(index);
+                    stack.pop();
+                }
+            }
+        }
+    }
+    post_order.reverse();
+    let mut new_indexes = vec![0_u64; header.cells_count];
+    for (new_index, index) in post_order.iter().enumerate() {
+        new_indexes[*index] = new_index as u64;
+    }
+
+    let ref_size = header.ref_size;
+    let offset_size = header.offset_size;
+    let mut result = Vec::with_capacity(len);
+    result.extend_from_slice(&BOC_GENERIC_TAG.to_be_bytes());
+    result.push(ref_size as u8);
+    result.push(offset_size as u8);
+    // cells, roots and absent counts are the same
+    result.extend_from_slice(&data[6..6 + 3 * ref_size]);
+    result.extend_from_slice(&(header.tot_cells_size as u64).to_be_bytes()[8 - offset_size..]);
+    for root in &roots {
+        result.extend_from_slice(&new_indexes[*root as usize].to_be_bytes()[8 - ref_size..]);
+    }
+    for index in post_order {
+        let cell_data = &cells_data[layout.offset(index)..];
+        result.extend_from_slice(&cell_data[..cell::full_len(cell_data)]);
+        for child in layout.refs(index, cell_data) {
+            result.extend_from_slice(&new_indexes[*child as usize].to_be_bytes()[8 - ref_size..]);
+        }
+    }
+    Ok((result, len))
 }
 
 fn read_refs_indexes<T>(
@@ -1024,7 +1860,7 @@ fn read_refs_indexes<T>(
     }
 }
 
-fn number_of_bytes_to_fit(l: usize) -> usize {
+pub(crate) fn number_of_bytes_to_fit(l: usize) -> usize {
     let mut n = 0;
     let mut l1 = l;
 
@@ -1036,21 +1872,21 @@ fn number_of_bytes_to_fit(l: usize) -> usize {
     n
 }
 
-static CRC: Crc<u32> = Crc::<u32>::new(&CRC_32_ISCSI);
-
 /// Filters given Write or Read object's write or read operations and calculates data's CRC
-struct IoCrcFilter<'a, T> {
+pub(crate) struct IoCrcFilter<'a, T> {
     io_object: &'a mut T,
-    hasher: crc::Digest<'static, u32>,
-    has_crc: bool,
+    hasher: Crc32c,
+    pub(crate) has_crc: bool,
+    bytes_written: usize,
 }
 
 impl<'a, T> IoCrcFilter<'a, T> {
     pub fn new(io_object: &'a mut T) -> Self {
         IoCrcFilter {
             io_object,
-            hasher: CRC.digest(),
+            hasher: Crc32c::new(),
             has_crc: true,
+            bytes_written: 0,
         }
     }
 
@@ -1066,31 +1902,87 @@ impl<'a> IoCrcFilter<'a, &'_ [u8]> {
 }
 
 impl<'a, T> Write for IoCrcFilter<'a, T> where T: Write {
-    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+    fn write(&mut self, buf: &[u8]) -> crate::io::Result<usize> {
         if self.has_crc {
             self.hasher.update(buf);
         }
-        self.io_object.write(buf)
+        let written = self.io_object.write(buf)?;
+        self.bytes_written += written;
+        Ok(written)
     }
 
-    fn flush(&mut self) -> std::io::Result<()> {
+    fn flush(&mut self) -> crate::io::Result<()> {
         self.io_object.flush()
     }
 
-    fn write_all(&mut self, buf: &[u8]) -> std::io::Result<()> {
+    fn write_all(&mut self, buf: &[u8]) -> crate::io::Result<()> {
         if self.has_crc {
             self.hasher.update(buf);
         }
-        self.io_object.write_all(buf)
+        self.io_object.write_all(buf)?;
+        self.bytes_written += buf.len();
+        Ok(())
+    }
+}
+
+/// Passes written data to inner writer and calculates its SHA-256
+pub struct Sha256Writer<T> {
+    inner: T,
+    hasher: Sha256,
+    bytes_written: usize,
+}
+
+impl<T> Sha256Writer<T> {
+    pub fn new(inner: T) -> Self {
+        Self {
+            inner,
+            hasher: Sha256::new(),
+            bytes_written: 0,
+        }
+    }
+
+    pub fn bytes_written(&self) -> usize {
+        self.bytes_written
+    }
+
+    /// Returns inner writer and hash of all written data
+    pub fn finish(self) -> (T, UInt256) {
+        (self.inner, UInt256::from(self.hasher.finalize()))
+    }
+}
+
+impl<T: Write> Write for Sha256Writer<T> {
+    fn write(&mut self, buf: &[u8]) -> crate::io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.hasher.update(&buf[..written]);
+        self.bytes_written += written;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> crate::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Discards written data
+struct NullWriter;
+
+impl Write for NullWriter {
+    fn write(&mut self, buf: &[u8]) -> crate::io::Result<usize> {
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> crate::io::Result<()> {
+        Ok(())
     }
 }
 
 impl<'a, T> Read for IoCrcFilter<'a, T> where T: Read {
-    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
-        let res = self.io_object.read(buf);
+    fn read(&mut self, buf: &mut [u8]) -> crate::io::Result<usize> {
+        let read = self.io_object.read(buf)?;
         if self.has_crc {
-            self.hasher.update(buf);
+            self.hasher.update(&buf[..read]);
         }
-        res
+        Ok(read)
     }
 }
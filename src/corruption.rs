@@ -0,0 +1,153 @@
+/*
+* Copyright 2018-2020 TON DEV SOLUTIONS LTD.
+*
+* Licensed under the SOFTWARE EVALUATION License (the "License"); you may not use
+* this file except in compliance with the License.
+*
+* Unless required by applicable law or agreed to in writing, software
+* distributed under the License is distributed on an "AS IS" BASIS,
+* WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+* See the License for the specific TON DEV software governing permissions and
+* limitations under the License.
+*/
+
+//! Corruption of serialized BOC shared by negative tests (feature `testing`)
+//! and fuzzing harnesses (feature `fuzz`).
+
+use alloc::vec::Vec;
+
+use smallvec::SmallVec;
+
+use crate::{
+    cell::{self, Cell, DEPTH_SIZE, HASHES_D1_FLAG, SHA256_SIZE},
+    cells_serialization::{deserialize_cells_tree_header, BagOfCells, BocSerialiseMode},
+    fail,
+    hashing::crc32c,
+    io::Cursor,
+    types::Result,
+};
+
+/// Controlled corruption of BOC for negative tests and fuzzing. Cells are numbered in order of BOC
+/// (roots first), bits are counted from the highest bit of the first byte.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Corruption {
+    /// flips bit of whole BOC
+    FlipBit { offset: usize },
+    /// truncates whole BOC to `len` bytes
+    Truncate { len: usize },
+    /// replaces byte of whole BOC
+    ReplaceByte { offset: usize, value: u8 },
+    /// appends bytes to the end of whole BOC
+    Append { data: Vec<u8> },
+    /// flips bit of data of cell
+    FlipDataBit { cell: usize, bit: usize },
+    /// swaps two references of cell
+    SwapRefs { cell: usize, first: usize, second: usize },
+    /// stores hashes and depths of cell in BOC with the first bit of the first hash flipped
+    BreakStoredHash { cell: usize },
+}
+
+/// Writes BOC of `roots` without index and applies `corruption`. CRC is calculated after
+/// corruption of cells, so it is found by checks of cells, whole BOC is corrupted after CRC.
+pub fn corrupted_boc(roots: &[Cell], crc: bool, corruption: &Corruption) -> Result<Vec<u8>> {
+    let mode = BocSerialiseMode::Generic { index: false, crc: false, cache_bits: false, flags: 0 };
+    // stored hashes make cells bigger, so total size must have place for it
+    let offset_size = match corruption {
+        Corruption::BreakStoredHash { .. } => Some(8),
+        _ => None
+    };
+    let mut boc = Vec::new();
+    BagOfCells::with_roots(roots).write_to_ex(&mut boc, mode, None, offset_size)?;
+    match *corruption {
+        Corruption::FlipDataBit { cell, bit } => {
+            let (offset, _) = cell_offset(&boc, cell)?;
+            let buf = &boc[offset..];
+            if bit >= cell::bit_len(buf) {
+                fail!("cell {} has {} bits of data, bit {} can not be flipped", cell, cell::bit_len(buf), bit)
+            }
+            let data_offset = offset + cell::data_offset(buf);
+            boc[data_offset + bit / 8] ^= 0x80 >> (bit % 8);
+        }
+        Corruption::SwapRefs { cell, first, second } => {
+            let (offset, ref_size) = cell_offset(&boc, cell)?;
+            let buf = &boc[offset..];
+            let refs_count = cell::refs_count(buf);
+            if first >= refs_count || second >= refs_count {
+                fail!("cell {} has {} references, {} and {} can not be swapped", cell, refs_count, first, second)
+            }
+            let refs = offset + cell::full_len(buf);
+            for index in 0..ref_size {
+                boc.swap(refs + first * ref_size + index, refs + second * ref_size + index);
+            }
+        }
+        Corruption::BreakStoredHash { cell: index } => {
+            let (offset, _) = cell_offset(&boc, index)?;
+            // bag of cells numbers cells from the end of BOC
+            let bag = BagOfCells::with_roots(roots);
+            let cell = match bag.get_cell_by_index(bag.cells_count() - 1 - index) {
+                Some(cell) => cell,
+                None => fail!("there is no cell {} in BOC", index)
+            };
+            if cell::store_hashes(&boc[offset..]) {
+                fail!("cell {} already has stored hashes", index)
+            }
+            // pruned branch stores only representation hash, it keeps others in data
+            let (hashes, depths) = match cell.is_pruned() {
+                true => (SmallVec::from_slice(&[cell.repr_hash()]), SmallVec::from_slice(&[cell.repr_depth()])),
+                false => (cell.hashes(), cell.depths())
+            };
+            let mut stored = Vec::with_capacity(hashes.len() * (SHA256_SIZE + DEPTH_SIZE));
+            hashes.iter().for_each(|hash| stored.extend_from_slice(hash.as_slice()));
+            depths.iter().for_each(|depth| stored.extend_from_slice(&depth.to_be_bytes()));
+            stored[0] ^= 0x80;
+            boc[offset] |= HASHES_D1_FLAG;
+            let len = stored.len();
+            boc.splice(offset + 2..offset + 2, stored);
+            // total cells size is the last field before roots list
+            let header = deserialize_cells_tree_header(&mut Cursor::new(&boc))?;
+            let size_offset = 6 + 3 * header.ref_size;
+            let size = (header.tot_cells_size + len) as u64;
+            boc[size_offset..size_offset + 8].copy_from_slice(&size.to_be_bytes());
+        }
+        _ => ()
+    }
+    if crc {
+        // BOC is written without CRC, so only flag is to be set
+        boc[4] |= 0x40;
+        let crc = crc32c(&boc);
+        boc.extend_from_slice(&crc.to_le_bytes());
+    }
+    corruption.apply(&mut boc)?;
+    Ok(boc)
+}
+
+impl Corruption {
+    /// Applies corruption of whole BOC, corruptions of cells are applied only by `corrupted_boc`
+    pub fn apply(&self, boc: &mut Vec<u8>) -> Result<()> {
+        match *self {
+            Corruption::FlipBit { offset } if offset < boc.len() * 8 => boc[offset / 8] ^= 0x80 >> (offset % 8),
+            Corruption::FlipBit { offset } => fail!("BOC has {} bits, bit {} can not be flipped", boc.len() * 8, offset),
+            Corruption::Truncate { len } => boc.truncate(len),
+            Corruption::ReplaceByte { offset, value } if offset < boc.len() => boc[offset] = value,
+            Corruption::ReplaceByte { offset, .. } => fail!("BOC has {} bytes, byte {} can not be replaced", boc.len(), offset),
+            Corruption::Append { ref data } => boc.extend_from_slice(data),
+            _ => ()
+        }
+        Ok(())
+    }
+}
+
+// offset of cell in BOC without index and size of references
+fn cell_offset(boc: &[u8], index: usize) -> Result<(usize, usize)> {
+    let mut cursor = Cursor::new(boc);
+    let header = deserialize_cells_tree_header(&mut cursor)?;
+    if index >= header.cells_count {
+        fail!("there is no cell {} in BOC of {} cells", index, header.cells_count)
+    }
+    let mut offset = cursor.position() as usize;
+    for _ in 0..index {
+        let buf = &boc[offset..];
+        offset += cell::full_len(buf) + cell::refs_count(buf) * header.ref_size;
+    }
+    Ok((offset, header.ref_size))
+}
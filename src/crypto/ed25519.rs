@@ -0,0 +1,124 @@
+/*
+* Copyright 2018-2020 TON DEV SOLUTIONS LTD.
+*
+* Licensed under the SOFTWARE EVALUATION License (the "License"); you may not use
+* this file except in compliance with the License.
+*
+* Unless required by applicable law or agreed to in writing, software
+* distributed under the License is distributed on an "AS IS" BASIS,
+* WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+* See the License for the specific TON DEV software governing permissions and
+* limitations under the License.
+*/
+
+//! Cell is signed by its representation hash, that is 32 bytes of `Cell::repr_hash`.
+
+use core::fmt;
+
+use ed25519_dalek::{Signer, SigningKey, Verifier, VerifyingKey};
+
+use crate::{cell::Cell, error, fail, types::Result};
+
+pub const ED25519_PRIVATE_KEY_LENGTH: usize = 32;
+pub const ED25519_PUBLIC_KEY_LENGTH: usize = 32;
+pub const ED25519_SIGNATURE_LENGTH: usize = 64;
+
+/// Secret key, its bytes are 32 bytes seed.
+/// With feature `zeroize` it is wiped on drop.
+#[derive(Clone)]
+pub struct Ed25519PrivateKey(SigningKey);
+
+impl Ed25519PrivateKey {
+    pub fn from_bytes(bytes: &[u8; ED25519_PRIVATE_KEY_LENGTH]) -> Self {
+        Self(SigningKey::from_bytes(bytes))
+    }
+
+    pub fn from_slice(bytes: &[u8]) -> Result<Self> {
+        match bytes.try_into() {
+            Ok(bytes) => Ok(Self::from_bytes(bytes)),
+            Err(_) => fail!("ed25519 private key has {} bytes instead of {}", bytes.len(), ED25519_PRIVATE_KEY_LENGTH)
+        }
+    }
+
+    #[cfg(all(feature = "rand", not(target_arch = "wasm32")))]
+    pub fn generate() -> Self {
+        Self::from_bytes(&rand::random())
+    }
+
+    /// Returned copy is not wiped, see `to_bytes_zeroizing`
+    pub fn to_bytes(&self) -> [u8; ED25519_PRIVATE_KEY_LENGTH] {
+        self.0.to_bytes()
+    }
+
+    #[cfg(feature = "zeroize")]
+    pub fn to_bytes_zeroizing(&self) -> zeroize::Zeroizing<[u8; ED25519_PRIVATE_KEY_LENGTH]> {
+        zeroize::Zeroizing::new(self.0.to_bytes())
+    }
+
+    pub fn public_key(&self) -> Ed25519PublicKey {
+        Ed25519PublicKey(self.0.verifying_key())
+    }
+
+    pub fn sign(&self, data: &[u8]) -> [u8; ED25519_SIGNATURE_LENGTH] {
+        self.0.sign(data).to_bytes()
+    }
+}
+
+// signing key wipes itself on drop
+#[cfg(feature = "zeroize")]
+impl zeroize::ZeroizeOnDrop for Ed25519PrivateKey {}
+
+/// Only public key is printed
+impl fmt::Debug for Ed25519PrivateKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Ed25519PrivateKey({:?})", self.public_key())
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Ed25519PublicKey(VerifyingKey);
+
+impl Ed25519PublicKey {
+    /// Fails if bytes are not a point of the curve
+    pub fn from_bytes(bytes: &[u8; ED25519_PUBLIC_KEY_LENGTH]) -> Result<Self> {
+        VerifyingKey::from_bytes(bytes)
+            .map(Self)
+            .map_err(|err| error!("invalid ed25519 public key: {}", err))
+    }
+
+    pub fn from_slice(bytes: &[u8]) -> Result<Self> {
+        match bytes.try_into() {
+            Ok(bytes) => Self::from_bytes(bytes),
+            Err(_) => fail!("ed25519 public key has {} bytes instead of {}", bytes.len(), ED25519_PUBLIC_KEY_LENGTH)
+        }
+    }
+
+    pub fn as_bytes(&self) -> &[u8; ED25519_PUBLIC_KEY_LENGTH] {
+        self.0.as_bytes()
+    }
+
+    pub fn verify(&self, data: &[u8], signature: &[u8; ED25519_SIGNATURE_LENGTH]) -> Result<()> {
+        let signature = ed25519_dalek::Signature::from_bytes(signature);
+        self.0.verify(data, &signature).map_err(|_| error!("ed25519 signature is invalid"))
+    }
+}
+
+impl fmt::Debug for Ed25519PublicKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Ed25519PublicKey({})", hex::encode(self.as_bytes()))
+    }
+}
+
+/// Signs representation hash of cell
+pub fn sign_cell_repr_hash(cell: &Cell, key: &Ed25519PrivateKey) -> [u8; ED25519_SIGNATURE_LENGTH] {
+    key.sign(cell.repr_hash().as_slice())
+}
+
+/// Verifies signature of representation hash of cell
+pub fn verify_cell_repr_hash(
+    cell: &Cell,
+    key: &Ed25519PublicKey,
+    signature: &[u8; ED25519_SIGNATURE_LENGTH],
+) -> Result<()> {
+    key.verify(cell.repr_hash().as_slice(), signature)
+}
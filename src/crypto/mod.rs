@@ -0,0 +1,43 @@
+/*
+* Copyright 2018-2020 TON DEV SOLUTIONS LTD.
+*
+* Licensed under the SOFTWARE EVALUATION License (the "License"); you may not use
+* this file except in compliance with the License.
+*
+* Unless required by applicable law or agreed to in writing, software
+* distributed under the License is distributed on an "AS IS" BASIS,
+* WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+* See the License for the specific TON DEV software governing permissions and
+* limitations under the License.
+*/
+
+//! Constant-time comparisons for verification paths.
+//! Ed25519 keys and signatures of cells with feature `ed25519`.
+
+use subtle::ConstantTimeEq;
+
+use crate::types::UInt256;
+
+#[cfg(feature = "ed25519")]
+mod ed25519;
+
+#[cfg(feature = "ed25519")]
+pub use self::ed25519::*;
+
+/// Compares data in time depending only on lengths, data of different lengths is not equal
+pub fn ct_eq(a: &[u8], b: &[u8]) -> bool {
+    a.ct_eq(b).into()
+}
+
+impl ConstantTimeEq for UInt256 {
+    fn ct_eq(&self, other: &Self) -> subtle::Choice {
+        self.as_slice().ct_eq(other.as_slice())
+    }
+}
+
+impl UInt256 {
+    /// Constant-time equality, use it instead of `==` for hashes and MACs to be verified
+    pub fn ct_eq(&self, other: &Self) -> bool {
+        ConstantTimeEq::ct_eq(self, other).into()
+    }
+}
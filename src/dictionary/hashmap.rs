@@ -11,11 +11,12 @@
 * limitations under the License.
 */
 
-use std::fmt;
+use core::fmt;
 
 use crate::{
     fail, Result, GasConsumer,
-    types::ExceptionCode,
+    merkle::MerkleProof,
+    types::{ExceptionCode, FxHashSet, UInt256},
     cell::{BuilderData, Cell, SliceData},
 
 };
@@ -35,7 +36,7 @@ pub struct HashmapE {
 impl fmt::Display for HashmapE {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self.data() {
-            Some(cell) => write!(f, "Hashmap: {}", cell),
+            Some(cell) => write!(f, "Hashmap: {}", cell.tree()),
             None => write!(f, "Empty Hashmap"),
         }
     }
@@ -86,6 +87,36 @@ impl HashmapE {
     pub fn get_with_gas(&self, key: SliceData, gas_consumer: &mut dyn GasConsumer) -> Leaf {
         self.hashmap_get(key, gas_consumer)
     }
+    /// Builds proof of `root_cell` containing forks and leaves on paths of `keys`,
+    /// absent keys are proven by path to place where they would be.
+    /// Dictionary root must be a cell of `root_cell` tree, references of values are pruned.
+    pub fn build_proof_for_keys(&self, root_cell: &Cell, keys: impl IntoIterator<Item = SliceData>) -> Result<MerkleProof> {
+        let mut collector = CellsCollector::default();
+        for key in keys {
+            self.hashmap_get(key, &mut collector)?;
+        }
+        let mut include = collector.0;
+        let mut path = FxHashSet::default();
+        if let Some(root) = self.data() {
+            if !collect_path(root_cell, &root.repr_hash(), &mut path, &mut FxHashSet::default())? {
+                fail!("dictionary root {:x} is not in tree of cell {:x}", root.repr_hash(), root_cell.repr_hash())
+            }
+        }
+        include.extend(path);
+        include.insert(root_cell.repr_hash());
+        MerkleProof::create(root_cell, |hash| include.contains(hash))
+    }
+    /// Checks labels, key lengths and forks of dictionary (see `check_hashmap_invariants`),
+    /// forks must not have data except references to children
+    pub fn check_invariants(&self) -> Result<Vec<DictionaryViolation>> {
+        check_hashmap_invariants(self.data(), self.bit_len, |_, remainder, is_fork| {
+            if is_fork && (remainder.remaining_bits() != 0 || remainder.remaining_references() != 2) {
+                return Ok(Some(format!("fork has {} bits and {} references instead of two references",
+                    remainder.remaining_bits(), remainder.remaining_references())))
+            }
+            Ok(None)
+        })
+    }
     /// sets value as SliceData
     pub fn set(&mut self, key: SliceData, value: &SliceData) -> Leaf {
         self.hashmap_set_with_mode(key, &BuilderData::from_slice(value), &mut 0, ADD | REPLACE)
@@ -184,10 +215,108 @@ impl HashmapE {
     pub fn split(&self, key: &SliceData) -> Result<(Self, Self)> {
         self.hashmap_split(key).map(|(left, right)| (Self::with_hashmap(self.bit_len, left), Self::with_hashmap(self.bit_len, right)))
     }
+    /// split to subtrees by key spending gas
+    pub fn split_with_gas(&self, key: &SliceData, gas_consumer: &mut dyn GasConsumer) -> Result<(Self, Self)> {
+        let (left, right) = self.hashmap_split_with_gas(key, gas_consumer)?;
+        Ok((Self::with_hashmap(self.bit_len, left), Self::with_hashmap(self.bit_len, right)))
+    }
     /// Merge other tree to current roots should be at least merge key
     pub fn merge(&mut self, other: &Self, key: &SliceData) -> Result<()> {
         self.hashmap_merge(other, key)
     }
+    /// merges other tree spending gas
+    pub fn merge_with_gas(&mut self, other: &Self, key: &SliceData, gas_consumer: &mut dyn GasConsumer) -> Result<()> {
+        self.hashmap_merge_with_gas(other, key, gas_consumer)
+    }
+    /// builds dictionary at once from entries sorted by key, keys must be unique
+    pub fn from_sorted(bit_len: usize, entries: &[(SliceData, BuilderData)]) -> Result<Self> {
+        Self::from_sorted_parallel(bit_len, entries, 1)
+    }
+    /// builds dictionary from sorted entries constructing subtrees of top forks on several threads
+    pub fn from_sorted_parallel(bit_len: usize, entries: &[(SliceData, BuilderData)], threads: usize) -> Result<Self> {
+        for (key, _) in entries {
+            Self::check_key_fail(bit_len, key)?;
+        }
+        for pair in entries.windows(2) {
+            if pair[0].0 >= pair[1].0 {
+                fail!("keys {} and {} are not sorted or not unique", pair[0].0, pair[1].0)
+            }
+        }
+        let data = match entries.is_empty() {
+            true => None,
+            false => Some(build_sorted_subtree(entries, 0, bit_len, threads.max(1))?)
+        };
+        Ok(Self::with_hashmap(bit_len, data))
+    }
+}
+
+const MIN_ENTRIES_PER_THREAD: usize = 1024;
+
+// entries are sorted, unique and share first offset bits of key
+fn build_sorted_subtree(entries: &[(SliceData, BuilderData)], offset: usize, bit_len: usize, threads: usize) -> Result<Cell> {
+    let first = &entries[0].0;
+    if entries.len() == 1 {
+        let mut builder = hm_label(&first.get_slice(offset, bit_len)?, bit_len)?;
+        builder.append_builder(&entries[0].1)?;
+        return builder.into_cell()
+    }
+    // common prefix of the first and the last keys is common for all of them
+    let last = &entries[entries.len() - 1].0;
+    let mut prefix_len = 0;
+    while first.get_bit(offset + prefix_len)? == last.get_bit(offset + prefix_len)? {
+        prefix_len += 1;
+    }
+    let fork_bit = offset + prefix_len;
+    let split = entries.partition_point(|(key, _)| !key.get_bit(fork_bit).unwrap_or_default());
+    let (left, right) = entries.split_at(split);
+    let next_bit_len = bit_len - prefix_len - 1;
+    let (left, right) = if threads > 1 && entries.len() >= 2 * MIN_ENTRIES_PER_THREAD {
+        build_sorted_forks_parallel(left, right, fork_bit + 1, next_bit_len, threads)?
+    } else {
+        (
+            build_sorted_subtree(left, fork_bit + 1, next_bit_len, 1)?,
+            build_sorted_subtree(right, fork_bit + 1, next_bit_len, 1)?
+        )
+    };
+    let mut builder = hm_label(&first.get_slice(offset, prefix_len)?, bit_len)?;
+    builder.checked_append_reference(left)?;
+    builder.checked_append_reference(right)?;
+    builder.into_cell()
+}
+
+#[cfg(all(feature = "std", not(target_arch = "wasm32")))]
+fn build_sorted_forks_parallel(
+    left: &[(SliceData, BuilderData)],
+    right: &[(SliceData, BuilderData)],
+    offset: usize,
+    bit_len: usize,
+    threads: usize,
+) -> Result<(Cell, Cell)> {
+    let left_threads = threads / 2;
+    let quota = crate::quota::InheritedQuota::current();
+    std::thread::scope(|scope| {
+        let left = scope.spawn(|| {
+            let _quota = quota.enter();
+            build_sorted_subtree(left, offset, bit_len, left_threads)
+        });
+        let right = build_sorted_subtree(right, offset, bit_len, threads - left_threads);
+        match left.join() {
+            Ok(left) => Ok((left?, right?)),
+            Err(_) => fail!("dictionary build thread panicked")
+        }
+    })
+}
+
+// without threads (no std or wasm) both forks are built by current thread
+#[cfg(any(not(feature = "std"), target_arch = "wasm32"))]
+fn build_sorted_forks_parallel(
+    left: &[(SliceData, BuilderData)],
+    right: &[(SliceData, BuilderData)],
+    offset: usize,
+    bit_len: usize,
+    _threads: usize,
+) -> Result<(Cell, Cell)> {
+    Ok((build_sorted_subtree(left, offset, bit_len, 1)?, build_sorted_subtree(right, offset, bit_len, 1)?))
 }
 
 // hm_edge#_ {n:#} {X:Type} {l:#} {m:#} label:(HmLabel ~l n)
@@ -229,7 +358,7 @@ impl HashmapRemover for HashmapE {}
 impl HashmapSubtree for HashmapE {}
 
 impl IntoIterator for &HashmapE {
-    type Item = <HashmapIterator<HashmapE> as std::iter::Iterator>::Item;
+    type Item = <HashmapIterator<HashmapE> as core::iter::Iterator>::Item;
     type IntoIter = HashmapIterator<HashmapE>;
 
     fn into_iter(self) -> Self::IntoIter {
@@ -237,3 +366,45 @@ impl IntoIterator for &HashmapE {
     }
 }
 
+// remembers cells loaded while walking dictionary
+#[derive(Default)]
+struct CellsCollector(FxHashSet<UInt256>);
+
+impl GasConsumer for CellsCollector {
+    fn finalize_cell(&mut self, builder: BuilderData) -> Result<Cell> {
+        builder.into_cell()
+    }
+    fn load_cell(&mut self, cell: Cell) -> Result<SliceData> {
+        self.0.insert(cell.repr_hash());
+        SliceData::load_cell(cell)
+    }
+    fn finalize_cell_and_load(&mut self, builder: BuilderData) -> Result<SliceData> {
+        SliceData::load_builder(builder)
+    }
+}
+
+// adds cells on paths from `cell` to cell with `hash`, returns false if there is no such cell
+fn collect_path(
+    cell: &Cell,
+    hash: &UInt256,
+    path: &mut FxHashSet<UInt256>,
+    not_found: &mut FxHashSet<UInt256>,
+) -> Result<bool> {
+    let cell_hash = cell.repr_hash();
+    if cell_hash == *hash || path.contains(&cell_hash) {
+        path.insert(cell_hash);
+        return Ok(true)
+    }
+    if not_found.contains(&cell_hash) {
+        return Ok(false)
+    }
+    let mut found = false;
+    for index in 0..cell.references_count() {
+        found |= collect_path(&cell.reference(index)?, hash, path, not_found)?;
+    }
+    match found {
+        true => path.insert(cell_hash),
+        false => not_found.insert(cell_hash)
+    };
+    Ok(found)
+}
@@ -11,7 +11,8 @@
 * limitations under the License.
 */
 
-use std::{cmp, iter::Iterator, marker::PhantomData};
+use alloc::{format, string::String, vec, vec::Vec};
+use core::{cmp, iter::Iterator, marker::PhantomData};
 
 use smallvec::SmallVec;
 
@@ -89,7 +90,7 @@ pub fn hm_label(key: &SliceData, max: usize) -> Result<BuilderData> {
     if key.is_empty() || max == 0 {
         return hm_empty()
     }
-    let len = 16 - (max as u16).leading_zeros() as usize;
+    let len = hm_label_len_bits(max);
     let length_of_long = 2 + len + key.remaining_bits(); // len == key.remaining_bits() + 1
     let length_of_short = 1 + 2 * key.remaining_bits() + 1;
     let length_of_same = 2 + 1 + len;
@@ -117,6 +118,79 @@ pub fn hm_label(key: &SliceData, max: usize) -> Result<BuilderData> {
     Ok(long_label)
 }
 
+/// Constructor of HmLabel
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HmLabelKind {
+    /// hml_short$0
+    Short,
+    /// hml_long$10
+    Long,
+    /// hml_same$11
+    Same,
+}
+
+// count of bits of label length for keys of up to `max` bits
+fn hm_label_len_bits(max: usize) -> usize {
+    16 - (max as u16).leading_zeros() as usize
+}
+
+/// Writes `key` as label of given constructor for keys of up to `max` bits,
+/// unlike `hm_label` the constructor is not chosen by length
+pub fn write_hm_label(key: &SliceData, max: usize, kind: HmLabelKind) -> Result<BuilderData> {
+    if key.remaining_bits() > max {
+        fail!("label of {} bits does not fit in {} bits", key.remaining_bits(), max)
+    }
+    match kind {
+        HmLabelKind::Short => hml_short(key).ok_or_else(|| error!(ExceptionCode::CellOverflow)),
+        HmLabelKind::Long => hml_long(key, hm_label_len_bits(max)),
+        HmLabelKind::Same => match hml_same(key, hm_label_len_bits(max)) {
+            Some(label) => Ok(label),
+            None => fail!("label {} has different bits and can not be written as hml_same", key)
+        }
+    }
+}
+
+/// Reads label of any constructor for keys of up to `max` bits, slice is moved past it
+pub fn read_hm_label(slice: &mut SliceData, max: usize) -> Result<(HmLabelKind, SliceData)> {
+    let kind = match slice.get_bit_opt(0) {
+        Some(false) => HmLabelKind::Short,
+        Some(true) => match slice.get_bit_opt(1) {
+            Some(false) => HmLabelKind::Long,
+            Some(true) => HmLabelKind::Same,
+            None => fail!(ExceptionCode::CellUnderflow)
+        }
+        None => fail!(ExceptionCode::CellUnderflow)
+    };
+    Ok((kind, read_hm_label_of(slice, max, kind)?))
+}
+
+/// Reads label of given constructor for keys of up to `max` bits, slice is moved past it
+pub fn read_hm_label_of(slice: &mut SliceData, mut max: usize, kind: HmLabelKind) -> Result<SliceData> {
+    let mut reader = LabelReader::new(slice.clone());
+    let label = match kind {
+        HmLabelKind::Short => {
+            if reader.cursor.get_next_bit()? {
+                fail!("label is not hml_short")
+            }
+            reader.get_label_short(&mut max)?
+        }
+        HmLabelKind::Long => {
+            if reader.cursor.get_next_bits(2)?[0] != LONG_LABEL_PREFIX {
+                fail!("label is not hml_long")
+            }
+            reader.get_label_long(&mut max)?
+        }
+        HmLabelKind::Same => {
+            if reader.cursor.get_next_bits(2)?[0] != SAME_LABEL_PREFIX {
+                fail!("label is not hml_same")
+            }
+            SliceData::load_builder(reader.get_label_same(&mut max, BuilderData::default())?)?
+        }
+    };
+    *slice = reader.cursor;
+    Ok(label)
+}
+
 // reading hmLabel from SliceData
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub struct LabelReader {
@@ -252,13 +326,13 @@ impl LabelReader {
 // use LabelReader adapter
 impl SliceData {
     pub fn get_label_raw(&mut self, max: &mut usize, key: BuilderData) -> Result<BuilderData> {
-        let mut cursor = LabelReader::new(std::mem::take(self));
+        let mut cursor = LabelReader::new(core::mem::take(self));
         let key = cursor.get_label_raw(max, key)?;
         *self = cursor.remainder()?;
         Ok(key)
     }
     pub fn get_label(&mut self, max: usize) -> Result<SliceData> {
-        let mut cursor = LabelReader::new(std::mem::take(self));
+        let mut cursor = LabelReader::new(core::mem::take(self));
         let key = cursor.get_label(max)?;
         *self = cursor.remainder()?;
         Ok(key)
@@ -378,6 +452,77 @@ pub fn get_min_max<T: HashmapType + ?Sized>(
     }
 }
 
+/// Violation of dictionary invariants found by `check_hashmap_invariants`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DictionaryViolation {
+    /// key prefix of node where violation is found
+    pub prefix: SliceData,
+    pub description: String,
+}
+
+/// Checks dictionary with `root` and keys of `bit_len` bits: labels are valid, shortest
+/// and fit into key length, forks have two children and leaves are at the full key length.
+/// `check_node` is called for every node with its key prefix, remainder after label and fork flag
+/// to check extra data (e.g. aggregates of augmented dictionaries), it returns description of violation.
+/// All violations are collected, only errors of loading cells are returned as error.
+pub fn check_hashmap_invariants<F>(root: Option<&Cell>, bit_len: usize, mut check_node: F) -> Result<Vec<DictionaryViolation>>
+where
+    F: FnMut(&SliceData, &SliceData, bool) -> Result<Option<String>>
+{
+    let mut violations = Vec::new();
+    if let Some(root) = root {
+        check_node_invariants(root, bit_len, BuilderData::new(), &mut check_node, &mut violations)?;
+    }
+    Ok(violations)
+}
+
+fn check_node_invariants<F>(
+    cell: &Cell,
+    bit_len: usize,
+    mut prefix: BuilderData,
+    check_node: &mut F,
+    violations: &mut Vec<DictionaryViolation>,
+) -> Result<()>
+where
+    F: FnMut(&SliceData, &SliceData, bool) -> Result<Option<String>>
+{
+    let mut violation = |prefix: &BuilderData, description: String| -> Result<()> {
+        let prefix = SliceData::load_builder(prefix.clone())?;
+        violations.push(DictionaryViolation { prefix, description });
+        Ok(())
+    };
+    let mut slice = SliceData::load_cell_ref(cell)?;
+    let total_bits = slice.remaining_bits();
+    let label = match slice.get_label(bit_len) {
+        Ok(label) => label,
+        Err(err) => return violation(&prefix, format!("invalid label for {} bits of key: {}", bit_len, err))
+    };
+    let label_bits = total_bits - slice.remaining_bits();
+    let canonical_bits = hm_label(&label, bit_len)?.length_in_bits();
+    if label_bits > canonical_bits {
+        violation(&prefix, format!("label of {} bits takes {} bits instead of {}",
+            label.remaining_bits(), label_bits, canonical_bits))?;
+    }
+    prefix.append_bytestring(&label)?;
+    let bit_len = bit_len - label.remaining_bits();
+    let is_fork = bit_len != 0;
+    if is_fork && slice.remaining_references() < 2 {
+        return violation(&prefix, format!("fork with {} bits of key left has {} references",
+            bit_len, slice.remaining_references()))
+    }
+    if let Some(description) = check_node(&SliceData::load_builder(prefix.clone())?, &slice, is_fork)? {
+        violation(&prefix, description)?;
+    }
+    if is_fork {
+        for index in 0..2 {
+            let mut child_prefix = prefix.clone();
+            child_prefix.append_bit_bool(index == 1)?;
+            check_node_invariants(&slice.reference(index)?, bit_len - 1, child_prefix, check_node, violations)?;
+        }
+    }
+    Ok(())
+}
+
 // difference for different hashmap types
 pub trait HashmapType {
     fn write_hashmap_data(&self, cell: &mut BuilderData) -> Result<()> {
@@ -433,7 +578,7 @@ pub trait HashmapType {
         let mut builder = hm_label(key, bit_len)?;
         let mut remainder = BuilderData::new();
         if swap {
-            std::mem::swap(&mut left, &mut right);
+            core::mem::swap(&mut left, &mut right);
         }
         remainder.checked_append_reference(left)?;
         remainder.checked_append_reference(right)?;
@@ -536,14 +681,20 @@ pub trait HashmapType {
     }
 
     /// iterate all elements with callback function
-    fn iterate_slices<F> (&self, mut p: F) -> Result<bool>
+    fn iterate_slices<F> (&self, p: F) -> Result<bool>
+    where F: FnMut(SliceData, SliceData) -> Result<bool> {
+        self.iterate_slices_with_gas(p, &mut 0)
+    }
+    /// iterates items spending gas for loaded cells
+    fn iterate_slices_with_gas<F> (&self, mut p: F, gas_consumer: &mut dyn GasConsumer) -> Result<bool>
     where F: FnMut(SliceData, SliceData) -> Result<bool> {
         if let Some(root) = self.data() {
             iterate_internal::<Self, _>(
-                LabelReader::with_cell(root)?,
+                LabelReader::new(gas_consumer.load_cell(root.clone())?),
                 BuilderData::default(),
                 self.bit_len(),
-                &mut |k, v| p(SliceData::load_builder(k)?, v))
+                &mut |k, v| p(SliceData::load_builder(k)?, v),
+                gas_consumer)
         } else {
             Ok(true)
         }
@@ -555,7 +706,7 @@ pub trait HashmapType {
             Some(root) => {
                 let mut len = 0;
                 let cursor = LabelReader::with_cell(root)?;
-                count_internal::<Self>(cursor, self.bit_len(), &mut len, std::usize::MAX)?;
+                count_internal::<Self>(cursor, self.bit_len(), &mut len, usize::MAX, &mut 0)?;
                 Ok(len)
             }
             None => Ok(0)
@@ -563,11 +714,15 @@ pub trait HashmapType {
     }
     /// counts elements to max counter - can be used as validate
     fn count(&self, max: usize) -> Result<usize> {
+        self.count_with_gas(max, &mut 0)
+    }
+    /// counts elements to max counter spending gas for loaded cells
+    fn count_with_gas(&self, max: usize, gas_consumer: &mut dyn GasConsumer) -> Result<usize> {
         match self.data() {
             Some(root) => {
                 let mut len = 0;
-                let cursor = LabelReader::with_cell(root)?;
-                count_internal::<Self>(cursor, self.bit_len(), &mut len, max)?;
+                let cursor = LabelReader::new(gas_consumer.load_cell(root.clone())?);
+                count_internal::<Self>(cursor, self.bit_len(), &mut len, max, gas_consumer)?;
                 Ok(len)
             }
             None => Ok(0)
@@ -587,12 +742,19 @@ pub trait HashmapType {
     }
     // split
     fn hashmap_split(&self, key: &SliceData) -> Result<(Option<Cell>, Option<Cell>)> {
+        self.hashmap_split_with_gas(key, &mut 0)
+    }
+    fn hashmap_split_with_gas(
+        &self,
+        key: &SliceData,
+        gas_consumer: &mut dyn GasConsumer
+    ) -> Result<(Option<Cell>, Option<Cell>)> {
         let mut bit_len = self.bit_len();
         let data = match self.data() {
             Some(data) => data,
             _ => return Ok((None, None))
         };
-        let mut cursor = SliceData::load_cell_ref(data)?;
+        let mut cursor = gas_consumer.load_cell(data.clone())?;
         let label = cursor.get_label(bit_len)?;
         let (left, right) = match SliceData::common_prefix(&label, key) {
             // normal case label == key
@@ -608,37 +770,40 @@ pub trait HashmapType {
             // wrong hashmap tree
             _ => fail!("split fail: root label: x{:x} and key: x{:x}", label, key),
         };
-        cursor = SliceData::load_cell(left)?;
+        cursor = gas_consumer.load_cell(left)?;
         let label = cursor.get_label(bit_len)?;
         let mut builder = BuilderData::from_slice(key);
         builder.append_bit_zero()?;
         builder.append_bytestring(&label)?;
         let left = Self::make_cell_with_label_and_data(SliceData::load_builder(builder)?, self.bit_len(), false, &cursor)?;
 
-        cursor = SliceData::load_cell(right)?;
+        cursor = gas_consumer.load_cell(right)?;
         let label = cursor.get_label(bit_len)?;
         let mut builder = BuilderData::from_slice(key);
         builder.append_bit_one()?;
         builder.append_bytestring(&label)?;
         let right = Self::make_cell_with_label_and_data(SliceData::load_builder(builder)?, self.bit_len(), false, &cursor)?;
 
-        Ok((Some(left.into_cell()?), Some(right.into_cell()?)))
+        Ok((Some(gas_consumer.finalize_cell(left)?), Some(gas_consumer.finalize_cell(right)?)))
     }
     // merge
     fn hashmap_merge(&mut self, other: &Self, key: &SliceData) -> Result<()> {
+        self.hashmap_merge_with_gas(other, key, &mut 0)
+    }
+    fn hashmap_merge_with_gas(&mut self, other: &Self, key: &SliceData, gas_consumer: &mut dyn GasConsumer) -> Result<()> {
         let bit_len = self.bit_len();
         if bit_len != other.bit_len() || key.remaining_bits() > bit_len {
             return Ok(()) // fail!("data in hashmaps do not correspond each other or key too long")
         }
         let mut cursor = match self.data() {
-            Some(data) => SliceData::load_cell_ref(data)?,
+            Some(data) => gas_consumer.load_cell(data.clone())?,
             None => {
                 *self.data_mut() = other.data().cloned();
                 return Ok(())
             }
         };
         let mut other = match other.data() {
-            Some(data) => SliceData::load_cell_ref(data)?,
+            Some(data) => gas_consumer.load_cell(data.clone())?,
             None => return Ok(())
         };
         let label1 = cursor.get_label(bit_len)?;
@@ -652,8 +817,8 @@ pub trait HashmapType {
                 let left_bit = left.get_next_bit()?;
                 let right_bit = right.get_next_bit()?;
                 if left_bit && !right_bit {
-                    std::mem::swap(&mut left, &mut right);
-                    std::mem::swap(&mut cursor, &mut other);
+                    core::mem::swap(&mut left, &mut right);
+                    core::mem::swap(&mut cursor, &mut other);
                 }
                 let is_leaf1 = Self::is_leaf(&mut cursor);
                 let is_leaf2 = Self::is_leaf(&mut other);
@@ -661,11 +826,13 @@ pub trait HashmapType {
                 let next_bit_len = bit_len.checked_sub(prefix.remaining_bits() + 1).ok_or(ExceptionCode::CellUnderflow)?;
                 let left = Self::make_cell_with_label_and_data(left, next_bit_len, is_leaf1, &cursor)?;
                 let right = Self::make_cell_with_label_and_data(right, next_bit_len, is_leaf2, &other)?;
-                let (root, _) = Self::make_fork(&prefix, bit_len, left.into_cell()?, right.into_cell()?, false)?;
-                *self.data_mut() = Some(root.into_cell()?);
+                let left = gas_consumer.finalize_cell(left)?;
+                let right = gas_consumer.finalize_cell(right)?;
+                let (root, _) = Self::make_fork(&prefix, bit_len, left, right, false)?;
+                *self.data_mut() = Some(gas_consumer.finalize_cell(root)?);
                 Ok(())
             }
-            result => fail!("Cannot merge {} {:?}", std::any::type_name::<Self>(), result)
+            result => fail!("Cannot merge {} {:?}", core::any::type_name::<Self>(), result)
         }
     }
 
@@ -681,13 +848,16 @@ pub trait HashmapType {
     /// combine all items from two hashamps
     /// will fail if trees have different items with same key
     fn combine_with(&mut self, other: &Self) -> Result<bool> {
+        self.combine_with_gas(other, &mut 0)
+    }
+    fn combine_with_gas(&mut self, other: &Self, gas_consumer: &mut dyn GasConsumer) -> Result<bool> {
         let bit_len = self.bit_len();
         if bit_len != other.bit_len() {
             fail!("Different bitlen")
         }
         match (self.data().cloned(), other.data().cloned()) {
             (Some(mut cell1), Some(cell2)) => {
-                if dict_combine_with_cell::<Self>(&mut cell1, cell2, bit_len)? {
+                if dict_combine_with_cell::<Self>(&mut cell1, cell2, bit_len, gas_consumer)? {
                     *self.data_mut() = Some(cell1);
                     return Ok(true)
                 }
@@ -702,18 +872,21 @@ pub trait HashmapType {
     }
 }
 
-fn dict_combine_with_cell<T: HashmapType + ?Sized>(cell1: &mut Cell, cell2: Cell, bit_len: usize) -> Result<bool> {
-    let mut cursor2 = SliceData::load_cell(cell2)?;
+fn dict_combine_with_cell<T: HashmapType + ?Sized>(
+    cell1: &mut Cell, cell2: Cell, bit_len: usize, gas_consumer: &mut dyn GasConsumer
+) -> Result<bool> {
+    let mut cursor2 = gas_consumer.load_cell(cell2)?;
     let label2 = cursor2.get_label(bit_len)?;
     let bit_len2 = bit_len.checked_sub(label2.remaining_bits()).ok_or(ExceptionCode::CellUnderflow)?;
-    dict_combine_with::<T>(cell1, bit_len, cursor2, label2, bit_len2)
+    dict_combine_with::<T>(cell1, bit_len, cursor2, label2, bit_len2, gas_consumer)
 }
 
 fn dict_combine_with<T: HashmapType + ?Sized>(
     cell1: &mut Cell, bit_len: usize,
-    mut cursor2: SliceData, label2: SliceData, bit_len2: usize
+    mut cursor2: SliceData, label2: SliceData, bit_len2: usize,
+    gas_consumer: &mut dyn GasConsumer
 ) -> Result<bool> {
-    let mut cursor1 = SliceData::load_cell_ref(cell1)?;
+    let mut cursor1 = gas_consumer.load_cell(cell1.clone())?;
     let label1 = cursor1.get_label(bit_len)?;
     let bit_len1 = bit_len.checked_sub(label1.remaining_bits()).ok_or(ExceptionCode::CellUnderflow)?;
     match SliceData::common_prefix(&label1, &label2) {
@@ -727,9 +900,9 @@ fn dict_combine_with<T: HashmapType + ?Sized>(
                 let left2 = cursor2.checked_drain_reference()?;
                 let mut right1 = cursor1.checked_drain_reference()?;
                 let right2 = cursor2.checked_drain_reference()?;
-                if dict_combine_with_cell::<T>(&mut left1, left2, bit_len1 - 1)? |
-                    dict_combine_with_cell::<T>(&mut right1, right2, bit_len1 - 1)? {
-                    *cell1 = T::make_fork(&label1, bit_len, left1, right1, false)?.0.into_cell()?;
+                if dict_combine_with_cell::<T>(&mut left1, left2, bit_len1 - 1, gas_consumer)? |
+                    dict_combine_with_cell::<T>(&mut right1, right2, bit_len1 - 1, gas_consumer)? {
+                    *cell1 = gas_consumer.finalize_cell(T::make_fork(&label1, bit_len, left1, right1, false)?.0)?;
                     return Ok(true)
                 }
             }
@@ -740,16 +913,16 @@ fn dict_combine_with<T: HashmapType + ?Sized>(
                 rem2.get_next_bit_int()?; // == 1 - next_index
                 let prefix = prefix_opt.unwrap_or_default(); //
                 let bit_len1 = bit_len - prefix.remaining_bits() - 1;
-                let left = T::make_cell_with_remainder(rem1, bit_len1, &cursor1)?.into_cell()?;
-                let right = T::make_cell_with_remainder(rem2, bit_len1, &cursor2)?.into_cell()?;
-                T::make_fork(&prefix, bit_len, left, right, next_index != 0)?.0.into_cell()?
+                let left = gas_consumer.finalize_cell(T::make_cell_with_remainder(rem1, bit_len1, &cursor1)?)?;
+                let right = gas_consumer.finalize_cell(T::make_cell_with_remainder(rem2, bit_len1, &cursor2)?)?;
+                gas_consumer.finalize_cell(T::make_fork(&prefix, bit_len, left, right, next_index != 0)?.0)?
             } else if bit_len2 == 0 { // second should not stop here
                 fail!(ExceptionCode::DictionaryError)
             } else { // slice edge of first and add items from first to second, then make new fork
                 let mut next = cursor2.reference(next_index)?;
                 let other = cursor2.reference(1 - next_index)?;
-                dict_combine_with::<T>(&mut next, bit_len2 - 1, cursor1, rem1, bit_len1)?;
-                T::make_fork(&label2, bit_len, next, other, next_index != 0)?.0.into_cell()?
+                dict_combine_with::<T>(&mut next, bit_len2 - 1, cursor1, rem1, bit_len1, gas_consumer)?;
+                gas_consumer.finalize_cell(T::make_fork(&label2, bit_len, next, other, next_index != 0)?.0)?
             };
             return Ok(true)
         }
@@ -760,10 +933,10 @@ fn dict_combine_with<T: HashmapType + ?Sized>(
                 let next_index = rem2.get_next_bit_int()?;
                 let mut next = cursor1.reference(next_index)?;
                 let other = cursor1.reference(1 - next_index)?;
-                if !dict_combine_with::<T>(&mut next, bit_len1 - 1, cursor2, rem2, bit_len2)? {
+                if !dict_combine_with::<T>(&mut next, bit_len1 - 1, cursor2, rem2, bit_len2, gas_consumer)? {
                     return Ok(false)
                 }
-                *cell1 = T::make_fork(&label1, bit_len, next, other, next_index != 0)?.0.into_cell()?;
+                *cell1 = gas_consumer.finalize_cell(T::make_fork(&label1, bit_len, next, other, next_index != 0)?.0)?;
                 return Ok(true)
             }
         }
@@ -808,7 +981,8 @@ where
                     true => Ok(true),
                     false => func(SliceData::load_builder(key)?, Some(cursor_1.clone()), Some(cursor))
                 }
-            }
+            },
+            &mut 0
         )? || (!chk && !func(SliceData::load_builder(key1)?, Some(cursor_1), None)?) {
             return Ok(false)
         }
@@ -828,7 +1002,8 @@ where
                     true => Ok(true),
                     false => func(SliceData::load_builder(key)?, Some(cursor), Some(cursor_2.clone()))
                 }
-            }
+            },
+            &mut 0
         )? || (!chk && !func(SliceData::load_builder(key2)?, None, Some(cursor_2))?) {
             return Ok(false)
         }
@@ -856,13 +1031,15 @@ where
             LabelReader::with_cell(&cell)?,
             key,
             bit_len_1,
-            &mut |key, cursor| func(SliceData::load_builder(key)?, Some(cursor), None)
+            &mut |key, cursor| func(SliceData::load_builder(key)?, Some(cursor), None),
+            &mut 0
         ),
         (None, Some(cell)) => return iterate_internal::<T, _>( // only 2 leaves
             LabelReader::with_cell(&cell)?,
             key,
             bit_len_2,
-            &mut |key, cursor| func(SliceData::load_builder(key)?, None, Some(cursor))
+            &mut |key, cursor| func(SliceData::load_builder(key)?, None, Some(cursor)),
+            &mut 0
         ),
         _ => return Ok(true)
     };
@@ -941,7 +1118,8 @@ fn iterate_internal<T, F>(
     mut cursor: LabelReader,
     mut key: BuilderData,
     mut bit_len: usize,
-    found: &mut F
+    found: &mut F,
+    gas_consumer: &mut dyn GasConsumer
 ) -> Result<bool>
 where
     F: FnMut(BuilderData, SliceData) -> Result<bool>,
@@ -957,7 +1135,8 @@ where
         for i in 0..2 {
             let mut key = key.clone();
             key.append_bit_bool(i != 0)?;
-            if !iterate_internal::<T, F>(cursor.next_reader::<T>(i, &mut 0)?, key, bit_len, found)? {
+            let cursor = cursor.next_reader::<T>(i, gas_consumer)?;
+            if !iterate_internal::<T, F>(cursor, key, bit_len, found, gas_consumer)? {
                 return Ok(false)
             }
         }
@@ -971,13 +1150,15 @@ fn count_internal<T: HashmapType + ?Sized>(
     mut bit_len: usize,
     len: &mut usize,
     max: usize,
+    gas_consumer: &mut dyn GasConsumer,
 ) -> Result<bool> {
     if !cursor.already_read() {
         cursor.skip_label(&mut bit_len)?;
     }
     match bit_len.checked_sub(1) {
         Some(bit_len) => for i in 0..2 {
-            if !count_internal::<T>(cursor.next_reader::<T>(i, &mut 0)?, bit_len, len, max)? {
+            let cursor = cursor.next_reader::<T>(i, gas_consumer)?;
+            if !count_internal::<T>(cursor, bit_len, len, max, gas_consumer)? {
                 return Ok(false)
             }
         }
@@ -1200,11 +1381,15 @@ pub trait HashmapRemover: HashmapType {
     fn remove(&mut self, key: SliceData) -> Leaf {
         self.hashmap_remove(key, &mut 0)
     }
-    fn hashmap_filter<F>(&mut self, mut func: F) -> Result<()>
+    fn hashmap_filter<F>(&mut self, func: F) -> Result<()>
+        where F: FnMut(&BuilderData, SliceData) -> Result<HashmapFilterResult> {
+        self.hashmap_filter_with_gas(func, &mut 0)
+    }
+    fn hashmap_filter_with_gas<F>(&mut self, mut func: F, gas_consumer: &mut dyn GasConsumer) -> Result<()>
         where F: FnMut(&BuilderData, SliceData) -> Result<HashmapFilterResult> {
         let bit_len = self.bit_len();
         let mut result = HashmapFilterResult::Accept;
-        filter_next::<Self, _>(self.data_mut(), &mut BuilderData::default(), bit_len, &mut result, &mut func)?;
+        filter_next::<Self, _>(self.data_mut(), &mut BuilderData::default(), bit_len, &mut result, &mut func, gas_consumer)?;
         self.after_remove()?;
         Ok(())
     }
@@ -1216,6 +1401,7 @@ fn filter_next<T, F>(
     mut bit_len: usize,
     result: &mut HashmapFilterResult,
     func: &mut F,
+    gas_consumer: &mut dyn GasConsumer,
 ) -> Result<(bool, Option<SliceData>)> // is_removed and remainder
     where
         T: HashmapType + ?Sized,
@@ -1229,11 +1415,11 @@ fn filter_next<T, F>(
             *result = HashmapFilterResult::Cancel;
             return Ok((false, None))
         }
-        Some(cell) => SliceData::load_cell_ref(cell)?,
+        Some(cell) => gas_consumer.load_cell(cell.clone())?,
     };
     let key_length = key.length_in_bits();
     let this_bit_len = bit_len;
-    *key = cursor.get_label_raw(&mut bit_len, std::mem::take(key))?;
+    *key = cursor.get_label_raw(&mut bit_len, core::mem::take(key))?;
     let remainder = cursor.clone();
     if bit_len == 0 {
         let removed = match func(key, cursor)? {
@@ -1256,7 +1442,7 @@ fn filter_next<T, F>(
         let mut key = key.clone();
         key.append_bit_bool(i == 1)?;
         let mut cell = Some(cursor.checked_drain_reference()?);
-        let (removed, remainder) = filter_next::<T, F>(&mut cell, &mut key, bit_len, result, func)?;
+        let (removed, remainder) = filter_next::<T, F>(&mut cell, &mut key, bit_len, result, func, gas_consumer)?;
         if *result == HashmapFilterResult::Cancel {
             return Ok((false, None))
         }
@@ -1272,7 +1458,7 @@ fn filter_next<T, F>(
             let mut label = SliceData::load_builder(key.clone())?;
             label.move_by(key_length)?;
             let (builder, remainder) = T::make_fork(&label, this_bit_len, left, right, false)?;
-            *cell_opt = Some(builder.into_cell()?);
+            *cell_opt = Some(gas_consumer.finalize_cell(builder)?);
             Ok((true, Some(remainder)))
         } else { // replace fork with edge
             *key = new_key;
@@ -1282,7 +1468,7 @@ fn filter_next<T, F>(
             if let Some(ref remainder) = next_remainder {
                 builder.checked_append_references_and_data(remainder)?;
             }
-            *cell_opt = Some(builder.into_cell()?);
+            *cell_opt = Some(gas_consumer.finalize_cell(builder)?);
             Ok((true, next_remainder))
         }
     } else {
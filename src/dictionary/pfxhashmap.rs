@@ -11,7 +11,7 @@
 * limitations under the License.
 */
 
-use std::fmt;
+use core::fmt;
 
 use crate::GasConsumer;
 use crate::types::Result;
@@ -29,7 +29,7 @@ pub struct PfxHashmapE {
 impl fmt::Display for PfxHashmapE {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self.data() {
-            Some(cell) => write!(f, "PfxHashmap: {}", cell),
+            Some(cell) => write!(f, "PfxHashmap: {}", cell.tree()),
             None => write!(f, "Empty PfxHashmap"),
         }
     }
@@ -238,7 +238,7 @@ impl HashmapType for PfxHashmapE {
         let mut remainder = BuilderData::new();
         remainder.append_bit_one()?;
         if swap {
-            std::mem::swap(&mut left, &mut right);
+            core::mem::swap(&mut left, &mut right);
         }
         remainder.checked_append_reference(left)?;
         remainder.checked_append_reference(right)?;
@@ -0,0 +1,125 @@
+/*
+* Copyright 2018-2020 TON DEV SOLUTIONS LTD.
+*
+* Licensed under the SOFTWARE EVALUATION License (the "License"); you may not use
+* this file except in compliance with the License.
+*
+* Unless required by applicable law or agreed to in writing, software
+* distributed under the License is distributed on an "AS IS" BASIS,
+* WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+* See the License for the specific TON DEV software governing permissions and
+* limitations under the License.
+*/
+
+//! Fail points for tests of error handling. With feature `failpoints` a test can make chosen
+//! operations fail deterministically, otherwise fail points are compiled out.
+//! Fail points are set per thread, so parallel tests do not affect each other
+//! (and operations performed by other threads are not affected).
+
+use crate::types::Result;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum FailPoint {
+    /// appending data or reference to builder fails with `ExceptionCode::CellOverflow`
+    BuilderAppend = 0,
+    /// finalization of cell fails with hash mismatch
+    CellFinalize = 1,
+    /// reading of BOC fails with IO error
+    BocRead = 2,
+    /// writing of BOC fails with IO error
+    BocWrite = 3,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FailMode {
+    /// every pass fails
+    Always,
+    /// next `count` passes fail
+    Times(u32),
+    /// one pass fails after `count` successful ones
+    After(u32),
+}
+
+#[cfg(feature = "failpoints")]
+mod registry {
+    use core::cell::RefCell;
+
+    use super::{FailMode, FailPoint};
+
+    const POINTS_COUNT: usize = 4;
+
+    #[derive(Default)]
+    struct Registry {
+        modes: [Option<FailMode>; POINTS_COUNT],
+        hits: [u64; POINTS_COUNT],
+    }
+
+    std::thread_local! {
+        static REGISTRY: RefCell<Registry> = RefCell::new(Registry::default());
+    }
+
+    /// Sets mode of fail point for current thread, counter of its passes is reset
+    pub fn set_fail_point(point: FailPoint, mode: FailMode) {
+        REGISTRY.with(|registry| {
+            let mut registry = registry.borrow_mut();
+            registry.modes[point as usize] = Some(mode);
+            registry.hits[point as usize] = 0;
+        })
+    }
+
+    pub fn clear_fail_point(point: FailPoint) {
+        REGISTRY.with(|registry| registry.borrow_mut().modes[point as usize] = None)
+    }
+
+    pub fn clear_fail_points() {
+        REGISTRY.with(|registry| *registry.borrow_mut() = Registry::default())
+    }
+
+    /// Count of passes of fail point in current thread since it was set
+    pub fn fail_point_hits(point: FailPoint) -> u64 {
+        REGISTRY.with(|registry| registry.borrow().hits[point as usize])
+    }
+
+    pub(super) fn should_fail(point: FailPoint) -> bool {
+        REGISTRY.with(|registry| {
+            let mut registry = registry.borrow_mut();
+            let index = point as usize;
+            let mode = match registry.modes[index] {
+                Some(mode) => mode,
+                None => return false
+            };
+            registry.hits[index] += 1;
+            let (mode, fail) = match mode {
+                FailMode::Always => (Some(mode), true),
+                FailMode::Times(0) => (None, false),
+                FailMode::Times(1) => (None, true),
+                FailMode::Times(count) => (Some(FailMode::Times(count - 1)), true),
+                FailMode::After(0) => (None, true),
+                FailMode::After(count) => (Some(FailMode::After(count - 1)), false),
+            };
+            registry.modes[index] = mode;
+            fail
+        })
+    }
+}
+
+#[cfg(feature = "failpoints")]
+pub use self::registry::{clear_fail_point, clear_fail_points, fail_point_hits, set_fail_point};
+
+/// Fails with error of fail point if it is set to fail in current thread
+#[inline(always)]
+pub(crate) fn inject(point: FailPoint) -> Result<()> {
+    #[cfg(feature = "failpoints")] {
+        if registry::should_fail(point) {
+            return Err(match point {
+                FailPoint::BuilderAppend => crate::types::ExceptionCode::CellOverflow.into(),
+                FailPoint::CellFinalize => crate::error!("Calculated hash is not equal stored one (fail point)"),
+                FailPoint::BocRead | FailPoint::BocWrite => std::io::Error::other("fail point").into(),
+            })
+        }
+    }
+    #[cfg(not(feature = "failpoints"))] {
+        let _ = point;
+    }
+    Ok(())
+}
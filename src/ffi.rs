@@ -0,0 +1,284 @@
+/*
+* Copyright 2018-2020 TON DEV SOLUTIONS LTD.
+*
+* Licensed under the SOFTWARE EVALUATION License (the "License"); you may not use
+* this file except in compliance with the License.
+*
+* Unless required by applicable law or agreed to in writing, software
+* distributed under the License is distributed on an "AS IS" BASIS,
+* WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+* See the License for the specific TON DEV software governing permissions and
+* limitations under the License.
+*/
+
+//! C interface for BOC reading and writing, hashes and slice reading.
+//! Cells and slices are passed as opaque handles which must be freed by
+//! `ton_cell_free` and `ton_slice_free`. Functions report errors by returning
+//! null or `false`, fallible ones also write `ExceptionCode::code_of` the error to optional
+//! `error_code`: code of `ExceptionCode` or `ErrorCode` (0 on success, `FatalError` for invalid
+//! arguments and panics).
+//! Panics never unwind into the caller. Header is generated by `cbindgen --config cbindgen.toml`
+//! into `include/ton_types.h`. To get a linkable library build the crate
+//! with `cargo rustc --release --features ffi --crate-type staticlib` (or `cdylib`).
+
+use alloc::boxed::Box;
+use core::{ptr, slice};
+use std::panic::{self, AssertUnwindSafe};
+
+use crate::{
+    cell::{Cell, SliceData},
+    cells_serialization::{deserialize_tree_of_cells, serialize_toc},
+    fail, ExceptionCode, Result,
+};
+
+/// Opaque handle of cell
+pub struct TonCell(Cell);
+
+/// Opaque handle of slice, it keeps its cell alive
+pub struct TonSlice(SliceData);
+
+fn into_cell_handle(cell: Cell) -> *mut TonCell {
+    Box::into_raw(Box::new(TonCell(cell)))
+}
+
+/// Runs body of exported function, stores its error code and returns `failed` on error or panic.
+/// # Safety
+/// `error_code` must be null or writable
+unsafe fn guarded<T>(error_code: *mut i32, failed: T, f: impl FnOnce() -> Result<T>) -> T {
+    let (code, result) = match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(result)) => (ExceptionCode::NormalTermination.code(), result),
+        Ok(Err(err)) => (ExceptionCode::code_of(&err), failed),
+        Err(_) => (ExceptionCode::FatalError.code(), failed)
+    };
+    if !error_code.is_null() {
+        *error_code = code;
+    }
+    result
+}
+
+/// Reads single root BOC, returns null if data is not a valid BOC.
+/// # Safety
+/// `data` must point to `len` readable bytes, `error_code` must be null or writable
+#[no_mangle]
+pub unsafe extern "C" fn ton_boc_parse(data: *const u8, len: usize, error_code: *mut i32) -> *mut TonCell {
+    guarded(error_code, ptr::null_mut(), || {
+        if data.is_null() {
+            fail!(ExceptionCode::FatalError)
+        }
+        let mut data = slice::from_raw_parts(data, len);
+        Ok(into_cell_handle(deserialize_tree_of_cells(&mut data)?))
+    })
+}
+
+/// Writes cell as BOC, returns buffer to be freed by `ton_bytes_free` and stores its length to `len`.
+/// # Safety
+/// `cell` must be a live handle, `len` must be writable, `error_code` must be null or writable
+#[no_mangle]
+pub unsafe extern "C" fn ton_boc_serialize(cell: *const TonCell, len: *mut usize, error_code: *mut i32) -> *mut u8 {
+    guarded(error_code, ptr::null_mut(), || {
+        if cell.is_null() || len.is_null() {
+            fail!(ExceptionCode::FatalError)
+        }
+        let data = serialize_toc(&(*cell).0)?;
+        *len = data.len();
+        Ok(Box::into_raw(data.into_boxed_slice()) as *mut u8)
+    })
+}
+
+/// # Safety
+/// `data` and `len` must be returned by `ton_boc_serialize`, each buffer is freed only once
+#[no_mangle]
+pub unsafe extern "C" fn ton_bytes_free(data: *mut u8, len: usize) {
+    guarded(ptr::null_mut(), (), || {
+        if !data.is_null() {
+            drop(Box::from_raw(ptr::slice_from_raw_parts_mut(data, len)));
+        }
+        Ok(())
+    })
+}
+
+/// # Safety
+/// `cell` must be null or a live handle, each handle is freed only once
+#[no_mangle]
+pub unsafe extern "C" fn ton_cell_free(cell: *mut TonCell) {
+    guarded(ptr::null_mut(), (), || {
+        if !cell.is_null() {
+            drop(Box::from_raw(cell));
+        }
+        Ok(())
+    })
+}
+
+/// Returns new handle of the same cell
+/// # Safety
+/// `cell` must be a live handle
+#[no_mangle]
+pub unsafe extern "C" fn ton_cell_clone(cell: *const TonCell) -> *mut TonCell {
+    guarded(ptr::null_mut(), ptr::null_mut(), || {
+        match cell.is_null() {
+            true => Ok(ptr::null_mut()),
+            false => Ok(into_cell_handle((*cell).0.clone()))
+        }
+    })
+}
+
+/// Writes 32 bytes of representation hash to `hash`.
+/// # Safety
+/// `cell` must be a live handle and `hash` must point to 32 writable bytes
+#[no_mangle]
+pub unsafe extern "C" fn ton_cell_repr_hash(cell: *const TonCell, hash: *mut u8) -> bool {
+    guarded(ptr::null_mut(), false, || {
+        if cell.is_null() || hash.is_null() {
+            return Ok(false)
+        }
+        ptr::copy_nonoverlapping((*cell).0.repr_hash().as_slice().as_ptr(), hash, 32);
+        Ok(true)
+    })
+}
+
+/// # Safety
+/// `cell` must be a live handle
+#[no_mangle]
+pub unsafe extern "C" fn ton_cell_bit_length(cell: *const TonCell) -> usize {
+    guarded(ptr::null_mut(), 0, || {
+        match cell.is_null() {
+            true => Ok(0),
+            false => Ok((*cell).0.bit_length())
+        }
+    })
+}
+
+/// # Safety
+/// `cell` must be a live handle
+#[no_mangle]
+pub unsafe extern "C" fn ton_cell_references_count(cell: *const TonCell) -> usize {
+    guarded(ptr::null_mut(), 0, || {
+        match cell.is_null() {
+            true => Ok(0),
+            false => Ok((*cell).0.references_count())
+        }
+    })
+}
+
+/// Returns new handle of child cell or null if there is no such reference.
+/// # Safety
+/// `cell` must be a live handle, `error_code` must be null or writable
+#[no_mangle]
+pub unsafe extern "C" fn ton_cell_reference(cell: *const TonCell, index: usize, error_code: *mut i32) -> *mut TonCell {
+    guarded(error_code, ptr::null_mut(), || {
+        if cell.is_null() {
+            fail!(ExceptionCode::FatalError)
+        }
+        Ok(into_cell_handle((*cell).0.reference(index)?))
+    })
+}
+
+/// Starts reading of cell, returns null for exotic cells.
+/// # Safety
+/// `cell` must be a live handle, `error_code` must be null or writable
+#[no_mangle]
+pub unsafe extern "C" fn ton_slice_new(cell: *const TonCell, error_code: *mut i32) -> *mut TonSlice {
+    guarded(error_code, ptr::null_mut(), || {
+        if cell.is_null() {
+            fail!(ExceptionCode::FatalError)
+        }
+        let slice = SliceData::load_cell((*cell).0.clone())?;
+        Ok(Box::into_raw(Box::new(TonSlice(slice))))
+    })
+}
+
+/// # Safety
+/// `slice` must be null or a live handle, each handle is freed only once
+#[no_mangle]
+pub unsafe extern "C" fn ton_slice_free(slice: *mut TonSlice) {
+    guarded(ptr::null_mut(), (), || {
+        if !slice.is_null() {
+            drop(Box::from_raw(slice));
+        }
+        Ok(())
+    })
+}
+
+/// # Safety
+/// `slice` must be a live handle
+#[no_mangle]
+pub unsafe extern "C" fn ton_slice_remaining_bits(slice: *const TonSlice) -> usize {
+    guarded(ptr::null_mut(), 0, || {
+        match slice.is_null() {
+            true => Ok(0),
+            false => Ok((*slice).0.remaining_bits())
+        }
+    })
+}
+
+/// # Safety
+/// `slice` must be a live handle
+#[no_mangle]
+pub unsafe extern "C" fn ton_slice_remaining_references(slice: *const TonSlice) -> usize {
+    guarded(ptr::null_mut(), 0, || {
+        match slice.is_null() {
+            true => Ok(0),
+            false => Ok((*slice).0.remaining_references())
+        }
+    })
+}
+
+/// Reads unsigned integer of `bits` (up to 64) bits, slice is not moved on failure.
+/// # Safety
+/// `slice` must be a live handle, `value` must be writable, `error_code` must be null or writable
+#[no_mangle]
+pub unsafe extern "C" fn ton_slice_get_next_int(
+    slice: *mut TonSlice,
+    bits: usize,
+    value: *mut u64,
+    error_code: *mut i32
+) -> bool {
+    guarded(error_code, false, || {
+        if slice.is_null() || value.is_null() {
+            fail!(ExceptionCode::FatalError)
+        }
+        if bits > 64 {
+            fail!(ExceptionCode::RangeCheckError)
+        }
+        *value = (*slice).0.get_next_int(bits)?;
+        Ok(true)
+    })
+}
+
+/// Reads `len` whole bytes to `data`, slice is not moved on failure.
+/// # Safety
+/// `slice` must be a live handle, `data` must point to `len` writable bytes,
+/// `error_code` must be null or writable
+#[no_mangle]
+pub unsafe extern "C" fn ton_slice_get_next_bytes(
+    slice: *mut TonSlice,
+    data: *mut u8,
+    len: usize,
+    error_code: *mut i32
+) -> bool {
+    guarded(error_code, false, || {
+        if slice.is_null() || data.is_null() {
+            fail!(ExceptionCode::FatalError)
+        }
+        let slice = &mut (*slice).0;
+        if len.checked_mul(8).is_none_or(|bits| bits > slice.remaining_bits()) {
+            fail!(ExceptionCode::CellUnderflow)
+        }
+        let bytes = slice.get_next_bytes(len)?;
+        ptr::copy_nonoverlapping(bytes.as_ptr(), data, len);
+        Ok(true)
+    })
+}
+
+/// Returns new handle of next reference or null if there are no references left.
+/// # Safety
+/// `slice` must be a live handle, `error_code` must be null or writable
+#[no_mangle]
+pub unsafe extern "C" fn ton_slice_get_next_reference(slice: *mut TonSlice, error_code: *mut i32) -> *mut TonCell {
+    guarded(error_code, ptr::null_mut(), || {
+        if slice.is_null() {
+            fail!(ExceptionCode::FatalError)
+        }
+        Ok(into_cell_handle((*slice).0.checked_drain_reference()?))
+    })
+}
@@ -0,0 +1,157 @@
+/*
+* Copyright 2018-2020 TON DEV SOLUTIONS LTD.
+*
+* Licensed under the SOFTWARE EVALUATION License (the "License"); you may not use
+* this file except in compliance with the License.
+*
+* Unless required by applicable law or agreed to in writing, software
+* distributed under the License is distributed on an "AS IS" BASIS,
+* WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+* See the License for the specific TON DEV software governing permissions and
+* limitations under the License.
+*/
+
+//! Generators for fuzzing harnesses.
+//! Raw fuzzer input is turned into structurally valid cell trees and BOCs,
+//! BOCs can be deliberately corrupted to test error paths of the reader.
+
+use arbitrary::Unstructured;
+
+use crate::{
+    fail, Result,
+    cell::{ArbitraryCellParams, Cell},
+    cells_serialization::{BagOfCells, BocDeserializer, BocSerialiseMode},
+    corruption::Corruption,
+};
+
+/// Knobs of the generator
+#[derive(Clone, Debug)]
+pub struct FuzzParams {
+    /// limits of every generated tree
+    pub cells: ArbitraryCellParams,
+    /// max count of roots in generated BOC
+    pub max_roots: usize,
+    /// allow to corrupt generated BOC
+    pub corrupt: bool,
+}
+
+impl Default for FuzzParams {
+    fn default() -> Self {
+        Self {
+            cells: ArbitraryCellParams::default(),
+            max_roots: 4,
+            corrupt: false,
+        }
+    }
+}
+
+/// Generated BOC with the roots it was built from
+#[derive(Clone, Debug)]
+pub struct FuzzBoc {
+    pub roots: Vec<Cell>,
+    pub mode: BocSerialiseMode,
+    pub boc: Vec<u8>,
+    pub corruption: Option<Corruption>,
+}
+
+impl FuzzParams {
+    pub fn with_cells(cells: ArbitraryCellParams) -> Self {
+        Self { cells, ..Self::default() }
+    }
+
+    pub fn set_max_roots(mut self, max_roots: usize) -> Self {
+        self.max_roots = max_roots;
+        self
+    }
+
+    pub fn set_corrupt(mut self, corrupt: bool) -> Self {
+        self.corrupt = corrupt;
+        self
+    }
+
+    /// Builds cell tree from fuzzer input, returns None if input is not suitable
+    pub fn cell(&self, data: &[u8]) -> Option<Cell> {
+        self.cells.cell(&mut Unstructured::new(data)).ok()
+    }
+
+    /// Builds BOC from fuzzer input, returns None if input is not suitable
+    pub fn boc(&self, data: &[u8]) -> Option<FuzzBoc> {
+        let mut u = Unstructured::new(data);
+        self.gen_boc(&mut u).ok()
+    }
+
+    fn gen_boc(&self, u: &mut Unstructured) -> arbitrary::Result<FuzzBoc> {
+        let count = u.int_in_range(1..=self.max_roots.max(1))?;
+        let mut roots = Vec::with_capacity(count);
+        for _ in 0..count {
+            let root = self.cells.cell(u)?;
+            // roots of BOC must be unique
+            if roots.iter().all(|other: &Cell| other.repr_hash() != root.repr_hash()) {
+                roots.push(root);
+            }
+        }
+        // legacy indexed modes support only single root
+        let mode = match u.choose_index(3)? {
+            0 if roots.len() == 1 => BocSerialiseMode::Indexed,
+            1 if roots.len() == 1 => BocSerialiseMode::IndexedCrc,
+            _ => BocSerialiseMode::Generic {
+                index: u.arbitrary()?,
+                crc: u.arbitrary()?,
+                cache_bits: u.arbitrary()?,
+                flags: 0,
+            }
+        };
+        // custom sizes can't be less than required ones
+        let ref_size = u.arbitrary::<bool>()?.then_some(4);
+        let offset_size = u.arbitrary::<bool>()?.then_some(8);
+        let mut boc = Vec::new();
+        BagOfCells::with_params(&roots, &[], &|| false)
+            .and_then(|bag| bag.write_to_ex(&mut boc, mode.clone(), ref_size, offset_size))
+            .map_err(|_| arbitrary::Error::IncorrectFormat)?;
+        let corruption = match self.corrupt && u.arbitrary()? {
+            true => Some(corrupt_boc(u, &mut boc)?),
+            false => None,
+        };
+        Ok(FuzzBoc { roots, mode, boc, corruption })
+    }
+}
+
+fn corrupt_boc(u: &mut Unstructured, boc: &mut Vec<u8>) -> arbitrary::Result<Corruption> {
+    let last = boc.len() - 1;
+    let corruption = match u.choose_index(4)? {
+        0 => Corruption::FlipBit { offset: u.int_in_range(0..=boc.len() * 8 - 1)? },
+        1 => Corruption::Truncate { len: u.int_in_range(0..=last)? },
+        // header fields are the most interesting to break
+        2 => Corruption::ReplaceByte { offset: u.int_in_range(0..=last.min(31))?, value: u.arbitrary()? },
+        _ => {
+            let len = u.int_in_range(1..=64)?;
+            Corruption::Append { data: (0..len).map(|_| u.arbitrary()).collect::<arbitrary::Result<_>>()? }
+        }
+    };
+    corruption.apply(boc).map_err(|_| arbitrary::Error::IncorrectFormat)?;
+    Ok(corruption)
+}
+
+impl FuzzBoc {
+    /// Reads BOC back and checks invariants:
+    /// not corrupted BOC must give the same roots, any successfully read roots must survive round trip.
+    pub fn check_invariants(&self) -> Result<()> {
+        let roots = match BocDeserializer::new().deserialize(&mut self.boc.as_slice()) {
+            Ok(result) => result.roots,
+            Err(err) => match self.corruption {
+                Some(_) => return Ok(()),
+                None => fail!("valid BOC is not read: {}", err)
+            }
+        };
+        if self.corruption.is_none() && roots != self.roots {
+            fail!("roots of BOC are changed after round trip")
+        }
+        let mut boc = Vec::new();
+        BagOfCells::with_params(&roots, &[], &|| false)?.write_to_ex(&mut boc, self.mode.clone(), None, None)?;
+        let again = BocDeserializer::new().deserialize(&mut boc.as_slice())?.roots;
+        if again != roots {
+            fail!("roots read from corrupted BOC are changed after round trip")
+        }
+        Ok(())
+    }
+}
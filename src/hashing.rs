@@ -0,0 +1,444 @@
+/*
+* Copyright 2018-2020 TON DEV SOLUTIONS LTD.
+*
+* Licensed under the SOFTWARE EVALUATION License (the "License"); you may not use
+* this file except in compliance with the License.
+*
+* Unless required by applicable law or agreed to in writing, software
+* distributed under the License is distributed on an "AS IS" BASIS,
+* WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+* See the License for the specific TON DEV software governing permissions and
+* limitations under the License.
+*/
+
+//! SHA-256 used for all hashes of the crate: cells' hashes, file hashes of BOCs.
+//! By default it is `sha2` crate, it uses SHA extensions of x86 and ARMv8 CPUs if they are
+//! detected at runtime. Embedders can set their own backend, for example hardware-offloaded
+//! or certified one. Used path is reported by `sha256_implementation`.
+//! `sha256_batch` hashes many independent messages such as cell representations at once.
+//! CRC32-C of BOC files is available for framing layers as streaming `Crc32c`.
+
+use alloc::{boxed::Box, vec::Vec};
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use sha2::Digest;
+
+use crate::{
+    error, fail,
+    io::Write,
+    metrics::{self, MetricsEvent},
+    types::{OnceBox, UInt256},
+    Result,
+};
+
+/// Incremental hashing of one message by backend
+pub trait Sha256State {
+    fn update(&mut self, data: &[u8]);
+    fn finalize(self: Box<Self>) -> [u8; 32];
+}
+
+/// Implementation of SHA-256.
+/// Cells are hashed incrementally so every cell allocates one state.
+pub trait Sha256Backend: Send + Sync {
+    fn new_state(&self) -> Box<dyn Sha256State>;
+    fn digest(&self, data: &[u8]) -> [u8; 32] {
+        let mut state = self.new_state();
+        state.update(data);
+        state.finalize()
+    }
+    fn digest_batch(&self, messages: &[&[u8]]) -> Vec<[u8; 32]> {
+        messages.iter().map(|message| self.digest(message)).collect()
+    }
+}
+
+static BACKEND: OnceBox<dyn Sha256Backend> = OnceBox::new();
+// set by the first calculated hash, backend can't be changed after it
+static HASHED: AtomicBool = AtomicBool::new(false);
+
+/// Sets global SHA-256 backend, it can be done only once before any hash is calculated
+pub fn set_sha256_backend(backend: Box<dyn Sha256Backend>) -> Result<()> {
+    if HASHED.load(Ordering::Acquire) {
+        fail!("sha256 backend can't be set after hashes are calculated")
+    }
+    BACKEND.set(backend).map_err(|_| error!("sha256 backend is already set"))
+}
+
+// backend for calculation of hash
+fn backend() -> Option<&'static dyn Sha256Backend> {
+    if !HASHED.load(Ordering::Relaxed) {
+        HASHED.store(true, Ordering::Release);
+    }
+    BACKEND.get()
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Sha256Implementation {
+    /// Set by `set_sha256_backend`
+    Backend,
+    /// SHA extensions of x86 (SHA-NI)
+    ShaNi,
+    /// Cryptographic extensions of ARMv8
+    ArmV8Crypto,
+    /// Portable implementation
+    Software,
+}
+
+/// Implementation calculating hashes of cells
+pub fn sha256_implementation() -> Sha256Implementation {
+    if BACKEND.get().is_some() {
+        Sha256Implementation::Backend
+    } else if sha_extensions_detected() {
+        #[cfg(target_arch = "aarch64")]
+        return Sha256Implementation::ArmV8Crypto;
+        #[cfg(not(target_arch = "aarch64"))]
+        return Sha256Implementation::ShaNi;
+    } else {
+        Sha256Implementation::Software
+    }
+}
+
+// the same detection as in `sha2`
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+cpufeatures::new!(sha_cpuid, "sha", "sse2", "ssse3", "sse4.1");
+
+#[cfg(target_arch = "aarch64")]
+cpufeatures::new!(sha_cpuid, "sha2");
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64", target_arch = "aarch64"))]
+fn sha_extensions_detected() -> bool {
+    sha_cpuid::get()
+}
+
+#[cfg(not(any(target_arch = "x86", target_arch = "x86_64", target_arch = "aarch64")))]
+fn sha_extensions_detected() -> bool {
+    false
+}
+
+/// Incremental SHA-256 with current backend
+pub(crate) enum Sha256 {
+    Default(sha2::Sha256),
+    Backend(Box<dyn Sha256State>),
+}
+
+impl Sha256 {
+    pub(crate) fn new() -> Self {
+        metrics::record(MetricsEvent::Sha256, 1);
+        match backend() {
+            Some(backend) => Self::Backend(backend.new_state()),
+            None => Self::Default(sha2::Sha256::new())
+        }
+    }
+
+    pub(crate) fn update(&mut self, data: impl AsRef<[u8]>) {
+        match self {
+            Self::Default(hasher) => hasher.update(data),
+            Self::Backend(state) => state.update(data.as_ref())
+        }
+    }
+
+    pub(crate) fn finalize(self) -> [u8; 32] {
+        match self {
+            Self::Default(hasher) => hasher.finalize().into(),
+            Self::Backend(state) => state.finalize()
+        }
+    }
+}
+
+/// SHA-256 of data with current backend
+pub fn sha256_digest(data: &[u8]) -> UInt256 {
+    metrics::record(MetricsEvent::Sha256, 1);
+    UInt256::from(digest(data))
+}
+
+fn digest(data: &[u8]) -> [u8; 32] {
+    match backend() {
+        Some(backend) => backend.digest(data),
+        None => sha2::Sha256::digest(data).into()
+    }
+}
+
+static CASTAGNOLI: crc::Crc<u32> = crc::Crc::<u32>::new(&crc::CRC_32_ISCSI);
+
+/// Streaming CRC32-C (Castagnoli) with the same parameters as in BOC files
+#[derive(Clone)]
+pub struct Crc32c(crc::Digest<'static, u32>);
+
+impl Crc32c {
+    pub fn new() -> Self {
+        Self(CASTAGNOLI.digest())
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        self.0.update(data)
+    }
+
+    pub fn finalize(self) -> u32 {
+        self.0.finalize()
+    }
+}
+
+impl Default for Crc32c {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Write for Crc32c {
+    fn write(&mut self, buf: &[u8]) -> crate::io::Result<usize> {
+        self.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> crate::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// CRC32-C of data at once
+pub fn crc32c(data: &[u8]) -> u32 {
+    CASTAGNOLI.checksum(data)
+}
+
+/// SHA-256 of every message in the same order.
+/// If CPU has AVX2 but has no SHA extensions, messages are grouped by length and hashed
+/// by several at once (multi-buffer), it is about twice faster than one by one.
+/// Backend set by `set_sha256_backend` hashes all messages by itself.
+pub fn sha256_batch<T: AsRef<[u8]>>(messages: &[T]) -> Vec<UInt256> {
+    metrics::record(MetricsEvent::Sha256, messages.len() as u64);
+    if let Some(backend) = backend() {
+        let messages = messages.iter().map(|message| message.as_ref()).collect::<Vec<_>>();
+        return backend.digest_batch(&messages).into_iter().map(UInt256::from).collect()
+    }
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    if messages.len() > 1 && multi_buffer::preferred() {
+        return multi_buffer::sha256_batch(messages)
+    }
+    messages.iter().map(|message| UInt256::from(digest(message.as_ref()))).collect()
+}
+
+/// Multi-buffer path of `sha256_batch` used even if CPU has SHA extensions,
+/// `None` if CPU has no AVX2 (or is not x86)
+#[cfg(feature = "testing")]
+pub fn sha256_batch_multi_buffer<T: AsRef<[u8]>>(messages: &[T]) -> Option<Vec<UInt256>> {
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    if multi_buffer::supported() {
+        return Some(multi_buffer::sha256_batch(messages))
+    }
+    let _ = messages;
+    None
+}
+
+// Rounds of all lanes are done together so compiler vectorizes them.
+// Without AVX2 vectorized rounds are not faster than usual ones.
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+mod multi_buffer {
+    use alloc::vec;
+
+    use super::*;
+
+    const LANES: usize = 8;
+    const BLOCK_LEN: usize = 64;
+
+    type Lanes = [u32; LANES];
+
+    const K: [u32; 64] = [
+        0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+        0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+        0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+        0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+        0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+        0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+        0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+        0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+    ];
+
+    const H0: [u32; 8] = [
+        0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19,
+    ];
+
+    cpufeatures::new!(avx2_cpuid, "avx2");
+
+    pub(super) fn supported() -> bool {
+        avx2_cpuid::get()
+    }
+
+    pub(super) fn preferred() -> bool {
+        !sha_extensions_detected() && supported()
+    }
+
+    pub(super) fn sha256_batch<T: AsRef<[u8]>>(messages: &[T]) -> Vec<UInt256> {
+        let mut order = (0..messages.len()).collect::<Vec<_>>();
+        order.sort_unstable_by_key(|index| blocks_count(messages[*index].as_ref().len()));
+        let mut hashes = vec![UInt256::default(); messages.len()];
+        for group in order.chunks(LANES) {
+            if group.len() == 1 {
+                hashes[group[0]] = UInt256::from(digest(messages[group[0]].as_ref()));
+                continue
+            }
+            let mut lanes: [&[u8]; LANES] = [&[]; LANES];
+            for (lane, index) in group.iter().enumerate() {
+                lanes[lane] = messages[*index].as_ref();
+            }
+            let result = sha256_lanes(&lanes);
+            for (lane, index) in group.iter().enumerate() {
+                hashes[*index] = UInt256::from(result[lane]);
+            }
+        }
+        hashes
+    }
+
+    // not inlined into callers, so only rounds use AVX registers
+    #[target_feature(enable = "avx2")]
+    #[inline(never)]
+    unsafe fn compress_avx2(state: &[Lanes; 8], block: &[Lanes; 16]) -> [Lanes; 8] {
+        compress(state, block)
+    }
+
+    // message, 0x80 byte and 64-bit length
+    fn blocks_count(len: usize) -> usize {
+        (len + 9).div_ceil(BLOCK_LEN)
+    }
+
+    // block of padded message
+    fn message_block(message: &[u8], index: usize, block: &mut [u8; BLOCK_LEN]) {
+        *block = [0; BLOCK_LEN];
+        let start = index * BLOCK_LEN;
+        if start < message.len() {
+            let end = core::cmp::min(message.len(), start + BLOCK_LEN);
+            block[..end - start].copy_from_slice(&message[start..end]);
+        }
+        if (start..start + BLOCK_LEN).contains(&message.len()) {
+            block[message.len() - start] = 0x80;
+        }
+        if index + 1 == blocks_count(message.len()) {
+            block[BLOCK_LEN - 8..].copy_from_slice(&((message.len() as u64) * 8).to_be_bytes());
+        }
+    }
+
+    fn sha256_lanes(messages: &[&[u8]; LANES]) -> [[u8; 32]; LANES] {
+        let blocks = messages.map(|message| blocks_count(message.len()));
+        let max_blocks = blocks.iter().copied().max().unwrap_or_default();
+        let mut state = H0.map(|h| [h; LANES]);
+        let mut w = [[0; LANES]; 16];
+        let mut data = [[0; BLOCK_LEN]; LANES];
+        for index in 0..max_blocks {
+            for (lane, message) in messages.iter().enumerate() {
+                let start = index * BLOCK_LEN;
+                if start + BLOCK_LEN <= message.len() {
+                    data[lane].copy_from_slice(&message[start..start + BLOCK_LEN]);
+                } else if index < blocks[lane] {
+                    message_block(message, index, &mut data[lane]);
+                }
+            }
+            for (t, w) in w.iter_mut().enumerate() {
+                for lane in 0..LANES {
+                    let word = &data[lane][t * 4..t * 4 + 4];
+                    w[lane] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+                }
+            }
+            // SAFETY: callers check `preferred`
+            let next = unsafe { compress_avx2(&state, &w) };
+            // lanes with shorter messages keep their state
+            for lane in 0..LANES {
+                if index < blocks[lane] {
+                    for i in 0..8 {
+                        state[i][lane] = next[i][lane];
+                    }
+                }
+            }
+        }
+        let mut hashes = [[0; 32]; LANES];
+        for (lane, hash) in hashes.iter_mut().enumerate() {
+            for i in 0..8 {
+                hash[i * 4..i * 4 + 4].copy_from_slice(&state[i][lane].to_be_bytes());
+            }
+        }
+        hashes
+    }
+
+    #[inline(always)]
+    fn compress(state: &[Lanes; 8], block: &[Lanes; 16]) -> [Lanes; 8] {
+        let mut w = [[0; LANES]; 64];
+        w[..16].copy_from_slice(block);
+        for t in 16..64 {
+            let s0 = xor3(rotr(w[t - 15], 7), rotr(w[t - 15], 18), shr(w[t - 15], 3));
+            let s1 = xor3(rotr(w[t - 2], 17), rotr(w[t - 2], 19), shr(w[t - 2], 10));
+            w[t] = add(add(w[t - 16], s0), add(w[t - 7], s1));
+        }
+        let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = *state;
+        for t in 0..64 {
+            let s1 = xor3(rotr(e, 6), rotr(e, 11), rotr(e, 25));
+            let ch = xor(and(e, f), andnot(e, g));
+            let t1 = add(add(add(h, s1), add(ch, w[t])), [K[t]; LANES]);
+            let s0 = xor3(rotr(a, 2), rotr(a, 13), rotr(a, 22));
+            let maj = xor3(and(a, b), and(a, c), and(b, c));
+            let t2 = add(s0, maj);
+            h = g;
+            g = f;
+            f = e;
+            e = add(d, t1);
+            d = c;
+            c = b;
+            b = a;
+            a = add(t1, t2);
+        }
+        let mut next = [a, b, c, d, e, f, g, h];
+        for (next, state) in next.iter_mut().zip(state) {
+            *next = add(*next, *state);
+        }
+        next
+    }
+
+    #[inline(always)]
+    fn add(mut x: Lanes, y: Lanes) -> Lanes {
+        for i in 0..LANES {
+            x[i] = x[i].wrapping_add(y[i]);
+        }
+        x
+    }
+
+    #[inline(always)]
+    fn and(mut x: Lanes, y: Lanes) -> Lanes {
+        for i in 0..LANES {
+            x[i] &= y[i];
+        }
+        x
+    }
+
+    #[inline(always)]
+    fn andnot(mut x: Lanes, y: Lanes) -> Lanes {
+        for i in 0..LANES {
+            x[i] = !x[i] & y[i];
+        }
+        x
+    }
+
+    #[inline(always)]
+    fn xor(mut x: Lanes, y: Lanes) -> Lanes {
+        for i in 0..LANES {
+            x[i] ^= y[i];
+        }
+        x
+    }
+
+    #[inline(always)]
+    fn xor3(x: Lanes, y: Lanes, z: Lanes) -> Lanes {
+        xor(xor(x, y), z)
+    }
+
+    #[inline(always)]
+    fn rotr(mut x: Lanes, n: u32) -> Lanes {
+        for x in x.iter_mut() {
+            *x = x.rotate_right(n);
+        }
+        x
+    }
+
+    #[inline(always)]
+    fn shr(mut x: Lanes, n: u32) -> Lanes {
+        for x in x.iter_mut() {
+            *x >>= n;
+        }
+        x
+    }
+}
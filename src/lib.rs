@@ -12,6 +12,13 @@
 */
 
 #![allow(clippy::derived_hash_with_manual_eq)]
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+use alloc::{string::String, vec::Vec};
+
+pub mod io;
 
 pub mod types;
 pub use self::types::*;
@@ -24,7 +31,50 @@ pub use self::dictionary::*;
 
 pub mod cells_serialization;
 pub use cells_serialization::*;
-use smallvec::SmallVec;
+
+pub mod hashing;
+pub use self::hashing::*;
+
+pub mod crypto;
+pub use self::crypto::*;
+
+pub mod merkle;
+pub use self::merkle::*;
+
+pub mod storage;
+pub use self::storage::*;
+
+pub mod tlb;
+pub use self::tlb::{Deserializable, LoadCell, Serializable, StoreCell};
+
+#[cfg(feature = "derive")]
+pub use ton_types_derive::{LoadCell, StoreCell};
+
+pub mod metrics;
+
+pub mod failpoints;
+
+pub mod quota;
+
+#[cfg(any(feature = "fuzz", feature = "testing"))]
+pub mod corruption;
+
+#[cfg(feature = "fuzz")]
+pub mod fuzz;
+
+#[cfg(feature = "testing")]
+pub mod testing;
+
+#[cfg(feature = "ffi")]
+pub mod ffi;
+
+#[cfg(feature = "async")]
+pub mod async_io;
+#[cfg(feature = "async")]
+pub use self::async_io::*;
+
+#[cfg(feature = "python")]
+pub mod python;
 
 pub trait Mask {
     fn bit(&self, bits: Self) -> bool;
@@ -48,9 +98,16 @@ impl Mask for u8 {
     }
 }
 
+/// Hook for gas accounting of cell operations supplied by TVM implementations.
+/// Operations taking it (dictionary `*_with_gas` methods) call it instead of
+/// `BuilderData::into_cell` and `SliceData::load_cell` for every cell they create or load.
+/// `u64` is a consumer which does not account anything.
 pub trait GasConsumer {
+    /// Called instead of `BuilderData::into_cell`
     fn finalize_cell(&mut self, builder: BuilderData) -> Result<Cell>;
+    /// Called instead of `SliceData::load_cell`
     fn load_cell(&mut self, cell: Cell) -> Result<SliceData>;
+    /// Cell is created only to be read
     fn finalize_cell_and_load(&mut self, builder: BuilderData) -> Result<SliceData>;
 }
 
@@ -66,10 +123,49 @@ impl GasConsumer for u64 {
     }
 }
 
-pub fn parse_slice_base(slice: &str, mut bits: usize, base: u32) -> Option<SmallVec<[u8; 128]>> {
+/// Standard base64 with padding
+pub fn base64_encode(input: impl AsRef<[u8]>) -> String {
+    base64::encode(input)
+}
+
+/// Standard base64 without padding
+pub fn base64_encode_no_pad(input: impl AsRef<[u8]>) -> String {
+    base64::encode_config(input, base64::STANDARD_NO_PAD)
+}
+
+/// URL-safe base64 (`-` and `_` instead of `+` and `/`) with padding
+pub fn base64_encode_url_safe(input: impl AsRef<[u8]>) -> String {
+    base64::encode_config(input, base64::URL_SAFE)
+}
+
+/// URL-safe base64 without padding
+pub fn base64_encode_url_safe_no_pad(input: impl AsRef<[u8]>) -> String {
+    base64::encode_config(input, base64::URL_SAFE_NO_PAD)
+}
+
+/// Decodes any of standard and URL-safe base64 with or without padding.
+/// Alphabet is detected by `-` or `_` characters, they can not be mixed with `+` or `/`.
+pub fn base64_decode(input: impl AsRef<[u8]>) -> Result<Vec<u8>> {
+    let mut input = input.as_ref();
+    if input.last() == Some(&b'=') {
+        if input.len() % 4 != 0 {
+            fail!("invalid base64 padding")
+        }
+        for _ in 0..2 {
+            input = input.strip_suffix(b"=").unwrap_or(input);
+        }
+    }
+    let config = match input.iter().any(|byte| *byte == b'-' || *byte == b'_') {
+        true => base64::URL_SAFE_NO_PAD,
+        false => base64::STANDARD_NO_PAD
+    };
+    base64::decode_config(input, config).map_err(|err| error!("invalid base64: {}", err))
+}
+
+pub fn parse_slice_base(slice: &str, mut bits: usize, base: u32) -> Option<BuilderDataBuffer> {
     debug_assert!(bits < 8, "it is offset to get slice parsed");
     let mut acc = 0u8;
-    let mut data = SmallVec::new();
+    let mut data = BuilderDataBuffer::new();
     let mut completion_tag = false;
     for ch in slice.chars() {
         if completion_tag {
@@ -0,0 +1,115 @@
+/*
+* Copyright 2018-2020 TON DEV SOLUTIONS LTD.
+*
+* Licensed under the SOFTWARE EVALUATION License (the "License"); you may not use
+* this file except in compliance with the License.
+*
+* Unless required by applicable law or agreed to in writing, software
+* distributed under the License is distributed on an "AS IS" BASIS,
+* WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+* See the License for the specific TON DEV software governing permissions and
+* limitations under the License.
+*/
+
+//! Merkle proofs and updates of cell trees.
+//! Parts of tree not needed by verifier are replaced with pruned branch cells keeping their hashes.
+
+use crate::{
+    cell::{BuilderData, Cell, CellType, IBitstring, LevelMask},
+    fail,
+    types::{FxHashMap, Result, UInt256},
+};
+
+mod proof;
+
+pub use self::proof::*;
+
+mod update;
+
+pub use self::update::*;
+
+mod multi_proof;
+
+pub use self::multi_proof::*;
+
+/// Replaces cell with pruned branch keeping its hashes and depths,
+/// `merkle_depth` is count of merkle cells above it (0 for root of proof)
+pub fn create_pruned_branch(cell: &Cell, merkle_depth: u8) -> Result<Cell> {
+    if merkle_depth > 2 {
+        fail!("merkle depth {} is too big for pruned branch", merkle_depth)
+    }
+    let level_mask = LevelMask::with_mask(cell.level_mask().mask() | (1 << merkle_depth));
+    if level_mask == cell.level_mask() {
+        fail!("cell with level mask {} can not be pruned at merkle depth {}", level_mask, merkle_depth)
+    }
+    pruned_branch_builder(level_mask, &cell.hashes(), &cell.depths())?.into_cell()
+}
+
+// type + level_mask + level * (hashes + depths)
+pub(crate) fn pruned_branch_builder(level_mask: LevelMask, hashes: &[UInt256], depths: &[u16]) -> Result<BuilderData> {
+    let level = level_mask.level() as usize;
+    if hashes.len() != level || depths.len() != level {
+        fail!("pruned branch with level mask {} needs {} hashes and depths", level_mask, level)
+    }
+    let mut builder = BuilderData::new();
+    builder.set_type(CellType::PrunedBranch);
+    builder.set_level_mask(level_mask);
+    builder.append_u8(u8::from(CellType::PrunedBranch))?;
+    builder.append_u8(level_mask.mask())?;
+    for hash in hashes {
+        builder.append_raw(hash.as_slice(), 256)?;
+    }
+    for depth in depths {
+        builder.append_u16(*depth)?;
+    }
+    Ok(builder)
+}
+
+/// Replaces pruned branches of partial tree (pruned at merkle depth 0) with cells supplied by
+/// `resolver` by hash of original cell. Resolved cells may be partial too, they are expanded
+/// the same way. Unknown pruned branches and merkle cells are kept as is, hashes of tree at
+/// level 0 are not changed.
+pub fn fill_pruned_branches<F>(root: &Cell, mut resolver: F) -> Result<Cell>
+where
+    F: FnMut(&UInt256) -> Result<Option<Cell>>
+{
+    fill_pruned_cell(root, &mut resolver, &mut FxHashMap::default())
+}
+
+fn fill_pruned_cell<F>(cell: &Cell, resolver: &mut F, done: &mut FxHashMap<UInt256, Cell>) -> Result<Cell>
+where
+    F: FnMut(&UInt256) -> Result<Option<Cell>>
+{
+    if !cell.level_mask().is_significant_index(1) || cell.is_merkle() {
+        return Ok(cell.clone())
+    }
+    let cell_hash = cell.repr_hash();
+    if let Some(filled) = done.get(&cell_hash) {
+        return Ok(filled.clone())
+    }
+    let filled = if cell.is_pruned() {
+        let hash = cell.hash(0);
+        match resolver(&hash)? {
+            // pruned branch is not a knowledge
+            Some(resolved) if resolved.is_pruned() => cell.clone(),
+            Some(resolved) => {
+                if resolved.hash(0) != hash {
+                    fail!("resolver returned cell {:x} instead of {:x}", resolved.hash(0), hash)
+                }
+                fill_pruned_cell(&resolved, resolver, done)?
+            }
+            None => cell.clone()
+        }
+    } else {
+        let mut builder = BuilderData::from_cell(cell);
+        // level mask is calculated from new children
+        builder.set_level_mask(LevelMask::with_mask(0));
+        for index in 0..cell.references_count() {
+            let child = fill_pruned_cell(&cell.reference(index)?, resolver, done)?;
+            builder.replace_reference_cell(index, child)?;
+        }
+        builder.into_cell()?
+    };
+    done.insert(cell_hash, filled.clone());
+    Ok(filled)
+}
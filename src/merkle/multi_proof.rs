@@ -0,0 +1,90 @@
+/*
+* Copyright 2018-2020 TON DEV SOLUTIONS LTD.
+*
+* Licensed under the SOFTWARE EVALUATION License (the "License"); you may not use
+* this file except in compliance with the License.
+*
+* Unless required by applicable law or agreed to in writing, software
+* distributed under the License is distributed on an "AS IS" BASIS,
+* WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+* See the License for the specific TON DEV software governing permissions and
+* limitations under the License.
+*/
+
+use alloc::vec::Vec;
+
+use crate::{
+    cell::Cell,
+    cells_serialization::{deserialize_cells_tree, BagOfCells},
+    fail,
+    merkle::MerkleProof,
+    tlb::{Deserializable, Serializable},
+    types::{FxHashMap, Result, UInt256},
+};
+
+/// Proofs of several roots (e.g. block and state) built together, so common subtrees
+/// and pruned branches are shared. Serialized as BOC with merkle proof cell for every root.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MultiMerkleProof {
+    pub proofs: Vec<MerkleProof>,
+}
+
+impl MultiMerkleProof {
+    /// Creates proofs of `roots` keeping cells for which `is_include` returns true, roots must be included
+    pub fn create<F: Fn(&UInt256) -> bool>(roots: &[Cell], is_include: F) -> Result<Self> {
+        let mut done = FxHashMap::default();
+        let mut proofs = Vec::with_capacity(roots.len());
+        for root in roots {
+            if !is_include(&root.repr_hash()) {
+                fail!("root cell {:x} is not included into proof", root.repr_hash())
+            }
+            proofs.push(MerkleProof {
+                hash: root.repr_hash(),
+                depth: root.repr_depth(),
+                proof: MerkleProof::create_raw(root, &is_include, 0, &mut done)?,
+            });
+        }
+        Ok(Self { proofs })
+    }
+
+    pub fn roots_count(&self) -> usize {
+        self.proofs.len()
+    }
+
+    /// Proven tree of root with `index`, pruned branches have hashes of cells of original tree
+    pub fn virtual_root(&self, index: usize) -> Option<Cell> {
+        self.proofs.get(index).map(MerkleProof::virtual_root)
+    }
+
+    pub fn virtual_roots(&self) -> Vec<Cell> {
+        self.proofs.iter().map(MerkleProof::virtual_root).collect()
+    }
+
+    /// Verifies every proof against hash of its root, see `MerkleProof::verify`
+    pub fn verify(&self, expected_hashes: &[UInt256]) -> Result<()> {
+        if self.proofs.len() != expected_hashes.len() {
+            fail!("there are {} proofs instead of expected {}", self.proofs.len(), expected_hashes.len())
+        }
+        for (index, (proof, hash)) in self.proofs.iter().zip(expected_hashes).enumerate() {
+            if let Err(err) = proof.verify(hash) {
+                fail!("proof of root {} is wrong: {}", index, err)
+            }
+        }
+        Ok(())
+    }
+
+    pub fn write_to_bytes(&self) -> Result<Vec<u8>> {
+        let roots = self.proofs.iter().map(|proof| proof.serialize()).collect::<Result<Vec<_>>>()?;
+        let mut data = Vec::new();
+        BagOfCells::with_roots(&roots).write_to(&mut data, false)?;
+        Ok(data)
+    }
+
+    pub fn construct_from_bytes(bytes: &[u8]) -> Result<Self> {
+        let proofs = deserialize_cells_tree(&mut &bytes[..])?
+            .into_iter()
+            .map(MerkleProof::construct_from_cell)
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self { proofs })
+    }
+}
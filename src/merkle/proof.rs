@@ -0,0 +1,261 @@
+/*
+* Copyright 2018-2020 TON DEV SOLUTIONS LTD.
+*
+* Licensed under the SOFTWARE EVALUATION License (the "License"); you may not use
+* this file except in compliance with the License.
+*
+* Unless required by applicable law or agreed to in writing, software
+* distributed under the License is distributed on an "AS IS" BASIS,
+* WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+* See the License for the specific TON DEV software governing permissions and
+* limitations under the License.
+*/
+
+use alloc::vec;
+
+use crate::{
+    cell::{BuilderData, Cell, CellType, IBitstring, LevelMask, SliceData, DEPTH_SIZE, SHA256_SIZE},
+    cells_serialization::number_of_bytes_to_fit,
+    dictionary::HashmapType,
+    fail,
+    merkle::{create_pruned_branch, fill_pruned_branches},
+    tlb::{Deserializable, Serializable},
+    types::{FxHashMap, FxHashSet, Result, UInt256},
+};
+
+/// Size of proof calculated without its creation
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ProofSizeEstimate {
+    /// Count of unique cells including merkle proof cell and pruned branches
+    pub cells: usize,
+    /// Size of BOC without index and CRC
+    pub bytes: usize,
+}
+
+/// Merkle proof: tree with hash `hash` and depth `depth` where not needed cells are pruned
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MerkleProof {
+    pub hash: UInt256,
+    pub depth: u16,
+    pub proof: Cell,
+}
+
+impl MerkleProof {
+    /// Creates proof of `root` keeping cells for which `is_include` returns true,
+    /// other cells are pruned. Root must be included.
+    pub fn create<F: Fn(&UInt256) -> bool>(root: &Cell, is_include: F) -> Result<Self> {
+        if !is_include(&root.repr_hash()) {
+            fail!("root cell {:x} is not included into proof", root.repr_hash())
+        }
+        let mut done = FxHashMap::default();
+        let proof = Self::create_raw(root, &is_include, 0, &mut done)?;
+        Ok(Self {
+            hash: root.repr_hash(),
+            depth: root.repr_depth(),
+            proof,
+        })
+    }
+
+    /// Checks that proof is of tree with `expected_hash`: declared and virtualized hashes
+    /// and depths, hashes stored in inner merkle cells and level masks of pruned branches
+    pub fn verify(&self, expected_hash: &UInt256) -> Result<()> {
+        if self.hash != *expected_hash {
+            fail!("proof is of {:x} instead of expected {:x}", self.hash, expected_hash)
+        }
+        if self.proof.hash(0) != self.hash {
+            fail!("proof has virtual hash {:x} instead of declared {:x}", self.proof.hash(0), self.hash)
+        }
+        if self.proof.depth(0) != self.depth {
+            fail!("proof has virtual depth {} instead of declared {}", self.proof.depth(0), self.depth)
+        }
+        let mut visited = FxHashSet::default();
+        let mut stack = vec![(self.proof.clone(), 0u8)];
+        while let Some((cell, merkle_depth)) = stack.pop() {
+            if !visited.insert((cell.repr_hash(), merkle_depth)) {
+                continue
+            }
+            match cell.cell_type() {
+                // bits above merkle depth are of outer proofs
+                CellType::PrunedBranch if cell.level_mask().mask() >> merkle_depth > 1 => {
+                    fail!("pruned branch {:x} at merkle depth {} has level mask {}",
+                        cell.repr_hash(), merkle_depth, cell.level_mask())
+                }
+                CellType::MerkleProof | CellType::MerkleUpdate => {
+                    // type + hashes + depths of children
+                    let data = cell.data();
+                    let count = cell.references_count();
+                    for index in 0..count {
+                        let child = cell.reference(index)?;
+                        let offset = 1 + index * SHA256_SIZE;
+                        if &data[offset..offset + SHA256_SIZE] != child.hash(0).as_slice() {
+                            fail!("merkle cell {:x} has wrong hash of reference {}", cell.repr_hash(), index)
+                        }
+                        let offset = 1 + count * SHA256_SIZE + index * DEPTH_SIZE;
+                        let depth = u16::from_be_bytes([data[offset], data[offset + 1]]);
+                        if depth != child.depth(0) {
+                            fail!("merkle cell {:x} has depth {} of reference {} instead of {}",
+                                cell.repr_hash(), depth, index, child.depth(0))
+                        }
+                    }
+                }
+                _ => ()
+            }
+            let child_merkle_depth = merkle_depth + cell.is_merkle() as u8;
+            for index in 0..cell.references_count() {
+                stack.push((cell.reference(index)?, child_merkle_depth));
+            }
+        }
+        Ok(())
+    }
+
+    /// Proven tree where pruned branches have hashes of cells of original tree
+    pub fn virtual_root(&self) -> Cell {
+        self.proof.clone().virtualize(1)
+    }
+
+    /// Replaces pruned branches of proof with cells supplied by `resolver` by hash of original cell,
+    /// see `fill_pruned_branches`
+    pub fn expand<F>(&self, resolver: F) -> Result<Self>
+    where
+        F: FnMut(&UInt256) -> Result<Option<Cell>>
+    {
+        Ok(Self {
+            hash: self.hash,
+            depth: self.depth,
+            proof: fill_pruned_branches(&self.proof, resolver)?,
+        })
+    }
+
+    /// Checks that proof contains cells with `hashes` (of original tree) not pruned
+    pub fn check_coverage<'a>(&self, hashes: impl IntoIterator<Item = &'a UInt256>) -> Result<()> {
+        let mut covered = FxHashSet::default();
+        let mut visited = FxHashSet::default();
+        let mut stack = vec![(self.proof.clone(), 0u8)];
+        while let Some((cell, merkle_depth)) = stack.pop() {
+            if cell.is_pruned() || !visited.insert((cell.repr_hash(), merkle_depth)) {
+                continue
+            }
+            covered.insert(cell.hash(merkle_depth as usize));
+            let child_merkle_depth = merkle_depth + cell.is_merkle() as u8;
+            for index in 0..cell.references_count() {
+                stack.push((cell.reference(index)?, child_merkle_depth));
+            }
+        }
+        let mut missing = hashes.into_iter().filter(|hash| !covered.contains(*hash));
+        if let Some(hash) = missing.next() {
+            fail!("proof does not contain cell {:x} and {} more cells", hash, missing.count())
+        }
+        Ok(())
+    }
+
+    /// Checks that values of `keys` or their absence can be read from dictionary,
+    /// which is loaded from `virtual_root` of proof
+    pub fn check_keys_coverage<T: HashmapType>(dict: &T, keys: impl IntoIterator<Item = SliceData>) -> Result<()> {
+        for key in keys {
+            if let Err(err) = dict.hashmap_get(key.clone(), &mut 0) {
+                fail!("proof does not contain dictionary key {:x}: {}", key, err)
+            }
+        }
+        Ok(())
+    }
+
+    /// Calculates size of proof which `create` would build with the same arguments
+    pub fn estimate_size<F: Fn(&UInt256) -> bool>(root: &Cell, is_include: F) -> Result<ProofSizeEstimate> {
+        if !is_include(&root.repr_hash()) {
+            fail!("root cell {:x} is not included into proof", root.repr_hash())
+        }
+        // merkle proof cell: descriptor bytes + type + hash + depth
+        let mut cells = 1;
+        let mut data_size = 2 + 1 + SHA256_SIZE + DEPTH_SIZE;
+        let mut references = 1;
+        let mut visited = FxHashSet::default();
+        let mut stack = vec![(root.clone(), 0u8, true)];
+        while let Some((cell, merkle_depth, included)) = stack.pop() {
+            if !visited.insert((cell.repr_hash(), merkle_depth)) {
+                continue
+            }
+            cells += 1;
+            if !included {
+                // descriptor bytes + type + level mask + hashes and depths of significant levels
+                // of original cell, the same as `create_pruned_branch` writes
+                let hashes_count = cell.level_mask().mask().count_ones() as usize + 1;
+                data_size += 2 + 2 + hashes_count * (SHA256_SIZE + DEPTH_SIZE);
+                continue
+            }
+            data_size += 2 + cell.bit_length().div_ceil(8);
+            references += cell.references_count();
+            let child_merkle_depth = merkle_depth + cell.is_merkle() as u8;
+            for index in 0..cell.references_count() {
+                let child = cell.reference(index)?;
+                let included = is_include(&child.repr_hash());
+                stack.push((child, child_merkle_depth, included));
+            }
+        }
+        let ref_size = number_of_bytes_to_fit(cells);
+        let cells_size = data_size + references * ref_size;
+        let offset_size = number_of_bytes_to_fit(cells_size);
+        // magic + flags and ref size + offset size + cells, roots and absent counts + size + root
+        let bytes = 4 + 1 + 1 + 3 * ref_size + offset_size + ref_size + cells_size;
+        Ok(ProofSizeEstimate { cells, bytes })
+    }
+
+    // cells are shared by hash and merkle depth, the same subtree is processed once
+    pub(crate) fn create_raw<F: Fn(&UInt256) -> bool>(
+        cell: &Cell,
+        is_include: &F,
+        merkle_depth: u8,
+        done: &mut FxHashMap<(UInt256, u8), Cell>,
+    ) -> Result<Cell> {
+        let key = (cell.repr_hash(), merkle_depth);
+        if let Some(proof_cell) = done.get(&key) {
+            return Ok(proof_cell.clone())
+        }
+        let child_merkle_depth = merkle_depth + cell.is_merkle() as u8;
+        let mut builder = BuilderData::from_cell(cell);
+        let mut children_mask = LevelMask::with_mask(0);
+        for index in 0..cell.references_count() {
+            let child = cell.reference(index)?;
+            let proof_child = if is_include(&child.repr_hash()) {
+                Self::create_raw(&child, is_include, child_merkle_depth, done)?
+            } else {
+                create_pruned_branch(&child, child_merkle_depth)?
+            };
+            children_mask |= proof_child.level_mask();
+            builder.replace_reference_cell(index, proof_child)?;
+        }
+        if cell.is_merkle() {
+            builder.set_level_mask(LevelMask::for_merkle_cell(children_mask));
+        }
+        let proof_cell = builder.into_cell()?;
+        done.insert(key, proof_cell.clone());
+        Ok(proof_cell)
+    }
+}
+
+impl Serializable for MerkleProof {
+    fn write_to(&self, cell: &mut BuilderData) -> Result<()> {
+        cell.set_type(CellType::MerkleProof);
+        cell.append_u8(u8::from(CellType::MerkleProof))?;
+        cell.append_raw(self.hash.as_slice(), 256)?;
+        cell.append_u16(self.depth)?;
+        cell.checked_append_reference(self.proof.clone())?;
+        cell.set_level_mask(LevelMask::for_merkle_cell(self.proof.level_mask()));
+        Ok(())
+    }
+}
+
+impl Deserializable for MerkleProof {
+    fn construct_from(slice: &mut SliceData) -> Result<Self> {
+        if slice.cell_type() != CellType::MerkleProof {
+            fail!("cell of type {} is not a merkle proof", slice.cell_type())
+        }
+        let tag = slice.get_next_byte()?;
+        if tag != u8::from(CellType::MerkleProof) {
+            fail!("merkle proof has wrong tag {}", tag)
+        }
+        let hash = slice.get_next_hash()?;
+        let depth = slice.get_next_u16()?;
+        let proof = slice.checked_drain_reference()?;
+        Ok(Self { hash, depth, proof })
+    }
+}
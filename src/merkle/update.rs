@@ -0,0 +1,238 @@
+/*
+* Copyright 2018-2020 TON DEV SOLUTIONS LTD.
+*
+* Licensed under the SOFTWARE EVALUATION License (the "License"); you may not use
+* this file except in compliance with the License.
+*
+* Unless required by applicable law or agreed to in writing, software
+* distributed under the License is distributed on an "AS IS" BASIS,
+* WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+* See the License for the specific TON DEV software governing permissions and
+* limitations under the License.
+*/
+
+use alloc::vec;
+
+use crate::{
+    cell::{BuilderData, Cell, CellType, IBitstring, LevelMask, SliceData},
+    fail,
+    merkle::{create_pruned_branch, MerkleProof},
+    tlb::{Deserializable, Serializable},
+    types::{FxHashMap, FxHashSet, Result, UInt256},
+};
+
+/// Merkle update: proofs of old and new trees where cells of new tree
+/// existing in old one are pruned and taken from old tree while applying
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MerkleUpdate {
+    pub old_hash: UInt256,
+    pub new_hash: UInt256,
+    pub old_depth: u16,
+    pub new_depth: u16,
+    pub old: Cell,
+    pub new: Cell,
+}
+
+impl MerkleUpdate {
+    /// Creates update from `old_root` to `new_root`. Subtrees of new tree found in old one
+    /// by hash are pruned in new part, old part keeps only paths to them.
+    pub fn create(old_root: &Cell, new_root: &Cell) -> Result<Self> {
+        let mut old_cells = FxHashSet::default();
+        let mut stack = vec![old_root.clone()];
+        while let Some(cell) = stack.pop() {
+            if old_cells.insert(cell.repr_hash()) {
+                for index in 0..cell.references_count() {
+                    stack.push(cell.reference(index)?);
+                }
+            }
+        }
+
+        let mut reused = FxHashSet::default();
+        let mut done = FxHashMap::default();
+        let new = Self::create_new_part(new_root, &old_cells, &mut reused, &mut done)?;
+
+        // old part contains all cells above reused ones, reused cells are pruned
+        let mut has_reused = FxHashMap::default();
+        Self::mark_paths(old_root, &reused, &mut has_reused)?;
+        let is_include = |hash: &UInt256| has_reused.get(hash).copied().unwrap_or_default();
+        let old = if is_include(&old_root.repr_hash()) {
+            MerkleProof::create_raw(old_root, &is_include, 0, &mut FxHashMap::default())?
+        } else {
+            create_pruned_branch(old_root, 0)?
+        };
+
+        Ok(Self {
+            old_hash: old_root.repr_hash(),
+            new_hash: new_root.repr_hash(),
+            old_depth: old_root.repr_depth(),
+            new_depth: new_root.repr_depth(),
+            old,
+            new,
+        })
+    }
+
+    /// Applies update to `old_root` returning new root. Old root and both parts
+    /// are checked against declared hashes and depths.
+    pub fn apply(&self, old_root: &Cell) -> Result<Cell> {
+        if old_root.repr_hash() != self.old_hash {
+            fail!("old root hash {:x} is not equal to update's old hash {:x}", old_root.repr_hash(), self.old_hash)
+        }
+        if old_root.repr_depth() != self.old_depth {
+            fail!("old root depth {} is not equal to update's old depth {}", old_root.repr_depth(), self.old_depth)
+        }
+        if self.old.hash(0) != self.old_hash || self.old.depth(0) != self.old_depth {
+            fail!("old part of update has hash {:x} and depth {} instead of declared {:x} and {}",
+                self.old.hash(0), self.old.depth(0), self.old_hash, self.old_depth)
+        }
+        if self.new.hash(0) != self.new_hash || self.new.depth(0) != self.new_depth {
+            fail!("new part of update has hash {:x} and depth {} instead of declared {:x} and {}",
+                self.new.hash(0), self.new.depth(0), self.new_hash, self.new_depth)
+        }
+
+        let mut known = FxHashMap::default();
+        Self::collect_known(&self.old, old_root, &mut known)?;
+        let new_root = Self::apply_new_part(&self.new, &known, &mut FxHashMap::default())?;
+
+        if new_root.repr_hash() != self.new_hash {
+            fail!("new root hash {:x} is not equal to update's new hash {:x}", new_root.repr_hash(), self.new_hash)
+        }
+        if new_root.repr_depth() != self.new_depth {
+            fail!("new root depth {} is not equal to update's new depth {}", new_root.repr_depth(), self.new_depth)
+        }
+        Ok(new_root)
+    }
+
+    // collects cells of old tree which are present in old part of update by their hashes of level 0
+    fn collect_known(part: &Cell, cell: &Cell, known: &mut FxHashMap<UInt256, Cell>) -> Result<()> {
+        let hash = cell.hash(0);
+        if part.hash(0) != hash {
+            fail!("old part of update has cell {:x} instead of {:x}", part.hash(0), hash)
+        }
+        if known.insert(hash, cell.clone()).is_some() || part.is_pruned() {
+            return Ok(())
+        }
+        if part.references_count() != cell.references_count() {
+            fail!("cell {:x} of old part of update has {} references instead of {}",
+                hash, part.references_count(), cell.references_count())
+        }
+        for index in 0..cell.references_count() {
+            Self::collect_known(&part.reference(index)?, &cell.reference(index)?, known)?;
+        }
+        Ok(())
+    }
+
+    // replaces pruned branches of new part with cells of old tree
+    fn apply_new_part(
+        part: &Cell,
+        known: &FxHashMap<UInt256, Cell>,
+        done: &mut FxHashMap<UInt256, Cell>,
+    ) -> Result<Cell> {
+        let part_hash = part.repr_hash();
+        if let Some(cell) = done.get(&part_hash) {
+            return Ok(cell.clone())
+        }
+        let cell = if part.is_pruned() {
+            if !part.level_mask().is_significant_index(1) {
+                fail!("pruned branch {:x} of new part of update has wrong level mask {}", part_hash, part.level_mask())
+            }
+            match known.get(&part.hash(0)) {
+                Some(cell) => cell.clone(),
+                None => fail!("new part of update refers to cell {:x} absent in old part", part.hash(0))
+            }
+        } else if part.is_merkle() || part.level() == 0 {
+            part.clone()
+        } else {
+            let mut builder = BuilderData::from_cell(part);
+            // level mask is calculated from new children
+            builder.set_level_mask(LevelMask::with_mask(0));
+            for index in 0..part.references_count() {
+                let child = Self::apply_new_part(&part.reference(index)?, known, done)?;
+                builder.replace_reference_cell(index, child)?;
+            }
+            builder.into_cell()?
+        };
+        done.insert(part_hash, cell.clone());
+        Ok(cell)
+    }
+
+    fn create_new_part(
+        cell: &Cell,
+        old_cells: &FxHashSet<UInt256>,
+        reused: &mut FxHashSet<UInt256>,
+        done: &mut FxHashMap<UInt256, Cell>,
+    ) -> Result<Cell> {
+        let hash = cell.repr_hash();
+        if let Some(update_cell) = done.get(&hash) {
+            return Ok(update_cell.clone())
+        }
+        let update_cell = if old_cells.contains(&hash) {
+            reused.insert(hash);
+            create_pruned_branch(cell, 0)?
+        } else if cell.is_merkle() {
+            // cells below merkle cell are at other merkle depth, they are kept as is
+            cell.clone()
+        } else {
+            let mut builder = BuilderData::from_cell(cell);
+            for index in 0..cell.references_count() {
+                let child = cell.reference(index)?;
+                let child = Self::create_new_part(&child, old_cells, reused, done)?;
+                builder.replace_reference_cell(index, child)?;
+            }
+            builder.into_cell()?
+        };
+        done.insert(hash, update_cell.clone());
+        Ok(update_cell)
+    }
+
+    // marks cells having reused cells below them
+    fn mark_paths(
+        cell: &Cell,
+        reused: &FxHashSet<UInt256>,
+        has_reused: &mut FxHashMap<UInt256, bool>,
+    ) -> Result<bool> {
+        let hash = cell.repr_hash();
+        if let Some(result) = has_reused.get(&hash) {
+            return Ok(*result || reused.contains(&hash))
+        }
+        let mut result = false;
+        for index in 0..cell.references_count() {
+            result |= Self::mark_paths(&cell.reference(index)?, reused, has_reused)?;
+        }
+        has_reused.insert(hash, result);
+        Ok(result || reused.contains(&hash))
+    }
+}
+
+impl Serializable for MerkleUpdate {
+    fn write_to(&self, cell: &mut BuilderData) -> Result<()> {
+        cell.set_type(CellType::MerkleUpdate);
+        cell.append_u8(u8::from(CellType::MerkleUpdate))?;
+        cell.append_raw(self.old_hash.as_slice(), 256)?;
+        cell.append_raw(self.new_hash.as_slice(), 256)?;
+        cell.append_u16(self.old_depth)?;
+        cell.append_u16(self.new_depth)?;
+        cell.checked_append_reference(self.old.clone())?;
+        cell.checked_append_reference(self.new.clone())?;
+        cell.set_level_mask(LevelMask::for_merkle_cell(self.old.level_mask() | self.new.level_mask()));
+        Ok(())
+    }
+}
+
+impl Deserializable for MerkleUpdate {
+    fn construct_from(slice: &mut SliceData) -> Result<Self> {
+        if slice.cell_type() != CellType::MerkleUpdate {
+            fail!("cell of type {} is not a merkle update", slice.cell_type())
+        }
+        let tag = slice.get_next_byte()?;
+        if tag != u8::from(CellType::MerkleUpdate) {
+            fail!("merkle update has wrong tag {}", tag)
+        }
+        let old_hash = slice.get_next_hash()?;
+        let new_hash = slice.get_next_hash()?;
+        let old_depth = slice.get_next_u16()?;
+        let new_depth = slice.get_next_u16()?;
+        let old = slice.checked_drain_reference()?;
+        let new = slice.checked_drain_reference()?;
+        Ok(Self { old_hash, new_hash, old_depth, new_depth, old, new })
+    }
+}
@@ -0,0 +1,86 @@
+/*
+* Copyright 2018-2020 TON DEV SOLUTIONS LTD.
+*
+* Licensed under the SOFTWARE EVALUATION License (the "License"); you may not use
+* this file except in compliance with the License.
+*
+* Unless required by applicable law or agreed to in writing, software
+* distributed under the License is distributed on an "AS IS" BASIS,
+* WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+* See the License for the specific TON DEV software governing permissions and
+* limitations under the License.
+*/
+
+//! Counters of internal operations for monitoring.
+//! Values are collected only if feature `metrics` is enabled, otherwise all counters stay zero
+//! and no hook can be set.
+
+use core::sync::atomic::{AtomicU64, Ordering};
+
+#[cfg(feature = "metrics")]
+use alloc::boxed::Box;
+#[cfg(feature = "metrics")]
+use crate::{error, Result, types::OnceBox};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum MetricsEvent {
+    CellCreated = 0,
+    CellFinalized = 1,
+    Sha256 = 2,
+    BocBytesRead = 3,
+    BocBytesWritten = 4,
+}
+
+/// Receives every recorded event, can be used to forward values to external monitoring
+pub trait MetricsHook: Send + Sync {
+    fn record(&self, event: MetricsEvent, value: u64);
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MetricsSnapshot {
+    pub cells_created: u64,
+    pub cells_finalized: u64,
+    pub sha256_calls: u64,
+    pub boc_bytes_read: u64,
+    pub boc_bytes_written: u64,
+}
+
+#[allow(clippy::declare_interior_mutable_const)]
+const ZERO: AtomicU64 = AtomicU64::new(0);
+static COUNTERS: [AtomicU64; 5] = [ZERO; 5];
+#[cfg(feature = "metrics")]
+static HOOK: OnceBox<dyn MetricsHook> = OnceBox::new();
+
+/// Sets global hook, it can be done only once
+#[cfg(feature = "metrics")]
+pub fn set_metrics_hook(hook: Box<dyn MetricsHook>) -> Result<()> {
+    HOOK.set(hook).map_err(|_| error!("metrics hook is already set"))
+}
+
+pub fn metrics_snapshot() -> MetricsSnapshot {
+    let get = |event: MetricsEvent| COUNTERS[event as usize].load(Ordering::Relaxed);
+    MetricsSnapshot {
+        cells_created: get(MetricsEvent::CellCreated),
+        cells_finalized: get(MetricsEvent::CellFinalized),
+        sha256_calls: get(MetricsEvent::Sha256),
+        boc_bytes_read: get(MetricsEvent::BocBytesRead),
+        boc_bytes_written: get(MetricsEvent::BocBytesWritten),
+    }
+}
+
+pub fn reset_metrics() {
+    COUNTERS.iter().for_each(|counter| counter.store(0, Ordering::Relaxed));
+}
+
+#[inline(always)]
+pub(crate) fn record(event: MetricsEvent, value: u64) {
+    #[cfg(feature = "metrics")] {
+        COUNTERS[event as usize].fetch_add(value, Ordering::Relaxed);
+        if let Some(hook) = HOOK.get() {
+            hook.record(event, value);
+        }
+    }
+    #[cfg(not(feature = "metrics"))] {
+        let _ = (event, value);
+    }
+}
@@ -0,0 +1,218 @@
+/*
+* Copyright 2018-2020 TON DEV SOLUTIONS LTD.
+*
+* Licensed under the SOFTWARE EVALUATION License (the "License"); you may not use
+* this file except in compliance with the License.
+*
+* Unless required by applicable law or agreed to in writing, software
+* distributed under the License is distributed on an "AS IS" BASIS,
+* WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+* See the License for the specific TON DEV software governing permissions and
+* limitations under the License.
+*/
+
+//! Python module `ton_types` with `Cell`, `Slice`, `Builder` classes and BOC functions.
+//! To get importable module build the crate with
+//! `cargo rustc --release --features python,pyo3/extension-module --crate-type cdylib`
+//! and rename `libton_types.so` to `ton_types.so` (or use maturin).
+//! Errors are raised as `ValueError`.
+
+use pyo3::{
+    exceptions::PyValueError,
+    prelude::*,
+    types::PyBytes,
+};
+
+use crate::{
+    cell::{BuilderData, Cell, CellType, IBitstring, SliceData},
+    cells_serialization::{deserialize_cells_tree, BagOfCells},
+    tlb,
+    types::Error,
+};
+
+fn to_py_err(err: Error) -> PyErr {
+    PyValueError::new_err(err.to_string())
+}
+
+#[pyclass(name = "Cell", frozen)]
+#[derive(Clone)]
+pub struct PyCell(pub Cell);
+
+#[pymethods]
+impl PyCell {
+    /// Reads BOC with single root
+    #[staticmethod]
+    fn from_boc(data: &[u8]) -> PyResult<Self> {
+        let mut roots = deserialize_cells_tree(&mut &data[..]).map_err(to_py_err)?;
+        match roots.len() {
+            1 => Ok(Self(roots.remove(0))),
+            count => Err(PyValueError::new_err(format!("BOC has {} roots instead of one", count)))
+        }
+    }
+
+    fn to_boc<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyBytes>> {
+        boc_serialize(py, vec![self.clone()])
+    }
+
+    fn repr_hash<'py>(&self, py: Python<'py>) -> Bound<'py, PyBytes> {
+        PyBytes::new(py, self.0.repr_hash().as_slice())
+    }
+
+    /// Data bytes, last one contains completion tag if length is not aligned
+    fn data<'py>(&self, py: Python<'py>) -> Bound<'py, PyBytes> {
+        PyBytes::new(py, self.0.data())
+    }
+
+    fn bit_length(&self) -> usize {
+        self.0.bit_length()
+    }
+
+    fn references_count(&self) -> usize {
+        self.0.references_count()
+    }
+
+    fn reference(&self, index: usize) -> PyResult<Self> {
+        self.0.reference(index).map(Self).map_err(to_py_err)
+    }
+
+    fn is_exotic(&self) -> bool {
+        self.0.cell_type() != CellType::Ordinary
+    }
+
+    fn slice(&self) -> PyResult<PySlice> {
+        SliceData::load_cell_ref(&self.0).map(PySlice).map_err(to_py_err)
+    }
+
+    fn __eq__(&self, other: &Self) -> bool {
+        self.0 == other.0
+    }
+
+    fn __hash__(&self) -> u64 {
+        u64::from_be_bytes(self.0.repr_hash().as_slice()[..8].try_into().unwrap())
+    }
+
+    fn __repr__(&self) -> String {
+        format!("Cell({:x})", self.0.repr_hash())
+    }
+
+    fn __str__(&self) -> String {
+        format!("{}", self.0)
+    }
+}
+
+#[pyclass(name = "Slice")]
+#[derive(Clone)]
+pub struct PySlice(pub SliceData);
+
+#[pymethods]
+impl PySlice {
+    fn remaining_bits(&self) -> usize {
+        self.0.remaining_bits()
+    }
+
+    fn remaining_references(&self) -> usize {
+        self.0.remaining_references()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    fn load_bit(&mut self) -> PyResult<bool> {
+        self.0.get_next_bit().map_err(to_py_err)
+    }
+
+    /// Unsigned integer of up to 64 bits
+    fn load_uint(&mut self, bits: usize) -> PyResult<u64> {
+        self.0.get_next_int(bits).map_err(to_py_err)
+    }
+
+    fn load_bytes<'py>(&mut self, py: Python<'py>, count: usize) -> PyResult<Bound<'py, PyBytes>> {
+        let bytes = self.0.get_next_bytes(count).map_err(to_py_err)?;
+        Ok(PyBytes::new(py, &bytes))
+    }
+
+    fn load_ref(&mut self) -> PyResult<PyCell> {
+        self.0.checked_drain_reference().map(PyCell).map_err(to_py_err)
+    }
+
+    fn __repr__(&self) -> String {
+        format!("Slice({})", self.0)
+    }
+}
+
+#[pyclass(name = "Builder")]
+#[derive(Clone, Default)]
+pub struct PyBuilder(pub BuilderData);
+
+#[pymethods]
+impl PyBuilder {
+    #[new]
+    fn new() -> Self {
+        Self::default()
+    }
+
+    fn bit_length(&self) -> usize {
+        self.0.length_in_bits()
+    }
+
+    fn references_count(&self) -> usize {
+        self.0.references_used()
+    }
+
+    fn store_bit(&mut self, bit: bool) -> PyResult<()> {
+        self.0.append_bit_bool(bit).map_err(to_py_err)?;
+        Ok(())
+    }
+
+    /// Unsigned integer of up to 64 bits
+    fn store_uint(&mut self, value: u64, bits: usize) -> PyResult<()> {
+        tlb::store_uint(&mut self.0, value, bits).map_err(to_py_err)
+    }
+
+    fn store_bytes(&mut self, data: &[u8]) -> PyResult<()> {
+        self.0.append_raw(data, data.len() * 8).map_err(to_py_err)?;
+        Ok(())
+    }
+
+    fn store_ref(&mut self, cell: &PyCell) -> PyResult<()> {
+        self.0.checked_append_reference(cell.0.clone()).map_err(to_py_err)?;
+        Ok(())
+    }
+
+    fn store_slice(&mut self, slice: &PySlice) -> PyResult<()> {
+        self.0.checked_append_references_and_data(&slice.0).map_err(to_py_err)?;
+        Ok(())
+    }
+
+    /// Builds cell, builder is left unchanged
+    fn build(&self) -> PyResult<PyCell> {
+        self.0.clone().into_cell().map(PyCell).map_err(to_py_err)
+    }
+}
+
+/// Reads all roots of BOC
+#[pyfunction]
+fn boc_parse(data: &[u8]) -> PyResult<Vec<PyCell>> {
+    let roots = deserialize_cells_tree(&mut &data[..]).map_err(to_py_err)?;
+    Ok(roots.into_iter().map(PyCell).collect())
+}
+
+/// Writes BOC with given roots
+#[pyfunction]
+fn boc_serialize(py: Python<'_>, roots: Vec<PyCell>) -> PyResult<Bound<'_, PyBytes>> {
+    let roots = roots.into_iter().map(|cell| cell.0).collect::<Vec<_>>();
+    let mut data = Vec::new();
+    BagOfCells::with_roots(&roots).write_to(&mut data, false).map_err(to_py_err)?;
+    Ok(PyBytes::new(py, &data))
+}
+
+#[pymodule]
+fn ton_types(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_class::<PyCell>()?;
+    module.add_class::<PySlice>()?;
+    module.add_class::<PyBuilder>()?;
+    module.add_function(wrap_pyfunction!(boc_parse, module)?)?;
+    module.add_function(wrap_pyfunction!(boc_serialize, module)?)?;
+    Ok(())
+}
@@ -0,0 +1,210 @@
+/*
+* Copyright 2018-2020 TON DEV SOLUTIONS LTD.
+*
+* Licensed under the SOFTWARE EVALUATION License (the "License"); you may not use
+* this file except in compliance with the License.
+*
+* Unless required by applicable law or agreed to in writing, software
+* distributed under the License is distributed on an "AS IS" BASIS,
+* WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+* See the License for the specific TON DEV software governing permissions and
+* limitations under the License.
+*/
+
+//! Quotas of cells created by untrusted code. With feature `quota` cells created by a thread
+//! while quota is entered are charged to it until they are dropped, creation of cell or
+//! reading of BOC exceeding the quota fails with `QuotaError`. Otherwise quotas are not checked.
+
+use alloc::sync::Arc;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::types::{ErrorCode, Result};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, thiserror::Error)]
+pub enum QuotaError {
+    #[error("quota of {limit} live cells is exceeded")]
+    CellsExceeded { limit: usize },
+    #[error("quota of {limit} bytes of live cells is exceeded")]
+    BytesExceeded { limit: usize },
+}
+
+impl QuotaError {
+    /// Stable numeric code (see `ExceptionCode::codes_table`)
+    pub const fn code(&self) -> i32 {
+        match self {
+            Self::CellsExceeded { .. } => ErrorCode::QuotaCellsExceeded.code(),
+            Self::BytesExceeded { .. } => ErrorCode::QuotaBytesExceeded.code(),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct QuotaState {
+    max_cells: usize,
+    max_bytes: usize,
+    cells: AtomicUsize,
+    bytes: AtomicUsize,
+}
+
+/// Limits of live cells and their approximate memory (cell structure and data buffer)
+#[derive(Clone, Debug)]
+pub struct CellQuota(Arc<QuotaState>);
+
+impl CellQuota {
+    pub fn new(max_cells: usize, max_bytes: usize) -> Self {
+        Self(Arc::new(QuotaState {
+            max_cells,
+            max_bytes,
+            cells: AtomicUsize::new(0),
+            bytes: AtomicUsize::new(0),
+        }))
+    }
+
+    pub fn live_cells(&self) -> usize {
+        self.0.cells.load(Ordering::Relaxed)
+    }
+
+    pub fn live_bytes(&self) -> usize {
+        self.0.bytes.load(Ordering::Relaxed)
+    }
+
+    /// Fails if `cells` cells of `bytes` bytes can not be created within quota
+    pub fn check(&self, cells: usize, bytes: usize) -> Result<()> {
+        if self.live_cells().saturating_add(cells) > self.0.max_cells {
+            return Err(QuotaError::CellsExceeded { limit: self.0.max_cells }.into())
+        }
+        if self.live_bytes().saturating_add(bytes) > self.0.max_bytes {
+            return Err(QuotaError::BytesExceeded { limit: self.0.max_bytes }.into())
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "quota")]
+mod scope {
+    use core::{cell::RefCell, marker::PhantomData, sync::atomic::Ordering};
+
+    use super::{CellQuota, QuotaError};
+    use crate::types::Result;
+
+    std::thread_local! {
+        static CURRENT: RefCell<Option<CellQuota>> = const { RefCell::new(None) };
+    }
+
+    /// Restores previous quota of thread when dropped
+    pub struct QuotaGuard {
+        previous: Option<CellQuota>,
+        _not_send: PhantomData<*const ()>,
+    }
+
+    impl Drop for QuotaGuard {
+        fn drop(&mut self) {
+            let previous = self.previous.take();
+            CURRENT.with(|current| *current.borrow_mut() = previous);
+        }
+    }
+
+    impl CellQuota {
+        /// Makes quota current for cells created by this thread until guard is dropped
+        pub fn enter(&self) -> QuotaGuard {
+            let previous = CURRENT.with(|current| current.borrow_mut().replace(self.clone()));
+            QuotaGuard { previous, _not_send: PhantomData }
+        }
+    }
+
+    /// Part of quota taken by a cell, it is returned when cell is dropped
+    #[derive(Debug)]
+    pub(crate) struct QuotaCharge {
+        quota: CellQuota,
+        bytes: usize,
+    }
+
+    impl Clone for QuotaCharge {
+        fn clone(&self) -> Self {
+            self.quota.0.cells.fetch_add(1, Ordering::Relaxed);
+            self.quota.0.bytes.fetch_add(self.bytes, Ordering::Relaxed);
+            Self { quota: self.quota.clone(), bytes: self.bytes }
+        }
+    }
+
+    impl Drop for QuotaCharge {
+        fn drop(&mut self) {
+            self.quota.0.cells.fetch_sub(1, Ordering::Relaxed);
+            self.quota.0.bytes.fetch_sub(self.bytes, Ordering::Relaxed);
+        }
+    }
+
+    pub(super) fn current() -> Option<CellQuota> {
+        CURRENT.with(|current| current.borrow().clone())
+    }
+
+    /// Charges cell of `bytes` bytes to current quota of thread
+    pub(crate) fn charge(bytes: usize) -> Result<Option<QuotaCharge>> {
+        let quota = match current() {
+            Some(quota) => quota,
+            None => return Ok(None)
+        };
+        let cells = quota.0.cells.fetch_add(1, Ordering::Relaxed);
+        let total = quota.0.bytes.fetch_add(bytes, Ordering::Relaxed);
+        // charge is returned back on failure
+        let charge = QuotaCharge { quota: quota.clone(), bytes };
+        if cells >= quota.0.max_cells {
+            return Err(QuotaError::CellsExceeded { limit: quota.0.max_cells }.into())
+        }
+        if total.saturating_add(bytes) > quota.0.max_bytes {
+            return Err(QuotaError::BytesExceeded { limit: quota.0.max_bytes }.into())
+        }
+        Ok(Some(charge))
+    }
+}
+
+#[cfg(feature = "quota")]
+pub use self::scope::QuotaGuard;
+#[cfg(feature = "quota")]
+pub(crate) use self::scope::{charge, QuotaCharge};
+
+/// Quota of current thread passed to worker threads, so cells they create are charged to it
+#[cfg(all(feature = "std", not(target_arch = "wasm32")))]
+pub(crate) struct InheritedQuota {
+    #[cfg(feature = "quota")]
+    quota: Option<CellQuota>,
+}
+
+/// Keeps inherited quota entered in worker thread
+#[cfg(all(feature = "std", not(target_arch = "wasm32")))]
+pub(crate) struct WorkerGuard {
+    #[cfg(feature = "quota")]
+    _guard: Option<QuotaGuard>,
+}
+
+#[cfg(all(feature = "std", not(target_arch = "wasm32")))]
+impl InheritedQuota {
+    pub(crate) fn current() -> Self {
+        Self {
+            #[cfg(feature = "quota")]
+            quota: scope::current(),
+        }
+    }
+
+    /// Enters quota in worker thread until returned guard is dropped
+    pub(crate) fn enter(&self) -> WorkerGuard {
+        WorkerGuard {
+            #[cfg(feature = "quota")]
+            _guard: self.quota.as_ref().map(CellQuota::enter),
+        }
+    }
+}
+
+/// Fails if `cells` cells of `bytes` bytes exceed current quota of thread
+#[inline(always)]
+pub(crate) fn check(cells: usize, bytes: usize) -> Result<()> {
+    #[cfg(feature = "quota")] {
+        if let Some(quota) = scope::current() {
+            return quota.check(cells, bytes)
+        }
+    }
+    #[cfg(not(feature = "quota"))] {
+        let _ = (cells, bytes);
+    }
+    Ok(())
+}
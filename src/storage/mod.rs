@@ -0,0 +1,285 @@
+/*
+* Copyright 2018-2020 TON DEV SOLUTIONS LTD.
+*
+* Licensed under the SOFTWARE EVALUATION License (the "License"); you may not use
+* this file except in compliance with the License.
+*
+* Unless required by applicable law or agreed to in writing, software
+* distributed under the License is distributed on an "AS IS" BASIS,
+* WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+* See the License for the specific TON DEV software governing permissions and
+* limitations under the License.
+*/
+
+//! Storage of cells by representation hash, streaming BOC through it and garbage collection of cells
+//! unreachable from given roots.
+
+use alloc::{sync::Arc, vec, vec::Vec};
+
+use smallvec::SmallVec;
+
+use crate::{
+    cell::{Cell, CellData, CellImpl, CellType, LevelMask},
+    error, fail,
+    types::{FxHashSet, Result, UInt256},
+};
+#[cfg(feature = "std")]
+use crate::{cell::LruCellCache, types::FxDashMap};
+
+mod record;
+
+pub use self::record::*;
+
+mod offload;
+
+pub use self::offload::*;
+
+mod stream;
+
+pub use self::stream::*;
+
+/// Key-value storage of cells by representation hash. Cells returned by storage can load
+/// their references lazily (see `ExternalCell`), storage methods may be called from several threads.
+pub trait CellDb: Send + Sync {
+    fn get(&self, hash: &UInt256) -> Result<Option<Cell>>;
+    /// Stores single cell, its references are expected to be stored separately
+    fn put(&self, hash: &UInt256, cell: &Cell) -> Result<()>;
+    fn contains(&self, hash: &UInt256) -> Result<bool>;
+    fn remove(&self, hash: &UInt256) -> Result<()>;
+    /// Hashes of all stored cells
+    fn hashes(&self) -> Result<Vec<UInt256>>;
+}
+
+/// Cell loaded from storage without its references, they are loaded from storage on every access
+/// (or taken from cache if it is set)
+pub struct ExternalCell {
+    cell_data: CellData,
+    references: SmallVec<[UInt256; 4]>,
+    tree_bits_count: u64,
+    tree_cell_count: u64,
+    db: Arc<dyn CellDb>,
+    #[cfg(feature = "std")]
+    cache: Option<Arc<LruCellCache>>,
+}
+
+impl ExternalCell {
+    pub fn with_params(
+        cell_data: CellData,
+        references: SmallVec<[UInt256; 4]>,
+        tree_bits_count: u64,
+        tree_cell_count: u64,
+        db: Arc<dyn CellDb>,
+    ) -> Result<Self> {
+        if cell_data.references_count() != references.len() {
+            fail!("cell has {} references but {} hashes are given", cell_data.references_count(), references.len())
+        }
+        Ok(Self {
+            cell_data,
+            references,
+            tree_bits_count,
+            tree_cell_count,
+            db,
+            #[cfg(feature = "std")]
+            cache: None,
+        })
+    }
+
+    /// References are looked up in `cache` before loading from storage, loaded ones are put to it
+    #[cfg(feature = "std")]
+    pub fn set_cache(mut self, cache: Arc<LruCellCache>) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    #[cfg(feature = "std")]
+    fn load_reference(&self, hash: &UInt256) -> Result<Option<Cell>> {
+        match &self.cache {
+            Some(cache) => match cache.get(hash) {
+                Some(cell) => Ok(Some(cell)),
+                None => {
+                    let cell = self.db.get(hash)?;
+                    if let Some(cell) = &cell {
+                        cache.insert(cell.clone());
+                    }
+                    Ok(cell)
+                }
+            }
+            None => self.db.get(hash)
+        }
+    }
+
+    #[cfg(not(feature = "std"))]
+    fn load_reference(&self, hash: &UInt256) -> Result<Option<Cell>> {
+        self.db.get(hash)
+    }
+}
+
+impl CellImpl for ExternalCell {
+    fn data(&self) -> &[u8] {
+        self.cell_data.data()
+    }
+
+    fn raw_data(&self) -> Result<&[u8]> {
+        Ok(self.cell_data.raw_data())
+    }
+
+    fn cell_data(&self) -> &CellData {
+        &self.cell_data
+    }
+
+    fn bit_length(&self) -> usize {
+        self.cell_data.bit_length()
+    }
+
+    fn references_count(&self) -> usize {
+        self.references.len()
+    }
+
+    fn reference(&self, index: usize) -> Result<Cell> {
+        let hash = self.reference_repr_hash(index)?;
+        self.load_reference(&hash)?.ok_or_else(|| error!("cell {:x} is absent in storage", hash))
+    }
+
+    fn reference_repr_hash(&self, index: usize) -> Result<UInt256> {
+        match self.references.get(index) {
+            Some(hash) => Ok(*hash),
+            None => fail!("cell has {} references, there is no reference {}", self.references.len(), index)
+        }
+    }
+
+    fn cell_type(&self) -> CellType {
+        self.cell_data.cell_type()
+    }
+
+    fn level_mask(&self) -> LevelMask {
+        self.cell_data.level_mask()
+    }
+
+    fn hash(&self, index: usize) -> UInt256 {
+        self.cell_data.hash(index)
+    }
+
+    fn depth(&self, index: usize) -> u16 {
+        self.cell_data.depth(index)
+    }
+
+    fn store_hashes(&self) -> bool {
+        self.cell_data.store_hashes()
+    }
+
+    fn tree_bits_count(&self) -> u64 {
+        self.tree_bits_count
+    }
+
+    fn tree_cell_count(&self) -> u64 {
+        self.tree_cell_count
+    }
+}
+
+/// Storage in memory, it is used for tests and as a cache in front of persistent storage
+#[cfg(feature = "std")]
+#[derive(Default)]
+pub struct MemoryCellDb {
+    cells: FxDashMap<UInt256, Cell>,
+}
+
+#[cfg(feature = "std")]
+impl MemoryCellDb {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.cells.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cells.is_empty()
+    }
+}
+
+#[cfg(feature = "std")]
+impl CellDb for MemoryCellDb {
+    fn get(&self, hash: &UInt256) -> Result<Option<Cell>> {
+        Ok(self.cells.get(hash).map(|cell| cell.clone()))
+    }
+
+    fn put(&self, hash: &UInt256, cell: &Cell) -> Result<()> {
+        self.cells.insert(*hash, cell.clone());
+        Ok(())
+    }
+
+    fn contains(&self, hash: &UInt256) -> Result<bool> {
+        Ok(self.cells.contains_key(hash))
+    }
+
+    fn remove(&self, hash: &UInt256) -> Result<()> {
+        self.cells.remove(hash);
+        Ok(())
+    }
+
+    fn hashes(&self) -> Result<Vec<UInt256>> {
+        Ok(self.cells.iter().map(|item| *item.key()).collect())
+    }
+}
+
+/// Puts all cells of tree to storage, subtrees with stored roots are skipped. Children are
+/// stored before parents, so interrupted storing doesn't leave cells with absent references.
+/// Returns count of new cells.
+pub fn store_tree(db: &dyn CellDb, root: &Cell) -> Result<usize> {
+    let mut stored = 0;
+    let mut stack = vec![(root.clone(), false)];
+    while let Some((cell, children_stored)) = stack.pop() {
+        let hash = cell.repr_hash();
+        if db.contains(&hash)? {
+            continue
+        }
+        if children_stored {
+            db.put(&hash, &cell)?;
+            stored += 1;
+        } else {
+            stack.push((cell.clone(), true));
+            for index in 0..cell.references_count() {
+                stack.push((cell.reference(index)?, false));
+            }
+        }
+    }
+    Ok(stored)
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct GarbageCollection {
+    /// cells reachable from roots
+    pub marked: usize,
+    /// removed cells
+    pub swept: usize,
+}
+
+/// Removes from storage all cells unreachable from `roots`. Cells are visited by hashes
+/// of references, so lazy cells don't load their children. Missing reachable cell is an error,
+/// nothing is removed in this case. Only cells stored before the call are candidates for removal,
+/// so cells stored during collection are kept, but tree stored concurrently must not reuse
+/// unreachable cells already present in storage (`store_tree` skips them).
+pub fn collect_garbage(db: &dyn CellDb, roots: &[UInt256]) -> Result<GarbageCollection> {
+    // cells stored after snapshot may be not reachable from `roots` yet
+    let candidates = db.hashes()?;
+    let mut marked = FxHashSet::default();
+    let mut stack = roots.to_vec();
+    while let Some(hash) = stack.pop() {
+        if !marked.insert(hash) {
+            continue
+        }
+        let cell = db.get(&hash)?.ok_or_else(|| error!("reachable cell {:x} is absent in storage", hash))?;
+        for index in 0..cell.references_count() {
+            stack.push(cell.reference_repr_hash(index)?);
+        }
+    }
+    let mut swept = 0;
+    for hash in candidates {
+        if !marked.contains(&hash) {
+            db.remove(&hash)?;
+            swept += 1;
+        }
+    }
+    Ok(GarbageCollection { marked: marked.len(), swept })
+}
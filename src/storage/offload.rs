@@ -0,0 +1,101 @@
+/*
+* Copyright 2018-2020 TON DEV SOLUTIONS LTD.
+*
+* Licensed under the SOFTWARE EVALUATION License (the "License"); you may not use
+* this file except in compliance with the License.
+*
+* Unless required by applicable law or agreed to in writing, software
+* distributed under the License is distributed on an "AS IS" BASIS,
+* WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+* See the License for the specific TON DEV software governing permissions and
+* limitations under the License.
+*/
+
+use alloc::vec::Vec;
+
+use crate::{
+    cell::{BuilderData, Cell},
+    cells_serialization::{deserialize_tree_of_cells, serialize_toc},
+    fail,
+    merkle::{create_pruned_branch, fill_pruned_branches},
+    types::{FxHashMap, Result, UInt256},
+};
+
+/// External storage of offloaded subtrees serialized as BOC
+pub trait BlobStore: Send + Sync {
+    fn put_blob(&self, hash: &UInt256, data: &[u8]) -> Result<()>;
+    fn get_blob(&self, hash: &UInt256) -> Result<Option<Vec<u8>>>;
+}
+
+/// Subtree moved to blob storage: representation hash of its root and size of its BOC
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct OffloadedPayload {
+    pub hash: UInt256,
+    pub size: usize,
+}
+
+/// Replaces subtrees selected by `is_cold` with handle cells and puts them to `store` by
+/// representation hash. Handle is pruned branch of subtree, so hash of skeleton at level 0
+/// is equal to the one of original tree. Only subtrees without pruned branches are offloaded,
+/// subtrees of merkle cells are kept as is.
+pub fn offload_payloads<F>(root: &Cell, store: &dyn BlobStore, mut is_cold: F) -> Result<(Cell, Vec<OffloadedPayload>)>
+where
+    F: FnMut(&Cell) -> bool
+{
+    let mut payloads = Vec::new();
+    let skeleton = offload_cell(root, store, &mut is_cold, &mut FxHashMap::default(), &mut payloads)?;
+    Ok((skeleton, payloads))
+}
+
+fn offload_cell<F>(
+    cell: &Cell,
+    store: &dyn BlobStore,
+    is_cold: &mut F,
+    done: &mut FxHashMap<UInt256, Cell>,
+    payloads: &mut Vec<OffloadedPayload>,
+) -> Result<Cell>
+where
+    F: FnMut(&Cell) -> bool
+{
+    if cell.is_merkle() || cell.is_pruned() {
+        return Ok(cell.clone())
+    }
+    let hash = cell.repr_hash();
+    if let Some(offloaded) = done.get(&hash) {
+        return Ok(offloaded.clone())
+    }
+    let offloaded = if cell.level() == 0 && is_cold(cell) {
+        let boc = serialize_toc(cell)?;
+        store.put_blob(&hash, &boc)?;
+        payloads.push(OffloadedPayload { hash, size: boc.len() });
+        create_pruned_branch(cell, 0)?
+    } else if cell.references_count() == 0 {
+        cell.clone()
+    } else {
+        let mut builder = BuilderData::from_cell(cell);
+        for index in 0..cell.references_count() {
+            let child = offload_cell(&cell.reference(index)?, store, is_cold, done, payloads)?;
+            builder.replace_reference_cell(index, child)?;
+        }
+        builder.into_cell()?
+    };
+    done.insert(hash, offloaded.clone());
+    Ok(offloaded)
+}
+
+/// Replaces handle cells of skeleton with subtrees loaded from `store`,
+/// handles of subtrees absent in store are kept
+pub fn rehydrate_payloads(skeleton: &Cell, store: &dyn BlobStore) -> Result<Cell> {
+    fill_pruned_branches(skeleton, |hash| {
+        match store.get_blob(hash)? {
+            Some(boc) => {
+                let cell = deserialize_tree_of_cells(&mut boc.as_slice())?;
+                if cell.repr_hash() != *hash {
+                    fail!("blob {:x} contains cell {:x}", hash, cell.repr_hash())
+                }
+                Ok(Some(cell))
+            }
+            None => Ok(None)
+        }
+    })
+}
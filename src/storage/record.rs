@@ -0,0 +1,149 @@
+/*
+* Copyright 2018-2020 TON DEV SOLUTIONS LTD.
+*
+* Licensed under the SOFTWARE EVALUATION License (the "License"); you may not use
+* this file except in compliance with the License.
+*
+* Unless required by applicable law or agreed to in writing, software
+* distributed under the License is distributed on an "AS IS" BASIS,
+* WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+* See the License for the specific TON DEV software governing permissions and
+* limitations under the License.
+*/
+
+use alloc::{sync::Arc, vec::Vec};
+
+use smallvec::SmallVec;
+
+#[cfg(feature = "std")]
+use crate::cell::LruCellCache;
+use crate::{
+    cell::{self, Cell, CellData, CellType, DEPTH_SIZE, HASHES_D1_FLAG, SHA256_SIZE},
+    fail,
+    storage::{CellDb, ExternalCell},
+    types::{Result, UInt256},
+};
+
+/// Record of single cell in key-value storage. It is encoded as cell in BOC with stored
+/// hashes and depths (descriptor bytes, hashes, depths, data with completion tag)
+/// followed by representation hashes of references instead of their indexes
+/// and by big-endian 64-bit bits and cells counts of the tree.
+#[derive(Clone, Debug, PartialEq)]
+pub struct StorageCell {
+    cell_data: CellData,
+    references: SmallVec<[UInt256; 4]>,
+    tree_bits_count: u64,
+    tree_cell_count: u64,
+}
+
+const TREE_COUNTS_SIZE: usize = 16;
+
+impl StorageCell {
+    /// Virtual cells are not supported, their hashes differ from stored in cell data
+    pub fn from_cell(cell: &Cell) -> Result<Self> {
+        if cell.virtualization() != 0 {
+            fail!("virtual cell can not be stored")
+        }
+        let mut references = SmallVec::with_capacity(cell.references_count());
+        for index in 0..cell.references_count() {
+            references.push(cell.reference_repr_hash(index)?);
+        }
+        Ok(Self {
+            cell_data: cell.cell_data().clone(),
+            references,
+            tree_bits_count: cell.tree_bits_count(),
+            tree_cell_count: cell.tree_cell_count(),
+        })
+    }
+
+    pub fn cell_data(&self) -> &CellData {
+        &self.cell_data
+    }
+
+    pub fn references(&self) -> &[UInt256] {
+        &self.references
+    }
+
+    pub fn tree_bits_count(&self) -> u64 {
+        self.tree_bits_count
+    }
+
+    pub fn tree_cell_count(&self) -> u64 {
+        self.tree_cell_count
+    }
+
+    pub fn repr_hash(&self) -> UInt256 {
+        self.cell_data.hash(cell::MAX_LEVEL)
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let raw = self.cell_data.raw_data();
+        let data = cell::cell_data(raw);
+        // pruned branch keeps hashes of lower levels in data, so it stores only representation one
+        let level_mask = self.cell_data.level_mask();
+        let indexes: SmallVec<[usize; 4]> = match self.cell_data.cell_type() {
+            CellType::PrunedBranch => SmallVec::from_slice(&[cell::MAX_LEVEL]),
+            _ => (0..=cell::MAX_LEVEL)
+                .filter(|index| level_mask.is_significant_index(*index))
+                .take(level_mask.level() as usize + 1)
+                .collect()
+        };
+        let hashes_count = indexes.len();
+        let mut buf = Vec::with_capacity(
+            2 + hashes_count * (SHA256_SIZE + DEPTH_SIZE) + data.len() + self.references.len() * SHA256_SIZE
+                + TREE_COUNTS_SIZE
+        );
+        buf.push(raw[0] | HASHES_D1_FLAG);
+        buf.push(raw[1]);
+        indexes.iter().for_each(|index| buf.extend_from_slice(self.cell_data.raw_hash(*index)));
+        indexes.iter().for_each(|index| buf.extend_from_slice(&self.cell_data.depth(*index).to_be_bytes()));
+        buf.extend_from_slice(data);
+        self.references.iter().for_each(|hash| buf.extend_from_slice(hash.as_slice()));
+        buf.extend_from_slice(&self.tree_bits_count.to_be_bytes());
+        buf.extend_from_slice(&self.tree_cell_count.to_be_bytes());
+        buf
+    }
+
+    /// Checks structure of record, hashes can not be checked without children
+    pub fn decode(data: &[u8]) -> Result<Self> {
+        if data.len() < 2 {
+            fail!("storage cell record is too short: {} bytes", data.len())
+        }
+        if data[0] & HASHES_D1_FLAG == 0 || cell::absent(data) {
+            fail!("storage cell record has invalid descriptor {:02x}", data[0])
+        }
+        let refs_count = cell::refs_count(data);
+        let full_len = cell::full_len(data);
+        let refs_end = full_len + refs_count * SHA256_SIZE;
+        if data.len() != refs_end + TREE_COUNTS_SIZE {
+            fail!(
+                "storage cell record has {} bytes instead of {} for cell with {} references",
+                data.len(), refs_end + TREE_COUNTS_SIZE, refs_count
+            )
+        }
+        let cell_data = CellData::with_raw_data(data[..full_len].to_vec())?;
+        let references = data[full_len..refs_end].chunks(SHA256_SIZE).map(UInt256::from_slice).collect();
+        let count = |offset: usize| {
+            let mut bytes = [0; 8];
+            bytes.copy_from_slice(&data[refs_end + offset..refs_end + offset + 8]);
+            u64::from_be_bytes(bytes)
+        };
+        Ok(Self { cell_data, references, tree_bits_count: count(0), tree_cell_count: count(8) })
+    }
+
+    /// Cell loading its references from `db`
+    pub fn into_cell(self, db: Arc<dyn CellDb>) -> Result<Cell> {
+        Ok(Cell::with_cell_impl(self.into_external_cell(db)?))
+    }
+
+    /// Cell looking up its references in `cache` before loading them from `db`,
+    /// loaded references are put to the cache
+    #[cfg(feature = "std")]
+    pub fn into_cached_cell(self, db: Arc<dyn CellDb>, cache: Arc<LruCellCache>) -> Result<Cell> {
+        Ok(Cell::with_cell_impl(self.into_external_cell(db)?.set_cache(cache)))
+    }
+
+    fn into_external_cell(self, db: Arc<dyn CellDb>) -> Result<ExternalCell> {
+        ExternalCell::with_params(self.cell_data, self.references, self.tree_bits_count, self.tree_cell_count, db)
+    }
+}
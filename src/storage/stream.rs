@@ -0,0 +1,233 @@
+/*
+* Copyright 2018-2020 TON DEV SOLUTIONS LTD.
+*
+* Licensed under the SOFTWARE EVALUATION License (the "License"); you may not use
+* this file except in compliance with the License.
+*
+* Unless required by applicable law or agreed to in writing, software
+* distributed under the License is distributed on an "AS IS" BASIS,
+* WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+* See the License for the specific TON DEV software governing permissions and
+* limitations under the License.
+*/
+
+use alloc::{boxed::Box, vec, vec::Vec};
+
+use smallvec::SmallVec;
+
+use crate::{
+    cell::{self, Cell, DataCell, MAX_SAFE_DEPTH},
+    cells_serialization::{
+        check_abort, deserialize_cells_tree_header, read_raw_cell, BagOfCells, BocDeserializeResult,
+        BocSerialiseMode, IndexedCellsStorage, IoCrcFilter, OrderedCellsStorage, RawCell, BOC_GENERIC_TAG,
+    },
+    error, fail,
+    io::{Read, Write},
+    storage::CellDb,
+    types::{ByteOrderRead, FxHashMap, Result, UInt256},
+};
+
+/// Order of cells for `BagOfCells` keeping only hashes, cells are loaded from storage when written
+struct DbOrderedCells<'a> {
+    db: &'a dyn CellDb,
+    // reversed index of cell, it is set when all children are pushed
+    indexes: FxHashMap<UInt256, u32>,
+    sorted_rev: Vec<UInt256>,
+}
+
+impl OrderedCellsStorage for DbOrderedCells<'_> {
+    fn get_cell_by_index(&self, index: u32) -> Result<Cell> {
+        match self.sorted_rev.get(index as usize) {
+            Some(hash) => self.db.get(hash)?.ok_or_else(|| error!("cell {:x} is not found in storage", hash)),
+            None => fail!("Can't find cell with index {}", index)
+        }
+    }
+
+    fn get_rev_index_by_hash(&self, hash: &UInt256) -> Result<u32> {
+        match self.indexes.get(hash) {
+            Some(index) => Ok(*index),
+            None => fail!("Can't find cell index with hash {:x}", hash)
+        }
+    }
+
+    fn store_cell(&mut self, cell: Cell) -> Result<()> {
+        self.indexes.insert(cell.repr_hash(), u32::MAX);
+        Ok(())
+    }
+
+    fn push_cell(&mut self, hash: &UInt256) -> Result<()> {
+        match self.indexes.get_mut(hash) {
+            Some(index) => *index = self.sorted_rev.len() as u32,
+            None => fail!("Can't find cell with hash {:x}", hash)
+        }
+        self.sorted_rev.push(*hash);
+        Ok(())
+    }
+
+    fn contains_hash(&self, hash: &UInt256) -> Result<bool> {
+        Ok(self.indexes.contains_key(hash))
+    }
+
+    fn cleanup(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Writes BOC of trees stored in `CellDb`. Cells are loaded from storage while they are
+/// ordered and written, only their hashes and indexes are kept in memory.
+/// Output is the same as of `BagOfCells` for the same roots.
+pub struct BocWriter<'a> {
+    db: &'a dyn CellDb,
+    roots: Vec<UInt256>,
+    mode: BocSerialiseMode,
+    abort: &'a dyn Fn() -> bool,
+}
+
+impl<'a> BocWriter<'a> {
+    /// Writer of generic BOC without index and CRC
+    pub fn new(db: &'a dyn CellDb, roots: Vec<UInt256>) -> Self {
+        Self {
+            db,
+            roots,
+            mode: BocSerialiseMode::Generic { index: false, crc: false, cache_bits: false, flags: 0 },
+            abort: &|| false,
+        }
+    }
+
+    pub fn set_mode(mut self, mode: BocSerialiseMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    pub fn set_abort(mut self, abort: &'a dyn Fn() -> bool) -> Self {
+        self.abort = abort;
+        self
+    }
+
+    pub fn write_to<T: Write>(self, dest: &mut T) -> Result<()> {
+        let mut roots = Vec::with_capacity(self.roots.len());
+        for hash in &self.roots {
+            match self.db.get(hash)? {
+                Some(root) => roots.push(root),
+                None => fail!("root {:x} is not found in storage", hash)
+            }
+        }
+        let cells = DbOrderedCells {
+            db: self.db,
+            indexes: FxHashMap::default(),
+            sorted_rev: Vec::new(),
+        };
+        BagOfCells::with_cells_storage(&roots, &[], cells, self.abort)?
+            .write_to_with_abort(dest, self.mode, None, None, self.abort)
+    }
+}
+
+/// Reads BOC from stream into `CellDb`. Raw cells are kept in indexed cells storage until
+/// cells are constructed from leaves to roots, every constructed cell is put to `CellDb` and
+/// dropped, its parents get it from storage. Roots of result are loaded from storage.
+pub struct BocReader<'a> {
+    db: &'a dyn CellDb,
+    indexed_cells: Box<dyn IndexedCellsStorage>,
+    max_depth: u16,
+    abort: &'a dyn Fn() -> bool,
+}
+
+impl<'a> BocReader<'a> {
+    /// Reader keeping raw cells in memory
+    pub fn new(db: &'a dyn CellDb) -> Self {
+        Self {
+            db,
+            indexed_cells: Box::<FxHashMap<u32, RawCell>>::default(),
+            max_depth: MAX_SAFE_DEPTH,
+            abort: &|| false,
+        }
+    }
+
+    /// Storage of raw cells, use persistent one to read BOC bigger than memory
+    pub fn set_indexed_cells_storage(mut self, indexed_cells: Box<dyn IndexedCellsStorage>) -> Self {
+        self.indexed_cells = indexed_cells;
+        self
+    }
+
+    pub fn set_max_cell_depth(mut self, max_depth: u16) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    pub fn set_abort(mut self, abort: &'a dyn Fn() -> bool) -> Self {
+        self.abort = abort;
+        self
+    }
+
+    pub fn read<T: Read>(mut self, src: &mut T) -> Result<BocDeserializeResult> {
+        let mut src = IoCrcFilter::new(src);
+        let header = deserialize_cells_tree_header(&mut src)?;
+        src.has_crc = header.has_crc;
+        check_abort(self.abort)?;
+
+        // Skip index
+        if header.index_included {
+            for _ in 0..header.cells_count {
+                src.read_be_uint(header.offset_size)?;
+            }
+        }
+
+        let mut actual_data_size = 0;
+        for cell_index in 0..header.cells_count {
+            check_abort(self.abort)?;
+            let raw_cell = read_raw_cell(&mut src, header.ref_size, cell_index, header.cells_count)?;
+            actual_data_size += raw_cell.data.len() + header.ref_size * cell::refs_count(&raw_cell.data);
+            if actual_data_size > header.tot_cells_size {
+                fail!("actual data size disagrees with the size from header")
+            }
+            self.indexed_cells.insert(cell_index as u32, raw_cell)?;
+        }
+        if actual_data_size != header.tot_cells_size {
+            fail!("actual data size disagrees with the size from header")
+        }
+        // corrupted BOC must not leave cells in storage
+        if header.has_crc {
+            let (src, crc) = src.sum32();
+            let read_crc = src.read_le_u32()?;
+            if read_crc != crc {
+                fail!("crc not the same, values: {}, {}", read_crc, crc)
+            }
+        }
+
+        // Constructing cells from leaves to roots, references always have greater indexes
+        let mut hashes = vec![UInt256::default(); header.cells_count];
+        for cell_index in (0..header.cells_count).rev() {
+            check_abort(self.abort)?;
+            let raw_cell = self.indexed_cells.remove(cell_index as u32)?;
+            let mut references = SmallVec::new();
+            for index in &raw_cell.refs[..cell::refs_count(&raw_cell.data)] {
+                references.push(self.load(&hashes, *index)?);
+            }
+            let cell = DataCell::with_raw_data_and_max_depth(references, raw_cell.data, self.max_depth)?;
+            let cell = Cell::with_cell_impl(cell);
+            hashes[cell_index] = cell.repr_hash();
+            self.db.put(&hashes[cell_index], &cell)?;
+        }
+
+        let roots_indexes = if header.magic == BOC_GENERIC_TAG {
+            &header.roots_indexes[..]
+        } else {
+            &[0]
+        };
+        let mut roots = Vec::with_capacity(roots_indexes.len());
+        for index in roots_indexes {
+            roots.push(self.load(&hashes, *index)?);
+        }
+
+        self.indexed_cells.cleanup()?;
+
+        Ok(BocDeserializeResult { roots, header })
+    }
+
+    fn load(&self, hashes: &[UInt256], index: u32) -> Result<Cell> {
+        match hashes.get(index as usize) {
+            Some(hash) => self.db.get(hash)?.ok_or_else(|| error!("cell {:x} is not found in storage", hash)),
+            None => fail!("reference to cell {} is out of range", index)
+        }
+    }
+}
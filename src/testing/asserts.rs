@@ -0,0 +1,100 @@
+/*
+* Copyright 2018-2020 TON DEV SOLUTIONS LTD.
+*
+* Licensed under the SOFTWARE EVALUATION License (the "License"); you may not use
+* this file except in compliance with the License.
+*
+* Unless required by applicable law or agreed to in writing, software
+* distributed under the License is distributed on an "AS IS" BASIS,
+* WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+* See the License for the specific TON DEV software governing permissions and
+* limitations under the License.
+*/
+
+use alloc::{format, string::String};
+use core::fmt::Write;
+
+use crate::{
+    cell::SliceData,
+    testing::snapshot::{bits_around, diff_cells, first_different_bit},
+    types::{FxHashSet, Result},
+};
+
+/// Readable report of differences of remaining bits and references of slices, `None` if they
+/// are equal. Trees of different references are compared like in `diff_trees`.
+pub fn diff_slices(left: &SliceData, right: &SliceData) -> Result<Option<String>> {
+    let mut report = String::new();
+    let (left_data, left_bits) = (left.get_bytestring(0), left.remaining_bits());
+    let (right_data, right_bits) = (right.get_bytestring(0), right.remaining_bits());
+    if let Some(bit) = first_different_bit(&left_data, left_bits, &right_data, right_bits) {
+        writeln!(report, "slice: bits {} and {} differ from bit {}: {} != {}", left_bits, right_bits,
+            bit, bits_around(&left_data, left_bits, bit), bits_around(&right_data, right_bits, bit))?;
+    }
+    let common = left.remaining_references().min(right.remaining_references());
+    if left.remaining_references() != right.remaining_references() {
+        writeln!(report, "slice: refs {} != {}", left.remaining_references(), right.remaining_references())?;
+        for index in common..left.remaining_references() {
+            writeln!(report, "ref{}: only in left {:x}", index, left.reference(index)?.repr_hash())?;
+        }
+        for index in common..right.remaining_references() {
+            writeln!(report, "ref{}: only in right {:x}", index, right.reference(index)?.repr_hash())?;
+        }
+    }
+    let mut done = FxHashSet::default();
+    for index in 0..common {
+        let mut path = format!("ref{}", index);
+        diff_cells(&left.reference(index)?, &right.reference(index)?, &mut path, &mut done, &mut report)?;
+    }
+    match report.is_empty() {
+        true => Ok(None),
+        false => Ok(Some(report))
+    }
+}
+
+/// Asserts that cell trees are equal, on failure panics with the first different bits,
+/// bits around them and different references of every different cell (see `diff_trees`)
+#[macro_export]
+macro_rules! assert_cells_eq {
+    ($left:expr, $right:expr $(,)?) => {
+        match (&$left, &$right) {
+            (left, right) => {
+                if let Some(report) = $crate::testing::diff_trees(left, right).expect("cells can not be compared") {
+                    panic!("assertion `left == right` failed for cells\n{}", report)
+                }
+            }
+        }
+    };
+    ($left:expr, $right:expr, $($arg:tt)+) => {
+        match (&$left, &$right) {
+            (left, right) => {
+                if let Some(report) = $crate::testing::diff_trees(left, right).expect("cells can not be compared") {
+                    panic!("assertion `left == right` failed for cells: {}\n{}", format_args!($($arg)+), report)
+                }
+            }
+        }
+    };
+}
+
+/// Asserts that remaining bits and references of slices are equal, on failure panics with
+/// report of differences (see `diff_slices`)
+#[macro_export]
+macro_rules! assert_slices_eq {
+    ($left:expr, $right:expr $(,)?) => {
+        match (&$left, &$right) {
+            (left, right) => {
+                if let Some(report) = $crate::testing::diff_slices(left, right).expect("slices can not be compared") {
+                    panic!("assertion `left == right` failed for slices\n{}", report)
+                }
+            }
+        }
+    };
+    ($left:expr, $right:expr, $($arg:tt)+) => {
+        match (&$left, &$right) {
+            (left, right) => {
+                if let Some(report) = $crate::testing::diff_slices(left, right).expect("slices can not be compared") {
+                    panic!("assertion `left == right` failed for slices: {}\n{}", format_args!($($arg)+), report)
+                }
+            }
+        }
+    };
+}
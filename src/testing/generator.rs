@@ -0,0 +1,204 @@
+/*
+* Copyright 2018-2020 TON DEV SOLUTIONS LTD.
+*
+* Licensed under the SOFTWARE EVALUATION License (the "License"); you may not use
+* this file except in compliance with the License.
+*
+* Unless required by applicable law or agreed to in writing, software
+* distributed under the License is distributed on an "AS IS" BASIS,
+* WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+* See the License for the specific TON DEV software governing permissions and
+* limitations under the License.
+*/
+
+use alloc::vec::Vec;
+use core::ops::RangeInclusive;
+
+use crate::{
+    cell::{BuilderData, Cell, CellType, MAX_DATA_BITS, MAX_REFERENCES_COUNT},
+    merkle::{create_pruned_branch, MerkleProof, MerkleUpdate},
+    tlb::Serializable,
+    types::Result,
+};
+
+/// Deterministic pseudo random generator (SplitMix64): the same seed gives
+/// the same sequence on every platform and with every version of dependencies
+#[derive(Clone, Debug)]
+pub struct SeededRng {
+    state: u64,
+}
+
+impl SeededRng {
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Returns value from `range` (inclusive), start of range if it is empty
+    pub fn range(&mut self, range: RangeInclusive<usize>) -> usize {
+        let (start, end) = range.into_inner();
+        if start >= end {
+            return start
+        }
+        let count = (end - start) as u64 + 1;
+        start + (self.next_u64() % count) as usize
+    }
+
+    /// Returns true with `probability` from 0.0 to 1.0
+    pub fn chance(&mut self, probability: f64) -> bool {
+        // 53 bits of mantissa
+        ((self.next_u64() >> 11) as f64) < probability * (1u64 << 53) as f64
+    }
+
+    pub fn fill_bytes(&mut self, data: &mut [u8]) {
+        for chunk in data.chunks_mut(8) {
+            let bytes = self.next_u64().to_le_bytes();
+            chunk.copy_from_slice(&bytes[..chunk.len()]);
+        }
+    }
+}
+
+/// Generator of cell trees driven by seed: with the same seed and parameters
+/// it returns the same sequence of trees
+#[derive(Clone, Debug)]
+pub struct CellTreeGenerator {
+    rng: SeededRng,
+    /// max depth of generated tree
+    pub max_depth: usize,
+    /// max count of cells in generated tree
+    pub max_cells: usize,
+    /// count of references in ordinary cell
+    pub refs: RangeInclusive<usize>,
+    /// count of data bits in ordinary cell
+    pub bits: RangeInclusive<usize>,
+    /// probability of pruned branch, library reference or merkle cell instead of ordinary one
+    pub exotic_probability: f64,
+}
+
+impl CellTreeGenerator {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            rng: SeededRng::new(seed),
+            max_depth: 8,
+            max_cells: 256,
+            refs: 0..=MAX_REFERENCES_COUNT,
+            bits: 0..=MAX_DATA_BITS,
+            exotic_probability: 0.0,
+        }
+    }
+
+    pub fn set_max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    pub fn set_max_cells(mut self, max_cells: usize) -> Self {
+        self.max_cells = max_cells;
+        self
+    }
+
+    pub fn set_refs(mut self, refs: RangeInclusive<usize>) -> Self {
+        let (start, end) = refs.into_inner();
+        self.refs = start.min(MAX_REFERENCES_COUNT)..=end.min(MAX_REFERENCES_COUNT);
+        self
+    }
+
+    pub fn set_bits(mut self, bits: RangeInclusive<usize>) -> Self {
+        let (start, end) = bits.into_inner();
+        self.bits = start.min(MAX_DATA_BITS)..=end.min(MAX_DATA_BITS);
+        self
+    }
+
+    pub fn set_exotic_probability(mut self, probability: f64) -> Self {
+        self.exotic_probability = probability;
+        self
+    }
+
+    pub fn rng(&mut self) -> &mut SeededRng {
+        &mut self.rng
+    }
+
+    /// Generates next tree, count of its cells (not unique) is limited by `max_cells`
+    pub fn tree(&mut self) -> Result<Cell> {
+        let mut budget = self.max_cells.max(1);
+        self.gen_cell(self.max_depth, &mut budget)
+    }
+
+    /// Generates next `count` trees
+    pub fn trees(&mut self, count: usize) -> Result<Vec<Cell>> {
+        (0..count).map(|_| self.tree()).collect()
+    }
+
+    /// Generates ordinary cell without references
+    pub fn leaf(&mut self) -> Result<Cell> {
+        self.gen_builder(0, &mut 0)?.into_cell()
+    }
+
+    fn gen_cell(&mut self, depth: usize, budget: &mut usize) -> Result<Cell> {
+        *budget = budget.saturating_sub(1);
+        if depth > 0 && self.rng.chance(self.exotic_probability) {
+            match self.rng.range(0..=3) {
+                0 => {
+                    // pruned branch of generated subtree at the first free level
+                    let cell = self.gen_cell(depth - 1, budget)?;
+                    let mask = cell.level_mask().mask();
+                    if let Some(merkle_depth) = (0..3).find(|bit| mask & (1 << bit) == 0) {
+                        return create_pruned_branch(&cell, merkle_depth)
+                    }
+                }
+                1 => {
+                    // type + hash
+                    let mut hash = [0; 32];
+                    self.rng.fill_bytes(&mut hash);
+                    let mut builder = BuilderData::new();
+                    builder.set_type(CellType::LibraryReference);
+                    builder.append_raw(&[u8::from(CellType::LibraryReference)], 8)?;
+                    builder.append_raw(&hash, 256)?;
+                    return builder.into_cell()
+                }
+                2 => {
+                    let proof = self.gen_cell(depth - 1, budget)?;
+                    return MerkleProof { hash: proof.hash(0), depth: proof.depth(0), proof }.serialize()
+                }
+                _ => {
+                    let old = self.gen_cell(depth - 1, budget)?;
+                    let new = self.gen_cell(depth - 1, budget)?;
+                    return MerkleUpdate {
+                        old_hash: old.hash(0),
+                        new_hash: new.hash(0),
+                        old_depth: old.depth(0),
+                        new_depth: new.depth(0),
+                        old,
+                        new,
+                    }.serialize()
+                }
+            }
+        }
+        self.gen_builder(depth, budget)?.into_cell()
+    }
+
+    fn gen_builder(&mut self, depth: usize, budget: &mut usize) -> Result<BuilderData> {
+        let bits = self.rng.range(self.bits.clone());
+        let mut data = alloc::vec![0; bits.div_ceil(8)];
+        self.rng.fill_bytes(&mut data);
+        let mut builder = BuilderData::with_raw_vec(data, bits)?;
+        if depth > 0 {
+            let refs = self.rng.range(self.refs.clone());
+            for _ in 0..refs {
+                if *budget == 0 {
+                    break
+                }
+                let child = self.gen_cell(depth - 1, budget)?;
+                builder.checked_append_reference(child)?;
+            }
+        }
+        Ok(builder)
+    }
+}
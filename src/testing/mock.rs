@@ -0,0 +1,175 @@
+/*
+* Copyright 2018-2020 TON DEV SOLUTIONS LTD.
+*
+* Licensed under the SOFTWARE EVALUATION License (the "License"); you may not use
+* this file except in compliance with the License.
+*
+* Unless required by applicable law or agreed to in writing, software
+* distributed under the License is distributed on an "AS IS" BASIS,
+* WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+* See the License for the specific TON DEV software governing permissions and
+* limitations under the License.
+*/
+
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::{
+    cell::{Cell, CellData, CellImpl, CellType, LevelMask},
+    error, fail,
+    types::{FxHashMap, Result, UInt256},
+};
+
+/// Scripted behaviour of reference of `MockCell`
+#[derive(Clone)]
+pub enum MockReference {
+    /// loading of reference fails
+    Fail,
+    /// other cell is returned, its hash differs from `reference_repr_hash`
+    Replace(Cell),
+}
+
+/// Cell which accessors are scripted to fail or to return values inconsistent with each other,
+/// it imitates misbehaving storage-backed cell. Not scripted accessors return values of wrapped cell.
+pub struct MockCell {
+    cell: Cell,
+    data: Option<(Vec<u8>, usize)>,
+    hash: Option<UInt256>,
+    depth: Option<u16>,
+    fail_raw_data: bool,
+    references: FxHashMap<usize, MockReference>,
+    loads_before_fail: Option<usize>,
+    loads: AtomicUsize,
+}
+
+impl MockCell {
+    pub fn new(cell: Cell) -> Self {
+        Self {
+            cell,
+            data: None,
+            hash: None,
+            depth: None,
+            fail_raw_data: false,
+            references: FxHashMap::default(),
+            loads_before_fail: None,
+            loads: AtomicUsize::new(0),
+        }
+    }
+
+    /// Data with completion tag (if length is not aligned) and its length in bits
+    pub fn set_data(&mut self, data: Vec<u8>, bits: usize) -> Result<()> {
+        if bits > data.len() * 8 {
+            fail!("{} bits can not be stored in {} bytes", bits, data.len())
+        }
+        self.data = Some((data, bits));
+        Ok(())
+    }
+
+    /// Hash returned for every level
+    pub fn set_hash(&mut self, hash: UInt256) {
+        self.hash = Some(hash);
+    }
+
+    /// Depth returned for every level
+    pub fn set_depth(&mut self, depth: u16) {
+        self.depth = Some(depth);
+    }
+
+    pub fn set_fail_raw_data(&mut self, fail: bool) {
+        self.fail_raw_data = fail;
+    }
+
+    pub fn set_reference(&mut self, index: usize, reference: MockReference) -> Result<()> {
+        if index >= self.cell.references_count() {
+            fail!("cell has {} references, {} can not be scripted", self.cell.references_count(), index)
+        }
+        self.references.insert(index, reference);
+        Ok(())
+    }
+
+    /// Every loading of reference fails after `loads` successful ones
+    pub fn set_fail_references_after(&mut self, loads: usize) {
+        self.loads_before_fail = Some(loads);
+    }
+
+    pub fn into_cell(self) -> Cell {
+        Cell::with_cell_impl(self)
+    }
+}
+
+impl CellImpl for MockCell {
+    fn data(&self) -> &[u8] {
+        match &self.data {
+            Some((data, _)) => data,
+            None => self.cell.data()
+        }
+    }
+
+    fn raw_data(&self) -> Result<&[u8]> {
+        if self.fail_raw_data {
+            fail!("mock cell fails to give raw data")
+        }
+        self.cell.raw_data()
+    }
+
+    fn cell_data(&self) -> &CellData {
+        self.cell.cell_data()
+    }
+
+    fn bit_length(&self) -> usize {
+        match &self.data {
+            Some((_, bits)) => *bits,
+            None => self.cell.bit_length()
+        }
+    }
+
+    fn references_count(&self) -> usize {
+        self.cell.references_count()
+    }
+
+    fn reference(&self, index: usize) -> Result<Cell> {
+        let loads = self.loads.fetch_add(1, Ordering::Relaxed);
+        if let Some(limit) = self.loads_before_fail {
+            if loads >= limit {
+                fail!("mock cell fails to load reference {} after {} loads", index, limit)
+            }
+        }
+        match self.references.get(&index) {
+            Some(MockReference::Fail) => Err(error!("mock cell fails to load reference {}", index)),
+            Some(MockReference::Replace(cell)) => Ok(cell.clone()),
+            None => self.cell.reference(index)
+        }
+    }
+
+    fn reference_repr_hash(&self, index: usize) -> Result<UInt256> {
+        self.cell.reference_repr_hash(index)
+    }
+
+    fn cell_type(&self) -> CellType {
+        self.cell.cell_type()
+    }
+
+    fn level_mask(&self) -> LevelMask {
+        self.cell.level_mask()
+    }
+
+    fn hash(&self, index: usize) -> UInt256 {
+        self.hash.unwrap_or_else(|| self.cell.hash(index))
+    }
+
+    fn depth(&self, index: usize) -> u16 {
+        self.depth.unwrap_or_else(|| self.cell.depth(index))
+    }
+
+    fn store_hashes(&self) -> bool {
+        self.cell.store_hashes()
+    }
+
+    fn tree_bits_count(&self) -> u64 {
+        self.cell.tree_bits_count()
+    }
+
+    fn tree_cell_count(&self) -> u64 {
+        self.cell.tree_cell_count()
+    }
+}
@@ -0,0 +1,37 @@
+/*
+* Copyright 2018-2020 TON DEV SOLUTIONS LTD.
+*
+* Licensed under the SOFTWARE EVALUATION License (the "License"); you may not use
+* this file except in compliance with the License.
+*
+* Unless required by applicable law or agreed to in writing, software
+* distributed under the License is distributed on an "AS IS" BASIS,
+* WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+* See the License for the specific TON DEV software governing permissions and
+* limitations under the License.
+*/
+
+//! Helpers for tests of this crate and its consumers: deterministic fixtures,
+//! enabled with feature `testing`.
+
+mod generator;
+
+pub use self::generator::*;
+
+mod snapshot;
+
+pub use self::snapshot::*;
+
+mod asserts;
+
+pub use self::asserts::*;
+
+pub use crate::corruption::*;
+
+mod shrink;
+
+pub use self::shrink::*;
+
+mod mock;
+
+pub use self::mock::*;
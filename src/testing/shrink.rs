@@ -0,0 +1,168 @@
+/*
+* Copyright 2018-2020 TON DEV SOLUTIONS LTD.
+*
+* Licensed under the SOFTWARE EVALUATION License (the "License"); you may not use
+* this file except in compliance with the License.
+*
+* Unless required by applicable law or agreed to in writing, software
+* distributed under the License is distributed on an "AS IS" BASIS,
+* WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+* See the License for the specific TON DEV software governing permissions and
+* limitations under the License.
+*/
+
+use alloc::{vec, vec::Vec};
+
+use smallvec::SmallVec;
+
+use crate::{
+    cell::{BuilderData, Cell, CellType, LevelMask},
+    cells_serialization::{deserialize_cells_tree, BagOfCells},
+    fail,
+    merkle::create_pruned_branch,
+    types::{FxHashSet, Result},
+};
+
+/// Finds smaller tree for which `is_failing` still returns true. Subtrees are repeatedly
+/// replaced with empty cells, their children or pruned branches, references are removed
+/// and data of ordinary cells is truncated while predicate keeps failing.
+/// Every accepted step makes tree smaller, so shrinking always finishes.
+pub fn shrink_cell<F: FnMut(&Cell) -> bool>(cell: &Cell, mut is_failing: F) -> Result<Cell> {
+    if !is_failing(cell) {
+        fail!("predicate does not fail on given tree")
+    }
+    let mut current = cell.clone();
+    'shrink: loop {
+        for path in unique_paths(&current)? {
+            for candidate in candidates(&current, &path)? {
+                if weight(&candidate) < weight(&current) && is_failing(&candidate) {
+                    current = candidate;
+                    continue 'shrink
+                }
+            }
+        }
+        return Ok(current)
+    }
+}
+
+/// Finds smaller BOC for which `is_failing` still returns true: roots are removed and
+/// shrinked with `shrink_cell`, BOC is written in default mode. BOC must be readable.
+pub fn shrink_boc<F: FnMut(&[u8]) -> bool>(boc: &[u8], mut is_failing: F) -> Result<Vec<u8>> {
+    if !is_failing(boc) {
+        fail!("predicate does not fail on given BOC")
+    }
+    let mut roots = deserialize_cells_tree(&mut &boc[..])?;
+    let mut index = 0;
+    while index < roots.len() && roots.len() > 1 {
+        let mut candidate = roots.clone();
+        candidate.remove(index);
+        match write_boc(&candidate).map(|boc| is_failing(&boc)) {
+            Ok(true) => roots = candidate,
+            _ => index += 1
+        }
+    }
+    let mut boc = write_boc(&roots)?;
+    if !is_failing(&boc) {
+        // failure depends on layout of source BOC
+        return Ok(boc)
+    }
+    for index in 0..roots.len() {
+        let root = roots[index].clone();
+        roots[index] = shrink_cell(&root, |cell| {
+            let mut candidate = roots.clone();
+            candidate[index] = cell.clone();
+            write_boc(&candidate).map(|boc| is_failing(&boc)).unwrap_or(false)
+        })?;
+        boc = write_boc(&roots)?;
+    }
+    Ok(boc)
+}
+
+fn write_boc(roots: &[Cell]) -> Result<Vec<u8>> {
+    let mut boc = Vec::new();
+    BagOfCells::with_roots(roots).write_to(&mut boc, false)?;
+    Ok(boc)
+}
+
+// pruning big subtree must make tree smaller even though pruned branch has more bits
+fn weight(cell: &Cell) -> u64 {
+    cell.tree_cell_count() * 1024 + cell.tree_bits_count()
+}
+
+// paths of references from root to every unique cell, parents go first
+fn unique_paths(root: &Cell) -> Result<Vec<Vec<usize>>> {
+    let mut visited = FxHashSet::default();
+    let mut paths = vec![Vec::new()];
+    let mut index = 0;
+    visited.insert(root.repr_hash());
+    while index < paths.len() {
+        let cell = cell_at(root, &paths[index])?;
+        for child_index in 0..cell.references_count() {
+            if visited.insert(cell.reference_repr_hash(child_index)?) {
+                let mut path = paths[index].clone();
+                path.push(child_index);
+                paths.push(path);
+            }
+        }
+        index += 1;
+    }
+    Ok(paths)
+}
+
+fn cell_at(root: &Cell, path: &[usize]) -> Result<Cell> {
+    let mut cell = root.clone();
+    for index in path {
+        cell = cell.reference(*index)?;
+    }
+    Ok(cell)
+}
+
+// ancestors of replaced cell are rebuilt, candidates with invalid ancestors are skipped
+fn replace_at(cell: &Cell, path: &[usize], new: Cell) -> Result<Cell> {
+    match path.split_first() {
+        None => Ok(new),
+        Some((index, rest)) => {
+            let child = replace_at(&cell.reference(*index)?, rest, new)?;
+            let mut builder = BuilderData::from_cell(cell);
+            if cell.cell_type() == CellType::Ordinary {
+                // level mask is calculated from new children
+                builder.set_level_mask(LevelMask::with_mask(0));
+            }
+            builder.replace_reference_cell(*index, child)?;
+            builder.into_cell()
+        }
+    }
+}
+
+fn candidates(root: &Cell, path: &[usize]) -> Result<Vec<Cell>> {
+    let cell = cell_at(root, path)?;
+    let mut replacements = vec![Cell::default()];
+    for index in 0..cell.references_count() {
+        replacements.push(cell.reference(index)?);
+    }
+    if cell.references_count() != 0 && !cell.level_mask().is_significant_index(1) {
+        replacements.push(create_pruned_branch(&cell, 0)?);
+    }
+    if cell.cell_type() == CellType::Ordinary {
+        for index in 0..cell.references_count() {
+            let mut builder = BuilderData::with_raw(SmallVec::from_slice(cell.data()), cell.bit_length())?;
+            for (i, child) in cell.clone_references().into_iter().enumerate() {
+                if i != index {
+                    builder.checked_append_reference(child)?;
+                }
+            }
+            replacements.push(builder.into_cell()?);
+        }
+        let bits = cell.bit_length();
+        let mut lengths = vec![0, bits / 2, bits.saturating_sub(1)];
+        lengths.dedup();
+        for length in lengths.into_iter().filter(|length| *length < bits) {
+            let mut builder = BuilderData::with_raw(SmallVec::from_slice(cell.data()), length)?;
+            for child in cell.clone_references() {
+                builder.checked_append_reference(child)?;
+            }
+            replacements.push(builder.into_cell()?);
+        }
+    }
+    Ok(replacements.into_iter().filter_map(|new| replace_at(root, path, new).ok()).collect())
+}
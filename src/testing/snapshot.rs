@@ -0,0 +1,133 @@
+/*
+* Copyright 2018-2020 TON DEV SOLUTIONS LTD.
+*
+* Licensed under the SOFTWARE EVALUATION License (the "License"); you may not use
+* this file except in compliance with the License.
+*
+* Unless required by applicable law or agreed to in writing, software
+* distributed under the License is distributed on an "AS IS" BASIS,
+* WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+* See the License for the specific TON DEV software governing permissions and
+* limitations under the License.
+*/
+
+use alloc::{format, string::String, vec::Vec};
+use core::fmt::Write;
+
+use crate::{
+    cell::{topological_order, Cell},
+    types::{FxHashMap, FxHashSet, Result, UInt256},
+};
+
+/// Canonical text of trees for snapshot tests. Every unique cell is written once on its own
+/// line in topological order (parents go before children) with its number, representation hash,
+/// type, level mask, data and numbers of referenced cells, so text depends only on trees.
+pub fn snapshot_text(roots: &[Cell]) -> Result<String> {
+    let order = topological_order(roots)?;
+    let index_of = order.iter().enumerate()
+        .map(|(index, cell)| (cell.repr_hash(), index))
+        .collect::<FxHashMap<_, _>>();
+    let mut text = String::new();
+    let roots = roots.iter().map(|root| format!("#{}", index_of[&root.repr_hash()])).collect::<Vec<_>>();
+    writeln!(text, "roots: {}", roots.join(" "))?;
+    for (index, cell) in order.iter().enumerate() {
+        write!(text, "#{} {:x} {} l:{:03b} bits:{} data:{} refs:[",
+            index, cell.repr_hash(), cell.cell_type(), cell.level_mask().mask(),
+            cell.bit_length(), cell.to_hex_string(true))?;
+        for i in 0..cell.references_count() {
+            let separator = if i == 0 { "" } else { " " };
+            write!(text, "{}#{}", separator, index_of[&cell.reference_repr_hash(i)?])?;
+        }
+        writeln!(text, "]")?;
+    }
+    Ok(text)
+}
+
+/// Readable report of differences of two trees, `None` if they are equal. Cells are compared
+/// pairwise by reference paths from roots (`root/1/0` is the first child of the second child),
+/// only cells differing by themselves are reported, equal subtrees are skipped.
+pub fn diff_trees(left: &Cell, right: &Cell) -> Result<Option<String>> {
+    let mut report = String::new();
+    let mut path = String::from("root");
+    let mut done = FxHashSet::default();
+    diff_cells(left, right, &mut path, &mut done, &mut report)?;
+    match report.is_empty() {
+        true => Ok(None),
+        false => Ok(Some(report))
+    }
+}
+
+pub(super) fn diff_cells(
+    left: &Cell,
+    right: &Cell,
+    path: &mut String,
+    done: &mut FxHashSet<(UInt256, UInt256)>,
+    report: &mut String,
+) -> Result<()> {
+    if left.repr_hash() == right.repr_hash() || !done.insert((left.repr_hash(), right.repr_hash())) {
+        return Ok(())
+    }
+    if left.cell_type() != right.cell_type() {
+        writeln!(report, "{}: type {} != {}", path, left.cell_type(), right.cell_type())?;
+    }
+    if left.level_mask() != right.level_mask() {
+        writeln!(report, "{}: level mask {:03b} != {:03b}", path, left.level_mask().mask(), right.level_mask().mask())?;
+    }
+    let (left_data, left_bits) = (left.data(), left.bit_length());
+    let (right_data, right_bits) = (right.data(), right.bit_length());
+    if let Some(bit) = first_different_bit(left_data, left_bits, right_data, right_bits) {
+        writeln!(report, "{}: bits {} and {} differ from bit {}: {} != {}", path, left_bits, right_bits,
+            bit, bits_around(left_data, left_bits, bit), bits_around(right_data, right_bits, bit))?;
+    }
+    let common = left.references_count().min(right.references_count());
+    if left.references_count() != right.references_count() {
+        writeln!(report, "{}: refs {} != {}", path, left.references_count(), right.references_count())?;
+        for index in common..left.references_count() {
+            writeln!(report, "{}/{}: only in left {:x}", path, index, left.reference_repr_hash(index)?)?;
+        }
+        for index in common..right.references_count() {
+            writeln!(report, "{}/{}: only in right {:x}", path, index, right.reference_repr_hash(index)?)?;
+        }
+    }
+    for index in 0..common {
+        let len = path.len();
+        write!(path, "/{}", index)?;
+        diff_cells(&left.reference(index)?, &right.reference(index)?, path, done, report)?;
+        path.truncate(len);
+    }
+    Ok(())
+}
+
+pub(super) fn first_different_bit(left: &[u8], left_bits: usize, right: &[u8], right_bits: usize) -> Option<usize> {
+    let bits = left_bits.min(right_bits);
+    for index in 0..bits.div_ceil(8) {
+        let diff = left[index] ^ right[index];
+        if diff != 0 {
+            let bit = index * 8 + diff.leading_zeros() as usize;
+            if bit < bits {
+                return Some(bit)
+            }
+        }
+    }
+    match left_bits != right_bits {
+        true => Some(bits),
+        false => None
+    }
+}
+
+// up to 8 bits before and after `bit` with `|` before it, `bit` may be the end of data
+pub(super) fn bits_around(data: &[u8], bits: usize, bit: usize) -> String {
+    let start = bit.saturating_sub(8);
+    let end = (bit + 8).min(bits);
+    let mut text = String::new();
+    for index in start..end {
+        if index == bit {
+            text.push('|');
+        }
+        text.push(if data[index / 8] & (0x80 >> (index % 8)) != 0 { '1' } else { '0' });
+    }
+    if bit == end {
+        text.push('|');
+    }
+    text
+}
@@ -0,0 +1,462 @@
+/*
+* Copyright 2018-2020 TON DEV SOLUTIONS LTD.
+*
+* Licensed under the SOFTWARE EVALUATION License (the "License"); you may not use
+* this file except in compliance with the License.
+*
+* Unless required by applicable law or agreed to in writing, software
+* distributed under the License is distributed on an "AS IS" BASIS,
+* WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+* See the License for the specific TON DEV software governing permissions and
+* limitations under the License.
+*/
+
+//! Traits for TL-B types stored in cells and helpers used by code generated with
+//! `#[derive(StoreCell, LoadCell)]` (feature `derive`).
+//!
+//! Field attributes of derive:
+//! * `#[tlb(bits = N)]` - unsigned integer of N bits (up to 64)
+//! * `#[tlb(maybe)]` - `Option` stored as bit and value (`Maybe X`)
+//! * `#[tlb(ref)]` - value is stored in separate child cell (`^X`)
+//! * `#[tlb(dict = N)]` - `HashmapE` with N bit keys
+//!
+//! `maybe` can be combined with other attributes. Structs and enum variants can have
+//! `#[tlb(tag = T, tag_bits = N)]` constructor tag, every enum variant must have it.
+//!
+//! Primitive values are also converted to `BuilderData` with `From` and back from `SliceData` with `TryFrom`.
+//!
+//! `Serializable` and `Deserializable` are the traits of TL-B types shared with block structures,
+//! implemented for primitives, `UInt256`, `Cell` (as reference), `Option` (`Maybe X`), tuples and arrays.
+
+use alloc::vec::Vec;
+
+use crate::{
+    cell::{BuilderData, Cell, IBitstring, SliceData},
+    cells_serialization::{deserialize_tree_of_cells, serialize_toc},
+    dictionary::{HashmapE, HashmapType},
+    error, fail,
+    types::{Error, Result, UInt256},
+};
+
+/// Type which can be appended to builder
+pub trait StoreCell {
+    fn store_to(&self, builder: &mut BuilderData) -> Result<()>;
+}
+
+/// Type which can be read from slice
+pub trait LoadCell: Sized {
+    fn load_from(slice: &mut SliceData) -> Result<Self>;
+}
+
+/// Type which is written to builder
+pub trait Serializable {
+    fn write_to(&self, cell: &mut BuilderData) -> Result<()>;
+
+    fn write_to_new_cell(&self) -> Result<BuilderData> {
+        let mut cell = BuilderData::new();
+        self.write_to(&mut cell)?;
+        Ok(cell)
+    }
+
+    fn serialize(&self) -> Result<Cell> {
+        self.write_to_new_cell()?.into_cell()
+    }
+
+    /// Serialized cell as BOC
+    fn write_to_bytes(&self) -> Result<Vec<u8>> {
+        serialize_toc(&self.serialize()?)
+    }
+}
+
+/// Type which is read from slice, implementation overrides `construct_from` or `read_from`
+pub trait Deserializable: Default {
+    fn construct_from(slice: &mut SliceData) -> Result<Self> {
+        let mut value = Self::default();
+        value.read_from(slice)?;
+        Ok(value)
+    }
+
+    fn read_from(&mut self, slice: &mut SliceData) -> Result<()> {
+        *self = Self::construct_from(slice)?;
+        Ok(())
+    }
+
+    fn construct_from_cell(cell: Cell) -> Result<Self> {
+        Self::construct_from(&mut SliceData::load_cell(cell)?)
+    }
+
+    /// Reads value from next reference of slice
+    fn construct_from_reference(slice: &mut SliceData) -> Result<Self> {
+        Self::construct_from_cell(slice.checked_drain_reference()?)
+    }
+
+    /// Reads value from BOC
+    fn construct_from_bytes(bytes: &[u8]) -> Result<Self> {
+        Self::construct_from_cell(deserialize_tree_of_cells(&mut &bytes[..])?)
+    }
+
+    fn read_from_cell(&mut self, cell: Cell) -> Result<()> {
+        self.read_from(&mut SliceData::load_cell(cell)?)
+    }
+
+    fn read_from_reference(&mut self, slice: &mut SliceData) -> Result<()> {
+        self.read_from_cell(slice.checked_drain_reference()?)
+    }
+}
+
+/// Appends unsigned integer of `bits` bits failing if value does not fit
+pub fn store_uint<T: TryInto<u64>>(builder: &mut BuilderData, value: T, bits: usize) -> Result<()> {
+    let value = match value.try_into() {
+        Ok(value) => value,
+        Err(_) => fail!("value does not fit into {} bits", bits)
+    };
+    if bits > 64 || (bits < 64 && value >> bits != 0) {
+        fail!("value {} does not fit into {} bits", value, bits)
+    }
+    let data = match bits {
+        0 => 0,
+        bits => value << (64 - bits)
+    };
+    builder.append_raw(&data.to_be_bytes(), bits)?;
+    Ok(())
+}
+
+/// Reads unsigned integer of `bits` bits and converts it to field type
+pub fn load_uint<T: TryFrom<u64>>(slice: &mut SliceData, bits: usize) -> Result<T> {
+    let value = slice.get_next_int(bits)?;
+    T::try_from(value).map_err(|_| error!("value {} of {} bits does not fit into field", value, bits))
+}
+
+/// Stores value into new cell and appends it as reference
+pub fn store_ref<T: StoreCell + ?Sized>(builder: &mut BuilderData, value: &T) -> Result<()> {
+    let mut child = BuilderData::new();
+    value.store_to(&mut child)?;
+    builder.checked_append_reference(child.into_cell()?)?;
+    Ok(())
+}
+
+/// Reads value from next reference, the whole child cell must be consumed
+pub fn load_ref<T: LoadCell>(slice: &mut SliceData) -> Result<T> {
+    let mut child = SliceData::load_cell(slice.checked_drain_reference()?)?;
+    let value = T::load_from(&mut child)?;
+    check_consumed(&child)?;
+    Ok(value)
+}
+
+fn check_consumed(slice: &SliceData) -> Result<()> {
+    if !slice.is_empty() {
+        fail!("{} bits and {} references left unread", slice.remaining_bits(), slice.remaining_references())
+    }
+    Ok(())
+}
+
+/// Reads `HashmapE` with keys of `bit_len` bits
+pub fn load_dict(slice: &mut SliceData, bit_len: usize) -> Result<HashmapE> {
+    Ok(HashmapE::with_hashmap(bit_len, slice.get_next_dictionary()?))
+}
+
+pub fn store_tag(builder: &mut BuilderData, tag: u64, bits: usize) -> Result<()> {
+    store_uint(builder, tag, bits)
+}
+
+/// Reads tag if it is next in slice, otherwise slice is left untouched
+pub fn check_tag(slice: &mut SliceData, tag: u64, bits: usize) -> bool {
+    if slice.remaining_bits() < bits {
+        return false
+    }
+    let mut probe = slice.clone();
+    match probe.get_next_int(bits) {
+        Ok(next) if next == tag => {
+            *slice = probe;
+            true
+        }
+        _ => false
+    }
+}
+
+pub fn load_tag(slice: &mut SliceData, tag: u64, bits: usize, type_name: &str) -> Result<()> {
+    match check_tag(slice, tag, bits) {
+        true => Ok(()),
+        false => fail!("invalid tag of {}, expected {:#x} of {} bits", type_name, tag, bits)
+    }
+}
+
+pub fn unknown_tag<T>(type_name: &str) -> Result<T> {
+    fail!("no variant of {} matches the tag", type_name)
+}
+
+macro_rules! impl_int {
+    ($($t:ty: $append:ident, $get:ident);* $(;)?) => {$(
+        impl StoreCell for $t {
+            fn store_to(&self, builder: &mut BuilderData) -> Result<()> {
+                builder.$append(*self)?;
+                Ok(())
+            }
+        }
+
+        impl LoadCell for $t {
+            fn load_from(slice: &mut SliceData) -> Result<Self> {
+                slice.$get()
+            }
+        }
+    )*};
+}
+
+impl_int! {
+    u8: append_u8, get_next_byte;
+    u16: append_u16, get_next_u16;
+    u32: append_u32, get_next_u32;
+    u64: append_u64, get_next_u64;
+    u128: append_u128, get_next_u128;
+    i16: append_i16, get_next_i16;
+    i32: append_i32, get_next_i32;
+}
+
+impl StoreCell for i128 {
+    fn store_to(&self, builder: &mut BuilderData) -> Result<()> {
+        builder.append_i128(*self)?;
+        Ok(())
+    }
+}
+
+impl LoadCell for i128 {
+    fn load_from(slice: &mut SliceData) -> Result<Self> {
+        Ok(slice.get_next_u128()? as i128)
+    }
+}
+
+impl StoreCell for i8 {
+    fn store_to(&self, builder: &mut BuilderData) -> Result<()> {
+        builder.append_i8(*self)?;
+        Ok(())
+    }
+}
+
+impl LoadCell for i8 {
+    fn load_from(slice: &mut SliceData) -> Result<Self> {
+        Ok(slice.get_next_byte()? as i8)
+    }
+}
+
+impl StoreCell for i64 {
+    fn store_to(&self, builder: &mut BuilderData) -> Result<()> {
+        builder.append_i64(*self)?;
+        Ok(())
+    }
+}
+
+impl LoadCell for i64 {
+    fn load_from(slice: &mut SliceData) -> Result<Self> {
+        Ok(slice.get_next_u64()? as i64)
+    }
+}
+
+impl StoreCell for bool {
+    fn store_to(&self, builder: &mut BuilderData) -> Result<()> {
+        builder.append_bit_bool(*self)?;
+        Ok(())
+    }
+}
+
+impl LoadCell for bool {
+    fn load_from(slice: &mut SliceData) -> Result<Self> {
+        slice.get_next_bit()
+    }
+}
+
+impl StoreCell for UInt256 {
+    fn store_to(&self, builder: &mut BuilderData) -> Result<()> {
+        builder.append_raw(self.as_slice(), 256)?;
+        Ok(())
+    }
+}
+
+impl LoadCell for UInt256 {
+    fn load_from(slice: &mut SliceData) -> Result<Self> {
+        slice.get_next_hash()
+    }
+}
+
+/// Cell is stored as reference
+impl StoreCell for Cell {
+    fn store_to(&self, builder: &mut BuilderData) -> Result<()> {
+        builder.checked_append_reference(self.clone())?;
+        Ok(())
+    }
+}
+
+impl LoadCell for Cell {
+    fn load_from(slice: &mut SliceData) -> Result<Self> {
+        slice.checked_drain_reference()
+    }
+}
+
+/// `Maybe X` with value stored inline
+impl<T: StoreCell> StoreCell for Option<T> {
+    fn store_to(&self, builder: &mut BuilderData) -> Result<()> {
+        match self {
+            Some(value) => {
+                builder.append_bit_one()?;
+                value.store_to(builder)
+            }
+            None => {
+                builder.append_bit_zero()?;
+                Ok(())
+            }
+        }
+    }
+}
+
+impl<T: LoadCell> LoadCell for Option<T> {
+    fn load_from(slice: &mut SliceData) -> Result<Self> {
+        match slice.get_next_bit()? {
+            true => Ok(Some(T::load_from(slice)?)),
+            false => Ok(None)
+        }
+    }
+}
+
+/// Key length is unknown while loading, so use `load_dict` or `#[tlb(dict = N)]`
+impl StoreCell for HashmapE {
+    fn store_to(&self, builder: &mut BuilderData) -> Result<()> {
+        self.write_hashmap_data(builder)
+    }
+}
+
+// values which always fit into one cell are converted to builder and back,
+// slice must contain exactly the value
+macro_rules! impl_conversions {
+    ($($t:ty),*) => {$(
+        impl From<$t> for BuilderData {
+            fn from(value: $t) -> Self {
+                let mut builder = BuilderData::new();
+                value.store_to(&mut builder).expect("value always fits into empty builder");
+                builder
+            }
+        }
+
+        impl TryFrom<SliceData> for $t {
+            type Error = Error;
+            fn try_from(mut slice: SliceData) -> Result<Self> {
+                let value = <$t>::load_from(&mut slice)?;
+                check_consumed(&slice)?;
+                Ok(value)
+            }
+        }
+    )*};
+}
+
+impl_conversions!(bool, u8, u16, u32, u64, u128, i8, i16, i32, i64, i128, UInt256);
+
+/// Bytes are copied to data of builder, there can be no more than 127 of them
+impl TryFrom<&[u8]> for BuilderData {
+    type Error = Error;
+    fn try_from(value: &[u8]) -> Result<Self> {
+        let mut builder = BuilderData::new();
+        builder.append_raw(value, value.len() * 8)?;
+        Ok(builder)
+    }
+}
+
+/// Slice must have whole bytes and no references
+impl TryFrom<SliceData> for Vec<u8> {
+    type Error = Error;
+    fn try_from(mut slice: SliceData) -> Result<Self> {
+        if !slice.remaining_bits().is_multiple_of(8) {
+            fail!("slice of {} bits is not whole bytes", slice.remaining_bits())
+        }
+        let value = slice.get_next_bytes(slice.remaining_bits() / 8)?;
+        check_consumed(&slice)?;
+        Ok(value)
+    }
+}
+
+// values having StoreCell and LoadCell implementations
+macro_rules! impl_serializable {
+    ($($t:ty),*) => {$(
+        impl Serializable for $t {
+            fn write_to(&self, cell: &mut BuilderData) -> Result<()> {
+                self.store_to(cell)
+            }
+        }
+
+        impl Deserializable for $t {
+            fn construct_from(slice: &mut SliceData) -> Result<Self> {
+                Self::load_from(slice)
+            }
+        }
+    )*};
+}
+
+impl_serializable!(bool, u8, u16, u32, u64, u128, i8, i16, i32, i64, i128, UInt256, Cell);
+
+/// `Maybe X` with value stored inline
+impl<T: Serializable> Serializable for Option<T> {
+    fn write_to(&self, cell: &mut BuilderData) -> Result<()> {
+        match self {
+            Some(value) => {
+                cell.append_bit_one()?;
+                value.write_to(cell)
+            }
+            None => {
+                cell.append_bit_zero()?;
+                Ok(())
+            }
+        }
+    }
+}
+
+impl<T: Deserializable> Deserializable for Option<T> {
+    fn construct_from(slice: &mut SliceData) -> Result<Self> {
+        match slice.get_next_bit()? {
+            true => Ok(Some(T::construct_from(slice)?)),
+            false => Ok(None)
+        }
+    }
+}
+
+// elements are written one after another
+macro_rules! impl_tuple {
+    ($(($($name:ident: $index:tt),+)),*) => {$(
+        impl<$($name: Serializable),+> Serializable for ($($name,)+) {
+            fn write_to(&self, cell: &mut BuilderData) -> Result<()> {
+                $(self.$index.write_to(cell)?;)+
+                Ok(())
+            }
+        }
+
+        impl<$($name: Deserializable),+> Deserializable for ($($name,)+) {
+            fn construct_from(slice: &mut SliceData) -> Result<Self> {
+                Ok(($($name::construct_from(slice)?,)+))
+            }
+        }
+    )*};
+}
+
+impl_tuple! {
+    (A: 0),
+    (A: 0, B: 1),
+    (A: 0, B: 1, C: 2),
+    (A: 0, B: 1, C: 2, D: 3),
+    (A: 0, B: 1, C: 2, D: 3, E: 4),
+    (A: 0, B: 1, C: 2, D: 3, E: 4, F: 5)
+}
+
+impl<T: Serializable, const N: usize> Serializable for [T; N] {
+    fn write_to(&self, cell: &mut BuilderData) -> Result<()> {
+        for item in self {
+            item.write_to(cell)?;
+        }
+        Ok(())
+    }
+}
+
+/// Arrays of up to 32 elements as longer ones do not implement `Default`
+impl<T: Deserializable, const N: usize> Deserializable for [T; N] where [T; N]: Default {
+    fn read_from(&mut self, slice: &mut SliceData) -> Result<()> {
+        for item in self {
+            item.read_from(slice)?;
+        }
+        Ok(())
+    }
+}
@@ -11,31 +11,75 @@
 * limitations under the License.
 */
 
-use crate::cell::{BuilderData, SliceData};
+use crate::cell::{BuilderData, ParseError, ParseErrorKind, SliceData};
+use crate::quota::QuotaError;
 use num::FromPrimitive;
-use sha2::Digest;
-use std::{cmp, convert::TryInto, fmt, fmt::{LowerHex, UpperHex}, str::{self, FromStr}};
-use std::hash::BuildHasherDefault;
+use alloc::{boxed::Box, string::String, vec::Vec};
+use core::sync::atomic::{AtomicPtr, Ordering};
+use core::{cmp, convert::TryInto, fmt, fmt::{LowerHex, UpperHex}, str::{self, FromStr}};
+use core::hash::BuildHasherDefault;
+use core::marker::PhantomData;
 use smallvec::SmallVec;
 
 pub type Error = anyhow::Error;
-pub type Result<T> = std::result::Result<T, anyhow::Error>;
+pub type Result<T> = core::result::Result<T, anyhow::Error>;
 pub type Failure = Option<anyhow::Error>;
 pub type Status = Result<()>;
 
+/// Hash map of `hashbrown` with Fx hasher, the same type with and without std
+pub type FxHashMap<K, V> = hashbrown::HashMap<K, V, BuildHasherDefault<rustc_hash::FxHasher>>;
+/// Hash set of `hashbrown` with Fx hasher, the same type with and without std
+pub type FxHashSet<V> = hashbrown::HashSet<V, BuildHasherDefault<rustc_hash::FxHasher>>;
+
+#[cfg(feature = "std")]
 pub type FxDashMap<K, V> = dashmap::DashMap<K, V, BuildHasherDefault<rustc_hash::FxHasher>>;
+#[cfg(feature = "std")]
 pub type FxDashSet<V> = dashmap::DashSet<V, BuildHasherDefault<rustc_hash::FxHasher>>;
 
+/// Value which can be set only once, it is never freed.
+/// Replacement of `OnceLock<Box<T>>` available without std
+pub(crate) struct OnceBox<T: ?Sized> {
+    ptr: AtomicPtr<Box<T>>,
+    // owns value, so it is shared between threads only if the value can be
+    _value: PhantomData<Box<T>>,
+}
+
+// SAFETY: value is set once and then only shared by reference, like in `OnceLock<Box<T>>`
+unsafe impl<T: ?Sized + Send + Sync> Sync for OnceBox<T> {}
+// SAFETY: see above
+unsafe impl<T: ?Sized + Send> Send for OnceBox<T> {}
+
+impl<T: ?Sized> OnceBox<T> {
+    pub(crate) const fn new() -> Self {
+        Self { ptr: AtomicPtr::new(core::ptr::null_mut()), _value: PhantomData }
+    }
+
+    /// Returns value back if it is already set
+    pub(crate) fn set(&self, value: Box<T>) -> core::result::Result<(), Box<T>> {
+        let ptr = Box::into_raw(Box::new(value));
+        match self.ptr.compare_exchange(core::ptr::null_mut(), ptr, Ordering::AcqRel, Ordering::Acquire) {
+            Ok(_) => Ok(()),
+            // SAFETY: pointer was created above and was not published
+            Err(_) => Err(*unsafe { Box::from_raw(ptr) })
+        }
+    }
+
+    pub(crate) fn get(&self) -> Option<&T> {
+        // SAFETY: pointer is either null or set once by `set` and never freed
+        unsafe { self.ptr.load(Ordering::Acquire).as_ref() }.map(|value| value.as_ref())
+    }
+}
+
 #[macro_export]
 macro_rules! error {
     ($error:literal) => {
-        anyhow::anyhow!(format!("{} {}:{}", $error, file!(), line!()))
+        anyhow::anyhow!("{} {}:{}", $error, file!(), line!())
     };
     ($error:expr) => {
         anyhow::Error::from($error)
     };
     ($fmt:expr, $($arg:tt)+) => {
-        anyhow::Error::msg(format!("{} {}:{}", format!($fmt, $($arg)*), file!(), line!()))
+        anyhow::anyhow!("{} {}:{}", format_args!($fmt, $($arg)*), file!(), line!())
     };
 }
 
@@ -52,7 +96,7 @@ macro_rules! fail {
         anyhow::bail!($error)
     };
     ($fmt:expr, $($arg:tt)*) => {
-        anyhow::bail!("{} {}:{}", format!($fmt, $($arg)*), file!(), line!())
+        anyhow::bail!("{} {}:{}", format_args!($fmt, $($arg)*), file!(), line!())
     };
 }
 
@@ -139,8 +183,7 @@ impl UInt256 {
     }
 
     pub fn calc_file_hash(bytes: &[u8]) -> Self {
-        let hash: [u8; 32] = sha2::Sha256::digest(bytes).into();
-        Self(hash)
+        crate::hashing::sha256_digest(bytes)
     }
 
     pub fn first_u64(&self) -> u64 {
@@ -153,6 +196,14 @@ impl UInt256 {
         Self(hash)
     }
 
+    /// Takes exactly 32 bytes, unlike `from_slice` other lengths are an error
+    pub fn from_vec(value: Vec<u8>) -> Result<Self> {
+        match value.try_into() {
+            Ok(hash) => Ok(Self(hash)),
+            Err(value) => fail!("invalid length of UInt256 {}", value.len())
+        }
+    }
+
     pub fn from_slice(value: &[u8]) -> Self {
         match value.try_into() {
             Ok(hash) => Self(hash),
@@ -180,6 +231,7 @@ impl UInt256 {
         UInt256::MAX
     }
 
+    #[cfg(all(feature = "rand", not(target_arch = "wasm32")))]
     pub fn rand() -> Self {
         Self(rand::random())
     }
@@ -192,6 +244,78 @@ impl UInt256 {
         self.0.to_vec()
     }
 
+    /// Raises self to the power of `exp`, returns None if overflow occurred
+    pub fn checked_pow(&self, exp: u32) -> Option<Self> {
+        match self.overflowing_pow(exp) {
+            (result, false) => Some(result),
+            (_, true) => None
+        }
+    }
+
+    /// Raises self to the power of `exp` using exponentiation by squaring.
+    /// Returns wrapped result and flag indicating whether an overflow occurred
+    pub fn overflowing_pow(&self, mut exp: u32) -> (Self, bool) {
+        let mut base = self.limbs();
+        let mut acc = [1, 0, 0, 0];
+        let mut overflow = false;
+        let mut base_overflow = false;
+        while exp != 0 {
+            if exp & 1 == 1 {
+                let (result, o) = Self::overflowing_mul_limbs(&acc, &base);
+                acc = result;
+                // overflow of base matters only if it takes part in the result
+                overflow |= o | base_overflow;
+            }
+            exp >>= 1;
+            if exp != 0 {
+                let (result, o) = Self::overflowing_mul_limbs(&base, &base);
+                base = result;
+                base_overflow |= o;
+            }
+        }
+        (Self::from_limbs(&acc), overflow)
+    }
+
+    // little-endian order of 64-bit words
+    fn limbs(&self) -> [u64; 4] {
+        let mut limbs = [0; 4];
+        for (i, limb) in limbs.iter_mut().enumerate() {
+            let offset = 32 - 8 * (i + 1);
+            *limb = u64::from_be_bytes(self.0[offset..offset + 8].try_into().unwrap());
+        }
+        limbs
+    }
+
+    fn from_limbs(limbs: &[u64; 4]) -> Self {
+        let mut data = [0; 32];
+        for (i, limb) in limbs.iter().enumerate() {
+            let offset = 32 - 8 * (i + 1);
+            data[offset..offset + 8].copy_from_slice(&limb.to_be_bytes());
+        }
+        Self(data)
+    }
+
+    fn overflowing_mul_limbs(a: &[u64; 4], b: &[u64; 4]) -> ([u64; 4], bool) {
+        let mut result = [0u64; 4];
+        let mut overflow = false;
+        for i in 0..4 {
+            let mut carry = 0u128;
+            for j in 0..4 {
+                let product = a[i] as u128 * b[j] as u128 + carry;
+                if i + j < 4 {
+                    let sum = result[i + j] as u128 + (product & u64::MAX as u128);
+                    result[i + j] = sum as u64;
+                    carry = (product >> 64) + (sum >> 64);
+                } else {
+                    overflow |= product != 0;
+                    carry = 0;
+                }
+            }
+            overflow |= carry != 0;
+        }
+        (result, overflow)
+    }
+
     pub const ZERO: UInt256 = UInt256([0; 32]);
     pub const MIN: UInt256 = UInt256([0; 32]);
     pub const MAX: UInt256 = UInt256([0xFF; 32]);
@@ -225,9 +349,10 @@ impl FromStr for UInt256 {
     type Err = anyhow::Error;
     fn from_str(value: &str) -> Result<Self> {
         let bytes = match value.len() {
-            64 => hex::decode(value)?,
-            66 => hex::decode(&value[2..])?,
-            44 => base64::decode(value)?,
+            // decoding errors implement std::error::Error only with std
+            64 => hex::decode(value).map_err(Error::msg)?,
+            66 => hex::decode(&value[2..]).map_err(Error::msg)?,
+            43 | 44 => crate::base64_decode(value)?,
             len => fail!("invalid account ID string length (64 expected), but got {} for string {}", len, value)
         };
         match bytes.try_into() {
@@ -315,6 +440,7 @@ impl str::FromStr for AccountId {
 // Exceptions *****************************************************************
 
 #[derive(Clone, Copy, Debug, num_derive::FromPrimitive, PartialEq, Eq, thiserror::Error)]
+#[repr(i32)]
 pub enum ExceptionCode {
     #[error("normal termination")]
     NormalTermination = 0,
@@ -360,7 +486,26 @@ impl fmt::Display for ExceptionCode {
 
 #[rustfmt::skip]
 impl ExceptionCode {
-/*
+    /// All exception codes in order of their numeric values
+    pub const ALL: [ExceptionCode; 16] = [
+        ExceptionCode::NormalTermination,
+        ExceptionCode::AlternativeTermination,
+        ExceptionCode::StackUnderflow,
+        ExceptionCode::StackOverflow,
+        ExceptionCode::IntegerOverflow,
+        ExceptionCode::RangeCheckError,
+        ExceptionCode::InvalidOpcode,
+        ExceptionCode::TypeCheckError,
+        ExceptionCode::CellOverflow,
+        ExceptionCode::CellUnderflow,
+        ExceptionCode::DictionaryError,
+        ExceptionCode::UnknownError,
+        ExceptionCode::FatalError,
+        ExceptionCode::OutOfGas,
+        ExceptionCode::IllegalInstruction,
+        ExceptionCode::PrunedCellAccess,
+    ];
+
     pub fn message(&self) -> &'static str {
         match self {
             ExceptionCode::NormalTermination        => "normal termination",
@@ -376,29 +521,119 @@ impl ExceptionCode {
             ExceptionCode::DictionaryError          => "dictionary error",
             ExceptionCode::UnknownError             => "unknown error",
             ExceptionCode::FatalError               => "fatal error",
-            ExceptionCode::OutOfGas                 => "out of gas error"
+            ExceptionCode::OutOfGas                 => "out of gas",
+            ExceptionCode::IllegalInstruction       => "illegal instruction",
+            ExceptionCode::PrunedCellAccess         => "pruned cell",
         }
     }
-*/
+
+    /// Stable numeric code of the exception, safe to pass across FFI boundaries
+    pub const fn code(&self) -> i32 {
+        *self as i32
+    }
+
+    pub fn from_code(code: i32) -> Option<ExceptionCode> {
+        FromPrimitive::from_i32(code)
+    }
+
+    /// Returns numeric code for any error of the crate: code of the outermost `ExceptionCode`,
+    /// `QuotaError`, `ParseError` (or its kind) or I/O error in the chain of causes.
+    /// Other errors are reported as `UnknownError`
+    pub fn code_of(error: &Error) -> i32 {
+        error.chain()
+            .find_map(|cause| {
+                if let Some(code) = cause.downcast_ref::<ExceptionCode>() {
+                    Some(code.code())
+                } else if let Some(error) = cause.downcast_ref::<QuotaError>() {
+                    Some(error.code())
+                } else if let Some(error) = cause.downcast_ref::<ParseError>() {
+                    Some(error.code())
+                } else if let Some(kind) = cause.downcast_ref::<ParseErrorKind>() {
+                    Some(kind.code())
+                } else {
+                    cause.downcast_ref::<crate::io::Error>().map(io_error_code)
+                }
+            })
+            .unwrap_or(ExceptionCode::UnknownError.code())
+    }
+
+    /// Mapping table of numeric codes to messages, exceptions are followed by other errors (`ErrorCode`)
+    pub fn codes_table() -> impl Iterator<Item = (i32, &'static str)> {
+        Self::ALL.iter().map(|code| (code.code(), code.message()))
+            .chain(ErrorCode::ALL.iter().map(|code| (code.code(), code.message())))
+    }
+
     pub fn from_usize(number: usize) -> Option<ExceptionCode> {
         FromPrimitive::from_usize(number)
     }
 }
 
+/// Stable codes of errors which are not exceptions, they don't intersect with `ExceptionCode` ones
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(i32)]
+pub enum ErrorCode {
+    QuotaCellsExceeded = 100,
+    QuotaBytesExceeded = 101,
+    ParseBitsExceeded = 110,
+    ParseReferencesExceeded = 111,
+    ParseCellsExceeded = 112,
+    ParseError = 113,
+    IoError = 120,
+    UnexpectedEof = 121,
+}
+
+#[rustfmt::skip]
+impl ErrorCode {
+    pub const ALL: [ErrorCode; 8] = [
+        ErrorCode::QuotaCellsExceeded,
+        ErrorCode::QuotaBytesExceeded,
+        ErrorCode::ParseBitsExceeded,
+        ErrorCode::ParseReferencesExceeded,
+        ErrorCode::ParseCellsExceeded,
+        ErrorCode::ParseError,
+        ErrorCode::IoError,
+        ErrorCode::UnexpectedEof,
+    ];
+
+    pub fn message(&self) -> &'static str {
+        match self {
+            ErrorCode::QuotaCellsExceeded       => "quota of live cells is exceeded",
+            ErrorCode::QuotaBytesExceeded       => "quota of bytes of live cells is exceeded",
+            ErrorCode::ParseBitsExceeded        => "parser budget of bits is exceeded",
+            ErrorCode::ParseReferencesExceeded  => "parser budget of references is exceeded",
+            ErrorCode::ParseCellsExceeded       => "parser budget of cells is exceeded",
+            ErrorCode::ParseError               => "parse error",
+            ErrorCode::IoError                  => "i/o error",
+            ErrorCode::UnexpectedEof            => "unexpected end of data",
+        }
+    }
+
+    pub const fn code(&self) -> i32 {
+        *self as i32
+    }
+}
+
+fn io_error_code(error: &crate::io::Error) -> i32 {
+    match error.kind() {
+        crate::io::ErrorKind::UnexpectedEof => ErrorCode::UnexpectedEof.code(),
+        _ => ErrorCode::IoError.code()
+    }
+}
+
 pub trait ByteOrderRead {
-    fn read_be_uint(&mut self, bytes: usize) -> std::io::Result<u64>;
-    fn read_byte(&mut self) -> std::io::Result<u8>;
-    fn read_be_u16(&mut self) -> std::io::Result<u16>;
-    fn read_be_u32(&mut self) -> std::io::Result<u32>;
-    fn read_be_u64(&mut self) -> std::io::Result<u64>;
-    fn read_le_u16(&mut self) -> std::io::Result<u16>;
-    fn read_le_u32(&mut self) -> std::io::Result<u32>;
-    fn read_le_u64(&mut self) -> std::io::Result<u64>;
-    fn read_u256(&mut self) -> std::io::Result<[u8; 32]>;
-}
-
-impl<T: std::io::Read> ByteOrderRead for T {
-    fn read_be_uint(&mut self, bytes: usize) -> std::io::Result<u64> {
+    fn read_be_uint(&mut self, bytes: usize) -> crate::io::Result<u64>;
+    fn read_byte(&mut self) -> crate::io::Result<u8>;
+    fn read_be_u16(&mut self) -> crate::io::Result<u16>;
+    fn read_be_u32(&mut self) -> crate::io::Result<u32>;
+    fn read_be_u64(&mut self) -> crate::io::Result<u64>;
+    fn read_le_u16(&mut self) -> crate::io::Result<u16>;
+    fn read_le_u32(&mut self) -> crate::io::Result<u32>;
+    fn read_le_u64(&mut self) -> crate::io::Result<u64>;
+    fn read_u256(&mut self) -> crate::io::Result<[u8; 32]>;
+}
+
+impl<T: crate::io::Read> ByteOrderRead for T {
+    fn read_be_uint(&mut self, bytes: usize) -> crate::io::Result<u64> {
         match bytes {
             1 => {
                 let mut buf = [0];
@@ -420,53 +655,53 @@ impl<T: std::io::Read> ByteOrderRead for T {
                 self.read_exact(&mut buf[8 - bytes..])?;
                 Ok(u64::from_be_bytes(buf))
             },
-            _ => Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, "too many bytes to read in u64")),
+            _ => Err(crate::io::Error::new(crate::io::ErrorKind::InvalidInput, "too many bytes to read in u64")),
         }
     }
 
-    fn read_byte(&mut self) -> std::io::Result<u8> {
+    fn read_byte(&mut self) -> crate::io::Result<u8> {
         let mut buf = [0];
         self.read_exact(&mut buf)?;
         Ok(buf[0])
     }
 
-    fn read_be_u16(&mut self) -> std::io::Result<u16> {
+    fn read_be_u16(&mut self) -> crate::io::Result<u16> {
         let mut buf = [0; 2];
         self.read_exact(&mut buf)?;
         Ok(u16::from_be_bytes(buf))
     }
 
-    fn read_be_u32(&mut self) -> std::io::Result<u32> {
+    fn read_be_u32(&mut self) -> crate::io::Result<u32> {
         let mut buf = [0; 4];
         self.read_exact(&mut buf)?;
         Ok(u32::from_be_bytes(buf))
     }
 
-    fn read_be_u64(&mut self) -> std::io::Result<u64> {
+    fn read_be_u64(&mut self) -> crate::io::Result<u64> {
         let mut buf = [0; 8];
         self.read_exact(&mut buf)?;
         Ok(u64::from_be_bytes(buf))
     }
 
-    fn read_le_u16(&mut self) -> std::io::Result<u16> {
+    fn read_le_u16(&mut self) -> crate::io::Result<u16> {
         let mut buf = [0; 2];
         self.read_exact(&mut buf)?;
         Ok(u16::from_le_bytes(buf))
     }
 
-    fn read_le_u32(&mut self) -> std::io::Result<u32> {
+    fn read_le_u32(&mut self) -> crate::io::Result<u32> {
         let mut buf = [0; 4];
         self.read_exact(&mut buf)?;
         Ok(u32::from_le_bytes(buf))
     }
 
-    fn read_le_u64(&mut self) -> std::io::Result<u64> {
+    fn read_le_u64(&mut self) -> crate::io::Result<u64> {
         let mut buf = [0; 8];
         self.read_exact(&mut buf)?;
         Ok(u64::from_le_bytes(buf))
     }
 
-    fn read_u256(&mut self) -> std::io::Result<[u8; 32]> {
+    fn read_u256(&mut self) -> crate::io::Result<[u8; 32]> {
         let mut buf = [0; 32];
         self.read_exact(&mut buf)?;
         Ok(buf)
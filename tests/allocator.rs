@@ -0,0 +1,50 @@
+/*
+* Copyright 2018-2020 TON DEV SOLUTIONS LTD.
+*
+* Licensed under the SOFTWARE EVALUATION License (the "License"); you may not use
+* this file except in compliance with the License.
+*
+* Unless required by applicable law or agreed to in writing, software
+* distributed under the License is distributed on an "AS IS" BASIS,
+* WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+* See the License for the specific TON DEV software governing permissions and
+* limitations under the License.
+*/
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use ton_types::{set_cell_allocator, BuilderData, CellAllocator, IBitstring, Result};
+
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+static RELEASED: AtomicUsize = AtomicUsize::new(0);
+
+struct CountingAllocator;
+
+impl CellAllocator for CountingAllocator {
+    fn allocate(&self, capacity: usize) -> Vec<u8> {
+        ALLOCATED.fetch_add(1, Ordering::SeqCst);
+        Vec::with_capacity(capacity)
+    }
+    fn release(&self, _buffer: Vec<u8>) {
+        RELEASED.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+#[test]
+fn test_spilled_builder_data_uses_cell_allocator() -> Result<()> {
+    set_cell_allocator(Box::new(CountingAllocator))?;
+    let mut builder = BuilderData::new();
+    builder.append_raw(&[0x55; 100], 800)?;
+    // whole slice is appended before truncation, so data grows over inline capacity
+    builder.append_raw(&[0xaa; 40], 8)?;
+    builder.append_u8(1)?;
+    assert_eq!(builder.data().len(), 102);
+    let allocated = ALLOCATED.load(Ordering::SeqCst);
+    assert!(allocated >= 1);
+
+    let clone = builder.clone();
+    drop(builder);
+    assert!(RELEASED.load(Ordering::SeqCst) >= 1);
+    assert_eq!(clone.data().len(), 102);
+    Ok(())
+}
@@ -0,0 +1,92 @@
+/*
+* Copyright 2018-2020 TON DEV SOLUTIONS LTD.
+*
+* Licensed under the SOFTWARE EVALUATION License (the "License"); you may not use
+* this file except in compliance with the License.
+*
+* Unless required by applicable law or agreed to in writing, software
+* distributed under the License is distributed on an "AS IS" BASIS,
+* WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+* See the License for the specific TON DEV software governing permissions and
+* limitations under the License.
+*/
+
+//! Cells read into shared buffer are the same as cells read one by one
+
+#![cfg(feature = "std")]
+
+use ton_types::{
+    create_pruned_branch, BagOfCells, BocDeserializer, BuilderData, Cell, IBitstring, MerkleProof,
+    Result, Serializable,
+};
+
+fn leaf(value: u32) -> Result<Cell> {
+    let mut builder = BuilderData::new();
+    builder.append_u32(value)?;
+    builder.append_bits(value as usize, value as usize % 9)?;
+    builder.into_cell()
+}
+
+fn fork(children: &[Cell], value: u8) -> Result<Cell> {
+    let mut builder = BuilderData::new();
+    builder.append_u8(value)?;
+    for child in children {
+        builder.checked_append_reference(child.clone())?;
+    }
+    builder.into_cell()
+}
+
+// ordinary tree with shared subtrees and merkle proof of it with pruned branch
+fn roots() -> Result<Vec<Cell>> {
+    let leaves = (0..8).map(leaf).collect::<Result<Vec<_>>>()?;
+    let left = fork(&leaves[..4], 1)?;
+    let right = fork(&[leaves[3].clone(), leaves[4].clone(), left.clone()], 2)?;
+    let root = fork(&[left.clone(), right.clone(), leaves[7].clone()], 3)?;
+    let partial = fork(&[create_pruned_branch(&left, 0)?, right.clone()], 3)?;
+    let proof = MerkleProof { hash: partial.hash(0), depth: partial.depth(0), proof: partial }.serialize()?;
+    Ok(vec![root, proof])
+}
+
+// representation hashes, hashes and depths of all levels of every cell in tree order
+fn tree_hashes(cell: &Cell, hashes: &mut Vec<String>) -> Result<()> {
+    hashes.push(format!("{:x} {:?} {:?}", cell.repr_hash(), cell.hashes(), cell.depths()));
+    for index in 0..cell.references_count() {
+        tree_hashes(&cell.reference(index)?, hashes)?;
+    }
+    Ok(())
+}
+
+#[test]
+fn test_arena_gives_the_same_cells() -> Result<()> {
+    let roots = roots()?;
+    let mut boc = Vec::new();
+    BagOfCells::with_roots(&roots).write_to(&mut boc, false)?;
+    let plain = BocDeserializer::new().deserialize(&mut &boc[..])?.roots;
+    let arena = BocDeserializer::new().set_use_arena(true).deserialize(&mut &boc[..])?.roots;
+    assert_eq!(plain, roots);
+    assert_eq!(arena, roots);
+    for (plain, arena) in plain.iter().zip(&arena) {
+        let mut plain_hashes = Vec::new();
+        tree_hashes(plain, &mut plain_hashes)?;
+        let mut arena_hashes = Vec::new();
+        tree_hashes(arena, &mut arena_hashes)?;
+        assert_eq!(plain_hashes, arena_hashes);
+        assert_eq!(plain.tree_cell_count(), arena.tree_cell_count());
+        assert_eq!(plain.tree_bits_count(), arena.tree_bits_count());
+    }
+    Ok(())
+}
+
+#[test]
+fn test_arena_cell_outlives_the_rest() -> Result<()> {
+    let roots = roots()?;
+    let mut boc = Vec::new();
+    BagOfCells::with_roots(&roots).write_to(&mut boc, false)?;
+    let mut arena = BocDeserializer::new().set_use_arena(true).deserialize(&mut &boc[..])?.roots;
+    let leaf = arena[0].reference(2)?;
+    arena.clear();
+    drop(boc);
+    assert_eq!(leaf, roots[0].reference(2)?);
+    assert_eq!(leaf.data(), roots[0].reference(2)?.data());
+    Ok(())
+}
@@ -0,0 +1,119 @@
+/*
+* Copyright 2018-2020 TON DEV SOLUTIONS LTD.
+*
+* Licensed under the SOFTWARE EVALUATION License (the "License"); you may not use
+* this file except in compliance with the License.
+*
+* Unless required by applicable law or agreed to in writing, software
+* distributed under the License is distributed on an "AS IS" BASIS,
+* WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+* See the License for the specific TON DEV software governing permissions and
+* limitations under the License.
+*/
+
+//! BOCs of the same cells written with different options have the same canonical form
+
+#![cfg(feature = "std")]
+
+use ton_types::{
+    canonicalize_boc, Boc, BocDeserializer, BocSerialiseMode, BuilderData, Cell, IBitstring,
+    Result,
+};
+
+// tree with shared subtree, so cells are referenced from several parents
+fn tree(seed: u32) -> Result<Cell> {
+    let mut shared = BuilderData::new();
+    shared.append_u32(seed)?;
+    let shared = shared.into_cell()?;
+    let mut forks = Vec::new();
+    for fork in 0..3_u32 {
+        let mut builder = BuilderData::new();
+        builder.append_u16(fork as u16)?;
+        builder.append_bits(seed as usize, 5)?;
+        builder.checked_append_reference(shared.clone())?;
+        forks.push(builder.into_cell()?);
+    }
+    let mut root = BuilderData::new();
+    root.append_u8(0xAB)?;
+    for fork in forks {
+        root.checked_append_reference(fork)?;
+    }
+    root.checked_append_reference(shared)?;
+    root.into_cell()
+}
+
+fn modes() -> Vec<BocSerialiseMode> {
+    let mut modes = vec![BocSerialiseMode::Indexed, BocSerialiseMode::IndexedCrc];
+    for index in [false, true] {
+        for crc in [false, true] {
+            modes.push(BocSerialiseMode::Generic { index, crc, cache_bits: false, flags: 0 });
+        }
+    }
+    modes
+}
+
+// BOCs of all modes, cache bits and flags can't be written by serializer, so they are set
+// in header of BOCs without CRC (canonical form doesn't use index they are applied to)
+fn bocs(roots: &[Cell]) -> Result<Vec<Vec<u8>>> {
+    let mut bocs = Vec::new();
+    for mode in modes() {
+        let generic = matches!(mode, BocSerialiseMode::Generic { .. });
+        let patchable = matches!(mode, BocSerialiseMode::Generic { crc: false, .. });
+        if roots.len() > 1 && !generic {
+            continue
+        }
+        let boc = Boc::with_mode(roots.to_vec(), mode).to_bytes()?;
+        if patchable {
+            for flags in [0b0010_0000, 0b0001_1000] {
+                let mut patched = boc.clone();
+                patched[4] |= flags;
+                bocs.push(patched);
+            }
+        }
+        bocs.push(boc);
+    }
+    Ok(bocs)
+}
+
+#[test]
+fn test_canonical_form_does_not_depend_on_options() -> Result<()> {
+    let root = tree(7)?;
+    let bocs = bocs(std::slice::from_ref(&root))?;
+    let (canonical, _) = canonicalize_boc(&bocs[0])?;
+    for boc in &bocs {
+        let (result, len) = canonicalize_boc(boc)?;
+        assert_eq!(len, boc.len());
+        assert_eq!(result, canonical, "header {:02x?}", &boc[..6]);
+        let lenient = BocDeserializer::new().set_lenient(true).deserialize(&mut &boc[..])?;
+        assert_eq!(lenient.roots[0].repr_hash(), root.repr_hash());
+    }
+    assert_eq!(canonicalize_boc(&canonical)?.0, canonical);
+    let result = BocDeserializer::new().deserialize(&mut &canonical[..])?;
+    assert_eq!(result.roots.len(), 1);
+    assert_eq!(result.roots[0].repr_hash(), root.repr_hash());
+    Ok(())
+}
+
+#[test]
+fn test_canonical_form_keeps_roots() -> Result<()> {
+    let roots = vec![tree(1)?, tree(2)?, tree(1)?.reference(0)?];
+    let bocs = bocs(&roots)?;
+    let (canonical, _) = canonicalize_boc(&bocs[0])?;
+    for boc in &bocs {
+        assert_eq!(canonicalize_boc(boc)?.0, canonical, "header {:02x?}", &boc[..6]);
+    }
+    let result = BocDeserializer::new().deserialize(&mut &canonical[..])?;
+    let hashes = result.roots.iter().map(|root| root.repr_hash()).collect::<Vec<_>>();
+    assert_eq!(hashes, roots.iter().map(|root| root.repr_hash()).collect::<Vec<_>>());
+    Ok(())
+}
+
+#[test]
+fn test_canonical_form_checks_crc() -> Result<()> {
+    let mode = BocSerialiseMode::Generic { index: true, crc: true, cache_bits: false, flags: 0 };
+    let mut boc = Boc::with_mode(vec![tree(3)?], mode).to_bytes()?;
+    let last = boc.len() - 1;
+    boc[last] ^= 1;
+    assert!(canonicalize_boc(&boc).is_err());
+    Ok(())
+}
@@ -0,0 +1,81 @@
+/*
+* Copyright 2018-2020 TON DEV SOLUTIONS LTD.
+*
+* Licensed under the SOFTWARE EVALUATION License (the "License"); you may not use
+* this file except in compliance with the License.
+*
+* Unless required by applicable law or agreed to in writing, software
+* distributed under the License is distributed on an "AS IS" BASIS,
+* WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+* See the License for the specific TON DEV software governing permissions and
+* limitations under the License.
+*/
+
+#![cfg(feature = "std")]
+
+use smallvec::smallvec;
+use ton_types::{
+    BagOfCells, BocDeserializer, BuilderData, Cell, CellType, DataCell, IBitstring, Result, UInt256,
+};
+
+fn cell_with_bits(value: usize, child: Option<Cell>) -> Result<Cell> {
+    let mut builder = BuilderData::new();
+    builder.append_bits(value, 15)?;
+    if let Some(child) = child {
+        builder.checked_append_reference(child)?;
+    }
+    builder.into_cell()
+}
+
+/// Returns genuine cell with child and BOC of the same cell with stored hashes
+fn boc_with_stored_hashes() -> Result<(Cell, Vec<u8>)> {
+    let child = cell_with_bits(1, None)?;
+    let cell = cell_with_bits(2, Some(child.clone()))?;
+    let stored = DataCell::with_params(
+        smallvec![child],
+        cell.data(),
+        CellType::Ordinary,
+        0,
+        Some([cell.repr_hash(), UInt256::default(), UInt256::default(), UInt256::default()]),
+        Some([cell.repr_depth(), 0, 0, 0]),
+    )?;
+    let mut boc = Vec::new();
+    BagOfCells::with_root(&Cell::with_cell_impl(stored)).write_to(&mut boc, false)?;
+    Ok((cell, boc))
+}
+
+fn deserialize(boc: &[u8], lookup: &dyn Fn(&UInt256) -> Option<Cell>, threads: usize) -> Result<Cell> {
+    let result = BocDeserializer::new()
+        .set_cell_lookup(lookup)
+        .set_threads(threads)
+        .deserialize(&mut &boc[..])?;
+    Ok(result.roots[0].clone())
+}
+
+#[test]
+fn test_lookup_reuses_matching_cell() -> Result<()> {
+    let (cell, boc) = boc_with_stored_hashes()?;
+    let lookup = |hash: &UInt256| (*hash == cell.repr_hash()).then(|| cell.clone());
+    for threads in [1, 4] {
+        assert_eq!(deserialize(&boc, &lookup, threads)?, cell);
+    }
+    Ok(())
+}
+
+#[test]
+fn test_lookup_rejects_cell_differing_from_boc() -> Result<()> {
+    let (cell, boc) = boc_with_stored_hashes()?;
+    let other_data = cell_with_bits(3, Some(cell_with_bits(1, None)?))?;
+    let other_child = cell_with_bits(2, Some(cell_with_bits(4, None)?))?;
+    for other in [other_data, other_child] {
+        let lookup = |hash: &UInt256| (*hash == cell.repr_hash()).then(|| other.clone());
+        for threads in [1, 4] {
+            assert!(deserialize(&boc, &lookup, threads).is_err());
+        }
+        let result = BocDeserializer::new()
+            .set_cell_lookup(&lookup)
+            .deserialize_unchecked(&mut &boc[..])?;
+        assert_eq!(result.roots[0], other);
+    }
+    Ok(())
+}
@@ -0,0 +1,146 @@
+/*
+* Copyright 2018-2020 TON DEV SOLUTIONS LTD.
+*
+* Licensed under the SOFTWARE EVALUATION License (the "License"); you may not use
+* this file except in compliance with the License.
+*
+* Unless required by applicable law or agreed to in writing, software
+* distributed under the License is distributed on an "AS IS" BASIS,
+* WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+* See the License for the specific TON DEV software governing permissions and
+* limitations under the License.
+*/
+
+//! Round trips of streaming BOC writing and reading against in-memory serializer
+
+#![cfg(feature = "std")]
+
+use std::io::Read;
+
+use ton_types::{
+    store_tree, BagOfCells, BocReader, BocSerialiseMode, BocWriter, BuilderData, Cell, CellDb,
+    IBitstring, MemoryCellDb, Result, deserialize_cells_tree,
+};
+
+/// Reader returning data by small chunks like network stream
+struct ChunkedReader<'a> {
+    data: &'a [u8],
+    chunk: usize,
+}
+
+impl Read for ChunkedReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let len = buf.len().min(self.chunk).min(self.data.len());
+        buf[..len].copy_from_slice(&self.data[..len]);
+        self.data = &self.data[len..];
+        Ok(len)
+    }
+}
+
+/// Tree with shared subtrees, cells differ by bits of `seed`
+fn tree(seed: u64, depth: usize) -> Result<Cell> {
+    let mut builder = BuilderData::new();
+    builder.append_u64(seed)?;
+    builder.append_bits(seed as usize, (seed % 13) as usize)?;
+    if depth > 0 {
+        let left = tree(seed * 3 + 1, depth - 1)?;
+        let right = tree(seed * 5 + 2, depth - 1)?;
+        builder.checked_append_reference(left.clone())?;
+        builder.checked_append_reference(right)?;
+        if seed.is_multiple_of(3) {
+            builder.checked_append_reference(left)?;
+        }
+    }
+    builder.into_cell()
+}
+
+fn modes() -> Vec<BocSerialiseMode> {
+    let mut modes = vec![BocSerialiseMode::Indexed, BocSerialiseMode::IndexedCrc];
+    for index in [false, true] {
+        for crc in [false, true] {
+            modes.push(BocSerialiseMode::Generic { index, crc, cache_bits: false, flags: 0 });
+        }
+    }
+    modes
+}
+
+fn roots_for(mode: &BocSerialiseMode) -> Result<Vec<Cell>> {
+    match mode {
+        BocSerialiseMode::Generic { .. } => Ok(vec![tree(1, 6)?, tree(7, 4)?, tree(0, 0)?]),
+        _ => Ok(vec![tree(1, 6)?]),
+    }
+}
+
+#[test]
+fn test_writer_output_is_the_same_as_in_memory() -> Result<()> {
+    for mode in modes() {
+        let roots = roots_for(&mode)?;
+        let db = MemoryCellDb::new();
+        for root in &roots {
+            store_tree(&db, root)?;
+        }
+        let mut expected = Vec::new();
+        BagOfCells::with_roots(&roots).write_to_ex(&mut expected, mode.clone(), None, None)?;
+        let mut written = Vec::new();
+        let hashes = roots.iter().map(|root| root.repr_hash()).collect::<Vec<_>>();
+        BocWriter::new(&db, hashes).set_mode(mode.clone()).write_to(&mut written)?;
+        assert_eq!(written, expected, "{:?}", mode);
+    }
+    Ok(())
+}
+
+#[test]
+fn test_reader_reads_in_memory_output() -> Result<()> {
+    for mode in modes() {
+        let roots = roots_for(&mode)?;
+        let mut boc = Vec::new();
+        BagOfCells::with_roots(&roots).write_to_ex(&mut boc, mode.clone(), None, None)?;
+        for chunk in [1, 7, 4096] {
+            let db = MemoryCellDb::new();
+            let result = BocReader::new(&db).read(&mut ChunkedReader { data: &boc, chunk })?;
+            assert_eq!(result.roots, roots, "{:?}", mode);
+            assert_eq!(result.header.mode, mode);
+            for root in &roots {
+                assert!(db.contains(&root.repr_hash())?);
+            }
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn test_stream_round_trip() -> Result<()> {
+    for mode in modes() {
+        let roots = roots_for(&mode)?;
+        let db = MemoryCellDb::new();
+        for root in &roots {
+            store_tree(&db, root)?;
+        }
+        let hashes = roots.iter().map(|root| root.repr_hash()).collect::<Vec<_>>();
+        let mut boc = Vec::new();
+        BocWriter::new(&db, hashes.clone()).set_mode(mode.clone()).write_to(&mut boc)?;
+
+        let target = MemoryCellDb::new();
+        let result = BocReader::new(&target).read(&mut ChunkedReader { data: &boc, chunk: 3 })?;
+        assert_eq!(result.roots.iter().map(|root| root.repr_hash()).collect::<Vec<_>>(), hashes);
+        assert_eq!(target.len(), db.len());
+        assert_eq!(deserialize_cells_tree(&mut &boc[..])?, roots);
+    }
+    Ok(())
+}
+
+#[test]
+fn test_reader_rejects_corrupted_boc() -> Result<()> {
+    let mode = BocSerialiseMode::Generic { index: false, crc: true, cache_bits: false, flags: 0 };
+    let mut boc = Vec::new();
+    BagOfCells::with_roots(&roots_for(&mode)?).write_to_ex(&mut boc, mode, None, None)?;
+    let last = boc.len() - 10;
+    boc[last] ^= 1;
+    let db = MemoryCellDb::new();
+    assert!(BocReader::new(&db).read(&mut ChunkedReader { data: &boc, chunk: 5 }).is_err());
+    assert!(db.is_empty());
+    boc.truncate(boc.len() / 2);
+    assert!(BocReader::new(&db).read(&mut &boc[..]).is_err());
+    assert!(db.is_empty());
+    Ok(())
+}
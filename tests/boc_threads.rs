@@ -0,0 +1,138 @@
+/*
+* Copyright 2018-2020 TON DEV SOLUTIONS LTD.
+*
+* Licensed under the SOFTWARE EVALUATION License (the "License"); you may not use
+* this file except in compliance with the License.
+*
+* Unless required by applicable law or agreed to in writing, software
+* distributed under the License is distributed on an "AS IS" BASIS,
+* WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+* See the License for the specific TON DEV software governing permissions and
+* limitations under the License.
+*/
+
+//! BOC reading with several threads gives the same cells as reading by one thread
+//! and rejects the same broken input
+
+#![cfg(feature = "std")]
+
+use std::sync::Arc;
+
+use smallvec::SmallVec;
+use ton_types::{
+    BagOfCells, BocDeserializer, BuilderData, Cell, CellType, DataCell, IBitstring, Result,
+    UInt256,
+};
+
+const THREADS: [usize; 4] = [1, 2, 4, 16];
+
+fn leaf(value: u32) -> Result<Cell> {
+    let mut builder = BuilderData::new();
+    builder.append_u32(value)?;
+    builder.append_bits(value as usize, 7)?;
+    builder.into_cell()
+}
+
+// leaf with stored hashes, they are written to BOC and verified while reading
+fn leaf_with_stored_hashes(value: u32) -> Result<Cell> {
+    let cell = leaf(value)?;
+    let stored = DataCell::with_params(
+        SmallVec::new(),
+        cell.data(),
+        CellType::Ordinary,
+        0,
+        Some([cell.repr_hash(), UInt256::default(), UInt256::default(), UInt256::default()]),
+        Some([cell.repr_depth(), 0, 0, 0]),
+    )?;
+    Ok(Cell::with_cell_impl(stored))
+}
+
+// waves of leaves and forks are wide enough to be split between threads
+fn wide_tree() -> Result<Cell> {
+    let mut level = (0..1024_u32).map(|value| {
+        match value {
+            777 => leaf_with_stored_hashes(value),
+            _ => leaf(value)
+        }
+    }).collect::<Result<Vec<_>>>()?;
+    while level.len() > 1 {
+        level = level.chunks(4).map(|chunk| {
+            let mut builder = BuilderData::new();
+            builder.append_u8(chunk.len() as u8)?;
+            for cell in chunk {
+                builder.checked_append_reference(cell.clone())?;
+            }
+            builder.into_cell()
+        }).collect::<Result<Vec<_>>>()?;
+    }
+    Ok(level.remove(0))
+}
+
+fn write_boc(root: &Cell, include_index: bool) -> Result<Vec<u8>> {
+    let mut boc = Vec::new();
+    BagOfCells::with_root(root).write_to(&mut boc, include_index)?;
+    Ok(boc)
+}
+
+fn read_boc(boc: &[u8], threads: usize, inmem: bool) -> Result<Cell> {
+    let deserializer = BocDeserializer::new().set_threads(threads);
+    match inmem {
+        false => deserializer.deserialize(&mut &boc[..]),
+        true => deserializer.deserialize_inmem(Arc::new(boc.to_vec()))
+    }?.withdraw_one_root()
+}
+
+#[test]
+fn test_threaded_reading_matches_sequential() -> Result<()> {
+    let tree = wide_tree()?;
+    for include_index in [false, true] {
+        let boc = write_boc(&tree, include_index)?;
+        for threads in THREADS {
+            for inmem in [false, true] {
+                let root = read_boc(&boc, threads, inmem)?;
+                assert_eq!(root, tree);
+                assert_eq!(root.repr_depth(), tree.repr_depth());
+                assert_eq!(root.tree_cell_count(), tree.tree_cell_count());
+                assert_eq!(write_boc(&root, include_index)?, boc);
+            }
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn test_threaded_reading_rejects_wrong_stored_hash() -> Result<()> {
+    let tree = wide_tree()?;
+    let stored = leaf_with_stored_hashes(777)?.repr_hash();
+    for include_index in [false, true] {
+        let mut boc = write_boc(&tree, include_index)?;
+        let pos = boc.windows(32).position(|window| window == stored.as_slice())
+            .expect("stored hash is written to BOC");
+        boc[pos] ^= 1;
+        for threads in THREADS {
+            for inmem in [false, true] {
+                assert!(read_boc(&boc, threads, inmem).is_err(), "threads: {}", threads);
+            }
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn test_threaded_reading_checks_max_depth() -> Result<()> {
+    let tree = wide_tree()?;
+    let boc = write_boc(&tree, false)?;
+    for threads in THREADS {
+        let result = BocDeserializer::new()
+            .set_threads(threads)
+            .set_max_cell_depth(tree.repr_depth() - 1)
+            .deserialize(&mut &boc[..]);
+        assert!(result.is_err(), "threads: {}", threads);
+        let result = BocDeserializer::new()
+            .set_threads(threads)
+            .set_max_cell_depth(tree.repr_depth())
+            .deserialize(&mut &boc[..]);
+        assert_eq!(result?.withdraw_one_root()?, tree);
+    }
+    Ok(())
+}
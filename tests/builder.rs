@@ -0,0 +1,181 @@
+/*
+* Copyright 2018-2020 TON DEV SOLUTIONS LTD.
+*
+* Licensed under the SOFTWARE EVALUATION License (the "License"); you may not use
+* this file except in compliance with the License.
+*
+* Unless required by applicable law or agreed to in writing, software
+* distributed under the License is distributed on an "AS IS" BASIS,
+* WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+* See the License for the specific TON DEV software governing permissions and
+* limitations under the License.
+*/
+
+//! Builders created from cells finalize to data cells reusing parts of original ones,
+//! lengths set directly and prepended data are checked against cell capacity
+
+use ton_types::{
+    deserialize_tree_of_cells, serialize_toc, BuilderData, Cell, ExceptionCode, IBitstring, Result,
+};
+
+fn leaf(value: u32) -> Result<Cell> {
+    let mut builder = BuilderData::new();
+    builder.append_u32(value)?;
+    builder.append_bits(value as usize, 5)?;
+    builder.into_cell()
+}
+
+fn fork(left: Cell, right: Cell) -> Result<Cell> {
+    let mut builder = BuilderData::new();
+    builder.append_u16(0xABCD)?;
+    builder.append_bits(1, 3)?;
+    builder.checked_append_reference(left)?;
+    builder.checked_append_reference(right)?;
+    builder.into_cell()
+}
+
+#[test]
+fn test_reference_replacement() -> Result<()> {
+    let built = fork(leaf(1)?, leaf(2)?)?;
+    let loaded = deserialize_tree_of_cells(&mut &serialize_toc(&built)?[..])?;
+    for cell in [built, loaded] {
+        let same = BuilderData::from_cell(&cell).into_cell()?;
+        assert_eq!(same.repr_hash(), cell.repr_hash());
+
+        let mut builder = BuilderData::from_cell(&cell);
+        builder.replace_reference_cell(1, leaf(3)?)?;
+        let replaced = builder.into_cell()?;
+        let expected = fork(leaf(1)?, leaf(3)?)?;
+        assert_eq!(replaced.repr_hash(), expected.repr_hash());
+        assert_eq!(replaced.repr_depth(), expected.repr_depth());
+        assert_eq!(replaced.tree_cell_count(), expected.tree_cell_count());
+        assert_eq!(replaced.reference(1)?.repr_hash(), leaf(3)?.repr_hash());
+
+        // data is changed after replacing of reference
+        let mut builder = BuilderData::from_cell(&cell);
+        builder.replace_reference_cell(0, leaf(3)?)?;
+        builder.append_bit_one()?;
+        let mut expected = BuilderData::from_cell(&fork(leaf(3)?, leaf(2)?)?);
+        expected.append_bit_one()?;
+        assert_eq!(builder.into_cell()?.repr_hash(), expected.into_cell()?.repr_hash());
+    }
+    Ok(())
+}
+
+#[cfg(feature = "testing")]
+#[test]
+fn test_wrapper_is_not_returned() -> Result<()> {
+    use ton_types::{testing::MockCell, UInt256};
+
+    let cell = fork(leaf(1)?, leaf(2)?)?;
+    let mut mock = MockCell::new(cell.clone());
+    mock.set_hash(UInt256::from([7; 32]));
+    let mock = mock.into_cell();
+    let rebuilt = BuilderData::from_cell(&mock).into_cell()?;
+    assert_eq!(rebuilt.repr_hash(), cell.repr_hash());
+    Ok(())
+}
+
+// checked without debug assertions, so it holds in release builds as well
+#[test]
+fn test_update_cell_rejects_overflow() -> Result<()> {
+    let mut builder = BuilderData::new();
+    builder.append_u32(0xDEADBEEF)?;
+    let err = builder.update_cell(|data, bits, _, _| {
+        data.resize(129, 0xFF);
+        *bits = BuilderData::bits_capacity() + 1;
+    }, ()).unwrap_err();
+    assert_eq!(err.downcast_ref::<ExceptionCode>(), Some(&ExceptionCode::CellOverflow));
+    assert_eq!(builder.length_in_bits(), 32);
+    assert_eq!(builder.data(), &[0xDE, 0xAD, 0xBE, 0xEF]);
+
+    // length which wraps around u16 is not truncated either, references are kept
+    builder.checked_append_reference(leaf(1)?)?;
+    let err = builder.update_cell(|data, bits, references, _| {
+        data.resize(129, 0xFF);
+        references.clear();
+        *bits = u16::MAX as usize + 9;
+    }, ()).unwrap_err();
+    assert_eq!(err.downcast_ref::<ExceptionCode>(), Some(&ExceptionCode::CellOverflow));
+    assert_eq!(builder.length_in_bits(), 32);
+    assert_eq!(builder.references_used(), 1);
+
+    builder.update_cell(|data, bits, _, _| {
+        data.resize(128, 0xFF);
+        *bits = BuilderData::bits_capacity();
+    }, ())?;
+    assert_eq!(builder.length_in_bits(), BuilderData::bits_capacity());
+    Ok(())
+}
+
+// size is checked on 64-bit targets (224 bytes before inline capacities were reduced),
+// data of the full cell spills from inline storage and comes back intact
+#[test]
+fn test_builder_footprint() -> Result<()> {
+    #[cfg(all(target_pointer_width = "64", not(feature = "compact_builder")))]
+    assert!(std::mem::size_of::<BuilderData>() <= 168, "{}", std::mem::size_of::<BuilderData>());
+    #[cfg(all(target_pointer_width = "64", feature = "compact_builder"))]
+    assert!(std::mem::size_of::<BuilderData>() <= 104, "{}", std::mem::size_of::<BuilderData>());
+
+    let mut builder = BuilderData::new();
+    for i in 0..127 {
+        builder.append_u8(i)?;
+    }
+    builder.append_bits(0x7F, 7)?;
+    let cell = builder.clone().into_cell()?;
+    assert_eq!(cell.bit_length(), BuilderData::bits_capacity());
+    assert_eq!(&cell.data()[..127], &builder.data()[..127]);
+    assert_eq!(BuilderData::from_cell(&cell), builder);
+    Ok(())
+}
+
+fn raw_builder(bits: usize) -> Result<BuilderData> {
+    let data = (0..128_u8).map(|i| i.wrapping_mul(37).wrapping_add(11)).collect::<Vec<_>>();
+    let mut builder = BuilderData::new();
+    builder.append_raw(&data, bits)?;
+    Ok(builder)
+}
+
+// data is shifted in place, result is the same as prefix appended before the data
+#[test]
+fn test_prepend_raw_boundaries() -> Result<()> {
+    // bits of prefix after prepended ones are set and must not leak into builder
+    let prefix = [0xA5_u8; 128];
+    let lengths = [0, 1, 7, 8, 9, 15, 16, 17, 500, 1000, 1015, 1016, 1022, 1023];
+    for length in lengths {
+        for bits in lengths {
+            if length + bits > BuilderData::bits_capacity() {
+                continue
+            }
+            let mut builder = raw_builder(length)?;
+            builder.prepend_raw(&prefix, bits)?;
+            let mut expected = BuilderData::new();
+            expected.append_raw(&prefix, bits)?;
+            expected.append_builder(&raw_builder(length)?)?;
+            assert_eq!(builder, expected, "{} bits prepended to {} bits", bits, length);
+            assert_eq!(builder.into_cell()?, expected.into_cell()?);
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn test_prepend_raw_overflow() -> Result<()> {
+    let mut builder = raw_builder(1000)?;
+    let err = builder.prepend_raw(&[0xFF; 3], 24).unwrap_err();
+    assert_eq!(err.downcast_ref::<ExceptionCode>(), Some(&ExceptionCode::CellOverflow));
+    assert_eq!(builder, raw_builder(1000)?);
+
+    builder.prepend_raw(&[0xFF; 3], 23)?;
+    assert_eq!(builder.length_in_bits(), BuilderData::bits_capacity());
+    let err = builder.prepend_raw(&[0xFF], 1).unwrap_err();
+    assert_eq!(err.downcast_ref::<ExceptionCode>(), Some(&ExceptionCode::CellOverflow));
+    builder.prepend_raw(&[], 0)?;
+    assert_eq!(builder.length_in_bits(), BuilderData::bits_capacity());
+
+    // prefix shorter than count of bits
+    let mut builder = BuilderData::new();
+    assert!(builder.prepend_raw(&[0xFF], 9).is_err());
+    assert_eq!(builder.length_in_bits(), 0);
+    Ok(())
+}
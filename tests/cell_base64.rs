@@ -0,0 +1,46 @@
+/*
+* Copyright 2018-2020 TON DEV SOLUTIONS LTD.
+*
+* Licensed under the SOFTWARE EVALUATION License (the "License"); you may not use
+* this file except in compliance with the License.
+*
+* Unless required by applicable law or agreed to in writing, software
+* distributed under the License is distributed on an "AS IS" BASIS,
+* WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+* See the License for the specific TON DEV software governing permissions and
+* limitations under the License.
+*/
+
+//! Cell is displayed as base64 of BOC and parsed back from it
+
+#![cfg(feature = "std")]
+
+use ton_types::{BuilderData, Cell, IBitstring, Result};
+
+fn cell() -> Result<Cell> {
+    let mut child = BuilderData::new();
+    child.append_u32(0xDEAD_BEEF)?;
+    let mut builder = BuilderData::new();
+    builder.append_bits(5, 3)?;
+    builder.checked_append_reference(child.into_cell()?)?;
+    builder.into_cell()
+}
+
+#[test]
+fn test_display_parses_back() -> Result<()> {
+    let cell = cell()?;
+    let text = cell.to_string();
+    assert_eq!(text, cell.to_base64()?);
+    assert_eq!(text.parse::<Cell>()?, cell);
+    assert_eq!(Cell::default().to_string().parse::<Cell>()?, Cell::default());
+    Ok(())
+}
+
+#[test]
+fn test_alternate_display_is_tree() -> Result<()> {
+    let cell = cell()?;
+    assert_eq!(format!("{:#}", cell), format!("{:#}", cell.tree()));
+    assert_eq!(format!("{:#.1}", cell), format!("{:#.1}", cell.tree()));
+    assert!(format!("{:#}", cell).parse::<Cell>().is_err());
+    Ok(())
+}
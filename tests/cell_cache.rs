@@ -0,0 +1,137 @@
+/*
+* Copyright 2018-2020 TON DEV SOLUTIONS LTD.
+*
+* Licensed under the SOFTWARE EVALUATION License (the "License"); you may not use
+* this file except in compliance with the License.
+*
+* Unless required by applicable law or agreed to in writing, software
+* distributed under the License is distributed on an "AS IS" BASIS,
+* WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+* See the License for the specific TON DEV software governing permissions and
+* limitations under the License.
+*/
+
+//! LRU cache of cells evicts least recently used cells and drops them out of lock
+
+#![cfg(feature = "std")]
+
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc, Weak,
+};
+
+use ton_types::{
+    BuilderData, Cell, CellData, CellImpl, CellType, IBitstring, LevelMask, LruCellCache, Result,
+    UInt256,
+};
+
+fn leaf(value: u32) -> Result<Cell> {
+    let mut builder = BuilderData::new();
+    builder.append_u32(value)?;
+    builder.into_cell()
+}
+
+#[test]
+fn test_least_recently_inserted_is_evicted() -> Result<()> {
+    let cache = LruCellCache::new(3);
+    let cells = (0..5).map(leaf).collect::<Result<Vec<_>>>()?;
+    for cell in &cells[..3] {
+        cache.insert(cell.clone());
+    }
+    assert_eq!(cache.len(), 3);
+    cache.insert(cells[3].clone());
+    assert_eq!(cache.len(), 3);
+    assert!(cache.get(&cells[0].repr_hash()).is_none());
+    cache.insert(cells[4].clone());
+    assert!(cache.get(&cells[1].repr_hash()).is_none());
+    for cell in &cells[2..] {
+        assert_eq!(cache.get(&cell.repr_hash()).as_ref(), Some(cell));
+    }
+    Ok(())
+}
+
+#[test]
+fn test_hit_refreshes_recency() -> Result<()> {
+    let cache = LruCellCache::new(3);
+    let cells = (0..5).map(leaf).collect::<Result<Vec<_>>>()?;
+    for cell in &cells[..3] {
+        cache.insert(cell.clone());
+    }
+    // both get and repeated insert make the cell the most recently used
+    assert!(cache.get(&cells[0].repr_hash()).is_some());
+    cache.insert(cells[1].clone());
+    cache.insert(cells[3].clone());
+    assert!(!cache.contains(&cells[2].repr_hash()));
+    assert!(cache.contains(&cells[0].repr_hash()));
+    assert!(cache.contains(&cells[1].repr_hash()));
+    cache.insert(cells[4].clone());
+    assert!(cache.get(&cells[0].repr_hash()).is_none());
+    assert_eq!(cache.len(), 3);
+    Ok(())
+}
+
+#[test]
+fn test_zero_capacity_and_removal() -> Result<()> {
+    let cache = LruCellCache::new(0);
+    cache.insert(leaf(1)?);
+    assert!(cache.is_empty());
+
+    let cache = LruCellCache::new(2);
+    cache.insert(leaf(1)?);
+    cache.insert(leaf(2)?);
+    assert_eq!(cache.remove(&leaf(1)?.repr_hash()), Some(leaf(1)?));
+    assert!(cache.get(&leaf(1)?.repr_hash()).is_none());
+    cache.clear();
+    assert!(cache.is_empty());
+    Ok(())
+}
+
+/// Cell which reads the cache while it is dropped
+struct CacheReader {
+    cell: Cell,
+    cache: Weak<LruCellCache>,
+    drops: Arc<AtomicUsize>,
+}
+
+impl Drop for CacheReader {
+    fn drop(&mut self) {
+        if let Some(cache) = self.cache.upgrade() {
+            cache.get(&UInt256::default());
+        }
+        self.drops.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+impl CellImpl for CacheReader {
+    fn data(&self) -> &[u8] { self.cell.data() }
+    fn raw_data(&self) -> Result<&[u8]> { self.cell.raw_data() }
+    fn cell_data(&self) -> &CellData { self.cell.cell_data() }
+    fn bit_length(&self) -> usize { self.cell.bit_length() }
+    fn references_count(&self) -> usize { self.cell.references_count() }
+    fn reference(&self, index: usize) -> Result<Cell> { self.cell.reference(index) }
+    fn cell_type(&self) -> CellType { self.cell.cell_type() }
+    fn level_mask(&self) -> LevelMask { self.cell.level_mask() }
+    fn hash(&self, index: usize) -> UInt256 { self.cell.hash(index) }
+    fn depth(&self, index: usize) -> u16 { self.cell.depth(index) }
+    fn store_hashes(&self) -> bool { self.cell.store_hashes() }
+}
+
+// cache lock is not reentrant, so dropping under it would deadlock
+#[test]
+fn test_evicted_cells_are_dropped_out_of_lock() -> Result<()> {
+    let cache = Arc::new(LruCellCache::new(1));
+    let drops = Arc::new(AtomicUsize::new(0));
+    let reader = |value| -> Result<Cell> {
+        Ok(Cell::with_cell_impl(CacheReader {
+            cell: leaf(value)?,
+            cache: Arc::downgrade(&cache),
+            drops: drops.clone(),
+        }))
+    };
+    cache.insert(reader(1)?);
+    cache.insert(reader(2)?);
+    assert_eq!(drops.load(Ordering::SeqCst), 1);
+    cache.clear();
+    assert_eq!(drops.load(Ordering::SeqCst), 2);
+    Ok(())
+}
@@ -0,0 +1,66 @@
+/*
+* Copyright 2018-2020 TON DEV SOLUTIONS LTD.
+*
+* Licensed under the SOFTWARE EVALUATION License (the "License"); you may not use
+* this file except in compliance with the License.
+*
+* Unless required by applicable law or agreed to in writing, software
+* distributed under the License is distributed on an "AS IS" BASIS,
+* WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+* See the License for the specific TON DEV software governing permissions and
+* limitations under the License.
+*/
+
+//! Cells in `CellSet` and `CellMap` are unique by representation hash and found by it
+
+use ton_types::{BuilderData, Cell, CellMap, CellSet, IBitstring, Result, UInt256};
+
+fn cell(value: u32) -> Result<Cell> {
+    let mut builder = BuilderData::new();
+    builder.append_u32(value)?;
+    builder.into_cell()
+}
+
+#[test]
+fn test_cell_set_contains_by_hash() -> Result<()> {
+    let mut set = CellSet::default();
+    assert!(set.insert(cell(1)?));
+    assert!(set.insert(cell(2)?));
+    assert!(set.contains(&cell(1)?.repr_hash()));
+    assert!(set.contains(&cell(2)?.repr_hash()));
+    assert!(!set.contains(&cell(3)?.repr_hash()));
+    assert!(!set.contains(&UInt256::default()));
+    assert_eq!(set.get(&cell(2)?.repr_hash()), Some(&cell(2)?));
+    Ok(())
+}
+
+#[test]
+fn test_cell_set_dedupes_equal_hashes() -> Result<()> {
+    let first = cell(1)?;
+    // the same cell built separately is another allocation with the same hash
+    let second = cell(1)?;
+    assert_eq!(first.repr_hash(), second.repr_hash());
+    let mut set = CellSet::default();
+    assert!(set.insert(first));
+    assert!(!set.insert(second.clone()));
+    assert!(!set.insert(second));
+    assert_eq!(set.len(), 1);
+    assert!(set.remove(&cell(1)?.repr_hash()));
+    assert!(set.is_empty());
+    Ok(())
+}
+
+#[test]
+fn test_cell_map_by_hash() -> Result<()> {
+    let mut map = CellMap::default();
+    assert_eq!(map.insert(cell(1)?, "one"), None);
+    assert_eq!(map.insert(cell(2)?, "two"), None);
+    assert_eq!(map.insert(cell(1)?, "uno"), Some("one"));
+    assert_eq!(map.len(), 2);
+    assert_eq!(map.get(&cell(1)?.repr_hash()), Some(&"uno"));
+    assert_eq!(map.get(&cell(2)?.repr_hash()), Some(&"two"));
+    assert_eq!(map.get(&cell(3)?.repr_hash()), None);
+    assert_eq!(map.remove(&cell(2)?.repr_hash()), Some("two"));
+    assert!(!map.contains_key(&cell(2)?.repr_hash()));
+    Ok(())
+}
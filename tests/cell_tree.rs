@@ -0,0 +1,80 @@
+/*
+* Copyright 2018-2020 TON DEV SOLUTIONS LTD.
+*
+* Licensed under the SOFTWARE EVALUATION License (the "License"); you may not use
+* this file except in compliance with the License.
+*
+* Unless required by applicable law or agreed to in writing, software
+* distributed under the License is distributed on an "AS IS" BASIS,
+* WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+* See the License for the specific TON DEV software governing permissions and
+* limitations under the License.
+*/
+
+//! Metadata dump of cell tree prints every cell with its hashes and depths
+
+#![cfg(feature = "std")]
+
+use std::sync::Arc;
+
+use smallvec::smallvec;
+use ton_types::{
+    create_pruned_branch, BuilderData, Cell, ExternalCell, IBitstring, MemoryCellDb, Result,
+};
+
+fn leaf() -> Result<Cell> {
+    let mut builder = BuilderData::new();
+    builder.append_u32(0x0102_0304)?;
+    builder.into_cell()
+}
+
+#[test]
+fn test_metadata_dump_prints_every_cell() -> Result<()> {
+    let leaf = leaf()?;
+    let pruned = create_pruned_branch(&leaf, 0)?;
+    let mut builder = BuilderData::new();
+    builder.append_u8(7)?;
+    builder.checked_append_reference(pruned.clone())?;
+    builder.checked_append_reference(leaf.clone())?;
+    builder.checked_append_reference(leaf.clone())?;
+    let root = builder.into_cell()?;
+
+    let dump = format!("{}", root.tree().with_metadata());
+    let lines = dump.lines().collect::<Vec<_>>();
+    assert_eq!(lines.len(), 4);
+    assert_eq!(lines[0], format!(
+        "Ordinary level_mask: 001 bits: 8 refs: 3 repr_hash: {:x} depths: 0=1 1=1", root.repr_hash()
+    ));
+    assert_eq!(lines[1], format!(
+        " ├─Pruned branch level_mask: 001 bits: 288 refs: 0 repr_hash: {:x} depths: 0=0 1=0", pruned.repr_hash()
+    ));
+    assert_eq!(lines[2], format!(
+        " ├─Ordinary level_mask: 000 bits: 32 refs: 0 repr_hash: {:x} depths: 0=0", leaf.repr_hash()
+    ));
+    assert_eq!(lines[3], format!(
+        " └─Ordinary level_mask: 000 bits: 32 refs: 0 repr_hash: {:x} depths: 0=0 (repeated)", leaf.repr_hash()
+    ));
+
+    let dump = format!("{:#.0}", root.tree().with_metadata());
+    assert_eq!(dump.lines().count(), 1);
+    assert!(dump.ends_with(" data: 07"));
+    Ok(())
+}
+
+#[test]
+fn test_tree_prints_absent_reference() -> Result<()> {
+    let leaf = leaf()?;
+    let mut builder = BuilderData::new();
+    builder.checked_append_reference(leaf.clone())?;
+    let root = builder.into_cell()?;
+    // storage is empty, so reference can't be loaded
+    let external = ExternalCell::with_params(
+        root.cell_data().clone(), smallvec![leaf.repr_hash()], 32, 2, Arc::new(MemoryCellDb::new())
+    )?;
+    let root = Cell::with_cell_impl(external);
+    let dump = format!("{:.1}", root.tree());
+    assert!(dump.lines().nth(1).unwrap().starts_with(" └─absent reference: "));
+    let dump = format!("{}", root.tree().with_metadata());
+    assert!(dump.lines().nth(1).unwrap().starts_with(" └─absent reference: "));
+    Ok(())
+}
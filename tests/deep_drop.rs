@@ -0,0 +1,72 @@
+/*
+* Copyright 2018-2020 TON DEV SOLUTIONS LTD.
+*
+* Licensed under the SOFTWARE EVALUATION License (the "License"); you may not use
+* this file except in compliance with the License.
+*
+* Unless required by applicable law or agreed to in writing, software
+* distributed under the License is distributed on an "AS IS" BASIS,
+* WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+* See the License for the specific TON DEV software governing permissions and
+* limitations under the License.
+*/
+
+//! Deep trees are finalized and dropped without recursion, so small stack is enough for them
+
+#![cfg(feature = "std")]
+
+use ton_types::{BuilderData, Cell, IBitstring, Result, MAX_DEPTH};
+
+// stack which recursive drop of the chain would overflow many times
+const STACK_SIZE: usize = 128 * 1024;
+
+// depth of cells is limited, so about million cells is the chain of the deepest cells
+// with a branch of 14 cells hanging from every one of them
+const BRANCH_LEN: u32 = 14;
+
+fn link(value: u32, next: Option<Cell>) -> Result<Cell> {
+    let mut builder = BuilderData::new();
+    builder.append_u32(value)?;
+    if let Some(next) = next {
+        builder.checked_append_reference(next)?;
+    }
+    builder.finalize(MAX_DEPTH)
+}
+
+fn comb(depth: u16) -> Result<Cell> {
+    let mut root = None;
+    for i in 0..depth as u32 {
+        let mut branch = None;
+        for j in 0..BRANCH_LEN {
+            branch = Some(link(i * 16 + j + 1, branch)?);
+        }
+        let mut builder = BuilderData::new();
+        builder.append_u32(i * 16)?;
+        if let Some(next) = root {
+            builder.checked_append_reference(next)?;
+        }
+        builder.checked_append_reference(branch.unwrap())?;
+        root = Some(builder.finalize(MAX_DEPTH)?);
+    }
+    Ok(root.unwrap())
+}
+
+#[test]
+fn test_deep_tree_is_dropped_on_small_stack() {
+    let depth = MAX_DEPTH - BRANCH_LEN as u16;
+    let count = std::thread::Builder::new()
+        .stack_size(STACK_SIZE)
+        .spawn(move || -> Result<u64> {
+            let root = comb(depth)?;
+            assert_eq!(root.repr_depth(), MAX_DEPTH - 1);
+            let count = root.tree_cell_count();
+            drop(root);
+            Ok(count)
+        })
+        .unwrap()
+        .join()
+        .unwrap()
+        .unwrap();
+    assert_eq!(count, depth as u64 * (BRANCH_LEN as u64 + 1));
+    assert!(count > 900_000);
+}
@@ -0,0 +1,68 @@
+/*
+* Copyright 2018-2020 TON DEV SOLUTIONS LTD.
+*
+* Licensed under the SOFTWARE EVALUATION License (the "License"); you may not use
+* this file except in compliance with the License.
+*
+* Unless required by applicable law or agreed to in writing, software
+* distributed under the License is distributed on an "AS IS" BASIS,
+* WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+* See the License for the specific TON DEV software governing permissions and
+* limitations under the License.
+*/
+
+//! Dictionary built from sorted entries is the same on any count of threads as built by `set`
+
+use ton_types::{BuilderData, HashmapE, HashmapType, IBitstring, Result, SliceData};
+
+fn entries(count: u32) -> Result<Vec<(SliceData, BuilderData)>> {
+    // scattered keys give forks with long labels and unbalanced subtrees
+    let mut keys = (0..count).map(|i| i.wrapping_mul(0x9E37_79B9) ^ (i >> 3)).collect::<Vec<_>>();
+    keys.sort_unstable();
+    keys.dedup();
+    keys.into_iter().map(|key| {
+        let mut key_builder = BuilderData::new();
+        key_builder.append_u32(key)?;
+        let mut value = BuilderData::new();
+        value.append_u32(!key)?;
+        Ok((SliceData::load_builder(key_builder)?, value))
+    }).collect()
+}
+
+fn sequential(entries: &[(SliceData, BuilderData)]) -> Result<HashmapE> {
+    let mut dict = HashmapE::with_bit_len(32);
+    for (key, value) in entries {
+        dict.set(key.clone(), &SliceData::load_builder(value.clone())?)?;
+    }
+    Ok(dict)
+}
+
+#[test]
+fn test_from_sorted_equals_sequential() -> Result<()> {
+    for count in [0, 1, 2, 3, 100, 2048, 9000] {
+        let entries = entries(count)?;
+        let expected = sequential(&entries)?;
+        for threads in [0, 1, 2, 3, 4, 7, 8, 16] {
+            let dict = HashmapE::from_sorted_parallel(32, &entries, threads)?;
+            assert_eq!(dict, expected, "{} entries on {} threads", count, threads);
+            assert_eq!(
+                dict.data().map(|root| root.repr_hash()),
+                expected.data().map(|root| root.repr_hash()),
+            );
+        }
+        assert_eq!(HashmapE::from_sorted(32, &entries)?, expected);
+    }
+    Ok(())
+}
+
+#[test]
+fn test_from_sorted_checks_entries() -> Result<()> {
+    let mut entries = entries(10)?;
+    assert!(HashmapE::from_sorted_parallel(16, &entries, 4).is_err());
+    entries.swap(3, 4);
+    assert!(HashmapE::from_sorted_parallel(32, &entries, 4).is_err());
+    entries.swap(3, 4);
+    entries[4] = entries[3].clone();
+    assert!(HashmapE::from_sorted_parallel(32, &entries, 4).is_err());
+    Ok(())
+}
@@ -0,0 +1,53 @@
+/*
+* Copyright 2018-2020 TON DEV SOLUTIONS LTD.
+*
+* Licensed under the SOFTWARE EVALUATION License (the "License"); you may not use
+* this file except in compliance with the License.
+*
+* Unless required by applicable law or agreed to in writing, software
+* distributed under the License is distributed on an "AS IS" BASIS,
+* WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+* See the License for the specific TON DEV software governing permissions and
+* limitations under the License.
+*/
+
+//! Every error of the crate has stable numeric code listed in codes table
+
+use ton_types::{
+    deserialize_tree_of_cells, error, serialize_toc, BuilderData, ErrorCode, ExceptionCode, IBitstring, ParseLimits, Parser,
+    Result,
+};
+
+#[test]
+fn test_codes_table_is_unique() {
+    let codes = ExceptionCode::codes_table().map(|(code, _)| code).collect::<Vec<_>>();
+    let mut unique = codes.clone();
+    unique.sort_unstable();
+    unique.dedup();
+    assert_eq!(unique.len(), codes.len());
+    for code in ErrorCode::ALL {
+        assert!(codes.contains(&code.code()));
+    }
+}
+
+#[test]
+fn test_codes_of_errors() -> Result<()> {
+    let mut builder = BuilderData::new();
+    builder.append_u32(1)?;
+    let cell = builder.into_cell()?;
+
+    let limits = ParseLimits { max_bits: 8, ..Default::default() };
+    let err = Parser::load_cell(cell.clone(), limits)?.load_uint(32).unwrap_err();
+    assert_eq!(ExceptionCode::code_of(&err), ErrorCode::ParseBitsExceeded.code());
+    let err = Parser::load_cell(cell.clone(), ParseLimits::default())?.load_reference().unwrap_err();
+    assert_eq!(ExceptionCode::code_of(&err), ExceptionCode::CellUnderflow.code());
+
+    let boc = serialize_toc(&cell)?;
+    let err = deserialize_tree_of_cells(&mut &boc[..boc.len() / 2]).unwrap_err();
+    assert_eq!(ExceptionCode::code_of(&err), ErrorCode::UnexpectedEof.code());
+
+    let err = anyhow::Error::from(ton_types::io::Error::from(ton_types::io::ErrorKind::UnexpectedEof));
+    assert_eq!(ExceptionCode::code_of(&err), ErrorCode::UnexpectedEof.code());
+    assert_eq!(ExceptionCode::code_of(&error!("message")), ExceptionCode::UnknownError.code());
+    Ok(())
+}
@@ -0,0 +1,64 @@
+/*
+* Copyright 2018-2020 TON DEV SOLUTIONS LTD.
+*
+* Licensed under the SOFTWARE EVALUATION License (the "License"); you may not use
+* this file except in compliance with the License.
+*
+* Unless required by applicable law or agreed to in writing, software
+* distributed under the License is distributed on an "AS IS" BASIS,
+* WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+* See the License for the specific TON DEV software governing permissions and
+* limitations under the License.
+*/
+
+//! Errors of C interface are reported by codes instead of panics
+
+#![cfg(feature = "ffi")]
+
+use std::ptr;
+
+use ton_types::{ffi::*, serialize_toc, BuilderData, ExceptionCode, IBitstring};
+
+#[test]
+fn test_slice_reading_reports_error_codes() {
+    let mut builder = BuilderData::new();
+    builder.append_u32(0xDEADBEEF).unwrap();
+    builder.checked_append_reference(BuilderData::new().into_cell().unwrap()).unwrap();
+    let boc = serialize_toc(&builder.into_cell().unwrap()).unwrap();
+
+    unsafe {
+        let mut code = -1;
+        let cell = ton_boc_parse(boc.as_ptr(), boc.len(), &mut code);
+        assert!(!cell.is_null());
+        assert_eq!(code, ExceptionCode::NormalTermination.code());
+
+        let slice = ton_slice_new(cell, ptr::null_mut());
+        let mut data = [0u8; 8];
+        for len in [usize::MAX, usize::MAX / 8 + 1, 5] {
+            assert!(!ton_slice_get_next_bytes(slice, data.as_mut_ptr(), len, &mut code));
+            assert_eq!(code, ExceptionCode::CellUnderflow.code());
+        }
+        assert_eq!(ton_slice_remaining_bits(slice), 32);
+
+        let mut value = 0;
+        assert!(!ton_slice_get_next_int(slice, 65, &mut value, &mut code));
+        assert_eq!(code, ExceptionCode::RangeCheckError.code());
+        assert!(ton_slice_get_next_bytes(slice, data.as_mut_ptr(), 4, &mut code));
+        assert_eq!(code, ExceptionCode::NormalTermination.code());
+        assert_eq!(data[..4], [0xDE, 0xAD, 0xBE, 0xEF]);
+
+        let child = ton_slice_get_next_reference(slice, &mut code);
+        assert!(!child.is_null());
+        assert!(ton_slice_get_next_reference(slice, &mut code).is_null());
+        assert_eq!(code, ExceptionCode::CellUnderflow.code());
+
+        assert!(ton_boc_parse(boc.as_ptr(), boc.len() - 1, &mut code).is_null());
+        assert_ne!(code, ExceptionCode::NormalTermination.code());
+        assert!(ton_slice_new(ptr::null(), &mut code).is_null());
+        assert_eq!(code, ExceptionCode::FatalError.code());
+
+        ton_cell_free(child);
+        ton_slice_free(slice);
+        ton_cell_free(cell);
+    }
+}
@@ -0,0 +1,115 @@
+/*
+* Copyright 2018-2020 TON DEV SOLUTIONS LTD.
+*
+* Licensed under the SOFTWARE EVALUATION License (the "License"); you may not use
+* this file except in compliance with the License.
+*
+* Unless required by applicable law or agreed to in writing, software
+* distributed under the License is distributed on an "AS IS" BASIS,
+* WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+* See the License for the specific TON DEV software governing permissions and
+* limitations under the License.
+*/
+
+//! Batched hashing gives the same hashes as one by one hashing
+
+use sha2::Digest;
+use ton_types::{
+    sha256_batch, BagOfCells, BocDeserializer, BuilderData, Cell, IBitstring, MerkleProof, Result,
+    Serializable, UInt256,
+};
+
+fn sha2_digest(message: &[u8]) -> UInt256 {
+    UInt256::from(<[u8; 32]>::from(sha2::Sha256::digest(message)))
+}
+
+fn message(len: usize, seed: usize) -> Vec<u8> {
+    (0..len).map(|i| (i * 31 + seed * 7 + len) as u8).collect()
+}
+
+// every length up to 200 bytes alone and all together, then groups of mixed lengths
+// crossing block boundaries of different lanes
+fn check_batch(batch: impl Fn(&[Vec<u8>]) -> Vec<UInt256>) {
+    let all = (0..=200).map(|len| message(len, 0)).collect::<Vec<_>>();
+    for message in &all {
+        assert_eq!(batch(core::slice::from_ref(message)), vec![sha2_digest(message)]);
+    }
+    let expected = all.iter().map(|message| sha2_digest(message)).collect::<Vec<_>>();
+    assert_eq!(batch(&all), expected);
+    for group_len in 2..=17 {
+        let group = (0..group_len)
+            .map(|seed| message((seed * 53 + group_len * 11) % 201, seed))
+            .collect::<Vec<_>>();
+        let expected = group.iter().map(|message| sha2_digest(message)).collect::<Vec<_>>();
+        assert_eq!(batch(&group), expected, "group of {}", group_len);
+    }
+}
+
+#[test]
+fn test_sha256_batch_matches_sha2() {
+    check_batch(sha256_batch);
+}
+
+#[cfg(feature = "testing")]
+#[test]
+fn test_sha256_multi_buffer_matches_sha2() {
+    if ton_types::sha256_batch_multi_buffer(&[[0u8; 0]]).is_some() {
+        check_batch(|messages| ton_types::sha256_batch_multi_buffer(messages).unwrap());
+    }
+}
+
+// leaves of all data lengths under forks, and merkle proof with pruned branches of the same tree
+fn mixed_tree() -> Result<Cell> {
+    let mut forks = Vec::new();
+    for fork in 0..64_u32 {
+        let mut builder = BuilderData::new();
+        builder.append_u32(fork)?;
+        for leaf in 0..4_u32 {
+            let mut child = BuilderData::new();
+            for byte in 0..(fork * 4 + leaf) % 128 {
+                child.append_u8(byte as u8)?;
+            }
+            child.append_bits(leaf as usize, 3)?;
+            builder.checked_append_reference(child.into_cell()?)?;
+        }
+        forks.push(builder.into_cell()?);
+    }
+    let mut root = BuilderData::new();
+    root.append_u8(0xAA)?;
+    let mut level = forks;
+    while level.len() > 1 {
+        level = level.chunks(4).map(|chunk| {
+            let mut builder = BuilderData::new();
+            builder.append_u16(chunk.len() as u16)?;
+            for cell in chunk {
+                builder.checked_append_reference(cell.clone())?;
+            }
+            builder.into_cell()
+        }).collect::<Result<Vec<_>>>()?;
+    }
+    let tree = level.remove(0);
+    let root_hash = tree.repr_hash();
+    let proof = MerkleProof::create(&tree, |hash| *hash == root_hash || hash.as_slice()[0] % 2 == 0)?;
+    root.checked_append_reference(tree)?;
+    root.checked_append_reference(proof.serialize()?)?;
+    root.into_cell()
+}
+
+#[test]
+fn test_threaded_boc_reading_hashes() -> Result<()> {
+    let tree = mixed_tree()?;
+    let mut boc = Vec::new();
+    BagOfCells::with_root(&tree).write_to(&mut boc, false)?;
+    for threads in [1, 4] {
+        for unchecked in [false, true] {
+            let deserializer = BocDeserializer::new().set_threads(threads);
+            let root = match unchecked {
+                false => deserializer.deserialize(&mut &boc[..])?,
+                true => deserializer.deserialize_unchecked(&mut &boc[..])?
+            }.withdraw_one_root()?;
+            assert_eq!(root.repr_hash(), tree.repr_hash());
+            assert_eq!(root.reference(1)?.repr_hash(), tree.reference(1)?.repr_hash());
+        }
+    }
+    Ok(())
+}
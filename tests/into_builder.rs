@@ -0,0 +1,80 @@
+/*
+* Copyright 2018-2020 TON DEV SOLUTIONS LTD.
+*
+* Licensed under the SOFTWARE EVALUATION License (the "License"); you may not use
+* this file except in compliance with the License.
+*
+* Unless required by applicable law or agreed to in writing, software
+* distributed under the License is distributed on an "AS IS" BASIS,
+* WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+* See the License for the specific TON DEV software governing permissions and
+* limitations under the License.
+*/
+
+//! Cell converted into builder gives the same builder as copied one,
+//! buffer of uniquely owned cell is moved
+
+use ton_types::{create_pruned_branch, BuilderData, Cell, IBitstring, Result};
+
+fn full_cell() -> Result<Cell> {
+    let mut builder = BuilderData::new();
+    for i in 0..127_u8 {
+        builder.append_u8(i.wrapping_mul(3))?;
+    }
+    builder.append_bits(0x55, 7)?;
+    builder.into_cell()
+}
+
+// full ordinary cell, pruned branch and ordinary cell with level mask and references
+fn cells() -> Result<Vec<Cell>> {
+    let full = full_cell()?;
+    let pruned = create_pruned_branch(&full, 0)?;
+    let mut builder = BuilderData::new();
+    builder.append_bits(0b101, 3)?;
+    builder.checked_append_reference(pruned.clone())?;
+    builder.checked_append_reference(full.clone())?;
+    let fork = builder.into_cell()?;
+    Ok(vec![full, pruned, fork])
+}
+
+fn check_builder(builder: &BuilderData, cell: &Cell) -> Result<()> {
+    let copied = BuilderData::from_cell(cell);
+    // cell type is compared as well
+    assert_eq!(builder, &copied);
+    assert_eq!(builder.data(), copied.data());
+    assert_eq!(builder.length_in_bits(), cell.bit_length());
+    assert_eq!(builder.references(), copied.references());
+    assert_eq!(builder.level_mask(), cell.level_mask());
+    Ok(())
+}
+
+#[test]
+fn test_uniquely_owned_cell_is_moved_into_builder() -> Result<()> {
+    for (index, expected) in cells()?.into_iter().enumerate() {
+        // the same cell built again is owned only here when the rest of its tree is dropped
+        let cell = cells()?.swap_remove(index);
+        let data = cell.data().as_ptr() as usize;
+        let builder = cell.try_into_builder()?;
+        check_builder(&builder, &expected)?;
+        if builder.data().len() == 128 {
+            // data is shifted over two descriptor bytes in the same buffer
+            assert_eq!(builder.data().as_ptr() as usize + 2, data);
+        }
+        assert_eq!(builder.finalize(u16::MAX - 1)?.repr_hash(), expected.repr_hash());
+    }
+    Ok(())
+}
+
+#[test]
+fn test_shared_cell_is_copied_into_builder() -> Result<()> {
+    for cell in cells()? {
+        let shared = cell.clone();
+        let builder = shared.try_into_builder()?;
+        check_builder(&builder, &cell)?;
+        assert_ne!(builder.data().as_ptr(), cell.data().as_ptr());
+        assert_eq!(builder.into_cell()?.repr_hash(), cell.repr_hash());
+        // source cell is not touched
+        assert_eq!(BuilderData::from_cell(&cell).into_cell()?, cell);
+    }
+    Ok(())
+}
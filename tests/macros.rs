@@ -0,0 +1,41 @@
+/*
+* Copyright 2018-2020 TON DEV SOLUTIONS LTD.
+*
+* Licensed under the SOFTWARE EVALUATION License (the "License"); you may not use
+* this file except in compliance with the License.
+*
+* Unless required by applicable law or agreed to in writing, software
+* distributed under the License is distributed on an "AS IS" BASIS,
+* WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+* See the License for the specific TON DEV software governing permissions and
+* limitations under the License.
+*/
+
+//! Cells built by `cell!` are the same as built by builder, errors are returned
+
+use ton_types::{bits, cell, BuilderData, Cell, IBitstring, Result, MAX_SAFE_DEPTH};
+
+#[test]
+fn test_cell_macro_builds_cells() -> Result<()> {
+    assert_eq!(cell!()?, Cell::default());
+    let mut child = BuilderData::new();
+    child.append_bits(0b101, 3)?;
+    let mut builder = BuilderData::new();
+    builder.append_u8(0xA4)?;
+    builder.checked_append_reference(child.clone().into_cell()?)?;
+    builder.checked_append_reference(child.into_cell()?)?;
+    let expected = builder.into_cell()?;
+    assert_eq!(cell!("x{A4}", [cell!("b{101}")?, cell!("x{B_}")?])?, expected);
+    assert_eq!(bits!("x{A4}").get_next_byte()?, 0xA4);
+    Ok(())
+}
+
+#[test]
+fn test_cell_macro_returns_depth_error() -> Result<()> {
+    let mut cell = cell!("b{1}")?;
+    for _ in 0..MAX_SAFE_DEPTH {
+        cell = cell!("x{01}", [cell])?;
+    }
+    assert!(cell!("x{01}", [cell]).is_err());
+    Ok(())
+}
@@ -0,0 +1,86 @@
+/*
+* Copyright 2018-2020 TON DEV SOLUTIONS LTD.
+*
+* Licensed under the SOFTWARE EVALUATION License (the "License"); you may not use
+* this file except in compliance with the License.
+*
+* Unless required by applicable law or agreed to in writing, software
+* distributed under the License is distributed on an "AS IS" BASIS,
+* WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+* See the License for the specific TON DEV software governing permissions and
+* limitations under the License.
+*/
+
+//! Budgets of parser are checked before reading
+
+use ton_types::{
+    BuilderData, Cell, ExceptionCode, IBitstring, ParseError, ParseErrorKind, ParseLimits, Parser,
+    Result,
+};
+
+fn cell(value: u32, references: usize) -> Result<Cell> {
+    let mut builder = BuilderData::new();
+    builder.append_u32(value)?;
+    for index in 0..references {
+        builder.checked_append_reference(cell(index as u32, 0)?)?;
+    }
+    builder.into_cell()
+}
+
+fn kind(err: anyhow::Error) -> ParseErrorKind {
+    err.downcast::<ParseError>().unwrap().kind
+}
+
+#[test]
+fn test_read_is_limited_by_remaining_budget() -> Result<()> {
+    let limits = ParseLimits { max_bits: 24, max_references: 1, ..Default::default() };
+    let mut parser = Parser::load_cell(cell(0x01020304, 2)?, limits)?;
+    let err = parser.read(|slice| slice.get_next_u32()).unwrap_err();
+    assert_eq!(kind(err), ParseErrorKind::BitsExceeded { limit: 24 });
+    assert_eq!(parser.bits_read(), 0);
+    assert_eq!(parser.remaining_bits(), 32);
+
+    assert_eq!(parser.read(|slice| slice.get_next_u16())?, 0x0102);
+    assert_eq!(parser.remaining_bits(), 16);
+    let err = parser.read(|slice| slice.get_next_u16()).unwrap_err();
+    assert_eq!(kind(err), ParseErrorKind::BitsExceeded { limit: 24 });
+    assert_eq!(parser.read(|slice| slice.get_next_byte())?, 0x03);
+    assert_eq!(parser.bits_read(), 24);
+
+
+    let limits = ParseLimits { max_references: 1, ..Default::default() };
+    let mut parser = Parser::load_cell(cell(0x01020304, 2)?, limits)?;
+    parser.read(|slice| slice.checked_drain_reference())?;
+    let err = parser.read(|slice| slice.checked_drain_reference()).unwrap_err();
+    assert_eq!(kind(err), ParseErrorKind::ReferencesExceeded { limit: 1 });
+    assert_eq!(parser.remaining_references(), 1);
+    Ok(())
+}
+
+#[test]
+fn test_loaded_references_are_counted_as_cells() -> Result<()> {
+    let limits = ParseLimits { max_cells: 2, ..Default::default() };
+    let mut parser = Parser::load_cell(cell(1, 2)?, limits)?;
+    parser.load::<Cell>()?;
+    assert_eq!(parser.cells_read(), 2);
+    let err = parser.load::<Cell>().unwrap_err();
+    assert_eq!(kind(err), ParseErrorKind::CellsExceeded { limit: 2 });
+
+    let mut parser = Parser::load_cell(cell(1, 2)?, ParseLimits::default())?;
+    parser.load_uint(32)?;
+    assert_eq!(parser.load_ref::<u32>()?, 0);
+    assert_eq!(parser.load_ref::<u32>()?, 1);
+    assert_eq!(parser.cells_read(), 3);
+    assert!(parser.is_empty());
+    Ok(())
+}
+
+#[test]
+fn test_huge_byte_count() -> Result<()> {
+    let mut parser = Parser::load_cell(cell(1, 0)?, ParseLimits::default())?;
+    let err = parser.load_bytes(usize::MAX).unwrap_err();
+    assert_eq!(kind(err), ParseErrorKind::BitsExceeded { limit: usize::MAX });
+    let err = parser.load_bytes(5).unwrap_err();
+    assert_eq!(kind(err), ParseErrorKind::Exception(ExceptionCode::CellUnderflow));
+    Ok(())
+}
@@ -0,0 +1,163 @@
+/*
+* Copyright 2018-2020 TON DEV SOLUTIONS LTD.
+*
+* Licensed under the SOFTWARE EVALUATION License (the "License"); you may not use
+* this file except in compliance with the License.
+*
+* Unless required by applicable law or agreed to in writing, software
+* distributed under the License is distributed on an "AS IS" BASIS,
+* WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+* See the License for the specific TON DEV software governing permissions and
+* limitations under the License.
+*/
+
+use std::sync::Arc;
+
+use ton_types::{
+    create_pruned_branch, BagOfCells, BocDeserializer, BuilderData, Cell, CellType, Deserializable, IBitstring,
+    MerkleProof, Result, Serializable, UInt256,
+};
+
+fn leaf(value: u32) -> Result<Cell> {
+    let mut builder = BuilderData::new();
+    builder.append_u32(value)?;
+    builder.into_cell()
+}
+
+fn node(value: u32, children: Vec<Cell>) -> Result<Cell> {
+    let mut builder = BuilderData::new();
+    builder.append_u32(value)?;
+    for child in children {
+        builder.checked_append_reference(child)?;
+    }
+    builder.into_cell()
+}
+
+fn proof_boc_size(root: &Cell, is_include: impl Fn(&UInt256) -> bool) -> Result<usize> {
+    let proof = MerkleProof::create(root, is_include)?.serialize()?;
+    let mut boc = Vec::new();
+    BagOfCells::with_root(&proof).write_to(&mut boc, false)?;
+    Ok(boc.len())
+}
+
+#[test]
+fn test_estimate_size_with_pruned_cells_of_nonzero_level() -> Result<()> {
+    // inner proof with pruned leaf makes cells of level 1 in outer tree
+    let pruned_leaf = leaf(2)?;
+    let inner = node(1, vec![leaf(1)?, pruned_leaf.clone()])?;
+    let inner_proof = MerkleProof::create(&inner, |hash| *hash != pruned_leaf.repr_hash())?.serialize()?;
+    let inner_proof_root = inner_proof.reference(0)?;
+    assert_eq!(inner_proof_root.level(), 1);
+
+    let outer = node(0, vec![inner_proof, node(3, vec![leaf(4)?, leaf(5)?])?])?;
+    let excluded = [inner_proof_root.repr_hash(), leaf(5)?.repr_hash()];
+    let is_include = |hash: &UInt256| !excluded.contains(hash);
+    let estimate = MerkleProof::estimate_size(&outer, is_include)?;
+    assert_eq!(estimate.bytes, proof_boc_size(&outer, is_include)?);
+    Ok(())
+}
+
+#[test]
+fn test_create_prunes_excluded_cells() -> Result<()> {
+    let pruned = node(2, vec![leaf(3)?, leaf(4)?])?;
+    let root = node(0, vec![leaf(1)?, pruned.clone()])?;
+    let proof = MerkleProof::create(&root, |hash| *hash != pruned.repr_hash())?;
+    assert_eq!(proof.hash, root.repr_hash());
+    assert_eq!(proof.depth, root.repr_depth());
+    assert_eq!(proof.proof.hash(0), root.repr_hash());
+    assert_eq!(proof.proof.reference(0)?, leaf(1)?);
+
+    let pruned_branch = proof.proof.reference(1)?;
+    assert_eq!(pruned_branch.cell_type(), CellType::PrunedBranch);
+    assert_eq!(pruned_branch.level_mask().mask(), 1);
+    assert_eq!(pruned_branch.references_count(), 0);
+    assert_eq!(pruned_branch.hash(0), pruned.repr_hash());
+    assert_eq!(pruned_branch.depth(0), pruned.repr_depth());
+
+    let cell = proof.serialize()?;
+    assert_eq!(cell.cell_type(), CellType::MerkleProof);
+    assert_eq!(cell.level(), 0);
+    assert_eq!(MerkleProof::construct_from_cell(cell)?, proof);
+    Ok(())
+}
+
+#[test]
+fn test_create_rejects_not_included_root() -> Result<()> {
+    let root = node(0, vec![leaf(1)?])?;
+    assert!(MerkleProof::create(&root, |hash| *hash != root.repr_hash()).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_create_rejects_cell_pruned_at_the_same_merkle_depth() -> Result<()> {
+    // pruned branch of level 1 can not be pruned again at merkle depth 0
+    let pruned = create_pruned_branch(&leaf(1)?, 0)?;
+    assert!(create_pruned_branch(&pruned, 0).is_err());
+    let root = node(0, vec![pruned.clone()])?;
+    assert!(MerkleProof::create(&root, |hash| *hash != pruned.repr_hash()).is_err());
+    // but it can be pruned inside of other proof
+    assert_eq!(create_pruned_branch(&pruned, 1)?.level_mask().mask(), 0b011);
+    Ok(())
+}
+
+#[test]
+fn test_create_pruned_branch_rejects_too_big_merkle_depth() -> Result<()> {
+    assert!(create_pruned_branch(&leaf(1)?, 2).is_ok());
+    assert!(create_pruned_branch(&leaf(1)?, 3).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_proof_is_not_read_from_ordinary_cell() -> Result<()> {
+    // data and reference of merkle proof in ordinary cell
+    let root = node(0, vec![leaf(1)?])?;
+    let mut builder = BuilderData::new();
+    builder.append_u8(3)?;
+    builder.append_raw(root.repr_hash().as_slice(), 256)?;
+    builder.append_u16(root.repr_depth())?;
+    builder.checked_append_reference(root)?;
+    assert!(MerkleProof::construct_from_cell(builder.into_cell()?).is_err());
+    Ok(())
+}
+
+fn read_expected_proof(boc: &[u8], expected: UInt256, threads: usize, unchecked: bool, inmem: bool) -> Result<Cell> {
+    let deserializer = BocDeserializer::new().set_expected_proof(expected).set_threads(threads);
+    match (unchecked, inmem) {
+        (false, false) => deserializer.deserialize(&mut &boc[..]),
+        (true, false) => deserializer.deserialize_unchecked(&mut &boc[..]),
+        (false, true) => deserializer.deserialize_inmem(Arc::new(boc.to_vec())),
+        (true, true) => deserializer.deserialize_inmem_unchecked(Arc::new(boc.to_vec())),
+    }?.withdraw_one_root()
+}
+
+#[test]
+fn test_expected_proof_rejects_forged_root_hash() -> Result<()> {
+    let expected = node(0, vec![leaf(1)?, leaf(2)?])?;
+    let other = node(0, vec![leaf(1)?, leaf(3)?])?;
+    let proof_boc = |root: &Cell| -> Result<Vec<u8>> {
+        let proof = MerkleProof::create(root, |hash| *hash == root.repr_hash())?.serialize()?;
+        let mut boc = Vec::new();
+        BagOfCells::with_root(&proof).write_to(&mut boc, false)?;
+        Ok(boc)
+    };
+
+    // proof of other tree claims hash of expected one in its root cell
+    let mut forged = proof_boc(&other)?;
+    let pos = forged.windows(32).position(|window| window == other.repr_hash().as_slice())
+        .expect("hash of proven tree is stored in root cell");
+    forged[pos..pos + 32].copy_from_slice(expected.repr_hash().as_slice());
+
+    let genuine = proof_boc(&expected)?;
+    for threads in [1, 4] {
+        for unchecked in [false, true] {
+            for inmem in [false, true] {
+                let root = read_expected_proof(&genuine, expected.repr_hash(), threads, unchecked, inmem)?;
+                assert_eq!(MerkleProof::construct_from_cell(root)?.virtual_root().repr_hash(), expected.repr_hash());
+                assert!(read_expected_proof(&genuine, other.repr_hash(), threads, unchecked, inmem).is_err());
+                assert!(read_expected_proof(&forged, expected.repr_hash(), threads, unchecked, inmem).is_err(),
+                    "threads: {}, unchecked: {}, inmem: {}", threads, unchecked, inmem);
+            }
+        }
+    }
+    Ok(())
+}
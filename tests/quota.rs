@@ -0,0 +1,103 @@
+/*
+* Copyright 2018-2020 TON DEV SOLUTIONS LTD.
+*
+* Licensed under the SOFTWARE EVALUATION License (the "License"); you may not use
+* this file except in compliance with the License.
+*
+* Unless required by applicable law or agreed to in writing, software
+* distributed under the License is distributed on an "AS IS" BASIS,
+* WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+* See the License for the specific TON DEV software governing permissions and
+* limitations under the License.
+*/
+
+//! Cells created by worker threads are charged to quota of the thread which started them
+
+#![cfg(feature = "quota")]
+
+use ton_types::{
+    quota::CellQuota, BagOfCells, BocDeserializer, BuilderData, Cell, ErrorCode, ExceptionCode,
+    HashmapE, HashmapType, IBitstring, Result, SliceData,
+};
+
+/// Wide tree of `width` leaves under two levels of forks, all cells are unique
+fn wide_tree(width: u32) -> Result<Cell> {
+    let mut forks = Vec::new();
+    for fork in 0..width / 4 {
+        let mut builder = BuilderData::new();
+        builder.append_u32(fork)?;
+        for leaf in 0..4 {
+            let mut child = BuilderData::new();
+            child.append_u32(fork * 4 + leaf)?;
+            child.append_u8(1)?;
+            builder.checked_append_reference(child.into_cell()?)?;
+        }
+        forks.push(builder.into_cell()?);
+    }
+    let mut level = forks;
+    while level.len() > 1 {
+        let mut next = Vec::new();
+        for (index, chunk) in level.chunks(4).enumerate() {
+            let mut builder = BuilderData::new();
+            builder.append_u32(index as u32)?;
+            builder.append_u16(level.len() as u16)?;
+            for cell in chunk {
+                builder.checked_append_reference(cell.clone())?;
+            }
+            next.push(builder.into_cell()?);
+        }
+        level = next;
+    }
+    Ok(level.remove(0))
+}
+
+fn count_cells(cell: &Cell) -> Result<usize> {
+    let mut count = 1;
+    for index in 0..cell.references_count() {
+        count += count_cells(&cell.reference(index)?)?;
+    }
+    Ok(count)
+}
+
+#[test]
+fn test_threaded_boc_reading_is_charged() -> Result<()> {
+    let mut boc = Vec::new();
+    BagOfCells::with_root(&wide_tree(4096)?).write_to(&mut boc, false)?;
+
+    let quota = CellQuota::new(usize::MAX, usize::MAX);
+    let root = {
+        let _guard = quota.enter();
+        BocDeserializer::new().set_threads(4).deserialize(&mut &boc[..])?.withdraw_one_root()?
+    };
+    assert_eq!(quota.live_cells(), count_cells(&root)?);
+    drop(root);
+    assert_eq!(quota.live_cells(), 0);
+
+    let quota = CellQuota::new(100, usize::MAX);
+    let _guard = quota.enter();
+    match BocDeserializer::new().set_threads(4).deserialize(&mut &boc[..]) {
+        Ok(_) => panic!("quota is not exceeded"),
+        Err(err) => assert_eq!(ExceptionCode::code_of(&err), ErrorCode::QuotaCellsExceeded.code())
+    }
+    Ok(())
+}
+
+#[test]
+fn test_parallel_dictionary_building_is_charged() -> Result<()> {
+    let entries = (0..4096_u32).map(|key| {
+        let mut key_builder = BuilderData::new();
+        key_builder.append_u32(key)?;
+        let mut value = BuilderData::new();
+        value.append_u32(!key)?;
+        Ok((SliceData::load_builder(key_builder)?, value))
+    }).collect::<Result<Vec<_>>>()?;
+
+    let quota = CellQuota::new(usize::MAX, usize::MAX);
+    let dict = {
+        let _guard = quota.enter();
+        HashmapE::from_sorted_parallel(32, &entries, 4)?
+    };
+    let root = dict.data().cloned().unwrap_or_default();
+    assert_eq!(quota.live_cells(), count_cells(&root)?);
+    Ok(())
+}
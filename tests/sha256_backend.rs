@@ -0,0 +1,44 @@
+/*
+* Copyright 2018-2020 TON DEV SOLUTIONS LTD.
+*
+* Licensed under the SOFTWARE EVALUATION License (the "License"); you may not use
+* this file except in compliance with the License.
+*
+* Unless required by applicable law or agreed to in writing, software
+* distributed under the License is distributed on an "AS IS" BASIS,
+* WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+* See the License for the specific TON DEV software governing permissions and
+* limitations under the License.
+*/
+
+//! SHA-256 backend can't be replaced after hashes are calculated by the default one
+
+use ton_types::{
+    set_sha256_backend, sha256_digest, sha256_implementation, Sha256Backend,
+    Sha256Implementation, Sha256State,
+};
+
+struct ZeroBackend;
+
+struct ZeroState;
+
+impl Sha256State for ZeroState {
+    fn update(&mut self, _data: &[u8]) {}
+    fn finalize(self: Box<Self>) -> [u8; 32] {
+        [0; 32]
+    }
+}
+
+impl Sha256Backend for ZeroBackend {
+    fn new_state(&self) -> Box<dyn Sha256State> {
+        Box::new(ZeroState)
+    }
+}
+
+#[test]
+fn test_backend_is_not_set_after_hashing() {
+    let hash = sha256_digest(b"message");
+    assert!(set_sha256_backend(Box::new(ZeroBackend)).is_err());
+    assert_ne!(sha256_implementation(), Sha256Implementation::Backend);
+    assert_eq!(sha256_digest(b"message"), hash);
+}
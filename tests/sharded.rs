@@ -0,0 +1,118 @@
+/*
+* Copyright 2018-2020 TON DEV SOLUTIONS LTD.
+*
+* Licensed under the SOFTWARE EVALUATION License (the "License"); you may not use
+* this file except in compliance with the License.
+*
+* Unless required by applicable law or agreed to in writing, software
+* distributed under the License is distributed on an "AS IS" BASIS,
+* WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+* See the License for the specific TON DEV software governing permissions and
+* limitations under the License.
+*/
+
+//! Shards of a cell behave as the cell itself
+
+#![cfg(feature = "std")]
+
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+use ton_types::{
+    BuilderData, Cell, CellData, CellImpl, CellType, IBitstring, LevelMask, Result, ShardedCell,
+    UInt256,
+};
+
+fn cell() -> Result<Cell> {
+    let mut child = BuilderData::new();
+    child.append_u32(7)?;
+    let mut builder = BuilderData::new();
+    builder.append_u64(0x0123_4567_89AB_CDEF)?;
+    builder.checked_append_reference(child.into_cell()?)?;
+    builder.into_cell()
+}
+
+// shard of current thread and shards of other threads
+fn shards(sharded: &ShardedCell) -> Vec<Cell> {
+    let mut shards = vec![sharded.cell()];
+    std::thread::scope(|scope| {
+        for _ in 1..sharded.shards_count() {
+            shards.push(scope.spawn(|| sharded.cell()).join().unwrap());
+        }
+    });
+    shards
+}
+
+#[test]
+fn test_shards_share_data_and_hashes() -> Result<()> {
+    let cell = cell()?;
+    let sharded = ShardedCell::with_shards(cell.clone(), 4);
+    assert_eq!(sharded.shards_count(), 4);
+    for shard in shards(&sharded) {
+        assert_eq!(shard, cell);
+        assert_eq!(shard.hashes(), cell.hashes());
+        assert_eq!(shard.depths(), cell.depths());
+        assert_eq!(shard.data().as_ptr(), cell.data().as_ptr());
+        assert_eq!(shard.reference(0)?, cell.reference(0)?);
+        assert_eq!(shard.tree_cell_count(), cell.tree_cell_count());
+    }
+    Ok(())
+}
+
+#[test]
+fn test_shards_are_not_data_cells() -> Result<()> {
+    let cell = cell()?;
+    let sharded = ShardedCell::with_shards(cell.clone(), 2);
+    for shard in shards(&sharded) {
+        // builder of shard doesn't keep the wrapper, it finalizes to new plain cell
+        let rebuilt = BuilderData::from_cell(&shard).into_cell()?;
+        assert_eq!(rebuilt, cell);
+        assert_ne!(rebuilt.data().as_ptr(), cell.data().as_ptr());
+    }
+    Ok(())
+}
+
+/// Cell counting its drops
+struct DropCounter {
+    cell: Cell,
+    drops: Arc<AtomicUsize>,
+}
+
+impl Drop for DropCounter {
+    fn drop(&mut self) {
+        self.drops.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+impl CellImpl for DropCounter {
+    fn data(&self) -> &[u8] { self.cell.data() }
+    fn raw_data(&self) -> Result<&[u8]> { self.cell.raw_data() }
+    fn cell_data(&self) -> &CellData { self.cell.cell_data() }
+    fn bit_length(&self) -> usize { self.cell.bit_length() }
+    fn references_count(&self) -> usize { self.cell.references_count() }
+    fn reference(&self, index: usize) -> Result<Cell> { self.cell.reference(index) }
+    fn cell_type(&self) -> CellType { self.cell.cell_type() }
+    fn level_mask(&self) -> LevelMask { self.cell.level_mask() }
+    fn hash(&self, index: usize) -> UInt256 { self.cell.hash(index) }
+    fn depth(&self, index: usize) -> u16 { self.cell.depth(index) }
+    fn store_hashes(&self) -> bool { self.cell.store_hashes() }
+}
+
+#[test]
+fn test_cell_is_dropped_with_the_last_shard() -> Result<()> {
+    let drops = Arc::new(AtomicUsize::new(0));
+    let cell = Cell::with_cell_impl(DropCounter { cell: cell()?, drops: drops.clone() });
+    let sharded = ShardedCell::with_shards(cell, 4);
+    let shards = shards(&sharded);
+    drop(sharded);
+    assert_eq!(drops.load(Ordering::SeqCst), 0);
+    let last = shards[2].clone();
+    drop(shards);
+    assert_eq!(drops.load(Ordering::SeqCst), 0);
+    assert_eq!(last.bit_length(), 64);
+    drop(last);
+    assert_eq!(drops.load(Ordering::SeqCst), 1);
+    Ok(())
+}
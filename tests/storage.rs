@@ -0,0 +1,134 @@
+/*
+* Copyright 2018-2020 TON DEV SOLUTIONS LTD.
+*
+* Licensed under the SOFTWARE EVALUATION License (the "License"); you may not use
+* this file except in compliance with the License.
+*
+* Unless required by applicable law or agreed to in writing, software
+* distributed under the License is distributed on an "AS IS" BASIS,
+* WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+* See the License for the specific TON DEV software governing permissions and
+* limitations under the License.
+*/
+
+//! Cells loaded from storage records keep counts of their trees and can resolve references by cache
+
+#![cfg(feature = "std")]
+
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc, Mutex, Weak,
+};
+
+use ton_types::{
+    fail, BuilderData, Cell, CellDb, FxHashMap, IBitstring, LruCellCache, Result, StorageCell,
+    UInt256,
+};
+
+/// Storage of encoded records like persistent key-value one, it counts loads
+#[derive(Default)]
+struct RecordDb {
+    records: Mutex<FxHashMap<UInt256, Vec<u8>>>,
+    cache: Option<Arc<LruCellCache>>,
+    this: Weak<RecordDb>,
+    loads: AtomicUsize,
+}
+
+impl RecordDb {
+    fn new(cache: Option<Arc<LruCellCache>>) -> Arc<Self> {
+        Arc::new_cyclic(|this| Self { cache, this: this.clone(), ..Default::default() })
+    }
+}
+
+impl CellDb for RecordDb {
+    fn get(&self, hash: &UInt256) -> Result<Option<Cell>> {
+        let record = match self.records.lock().unwrap().get(hash) {
+            Some(data) => StorageCell::decode(data)?,
+            None => return Ok(None)
+        };
+        self.loads.fetch_add(1, Ordering::Relaxed);
+        let db = match self.this.upgrade() {
+            Some(db) => db,
+            None => fail!("storage is dropped")
+        };
+        match &self.cache {
+            Some(cache) => record.into_cached_cell(db, cache.clone()).map(Some),
+            None => record.into_cell(db).map(Some)
+        }
+    }
+
+    fn put(&self, hash: &UInt256, cell: &Cell) -> Result<()> {
+        let record = StorageCell::from_cell(cell)?.encode();
+        self.records.lock().unwrap().insert(*hash, record);
+        Ok(())
+    }
+
+    fn contains(&self, hash: &UInt256) -> Result<bool> {
+        Ok(self.records.lock().unwrap().contains_key(hash))
+    }
+
+    fn remove(&self, hash: &UInt256) -> Result<()> {
+        self.records.lock().unwrap().remove(hash);
+        Ok(())
+    }
+
+    fn hashes(&self) -> Result<Vec<UInt256>> {
+        Ok(self.records.lock().unwrap().keys().copied().collect())
+    }
+}
+
+fn tree(seed: u32, depth: usize) -> Result<Cell> {
+    let mut builder = BuilderData::new();
+    builder.append_u32(seed)?;
+    builder.append_bits(seed as usize, (seed % 7) as usize)?;
+    if depth > 0 {
+        builder.checked_append_reference(tree(seed * 2 + 1, depth - 1)?)?;
+        builder.checked_append_reference(tree(seed * 2 + 2, depth - 1)?)?;
+    }
+    builder.into_cell()
+}
+
+fn visit(cell: &Cell) -> Result<()> {
+    for index in 0..cell.references_count() {
+        visit(&cell.reference(index)?)?;
+    }
+    Ok(())
+}
+
+#[test]
+fn test_stored_cells_keep_tree_counts() -> Result<()> {
+    let root = tree(0, 4)?;
+    let record = StorageCell::from_cell(&root)?;
+    let decoded = StorageCell::decode(&record.encode())?;
+    assert_eq!(decoded.encode(), record.encode());
+    assert_eq!(decoded.tree_cell_count(), 31);
+    assert_eq!(decoded.tree_bits_count(), root.tree_bits_count());
+
+    let db = RecordDb::new(None);
+    ton_types::store_tree(db.as_ref(), &root)?;
+    let loaded = db.get(&root.repr_hash())?.unwrap();
+    assert_eq!(loaded.repr_hash(), root.repr_hash());
+    assert_eq!(loaded.tree_cell_count(), root.tree_cell_count());
+    assert_eq!(loaded.tree_bits_count(), root.tree_bits_count());
+    let child = loaded.reference(1)?;
+    assert_eq!(child.tree_cell_count(), root.reference(1)?.tree_cell_count());
+    assert_eq!(child.tree_bits_count(), root.reference(1)?.tree_bits_count());
+    Ok(())
+}
+
+#[test]
+fn test_stored_cells_resolve_references_by_cache() -> Result<()> {
+    let root = tree(0, 4)?;
+    let cache = Arc::new(LruCellCache::new(1024));
+    let db = RecordDb::new(Some(cache.clone()));
+    ton_types::store_tree(db.as_ref(), &root)?;
+    let loaded = db.get(&root.repr_hash())?.unwrap();
+
+    visit(&loaded)?;
+    let loads = db.loads.load(Ordering::Relaxed);
+    assert_eq!(loads, root.tree_cell_count() as usize);
+    assert_eq!(cache.len(), loads - 1);
+    visit(&loaded)?;
+    assert_eq!(db.loads.load(Ordering::Relaxed), loads);
+    Ok(())
+}
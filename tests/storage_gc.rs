@@ -0,0 +1,98 @@
+/*
+* Copyright 2018-2020 TON DEV SOLUTIONS LTD.
+*
+* Licensed under the SOFTWARE EVALUATION License (the "License"); you may not use
+* this file except in compliance with the License.
+*
+* Unless required by applicable law or agreed to in writing, software
+* distributed under the License is distributed on an "AS IS" BASIS,
+* WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+* See the License for the specific TON DEV software governing permissions and
+* limitations under the License.
+*/
+
+//! Garbage collection removes only cells unreachable from roots and stored before it started
+
+#![cfg(feature = "std")]
+
+use std::sync::Mutex;
+
+use ton_types::{
+    collect_garbage, store_tree, BuilderData, Cell, CellDb, IBitstring, MemoryCellDb, Result,
+    UInt256,
+};
+
+fn tree(value: u32) -> Result<Cell> {
+    let mut leaf = BuilderData::new();
+    leaf.append_u32(value)?;
+    let mut root = BuilderData::new();
+    root.append_u32(value + 1)?;
+    root.checked_append_reference(leaf.into_cell()?)?;
+    root.into_cell()
+}
+
+/// Storage which stores pending tree while collector marks cells, like concurrent writer does
+struct WriterDb {
+    db: MemoryCellDb,
+    pending: Mutex<Option<Cell>>,
+}
+
+impl CellDb for WriterDb {
+    fn get(&self, hash: &UInt256) -> Result<Option<Cell>> {
+        if let Some(tree) = self.pending.lock().unwrap().take() {
+            store_tree(&self.db, &tree)?;
+        }
+        self.db.get(hash)
+    }
+
+    fn put(&self, hash: &UInt256, cell: &Cell) -> Result<()> {
+        self.db.put(hash, cell)
+    }
+
+    fn contains(&self, hash: &UInt256) -> Result<bool> {
+        self.db.contains(hash)
+    }
+
+    fn remove(&self, hash: &UInt256) -> Result<()> {
+        self.db.remove(hash)
+    }
+
+    fn hashes(&self) -> Result<Vec<UInt256>> {
+        self.db.hashes()
+    }
+}
+
+#[test]
+fn test_collect_garbage_sweeps_unreachable_cells() -> Result<()> {
+    let db = MemoryCellDb::new();
+    let live = tree(1)?;
+    let dead = tree(10)?;
+    store_tree(&db, &live)?;
+    store_tree(&db, &dead)?;
+    let gc = collect_garbage(&db, &[live.repr_hash()])?;
+    assert_eq!((gc.marked, gc.swept), (2, 2));
+    assert_eq!(db.len(), 2);
+    assert!(db.contains(&live.repr_hash())?);
+    assert!(!db.contains(&dead.repr_hash())?);
+    Ok(())
+}
+
+#[test]
+fn test_collect_garbage_keeps_tree_stored_during_marking() -> Result<()> {
+    let live = tree(1)?;
+    let dead = tree(10)?;
+    let stored_later = tree(100)?;
+    let db = WriterDb { db: MemoryCellDb::new(), pending: Mutex::new(None) };
+    store_tree(&db, &live)?;
+    store_tree(&db, &dead)?;
+    *db.pending.lock().unwrap() = Some(stored_later.clone());
+    let gc = collect_garbage(&db, &[live.repr_hash()])?;
+    assert!(db.pending.lock().unwrap().is_none());
+    assert_eq!((gc.marked, gc.swept), (2, 2));
+    assert_eq!(db.db.len(), 4);
+    assert!(db.contains(&live.repr_hash())?);
+    assert!(db.contains(&stored_later.repr_hash())?);
+    assert!(db.contains(&stored_later.reference_repr_hash(0)?)?);
+    assert!(!db.contains(&dead.repr_hash())?);
+    Ok(())
+}
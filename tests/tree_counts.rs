@@ -0,0 +1,67 @@
+/*
+* Copyright 2018-2020 TON DEV SOLUTIONS LTD.
+*
+* Licensed under the SOFTWARE EVALUATION License (the "License"); you may not use
+* this file except in compliance with the License.
+*
+* Unless required by applicable law or agreed to in writing, software
+* distributed under the License is distributed on an "AS IS" BASIS,
+* WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+* See the License for the specific TON DEV software governing permissions and
+* limitations under the License.
+*/
+
+//! Tree counts of cell count shared subtrees once per reference and saturate at 2^56 - 1
+
+use ton_types::{BuilderData, Cell, IBitstring, Result};
+
+const MAX_56_BITS: u64 = (1 << 56) - 1;
+
+fn cell_with_refs(bits: usize, reference: &Cell, count: usize) -> Result<Cell> {
+    let mut builder = BuilderData::new();
+    builder.append_raw(&[0xAA; 128], bits)?;
+    for _ in 0..count {
+        builder.checked_append_reference(reference.clone())?;
+    }
+    builder.into_cell()
+}
+
+#[test]
+fn test_shared_subtree_counted_per_reference() -> Result<()> {
+    let mut leaf = BuilderData::new();
+    leaf.append_u8(1)?;
+    let leaf = leaf.into_cell()?;
+    assert_eq!((leaf.tree_cell_count(), leaf.tree_bits_count()), (1, 8));
+
+    // node refers to the same leaf twice
+    let node = cell_with_refs(3, &leaf, 2)?;
+    assert_eq!((node.tree_cell_count(), node.tree_bits_count()), (3, 3 + 2 * 8));
+
+    // root refers to the node twice and to the leaf once more
+    let mut root = BuilderData::new();
+    root.append_raw(&[0xFF], 5)?;
+    root.checked_append_reference(node.clone())?;
+    root.checked_append_reference(leaf.clone())?;
+    root.checked_append_reference(node)?;
+    let root = root.into_cell()?;
+    assert_eq!(root.tree_cell_count(), 1 + 2 * 3 + 1);
+    assert_eq!(root.tree_bits_count(), 5 + 2 * 19 + 8);
+    Ok(())
+}
+
+#[test]
+fn test_tree_counts_saturate() -> Result<()> {
+    let mut cell = cell_with_refs(1023, &Cell::default(), 0)?;
+    let mut cells = 1u64;
+    let mut bits = 1023u64;
+    for _ in 0..40 {
+        cell = cell_with_refs(1023, &cell, 4)?;
+        cells = cells.saturating_mul(4).saturating_add(1).min(MAX_56_BITS);
+        bits = bits.saturating_mul(4).saturating_add(1023).min(MAX_56_BITS);
+        assert_eq!(cell.tree_cell_count(), cells);
+        assert_eq!(cell.tree_bits_count(), bits);
+    }
+    assert_eq!(cell.tree_cell_count(), MAX_56_BITS);
+    assert_eq!(cell.tree_bits_count(), MAX_56_BITS);
+    Ok(())
+}
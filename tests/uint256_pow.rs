@@ -0,0 +1,71 @@
+/*
+* Copyright 2018-2020 TON DEV SOLUTIONS LTD.
+*
+* Licensed under the SOFTWARE EVALUATION License (the "License"); you may not use
+* this file except in compliance with the License.
+*
+* Unless required by applicable law or agreed to in writing, software
+* distributed under the License is distributed on an "AS IS" BASIS,
+* WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+* See the License for the specific TON DEV software governing permissions and
+* limitations under the License.
+*/
+
+//! Exponentiation of UInt256 wraps modulo 2^256 and reports overflow
+
+use std::str::FromStr;
+
+use ton_types::{Result, UInt256};
+
+fn uint(value: u128) -> UInt256 {
+    UInt256::from_be_bytes(&value.to_be_bytes())
+}
+
+fn power_of_two(exp: usize) -> UInt256 {
+    let mut data = [0; 32];
+    data[31 - exp / 8] = 1 << (exp % 8);
+    UInt256::from(data)
+}
+
+#[test]
+fn test_pow_with_exponent_zero_and_one() {
+    for base in [UInt256::ZERO, uint(1), uint(7), UInt256::MAX] {
+        assert_eq!(base.overflowing_pow(0), (uint(1), false));
+        assert_eq!(base.checked_pow(0), Some(uint(1)));
+        assert_eq!(base.overflowing_pow(1), (base, false));
+        assert_eq!(base.checked_pow(1), Some(base));
+    }
+}
+
+#[test]
+fn test_pow_of_zero_and_one() {
+    for exp in [2, 3, 255, 256, u32::MAX] {
+        assert_eq!(UInt256::ZERO.overflowing_pow(exp), (UInt256::ZERO, false));
+        assert_eq!(uint(1).overflowing_pow(exp), (uint(1), false));
+    }
+}
+
+#[test]
+fn test_pow_overflow_boundaries() -> Result<()> {
+    // the highest power of two fits, the next one wraps to zero
+    assert_eq!(uint(2).checked_pow(255), Some(power_of_two(255)));
+    assert_eq!(uint(2).overflowing_pow(256), (UInt256::ZERO, true));
+    assert_eq!(uint(2).checked_pow(256), None);
+    // limbs are multiplied with carries between them
+    assert_eq!(uint(1 << 64).checked_pow(3), Some(power_of_two(192)));
+    assert_eq!(uint(u64::MAX as u128 + 1).overflowing_pow(4), (UInt256::ZERO, true));
+    // 3^161 < 2^256 < 3^162
+    let fits = UInt256::from_str("90e7a7d36283c4589cff2b2b8d32d43e1eeb4315dc9ac9ead2ceaacca8492983")?;
+    let wrapped = UInt256::from_str("b2b6f77a278b4d09d6fd8182a7987cba5cc1c94195d05dc0786c0065f8db7c89")?;
+    assert_eq!(uint(3).overflowing_pow(161), (fits, false));
+    assert_eq!(uint(3).overflowing_pow(162), (wrapped, true));
+    // result is wrapped even if the lowest limbs are exact
+    let mut data = [0; 32];
+    data[15] = 1;
+    data[31] = 1;
+    let wrapped = UInt256::from_str("0000000000000000000000000000000200000000000000000000000000000001")?;
+    assert_eq!(UInt256::from(data).overflowing_pow(2), (wrapped, true));
+    assert_eq!(UInt256::MAX.overflowing_pow(2), (uint(1), true));
+    assert_eq!(UInt256::MAX.checked_pow(2), None);
+    Ok(())
+}